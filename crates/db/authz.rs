@@ -205,6 +205,10 @@ impl Rbac {
         self.permissions.contains(&Permission::SetupModels)
     }
 
+    pub fn can_view_feedback(&self) -> bool {
+        self.permissions.contains(&Permission::ViewFeedback)
+    }
+
     pub fn can_view_system_prompt(&self) -> bool {
         self.permissions.contains(&Permission::ViewSystemPrompt)
     }
@@ -10,7 +10,7 @@ pub use deadpool_postgres::{Pool, PoolError, Transaction};
 pub use licence::Licence;
 pub use queries::api_keys::ApiKey;
 pub use queries::audit_trail::AuditTrail;
-pub use queries::automation_triggers::CronTrigger;
+pub use queries::automation_triggers::{CronTrigger, WebhookTrigger};
 pub use queries::categories::Category;
 pub use queries::chats::Chat;
 pub use queries::connections::{
@@ -36,8 +36,10 @@ pub use queries::users::User;
 pub use tokio_postgres::types::Json;
 pub use tokio_postgres::Error as TokioPostgresError;
 pub use types::public::{
-    AuditAccessType, AuditAction, ChatRole, ChatStatus, IntegrationType, ModelCapability,
-    ModelType, Permission, PromptFlagType, PromptType, Role, TokenUsageType, Visibility,
+    AuditAccessType, AuditAction, AutomationRunStatus, ChatRole, ChatStatus, FeedbackRating,
+    HistoryStrategy, IntegrationType, LoadBalancing, ModelCapability, ModelProvider, ModelType,
+    Permission, PromptFlagType, PromptType, Role, TokenUsageSource, TokenUsageType, ToolCallStatus,
+    Visibility,
 };
 pub use vector_search::{get_related_context, RelatedContext};
 
@@ -0,0 +1,27 @@
+-- migrate:up
+
+-- The input we pass to the automation as the user message
+ALTER TABLE automation_runs ADD COLUMN input TEXT;
+
+-- Which cron trigger (if any) created this run and for which tick
+ALTER TABLE automation_runs ADD COLUMN cron_trigger_id INT
+    REFERENCES automation_cron_triggers(id) ON DELETE SET NULL;
+ALTER TABLE automation_runs ADD COLUMN scheduled_for TIMESTAMPTZ;
+
+-- Only one scheduler (web-server replica) can create a run for a given tick
+CREATE UNIQUE INDEX automation_runs_cron_tick
+    ON automation_runs(cron_trigger_id, scheduled_for);
+
+-- Used by the scheduler to claim the next pending run
+CREATE INDEX automation_runs_status ON automation_runs(status);
+
+-- The last tick the scheduler created a run for
+ALTER TABLE automation_cron_triggers ADD COLUMN last_scheduled_at TIMESTAMPTZ;
+
+-- migrate:down
+ALTER TABLE automation_cron_triggers DROP COLUMN IF EXISTS last_scheduled_at;
+DROP INDEX IF EXISTS automation_runs_status;
+DROP INDEX IF EXISTS automation_runs_cron_tick;
+ALTER TABLE automation_runs DROP COLUMN IF EXISTS scheduled_for;
+ALTER TABLE automation_runs DROP COLUMN IF EXISTS cron_trigger_id;
+ALTER TABLE automation_runs DROP COLUMN IF EXISTS input;
@@ -0,0 +1,8 @@
+-- migrate:up
+
+-- Which webhook trigger (if any) created this run
+ALTER TABLE automation_runs ADD COLUMN webhook_trigger_id INT
+    REFERENCES automation_webhook_triggers(id) ON DELETE SET NULL;
+
+-- migrate:down
+ALTER TABLE automation_runs DROP COLUMN IF EXISTS webhook_trigger_id;
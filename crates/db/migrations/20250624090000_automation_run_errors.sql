@@ -0,0 +1,7 @@
+-- migrate:up
+
+-- Why a run failed, shown in the run history
+ALTER TABLE automation_runs ADD COLUMN error_message TEXT;
+
+-- migrate:down
+ALTER TABLE automation_runs DROP COLUMN IF EXISTS error_message;
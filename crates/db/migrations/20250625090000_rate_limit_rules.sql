@@ -0,0 +1,57 @@
+-- migrate:up
+
+-- Rate limits are token budgets over a rolling hour. A limit applies to a
+-- single user (by email), everyone with a role, or everyone, and optionally
+-- only to one model. The per API key limits were never enforced.
+ALTER TABLE rate_limits ADD COLUMN limits_role role;
+ALTER TABLE rate_limits ADD COLUMN user_email VARCHAR;
+ALTER TABLE rate_limits ADD COLUMN model_id INT
+    REFERENCES models(id) ON DELETE CASCADE;
+ALTER TABLE rate_limits ADD COLUMN tokens_per_hour INT;
+
+-- Keep the existing limits. A per minute limit becomes an hourly one and a
+-- limit on an API key applies to the key's owner on the key's model.
+UPDATE rate_limits SET
+    tokens_per_hour = LEAST(tpm_limit::BIGINT * 60, 2147483647)::INT;
+UPDATE rate_limits l SET
+    user_email = u.email,
+    model_id = p.model_id
+FROM
+    api_keys a
+JOIN
+    users u ON u.id = a.user_id
+JOIN
+    prompts p ON p.id = a.prompt_id
+WHERE
+    a.id = l.api_key_id;
+-- A limit of zero tokens never restricted anyone
+DELETE FROM rate_limits WHERE tokens_per_hour <= 0;
+
+ALTER TABLE rate_limits ALTER COLUMN tokens_per_hour SET NOT NULL;
+ALTER TABLE rate_limits ADD CONSTRAINT tokens_per_hour_positive CHECK (tokens_per_hour > 0);
+-- A limit is either for a user or a role, not both
+ALTER TABLE rate_limits ADD CONSTRAINT user_or_role
+    CHECK (user_email IS NULL OR limits_role IS NULL);
+
+ALTER TABLE rate_limits DROP COLUMN api_key_id;
+ALTER TABLE rate_limits DROP COLUMN tpm_limit;
+ALTER TABLE rate_limits DROP COLUMN rpm_limit;
+
+COMMENT ON TABLE rate_limits IS 'Hourly token budgets across all teams, the most specific matching limit wins';
+
+-- migrate:down
+
+ALTER TABLE rate_limits ADD COLUMN api_key_id INT
+    REFERENCES api_keys(id) ON DELETE CASCADE;
+ALTER TABLE rate_limits ADD COLUMN tpm_limit INT;
+ALTER TABLE rate_limits ADD COLUMN rpm_limit INT NOT NULL DEFAULT 0;
+UPDATE rate_limits SET tpm_limit = GREATEST(tokens_per_hour / 60, 1);
+ALTER TABLE rate_limits ALTER COLUMN tpm_limit SET NOT NULL;
+ALTER TABLE rate_limits ALTER COLUMN rpm_limit DROP DEFAULT;
+
+ALTER TABLE rate_limits DROP CONSTRAINT user_or_role;
+ALTER TABLE rate_limits DROP CONSTRAINT tokens_per_hour_positive;
+ALTER TABLE rate_limits DROP COLUMN limits_role;
+ALTER TABLE rate_limits DROP COLUMN user_email;
+ALTER TABLE rate_limits DROP COLUMN model_id;
+ALTER TABLE rate_limits DROP COLUMN tokens_per_hour;
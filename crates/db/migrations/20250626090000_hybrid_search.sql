@@ -0,0 +1,44 @@
+-- migrate:up
+
+-- Full text search over chunks so exact matches (part numbers, error codes,
+-- names) are found alongside the vector search.
+--
+-- When encryption is on we can't keep the lexemes next to the encrypted text
+-- so search_vector stays NULL and the search decrypts chunks on the fly.
+ALTER TABLE chunks ADD COLUMN search_vector tsvector;
+
+CREATE INDEX chunks_search_vector ON chunks USING GIN(search_vector);
+
+CREATE OR REPLACE FUNCTION chunks_search_vector() RETURNS trigger AS $$
+BEGIN
+    IF current_setting('encryption.root_key', true) IS NULL THEN
+        NEW.search_vector := to_tsvector('english', NEW.text);
+    ELSE
+        NEW.search_vector := NULL;
+    END IF;
+    RETURN NEW;
+END;
+$$ LANGUAGE plpgsql;
+
+CREATE TRIGGER chunks_search_vector
+    BEFORE INSERT OR UPDATE OF text ON chunks
+    FOR EACH ROW EXECUTE FUNCTION chunks_search_vector();
+
+-- Backfill chunks that were stored as plain text. Encrypted chunks are
+-- stored as hex encoded bytea.
+UPDATE chunks
+SET search_vector = to_tsvector('english', text)
+WHERE left(text, 2) <> '\x';
+
+-- How much each retriever counts when the results are fused.
+-- Set a weight to zero to turn that retriever off.
+ALTER TABLE prompts ADD COLUMN vector_weight REAL NOT NULL DEFAULT 1.0;
+ALTER TABLE prompts ADD COLUMN lexical_weight REAL NOT NULL DEFAULT 1.0;
+
+-- migrate:down
+ALTER TABLE prompts DROP COLUMN IF EXISTS lexical_weight;
+ALTER TABLE prompts DROP COLUMN IF EXISTS vector_weight;
+DROP TRIGGER IF EXISTS chunks_search_vector ON chunks;
+DROP FUNCTION IF EXISTS chunks_search_vector();
+DROP INDEX IF EXISTS chunks_search_vector;
+ALTER TABLE chunks DROP COLUMN IF EXISTS search_vector;
@@ -0,0 +1,11 @@
+-- migrate:up
+ALTER TYPE model_type ADD VALUE IF NOT EXISTS 'Reranker';
+
+-- When enabled we fetch rerank_pool_size candidates and let the
+-- reranker model pick the best max_chunks of them.
+ALTER TABLE prompts ADD COLUMN rerank_enabled BOOLEAN NOT NULL DEFAULT false;
+ALTER TABLE prompts ADD COLUMN rerank_pool_size INT NOT NULL DEFAULT 20 CHECK (rerank_pool_size > 0);
+
+-- migrate:down
+ALTER TABLE prompts DROP COLUMN IF EXISTS rerank_pool_size;
+ALTER TABLE prompts DROP COLUMN IF EXISTS rerank_enabled;
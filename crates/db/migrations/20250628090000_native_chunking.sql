@@ -0,0 +1,12 @@
+-- migrate:up
+-- ByTitle is handled by Unstructured, the others are handled in process
+-- by the rag-engine for text based formats.
+ALTER TYPE chunking_strategy ADD VALUE IF NOT EXISTS 'Recursive';
+ALTER TYPE chunking_strategy ADD VALUE IF NOT EXISTS 'Sentence';
+ALTER TYPE chunking_strategy ADD VALUE IF NOT EXISTS 'TokenWindow';
+
+-- How much of the end of one chunk is repeated at the start of the next.
+ALTER TABLE datasets ADD COLUMN chunk_overlap INT NOT NULL DEFAULT 0 CHECK (chunk_overlap >= 0);
+
+-- migrate:down
+ALTER TABLE datasets DROP COLUMN IF EXISTS chunk_overlap;
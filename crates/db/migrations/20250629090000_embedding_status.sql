@@ -0,0 +1,38 @@
+-- migrate:up
+CREATE TYPE embedding_status AS ENUM (
+    'Pending',
+    'Done',
+    'Failed'
+);
+
+-- Replaces the processed flag, which couldn't tell a chunk that was
+-- embedded from one that failed.
+ALTER TABLE chunks ADD COLUMN embedding_status embedding_status NOT NULL DEFAULT 'Pending';
+ALTER TABLE chunks ADD COLUMN failure_reason TEXT;
+
+UPDATE chunks SET embedding_status = 'Done' WHERE embeddings IS NOT NULL;
+UPDATE chunks SET
+    embedding_status = 'Failed',
+    failure_reason = 'The embeddings API returned an error'
+WHERE embeddings IS NULL AND processed IS TRUE;
+
+ALTER TABLE chunks DROP COLUMN processed;
+
+CREATE INDEX chunks_embedding_status ON chunks(embedding_status)
+    WHERE embedding_status = 'Pending';
+
+-- How the rag-engine calls each embeddings model
+ALTER TABLE models ADD COLUMN embeddings_batch_size INT NOT NULL DEFAULT 32
+    CHECK (embeddings_batch_size > 0);
+ALTER TABLE models ADD COLUMN embeddings_concurrency INT NOT NULL DEFAULT 4
+    CHECK (embeddings_concurrency > 0);
+
+-- migrate:down
+ALTER TABLE models DROP COLUMN embeddings_concurrency;
+ALTER TABLE models DROP COLUMN embeddings_batch_size;
+ALTER TABLE chunks ADD COLUMN processed BOOL NOT NULL DEFAULT false;
+UPDATE chunks SET processed = TRUE WHERE embedding_status <> 'Pending';
+DROP INDEX chunks_embedding_status;
+ALTER TABLE chunks DROP COLUMN failure_reason;
+ALTER TABLE chunks DROP COLUMN embedding_status;
+DROP TYPE embedding_status;
@@ -0,0 +1,6 @@
+-- migrate:up
+-- The tokenizer used to count tokens for the model, i.e. cl100k_base
+ALTER TABLE models ADD COLUMN tokenizer VARCHAR NOT NULL DEFAULT 'cl100k_base';
+
+-- migrate:down
+ALTER TABLE models DROP COLUMN tokenizer;
@@ -0,0 +1,28 @@
+-- migrate:up
+-- Which model made each vector, so searches never compare vectors from
+-- different models.
+ALTER TABLE chunks ADD COLUMN embeddings_model_id INT REFERENCES models(id) ON DELETE SET NULL;
+ALTER TABLE chunks ADD COLUMN embeddings_dimension INT;
+
+-- Vectors from the model a dataset is moving to. They wait here until every
+-- chunk in the dataset has one, then replace the old vectors in one go.
+ALTER TABLE chunks ADD COLUMN reindex_embeddings vector;
+ALTER TABLE chunks ADD COLUMN reindex_model_id INT REFERENCES models(id) ON DELETE SET NULL;
+
+ALTER TABLE datasets ADD COLUMN reindex_model_id INT REFERENCES models(id) ON DELETE SET NULL;
+
+-- Existing vectors came from whichever model the dataset uses now
+UPDATE chunks c SET
+    embeddings_model_id = ds.embeddings_model_id,
+    embeddings_dimension = vector_dims(c.embeddings)
+FROM documents d
+JOIN datasets ds ON ds.id = d.dataset_id
+WHERE d.id = c.document_id
+AND c.embeddings IS NOT NULL;
+
+-- migrate:down
+ALTER TABLE datasets DROP COLUMN reindex_model_id;
+ALTER TABLE chunks DROP COLUMN reindex_model_id;
+ALTER TABLE chunks DROP COLUMN reindex_embeddings;
+ALTER TABLE chunks DROP COLUMN embeddings_dimension;
+ALTER TABLE chunks DROP COLUMN embeddings_model_id;
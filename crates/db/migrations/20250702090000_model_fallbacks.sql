@@ -0,0 +1,54 @@
+-- migrate:up
+
+-- How hard we try a model before moving on to its fallbacks. Connection
+-- errors and timeouts are always retried, status codes only if listed.
+ALTER TABLE models ADD COLUMN retry_max_attempts INT NOT NULL DEFAULT 1
+    CHECK (retry_max_attempts > 0);
+ALTER TABLE models ADD COLUMN retry_backoff_ms INT NOT NULL DEFAULT 500
+    CHECK (retry_backoff_ms >= 0);
+ALTER TABLE models ADD COLUMN retry_status_codes VARCHAR NOT NULL DEFAULT '429,500,502,503,504';
+
+-- After this many failures in a row a model is skipped for the cool down
+ALTER TABLE models ADD COLUMN circuit_breaker_threshold INT NOT NULL DEFAULT 5
+    CHECK (circuit_breaker_threshold > 0);
+ALTER TABLE models ADD COLUMN circuit_breaker_cooldown_seconds INT NOT NULL DEFAULT 30
+    CHECK (circuit_breaker_cooldown_seconds >= 0);
+
+CREATE TABLE model_fallbacks (
+    id int GENERATED BY DEFAULT AS IDENTITY PRIMARY KEY,
+    model_id INT NOT NULL,
+    fallback_model_id INT NOT NULL,
+    priority INT NOT NULL,
+
+    CONSTRAINT FK_model FOREIGN KEY(model_id)
+        REFERENCES models(id) ON DELETE CASCADE,
+    CONSTRAINT FK_fallback_model FOREIGN KEY(fallback_model_id)
+        REFERENCES models(id) ON DELETE CASCADE,
+
+    CONSTRAINT unique_fallback UNIQUE (model_id, fallback_model_id),
+    CONSTRAINT not_self CHECK (model_id <> fallback_model_id)
+);
+
+COMMENT ON TABLE model_fallbacks IS 'Models to try in priority order when a model is unavailable';
+
+-- The model that actually answered, which may be a fallback
+ALTER TABLE chats ADD COLUMN model_id INT REFERENCES models(id) ON DELETE SET NULL;
+ALTER TABLE api_chats ADD COLUMN model_id INT REFERENCES models(id) ON DELETE SET NULL;
+
+-- Give access to the application user.
+GRANT SELECT, INSERT, UPDATE, DELETE ON model_fallbacks TO bionic_application;
+GRANT USAGE, SELECT ON model_fallbacks_id_seq TO bionic_application;
+
+-- Give access to the readonly user
+GRANT SELECT ON model_fallbacks TO bionic_readonly;
+GRANT SELECT ON model_fallbacks_id_seq TO bionic_readonly;
+
+-- migrate:down
+ALTER TABLE api_chats DROP COLUMN model_id;
+ALTER TABLE chats DROP COLUMN model_id;
+DROP TABLE model_fallbacks;
+ALTER TABLE models DROP COLUMN circuit_breaker_cooldown_seconds;
+ALTER TABLE models DROP COLUMN circuit_breaker_threshold;
+ALTER TABLE models DROP COLUMN retry_status_codes;
+ALTER TABLE models DROP COLUMN retry_backoff_ms;
+ALTER TABLE models DROP COLUMN retry_max_attempts;
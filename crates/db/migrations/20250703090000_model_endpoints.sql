@@ -0,0 +1,41 @@
+-- migrate:up
+
+-- How the proxy spreads requests over a model's endpoints
+CREATE TYPE load_balancing AS ENUM (
+    'LeastOutstanding',
+    'WeightedRoundRobin'
+);
+
+ALTER TABLE models ADD COLUMN load_balancing load_balancing NOT NULL DEFAULT 'LeastOutstanding';
+
+-- Replicas or provider keys serving the same model. When a model has
+-- endpoints they are used instead of its base_url and api_key.
+CREATE TABLE model_endpoints (
+    id int GENERATED BY DEFAULT AS IDENTITY PRIMARY KEY,
+    model_id INT NOT NULL,
+    base_url VARCHAR NOT NULL,
+    api_key VARCHAR,
+    weight INT NOT NULL DEFAULT 1 CHECK (weight > 0),
+    -- 0 means no limit
+    max_concurrency INT NOT NULL DEFAULT 0 CHECK (max_concurrency >= 0),
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+
+    CONSTRAINT FK_model FOREIGN KEY(model_id)
+        REFERENCES models(id) ON DELETE CASCADE
+);
+
+COMMENT ON TABLE model_endpoints IS 'A pool of endpoints for one model, health is tracked by the proxy';
+COMMENT ON COLUMN model_endpoints.api_key IS 'Encrypted with encrypt_text';
+
+-- Give access to the application user.
+GRANT SELECT, INSERT, UPDATE, DELETE ON model_endpoints TO bionic_application;
+GRANT USAGE, SELECT ON model_endpoints_id_seq TO bionic_application;
+
+-- Give access to the readonly user
+GRANT SELECT ON model_endpoints TO bionic_readonly;
+GRANT SELECT ON model_endpoints_id_seq TO bionic_readonly;
+
+-- migrate:down
+DROP TABLE model_endpoints;
+ALTER TABLE models DROP COLUMN load_balancing;
+DROP TYPE load_balancing;
@@ -0,0 +1,15 @@
+-- migrate:up
+
+-- The API a model speaks. Anything OpenAI compatible uses 'OpenAI', the
+-- others are translated by the proxy.
+CREATE TYPE model_provider AS ENUM (
+    'OpenAI',
+    'Anthropic',
+    'Ollama'
+);
+
+ALTER TABLE models ADD COLUMN provider model_provider NOT NULL DEFAULT 'OpenAI';
+
+-- migrate:down
+ALTER TABLE models DROP COLUMN provider;
+DROP TYPE model_provider;
@@ -0,0 +1,7 @@
+-- migrate:up
+ALTER TYPE model_type ADD VALUE IF NOT EXISTS 'SpeechToText';
+
+COMMENT ON COLUMN token_usage_metrics.duration_ms IS 'Duration in milliseconds (only for completion type), for transcriptions the length of the audio';
+
+-- migrate:down
+COMMENT ON COLUMN token_usage_metrics.duration_ms IS 'Duration in milliseconds (only for completion type)';
@@ -0,0 +1,39 @@
+-- migrate:up
+
+-- Images created by the image generation tool. The image itself lives in
+-- objects and is attached to the chat, this lets us enforce per user limits.
+-- A row is reserved before the image model is called so concurrent requests
+-- count towards the limit, the object is filled in once it's stored.
+CREATE TABLE generated_images (
+    id int GENERATED BY DEFAULT AS IDENTITY PRIMARY KEY,
+    object_id INT,
+    user_id INT NOT NULL,
+    model_id INT NOT NULL,
+    prompt VARCHAR NOT NULL,
+    size VARCHAR NOT NULL,
+    quality VARCHAR NOT NULL,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+
+    CONSTRAINT FK_object FOREIGN KEY(object_id)
+        REFERENCES objects(id) ON DELETE CASCADE,
+    CONSTRAINT FK_user FOREIGN KEY(user_id)
+        REFERENCES users(id) ON DELETE CASCADE,
+    CONSTRAINT FK_model FOREIGN KEY(model_id)
+        REFERENCES models(id) ON DELETE CASCADE
+);
+
+CREATE INDEX idx_generated_images_user_id ON generated_images(user_id, created_at);
+
+COMMENT ON TABLE generated_images IS 'Images generated for users by the image generation tool';
+COMMENT ON COLUMN generated_images.object_id IS 'The stored image, NULL while it is being generated';
+
+-- Give access to the application user.
+GRANT SELECT, INSERT, UPDATE, DELETE ON generated_images TO bionic_application;
+GRANT USAGE, SELECT ON generated_images_id_seq TO bionic_application;
+
+-- Give access to the readonly user
+GRANT SELECT ON generated_images TO bionic_readonly;
+GRANT SELECT ON generated_images_id_seq TO bionic_readonly;
+
+-- migrate:down
+DROP TABLE generated_images;
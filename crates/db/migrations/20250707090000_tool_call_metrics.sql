@@ -0,0 +1,51 @@
+-- migrate:up
+CREATE TYPE tool_call_status AS ENUM (
+    'Success',
+    'Error',
+    'Timeout',
+    'Cancelled'
+);
+
+CREATE TABLE tool_call_metrics (
+    id BIGINT PRIMARY KEY GENERATED ALWAYS AS IDENTITY,
+    chat_id INT,
+    api_key_id INT,
+    tool_name VARCHAR NOT NULL,
+    status tool_call_status NOT NULL,
+    duration_ms INT NOT NULL,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+
+    CONSTRAINT fk_chat
+        FOREIGN KEY(chat_id) 
+        REFERENCES chats(id)
+        ON DELETE CASCADE,
+
+    CONSTRAINT fk_api_key
+        FOREIGN KEY(api_key_id) 
+        REFERENCES api_keys(id)
+        ON DELETE CASCADE,
+
+    -- Ensure either chat_id or api_key_id is set, but not both
+    CONSTRAINT check_exclusive_reference 
+        CHECK ((chat_id IS NOT NULL AND api_key_id IS NULL) OR 
+               (chat_id IS NULL AND api_key_id IS NOT NULL))
+);
+
+COMMENT ON TABLE tool_call_metrics IS 'How long each tool call took and whether it finished';
+COMMENT ON COLUMN tool_call_metrics.chat_id IS 'The chat holding the tool result (mutually exclusive with api_key_id)';
+COMMENT ON COLUMN tool_call_metrics.api_key_id IS 'Reference to API key for tools run for API calls (mutually exclusive with chat_id)';
+COMMENT ON COLUMN tool_call_metrics.status IS 'Whether the tool finished, failed, timed out or was cancelled';
+
+CREATE INDEX idx_tool_call_metrics_chat_id ON tool_call_metrics(chat_id);
+CREATE INDEX idx_tool_call_metrics_api_key_id ON tool_call_metrics(api_key_id);
+CREATE INDEX idx_tool_call_metrics_created_at ON tool_call_metrics(created_at);
+
+-- Grant permissions
+GRANT SELECT, INSERT, UPDATE, DELETE ON tool_call_metrics TO bionic_application;
+GRANT USAGE, SELECT ON tool_call_metrics_id_seq TO bionic_application;
+GRANT SELECT ON tool_call_metrics TO bionic_readonly;
+GRANT SELECT ON tool_call_metrics_id_seq TO bionic_readonly;
+
+-- migrate:down
+DROP TABLE tool_call_metrics;
+DROP TYPE tool_call_status;
@@ -0,0 +1,25 @@
+-- migrate:up
+CREATE TYPE history_strategy AS ENUM (
+    'Trim',
+    'Summarise'
+);
+
+-- What to do when the history doesn't fit. Trim drops the oldest turns,
+-- Summarise folds them into a summary kept against the conversation.
+ALTER TABLE prompts ADD COLUMN history_strategy history_strategy NOT NULL DEFAULT 'Trim';
+-- The model that writes summaries, the assistant's own model if not set
+ALTER TABLE prompts ADD COLUMN summary_model_id INT
+    REFERENCES models(id) ON DELETE SET NULL;
+
+ALTER TABLE conversations ADD COLUMN history_summary TEXT;
+ALTER TABLE conversations ADD COLUMN history_summary_chat_id INT;
+
+COMMENT ON COLUMN conversations.history_summary IS 'Encrypted summary of the turns that no longer fit the context';
+COMMENT ON COLUMN conversations.history_summary_chat_id IS 'The newest chat folded into the summary, later chats are sent as they are';
+
+-- migrate:down
+ALTER TABLE conversations DROP COLUMN IF EXISTS history_summary_chat_id;
+ALTER TABLE conversations DROP COLUMN IF EXISTS history_summary;
+ALTER TABLE prompts DROP COLUMN IF EXISTS summary_model_id;
+ALTER TABLE prompts DROP COLUMN IF EXISTS history_strategy;
+DROP TYPE history_strategy;
@@ -0,0 +1,17 @@
+-- migrate:up
+-- Models that send a usage chunk at the end of a stream when asked with
+-- stream_options.include_usage
+ALTER TYPE model_capability ADD VALUE IF NOT EXISTS 'StreamUsage';
+
+CREATE TYPE token_usage_source AS ENUM (
+    'Estimated',
+    'Reported'
+);
+
+ALTER TABLE token_usage_metrics ADD COLUMN source token_usage_source NOT NULL DEFAULT 'Estimated';
+
+COMMENT ON COLUMN token_usage_metrics.source IS 'Whether the model reported the tokens or we counted them with its tokenizer';
+
+-- migrate:down
+ALTER TABLE token_usage_metrics DROP COLUMN IF EXISTS source;
+DROP TYPE token_usage_source;
@@ -0,0 +1,47 @@
+-- migrate:up
+-- Chats form a tree. Editing a message or regenerating a reply adds a sibling
+-- with the same parent, the conversation remembers which branch is showing.
+ALTER TABLE chats ADD COLUMN parent_id INT
+    REFERENCES chats(id) ON DELETE CASCADE;
+ALTER TABLE conversations ADD COLUMN active_chat_id INT
+    REFERENCES chats(id) ON DELETE SET NULL;
+
+COMMENT ON COLUMN chats.parent_id IS 'The chat this one follows, siblings are alternative branches';
+COMMENT ON COLUMN conversations.active_chat_id IS 'The last chat of the branch being shown, new chats are added after it';
+
+CREATE INDEX idx_chats_parent_id ON chats(parent_id);
+
+-- Existing conversations become a single branch
+UPDATE chats c
+SET parent_id = previous.parent_id
+FROM (
+    SELECT id, LAG(id) OVER (PARTITION BY conversation_id ORDER BY id) AS parent_id
+    FROM chats
+) previous
+WHERE c.id = previous.id;
+
+UPDATE conversations
+SET active_chat_id = (SELECT MAX(id) FROM chats WHERE conversation_id = conversations.id);
+
+-- The ids of the chats on the branch being shown, a chat's parent always has
+-- a lower id so ordering by id gives the conversation order.
+CREATE FUNCTION active_branch(_conversation_id BIGINT) RETURNS TABLE (chat_id INT) AS
+$$
+    WITH RECURSIVE branch AS (
+        SELECT id, parent_id
+        FROM chats
+        WHERE id = (SELECT active_chat_id FROM conversations WHERE id = _conversation_id)
+        UNION ALL
+        SELECT c.id, c.parent_id
+        FROM chats c
+        JOIN branch b ON c.id = b.parent_id
+    )
+    SELECT id FROM branch
+$$ LANGUAGE SQL STABLE;
+COMMENT ON FUNCTION active_branch IS
+    'The chats from the start of a conversation to its active chat.';
+
+-- migrate:down
+DROP FUNCTION active_branch;
+ALTER TABLE conversations DROP COLUMN IF EXISTS active_chat_id;
+ALTER TABLE chats DROP COLUMN IF EXISTS parent_id;
@@ -0,0 +1,8 @@
+-- migrate:up
+-- Chunks are numbered in the context so the model can cite them as [n]
+ALTER TABLE chunks_chats ADD COLUMN position INT;
+
+COMMENT ON COLUMN chunks_chats.position IS 'The number the chunk was given in the context, the model cites it as [position]';
+
+-- migrate:down
+ALTER TABLE chunks_chats DROP COLUMN IF EXISTS position;
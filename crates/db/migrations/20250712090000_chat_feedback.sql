@@ -0,0 +1,46 @@
+-- migrate:up
+CREATE TYPE feedback_rating AS ENUM (
+    'Positive',
+    'Negative'
+);
+
+CREATE TABLE chat_feedback (
+    id INT PRIMARY KEY GENERATED BY DEFAULT AS IDENTITY,
+    chat_id INT NOT NULL UNIQUE,
+    user_id INT NOT NULL,
+    rating feedback_rating NOT NULL,
+    comment TEXT,
+    reasons TEXT[] NOT NULL DEFAULT '{}',
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    updated_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+
+    CONSTRAINT fk_chat
+        FOREIGN KEY(chat_id)
+        REFERENCES chats(id)
+        ON DELETE CASCADE,
+
+    CONSTRAINT fk_user
+        FOREIGN KEY(user_id)
+        REFERENCES users(id)
+        ON DELETE CASCADE
+);
+
+COMMENT ON TABLE chat_feedback IS 'What users thought of an answer, one rating per chat';
+COMMENT ON COLUMN chat_feedback.comment IS 'Optional comment, encrypted like the chat content';
+COMMENT ON COLUMN chat_feedback.reasons IS 'The reason tags picked by the user, i.e. Inaccurate';
+
+CREATE INDEX idx_chat_feedback_created_at ON chat_feedback(created_at);
+
+-- Who can see the team's feedback dashboard and export
+ALTER TYPE permission ADD VALUE IF NOT EXISTS 'ViewFeedback';
+
+-- Grant permissions
+GRANT SELECT, INSERT, UPDATE, DELETE ON chat_feedback TO bionic_application;
+GRANT USAGE, SELECT ON chat_feedback_id_seq TO bionic_application;
+GRANT SELECT ON chat_feedback TO bionic_readonly;
+GRANT SELECT ON chat_feedback_id_seq TO bionic_readonly;
+
+-- migrate:down
+DROP TABLE chat_feedback;
+DROP TYPE feedback_rating;
+-- Note: The permission enum values cannot be removed in PostgreSQL once added
@@ -0,0 +1,9 @@
+-- migrate:up
+
+-- The dashboard and export show other people's answers, so only managers
+INSERT INTO roles_permissions VALUES('TeamManager', 'ViewFeedback');
+INSERT INTO roles_permissions VALUES('SystemAdministrator', 'ViewFeedback');
+
+-- migrate:down
+DELETE FROM roles_permissions WHERE role = 'TeamManager' AND permission = 'ViewFeedback';
+DELETE FROM roles_permissions WHERE role = 'SystemAdministrator' AND permission = 'ViewFeedback';
@@ -0,0 +1,10 @@
+-- migrate:up
+-- Usage counts against the model that did the work. Chats record the model
+-- that answered, other usage (i.e. API keys and transcriptions) records it here.
+ALTER TABLE token_usage_metrics ADD COLUMN model_id INT
+    REFERENCES models(id) ON DELETE SET NULL;
+
+COMMENT ON COLUMN token_usage_metrics.model_id IS 'The model used when it is not the prompt''s model, i.e. a fallback or speech to text';
+
+-- migrate:down
+ALTER TABLE token_usage_metrics DROP COLUMN IF EXISTS model_id;
@@ -0,0 +1,22 @@
+-- migrate:up
+-- Usage that isn't part of a chat or an API call, i.e. console transcriptions,
+-- is recorded against the user so it still counts towards their rate limit.
+ALTER TABLE token_usage_metrics ADD COLUMN user_id INT
+    REFERENCES users(id) ON DELETE CASCADE;
+
+ALTER TABLE token_usage_metrics DROP CONSTRAINT check_exclusive_reference;
+ALTER TABLE token_usage_metrics ADD CONSTRAINT check_exclusive_reference
+    CHECK (num_nonnulls(chat_id, api_key_id, user_id) = 1);
+
+COMMENT ON COLUMN token_usage_metrics.user_id IS 'Reference to the user for console usage outside a chat (mutually exclusive with chat_id and api_key_id)';
+
+CREATE INDEX idx_token_usage_metrics_user_id ON token_usage_metrics(user_id);
+
+-- migrate:down
+DELETE FROM token_usage_metrics WHERE user_id IS NOT NULL;
+ALTER TABLE token_usage_metrics DROP CONSTRAINT check_exclusive_reference;
+ALTER TABLE token_usage_metrics ADD CONSTRAINT check_exclusive_reference
+    CHECK ((chat_id IS NOT NULL AND api_key_id IS NULL) OR
+           (chat_id IS NULL AND api_key_id IS NOT NULL));
+DROP INDEX IF EXISTS idx_token_usage_metrics_user_id;
+ALTER TABLE token_usage_metrics DROP COLUMN IF EXISTS user_id;
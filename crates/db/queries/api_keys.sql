@@ -6,6 +6,7 @@ SELECT
     a.name,
     a.prompt_id,
     a.user_id,
+    a.team_id,
     (SELECT name FROM prompts p WHERE p.id = a.prompt_id) as prompt_name,
     (SELECT prompt_type FROM prompts p WHERE p.id = a.prompt_id) as prompt_type,
     (SELECT model_id FROM prompts p WHERE p.id = a.prompt_id) as model_id,
@@ -31,6 +32,7 @@ SELECT
     a.name,
     a.prompt_id,
     a.user_id,
+    a.team_id,
     (SELECT name FROM prompts p WHERE p.id = a.prompt_id) as prompt_name,
     (SELECT prompt_type FROM prompts p WHERE p.id = a.prompt_id) as prompt_type,
     (SELECT model_id FROM prompts p WHERE p.id = a.prompt_id) as model_id,
@@ -55,4 +57,11 @@ INSERT INTO api_chats
     (api_key_id, content, role, status)
 VALUES
     (:api_key_id, :content, :role, :status)
-RETURNING id;
\ No newline at end of file
+RETURNING id;
+
+--! set_api_chat_model
+UPDATE api_chats
+SET
+    model_id = :model_id
+WHERE
+    id = :api_chat_id;
\ No newline at end of file
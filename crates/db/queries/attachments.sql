@@ -1,5 +1,6 @@
 --: AttachmentObject()
 --: AttachmentData()
+--: AttachmentImage()
 
 --! insert
 INSERT INTO chats_attachments (
@@ -10,6 +11,20 @@ INSERT INTO chats_attachments (
     :object_id
 );
 
+-- An edited message keeps the attachments of the original
+--! copy_to_chat
+INSERT INTO chats_attachments (
+    chat_id,
+    object_id
+)
+SELECT
+    :new_chat_id,
+    object_id
+FROM
+    chats_attachments
+WHERE
+    chat_id = :chat_id;
+
 --! get_by_conversation : AttachmentObject
 SELECT
     o.id,
@@ -67,4 +82,27 @@ AND
 ORDER BY
     ch.id DESC,
     o.id DESC
-LIMIT 1;
\ No newline at end of file
+LIMIT 1;
+
+--! get_images_by_chats : AttachmentImage
+SELECT
+    ca.chat_id,
+    o.object_data,
+    o.file_name,
+    o.mime_type
+FROM
+    objects o
+JOIN
+    chats_attachments ca ON o.id = ca.object_id
+JOIN
+    chats ch ON ca.chat_id = ch.id
+JOIN
+    conversations c ON ch.conversation_id = c.id
+WHERE
+    ca.chat_id = ANY(:chat_ids)
+AND
+    o.mime_type LIKE 'image/%'
+AND
+    c.user_id = current_app_user()
+ORDER BY
+    o.id;
@@ -0,0 +1,184 @@
+--: DueCronTrigger()
+
+--! due_cron_triggers : DueCronTrigger
+SELECT
+    id,
+    prompt_id,
+    cron_expression,
+    COALESCE(last_scheduled_at, created_at, now()) as scheduled_from
+FROM
+    automation_cron_triggers
+ORDER BY id;
+
+--! insert_scheduled_run
+INSERT INTO automation_runs (prompt_id, cron_trigger_id, scheduled_for, input)
+VALUES (:prompt_id, :cron_trigger_id, :scheduled_for, :input)
+ON CONFLICT (cron_trigger_id, scheduled_for) DO NOTHING
+RETURNING id;
+
+--! set_last_scheduled
+UPDATE automation_cron_triggers
+SET
+    last_scheduled_at = :last_scheduled_at
+WHERE
+    id = :id
+AND
+    (last_scheduled_at IS NULL OR last_scheduled_at < :last_scheduled_at);
+
+--: ClaimedRun(input?)
+--: AutomationRun(input?, error_message?, started_at?, completed_at?, duration_ms?)
+--: RunChat(content?, tool_calls?, tool_call_id?)
+
+--! claim_pending_run : ClaimedRun
+UPDATE automation_runs
+SET
+    status = 'Running',
+    started_at = now()
+WHERE id = (
+    -- SKIP LOCKED means several web-server replicas can poll at the same
+    -- time and each run is still only picked up once.
+    SELECT id
+    FROM automation_runs
+    WHERE status = 'Pending'
+    ORDER BY id
+    FOR UPDATE SKIP LOCKED
+    LIMIT 1
+)
+RETURNING
+    id,
+    prompt_id,
+    input,
+    (SELECT team_id FROM prompts WHERE id = prompt_id) as team_id,
+    (
+        SELECT openid_sub FROM users WHERE id IN (
+            SELECT created_by FROM prompts WHERE id = prompt_id
+        )
+    ) as openid_sub;
+
+--! finish_run(error_message?)
+UPDATE automation_runs
+SET
+    status = :status,
+    error_message = :error_message,
+    completed_at = now()
+WHERE
+    id = :id;
+
+--! fail_stale_runs
+UPDATE automation_runs
+SET
+    status = 'Failed',
+    error_message = 'The run did not complete in time',
+    completed_at = now()
+WHERE
+    -- Runs left in Running by a replica that went away.
+    status = 'Running'
+AND
+    started_at < now() - make_interval(mins => :minutes);
+
+--! link_chats_to_run
+UPDATE chats
+SET
+    automation_run_id = :automation_run_id
+WHERE
+    conversation_id = :conversation_id;
+
+--! insert_webhook_run
+INSERT INTO automation_runs (prompt_id, webhook_trigger_id, input)
+VALUES (:prompt_id, :webhook_trigger_id, :input)
+RETURNING id;
+
+--! insert_rerun
+INSERT INTO automation_runs (prompt_id, input)
+-- Queue a fresh run with the same input as an earlier one
+SELECT prompt_id, input
+FROM automation_runs
+WHERE id = :id AND prompt_id = :prompt_id
+RETURNING id;
+
+--! runs_by_prompt : AutomationRun
+SELECT
+    r.id,
+    r.prompt_id,
+    r.status,
+    r.input,
+    r.error_message,
+    CASE
+        WHEN r.cron_trigger_id IS NOT NULL THEN 'Cron'
+        WHEN r.webhook_trigger_id IS NOT NULL THEN 'Webhook'
+        ELSE 'Manual'
+    END as trigger_type,
+    -- Convert times to ISO 8601 string.
+    trim(both '"' from to_json(r.created_at)::text) as created_at,
+    trim(both '"' from to_json(r.started_at)::text) as started_at,
+    trim(both '"' from to_json(r.completed_at)::text) as completed_at,
+    (EXTRACT(EPOCH FROM (r.completed_at - r.started_at)) * 1000)::BIGINT as duration_ms,
+    COALESCE((
+        SELECT SUM(tokens) FROM token_usage_metrics
+        WHERE type = 'Prompt'
+        AND chat_id IN (SELECT id FROM chats WHERE automation_run_id = r.id)
+    ), 0) as prompt_tokens,
+    COALESCE((
+        SELECT SUM(tokens) FROM token_usage_metrics
+        WHERE type = 'Completion'
+        AND chat_id IN (SELECT id FROM chats WHERE automation_run_id = r.id)
+    ), 0) as completion_tokens
+FROM
+    automation_runs r
+WHERE
+    r.prompt_id = :prompt_id
+ORDER BY r.id DESC
+LIMIT :limit;
+
+--! run : AutomationRun
+SELECT
+    r.id,
+    r.prompt_id,
+    r.status,
+    r.input,
+    r.error_message,
+    CASE
+        WHEN r.cron_trigger_id IS NOT NULL THEN 'Cron'
+        WHEN r.webhook_trigger_id IS NOT NULL THEN 'Webhook'
+        ELSE 'Manual'
+    END as trigger_type,
+    trim(both '"' from to_json(r.created_at)::text) as created_at,
+    trim(both '"' from to_json(r.started_at)::text) as started_at,
+    trim(both '"' from to_json(r.completed_at)::text) as completed_at,
+    (EXTRACT(EPOCH FROM (r.completed_at - r.started_at)) * 1000)::BIGINT as duration_ms,
+    COALESCE((
+        SELECT SUM(tokens) FROM token_usage_metrics
+        WHERE type = 'Prompt'
+        AND chat_id IN (SELECT id FROM chats WHERE automation_run_id = r.id)
+    ), 0) as prompt_tokens,
+    COALESCE((
+        SELECT SUM(tokens) FROM token_usage_metrics
+        WHERE type = 'Completion'
+        AND chat_id IN (SELECT id FROM chats WHERE automation_run_id = r.id)
+    ), 0) as completion_tokens
+FROM
+    automation_runs r
+WHERE
+    r.id = :id
+AND
+    r.prompt_id = :prompt_id;
+
+--! run_chats : RunChat
+SELECT
+    c.id,
+    c.role,
+    c.status,
+    decrypt_text(c.content) as content,
+    decrypt_text(c.tool_calls) as tool_calls,
+    c.tool_call_id,
+    COALESCE((
+        SELECT SUM(tokens) FROM token_usage_metrics WHERE chat_id = c.id
+    ), 0) as tokens,
+    trim(both '"' from to_json(c.created_at)::text) as created_at
+FROM
+    chats c
+WHERE
+    c.automation_run_id = :automation_run_id
+AND
+    c.automation_run_id IN (SELECT id FROM automation_runs WHERE prompt_id = :prompt_id)
+ORDER BY c.id ASC;
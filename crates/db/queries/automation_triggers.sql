@@ -20,3 +20,53 @@ RETURNING id;
 --! delete_cron_trigger
 DELETE FROM automation_cron_triggers
 WHERE id = :id AND prompt_id = :prompt_id;
+
+
+--: WebhookTrigger()
+
+--! webhook_triggers_by_prompt : WebhookTrigger
+SELECT
+    id,
+    prompt_id,
+    secret,
+    trim(both '"' from to_json(created_at)::text) as created_at
+FROM
+    automation_webhook_triggers
+WHERE
+    prompt_id = :prompt_id
+ORDER BY id;
+
+--! webhook_trigger : WebhookTrigger
+SELECT
+    id,
+    prompt_id,
+    secret,
+    trim(both '"' from to_json(created_at)::text) as created_at
+FROM
+    automation_webhook_triggers
+WHERE
+    id = :id;
+
+-- Webhook secrets can only be seen and changed from the prompt's own team
+--! is_team_prompt
+SELECT EXISTS (
+    SELECT 1 FROM prompts
+    WHERE id = :prompt_id
+    AND team_id = :team_id
+);
+
+--! insert_webhook_trigger
+INSERT INTO automation_webhook_triggers (prompt_id, secret)
+VALUES (:prompt_id, :secret)
+RETURNING id;
+
+--! rotate_webhook_trigger
+UPDATE automation_webhook_triggers
+SET
+    secret = :secret
+WHERE
+    id = :id AND prompt_id = :prompt_id;
+
+--! delete_webhook_trigger
+DELETE FROM automation_webhook_triggers
+WHERE id = :id AND prompt_id = :prompt_id;
@@ -0,0 +1,169 @@
+--: ChatFeedback(comment?)
+--: DailySatisfaction()
+--: Satisfaction()
+--: LowRated(comment?, model?, system_prompt?, question?, answer?, chunks?)
+
+-- Rating an answer again replaces the earlier feedback
+--! upsert_feedback(comment?)
+INSERT INTO chat_feedback
+    (chat_id, user_id, rating, comment, reasons)
+VALUES
+    (:chat_id, current_app_user(), :rating, encrypt_text(:comment), :reasons)
+ON CONFLICT (chat_id) DO UPDATE SET
+    user_id = EXCLUDED.user_id,
+    rating = EXCLUDED.rating,
+    comment = EXCLUDED.comment,
+    reasons = EXCLUDED.reasons,
+    updated_at = NOW();
+
+--! conversation_feedback : ChatFeedback
+SELECT
+    f.chat_id,
+    f.rating,
+    decrypt_text(f.comment) as comment,
+    f.reasons
+FROM
+    chat_feedback f
+JOIN
+    chats c ON c.id = f.chat_id
+WHERE
+    c.conversation_id = :conversation_id
+AND
+    -- Make sure the chat belongs to the user
+    c.conversation_id IN (SELECT id FROM conversations WHERE user_id = current_app_user());
+
+--! daily_satisfaction : DailySatisfaction
+SELECT
+    DATE(f.created_at) as feedback_date,
+    COUNT(*) FILTER (WHERE f.rating = 'Positive') as positive,
+    COUNT(*) FILTER (WHERE f.rating = 'Negative') as negative
+FROM
+    chat_feedback f
+JOIN
+    chats c ON c.id = f.chat_id
+WHERE
+    c.conversation_id IN (SELECT id FROM conversations WHERE team_id = :team_id)
+AND
+    f.created_at >= NOW() - (:days || ' days')::INTERVAL
+GROUP BY DATE(f.created_at)
+ORDER BY feedback_date;
+
+--! satisfaction_by_assistant : Satisfaction
+SELECT
+    p.name,
+    COUNT(*) FILTER (WHERE f.rating = 'Positive') as positive,
+    COUNT(*) FILTER (WHERE f.rating = 'Negative') as negative
+FROM
+    chat_feedback f
+JOIN
+    chats c ON c.id = f.chat_id
+JOIN
+    prompts p ON p.id = c.prompt_id
+WHERE
+    c.conversation_id IN (SELECT id FROM conversations WHERE team_id = :team_id)
+AND
+    f.created_at >= NOW() - (:days || ' days')::INTERVAL
+GROUP BY p.name
+ORDER BY negative DESC, p.name;
+
+--! satisfaction_by_model : Satisfaction
+SELECT
+    m.name,
+    COUNT(*) FILTER (WHERE f.rating = 'Positive') as positive,
+    COUNT(*) FILTER (WHERE f.rating = 'Negative') as negative
+FROM
+    chat_feedback f
+JOIN
+    chats c ON c.id = f.chat_id
+JOIN
+    prompts p ON p.id = c.prompt_id
+JOIN
+    -- The model that answered if we know it, otherwise the prompt's model
+    models m ON m.id = COALESCE(c.model_id, p.model_id)
+WHERE
+    c.conversation_id IN (SELECT id FROM conversations WHERE team_id = :team_id)
+AND
+    f.created_at >= NOW() - (:days || ' days')::INTERVAL
+GROUP BY m.name
+ORDER BY negative DESC, m.name;
+
+-- An answer counts towards every dataset its assistant searches
+--! satisfaction_by_dataset : Satisfaction
+SELECT
+    d.name,
+    COUNT(*) FILTER (WHERE f.rating = 'Positive') as positive,
+    COUNT(*) FILTER (WHERE f.rating = 'Negative') as negative
+FROM
+    chat_feedback f
+JOIN
+    chats c ON c.id = f.chat_id
+JOIN
+    prompt_dataset pd ON pd.prompt_id = c.prompt_id
+JOIN
+    datasets d ON d.id = pd.dataset_id
+WHERE
+    c.conversation_id IN (SELECT id FROM conversations WHERE team_id = :team_id)
+AND
+    f.created_at >= NOW() - (:days || ' days')::INTERVAL
+GROUP BY d.name
+ORDER BY negative DESC, d.name;
+
+-- The answers rated down, with the question and the chunks retrieved for it.
+-- The turn is the answer's chats back to the user's question.
+--! low_rated : LowRated
+SELECT
+    f.chat_id,
+    c.conversation_id,
+    -- Convert times to ISO 8601 string.
+    trim(both '"' from to_json(f.created_at)::text) as created_at,
+    f.reasons,
+    decrypt_text(f.comment) as comment,
+    p.name as assistant,
+    COALESCE(
+        (SELECT name FROM models WHERE id = c.model_id),
+        (SELECT name FROM models WHERE id = p.model_id)
+    ) as model,
+    p.system_prompt,
+    turn.question,
+    decrypt_text(c.content) as answer,
+    turn.chunks
+FROM
+    chat_feedback f
+JOIN
+    chats c ON c.id = f.chat_id
+JOIN
+    prompts p ON p.id = c.prompt_id
+LEFT JOIN LATERAL (
+    WITH RECURSIVE turn_chats AS (
+        SELECT id, parent_id, role FROM chats WHERE id = c.parent_id
+        UNION ALL
+        SELECT parent.id, parent.parent_id, parent.role
+        FROM chats parent
+        JOIN turn_chats t ON parent.id = t.parent_id
+        WHERE t.role <> 'User'
+    )
+    SELECT
+        (
+            SELECT decrypt_text(content) FROM chats
+            WHERE id = (SELECT MAX(id) FROM turn_chats WHERE role = 'User')
+        ) as question,
+        (
+            SELECT json_agg(json_build_object(
+                'citation', cc.position,
+                'file_name', d.file_name,
+                'page_number', ch.page_number,
+                'text', decrypt_text(ch.text)
+            ) ORDER BY cc.chat_id, cc.position)
+            FROM chunks_chats cc
+            JOIN chunks ch ON ch.id = cc.chunk_id
+            JOIN documents d ON d.id = ch.document_id
+            WHERE cc.chat_id IN (SELECT id FROM turn_chats)
+        ) as chunks
+) turn ON true
+WHERE
+    c.conversation_id IN (SELECT id FROM conversations WHERE team_id = :team_id)
+AND
+    f.rating = 'Negative'
+AND
+    f.created_at >= NOW() - (:days || ' days')::INTERVAL
+ORDER BY f.created_at DESC;
@@ -1,26 +1,49 @@
---: Chat(content?, tool_calls?, tool_call_id?, attachments?)
+--: Chat(parent_id?, content?, tool_calls?, tool_call_id?, attachments?)
 
 
+-- New chats follow the active chat and become the active chat
 --! new_chat(tool_call_id?, tool_calls?)
-INSERT INTO chats
-    (conversation_id, prompt_id, tool_call_id, tool_calls, content, role, status)
-VALUES
-    (:conversation_id, :prompt_id, :tool_call_id, :tool_calls, encrypt_text(:content), :role, :status)
-RETURNING id;
+WITH chat AS (
+    INSERT INTO chats
+        (conversation_id, parent_id, prompt_id, tool_call_id, tool_calls, content, role, status)
+    VALUES
+        (
+            :conversation_id,
+            (SELECT active_chat_id FROM conversations WHERE id = :conversation_id),
+            :prompt_id,
+            :tool_call_id,
+            :tool_calls,
+            encrypt_text(:content),
+            :role,
+            :status
+        )
+    RETURNING id, conversation_id
+), activate AS (
+    UPDATE conversations
+    SET active_chat_id = (SELECT id FROM chat)
+    WHERE id = (SELECT conversation_id FROM chat)
+)
+SELECT id FROM chat;
     
 --! chats : Chat
 SELECT
     id,
     conversation_id,
+    parent_id,
     decrypt_text(content) as content,
     role,
     tool_call_id,
     decrypt_text(tool_calls) as tool_calls,
     prompt_id,
-    (SELECT name FROM models WHERE id IN (SELECT model_id FROM prompts WHERE id = prompt_id)) as model_name,
+    -- The model that answered if we know it, otherwise the prompt's model
+    COALESCE(
+        (SELECT name FROM models WHERE id = chats.model_id),
+        (SELECT name FROM models WHERE id IN (SELECT model_id FROM prompts WHERE id = prompt_id))
+    ) as model_name,
     status,
     (
         SELECT json_agg(json_build_object(
+            'id', o.id,
             'name', o.file_name,
             'type', o.mime_type,
             'size', o.file_size
@@ -38,21 +61,70 @@ WHERE
     conversation_id IN (SELECT id FROM conversations WHERE user_id = current_app_user())
 AND
     conversation_id = :conversation_id
+AND
+    id IN (SELECT chat_id FROM active_branch(:conversation_id))
 ORDER BY id;
 
 --! chat_history : Chat
 SELECT
     id,
     conversation_id,
+    parent_id,
+    decrypt_text(content) as content,
+    role,
+    tool_call_id,
+    decrypt_text(tool_calls) as tool_calls,
+    prompt_id,
+    -- The model that answered if we know it, otherwise the prompt's model
+    COALESCE(
+        (SELECT name FROM models WHERE id = chats.model_id),
+        (SELECT name FROM models WHERE id IN (SELECT model_id FROM prompts WHERE id = prompt_id))
+    ) as model_name,
+    status,
+    (
+        SELECT json_agg(json_build_object(
+            'id', o.id,
+            'name', o.file_name,
+            'type', o.mime_type,
+            'size', o.file_size
+        ))
+        FROM chats_attachments ca
+        JOIN objects o ON ca.object_id = o.id
+        WHERE ca.chat_id = chats.id
+    ) as attachments,
+    created_at,
+    updated_at
+FROM
+    chats
+WHERE
+    -- Make sure the chat belongs to the user
+    conversation_id IN (SELECT id FROM conversations WHERE user_id = current_app_user())
+AND
+    conversation_id = :conversation_id
+AND
+    id IN (SELECT chat_id FROM active_branch(:conversation_id))
+ORDER BY id ASC
+LIMIT :limit;
+
+--! chat_history_after : Chat
+SELECT
+    id,
+    conversation_id,
+    parent_id,
     decrypt_text(content) as content,
     role,
     tool_call_id,
     decrypt_text(tool_calls) as tool_calls,
     prompt_id,
-    (SELECT name FROM models WHERE id IN (SELECT model_id FROM prompts WHERE id = prompt_id)) as model_name,
+    -- The model that answered if we know it, otherwise the prompt's model
+    COALESCE(
+        (SELECT name FROM models WHERE id = chats.model_id),
+        (SELECT name FROM models WHERE id IN (SELECT model_id FROM prompts WHERE id = prompt_id))
+    ) as model_name,
     status,
     (
         SELECT json_agg(json_build_object(
+            'id', o.id,
             'name', o.file_name,
             'type', o.mime_type,
             'size', o.file_size
@@ -70,6 +142,11 @@ WHERE
     conversation_id IN (SELECT id FROM conversations WHERE user_id = current_app_user())
 AND
     conversation_id = :conversation_id
+AND
+    -- Chats up to here are in the conversation's history summary
+    id > :chat_id
+AND
+    id IN (SELECT chat_id FROM active_branch(:conversation_id))
 ORDER BY id ASC
 LIMIT :limit;
 
@@ -77,15 +154,21 @@ LIMIT :limit;
 SELECT
     id,
     conversation_id,
+    parent_id,
     decrypt_text(content) as content,
     role,
     tool_call_id,
     decrypt_text(tool_calls) as tool_calls,
     prompt_id,
-    (SELECT name FROM models WHERE id IN (SELECT model_id FROM prompts WHERE id = prompt_id)) as model_name,
+    -- The model that answered if we know it, otherwise the prompt's model
+    COALESCE(
+        (SELECT name FROM models WHERE id = chats.model_id),
+        (SELECT name FROM models WHERE id IN (SELECT model_id FROM prompts WHERE id = prompt_id))
+    ) as model_name,
     status,
     (
         SELECT json_agg(json_build_object(
+            'id', o.id,
             'name', o.file_name,
             'type', o.mime_type,
             'size', o.file_size
@@ -105,6 +188,16 @@ AND
     id = :chat_id
 ORDER BY id;
 
+--! set_chat_model
+UPDATE chats
+SET
+    model_id = :model_id
+WHERE
+    id = :chat_id
+AND
+    -- Make sure the chat belongs to the user
+    conversation_id IN (SELECT id FROM conversations WHERE user_id = current_app_user());
+
 --! set_chat_status
 UPDATE chats
 SET
@@ -113,4 +206,47 @@ WHERE
     id = :chat_id
 AND
     -- Make sure the chat belongs to the user
-    conversation_id IN (SELECT id FROM conversations WHERE user_id = current_app_user());
\ No newline at end of file
+    conversation_id IN (SELECT id FROM conversations WHERE user_id = current_app_user());
+
+--: Sibling(parent_id?)
+
+-- Every chat in the conversation, so we can show the alternatives to a chat
+--! siblings : Sibling
+SELECT
+    id,
+    parent_id
+FROM
+    chats
+WHERE
+    -- Make sure the chat belongs to the user
+    conversation_id IN (SELECT id FROM conversations WHERE user_id = current_app_user())
+AND
+    conversation_id = :conversation_id
+ORDER BY id;
+
+-- Editing and regenerating start a new branch after this chat
+--! set_active_chat(chat_id?)
+UPDATE conversations
+SET
+    active_chat_id = :chat_id
+WHERE
+    id = :conversation_id
+AND
+    user_id = current_app_user();
+
+-- Show the branch through a chat, following its newest replies to the end
+--! switch_branch
+WITH RECURSIVE latest AS (
+    SELECT id FROM chats WHERE id = :chat_id
+    UNION ALL
+    SELECT (SELECT MAX(c.id) FROM chats c WHERE c.parent_id = latest.id)
+    FROM latest
+    WHERE latest.id IS NOT NULL
+)
+UPDATE conversations
+SET
+    active_chat_id = (SELECT MAX(id) FROM latest)
+WHERE
+    id = (SELECT conversation_id FROM chats WHERE id = :chat_id)
+AND
+    user_id = current_app_user();
\ No newline at end of file
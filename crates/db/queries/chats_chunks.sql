@@ -1,21 +1,55 @@
---: ChatChunks()
+--: ChatChunks(position?)
 
 --! chunks_chats : ChatChunks
 SELECT 
     chunk_id, 
     chat_id,
+    position,
     (SELECT page_number from chunks c WHERE c.id = chunk_id) as page_number,
     (SELECT 
         file_name 
     FROM documents d 
     WHERE d.id = (SELECT document_id FROM chunks c WHERE c.id = chunk_id)) 
-    AS file_name
+    AS file_name,
+    (SELECT LEFT(decrypt_text(text), 280) FROM chunks c WHERE c.id = chunk_id) as preview
 FROM
     chunks_chats 
-WHERE chat_id = :chat_id;
+WHERE chat_id = :chat_id
+ORDER BY position;
 
 --! create_chunks_chats
 INSERT INTO chunks_chats 
-    (chunk_id, chat_id)
+    (chunk_id, chat_id, position)
 VALUES
-    (:chunk_id, (SELECT id FROM chats WHERE conversation_id = :conversation_id ORDER BY created_at DESC LIMIT 1));
\ No newline at end of file
+    (:chunk_id, (SELECT active_chat_id FROM conversations WHERE id = :conversation_id), :position);
+
+-- Chunks found by the search tool carry on the numbering of the chunks
+-- already given to the model since the user's question.
+--! turn_chunk_count
+SELECT
+    COUNT(*)::INT
+FROM
+    chunks_chats
+WHERE
+    chat_id IN (SELECT chat_id FROM active_branch(:conversation_id))
+AND
+    chat_id >= (
+        SELECT MAX(id) FROM chats
+        WHERE role = 'User'
+        AND id IN (SELECT chat_id FROM active_branch(:conversation_id))
+    );
+
+--: Citation()
+
+--! citations : Citation
+SELECT
+    c.id as chunk_id,
+    d.file_name,
+    c.page_number,
+    LEFT(decrypt_text(c.text), 280) as preview
+FROM
+    chunks c
+JOIN
+    documents d ON d.id = c.document_id
+WHERE
+    c.id = ANY(:chunk_ids);
@@ -1,46 +1,119 @@
---! unprocessed_chunks : Chunk(api_key?)
+--: Chunk(api_key?)
+
+--! pending_chunks : Chunk
 SELECT
-    id,
-    decrypt_text(text) as text,
-    (SELECT 
-        base_url 
-    FROM 
-        models 
-    WHERE 
-        id IN (SELECT embeddings_model_id FROM datasets ds WHERE ds.id IN
-        (SELECT dataset_id FROM documents d WHERE d.id = document_id))
-    ) as base_url,
-    (SELECT 
-        api_key 
-    FROM 
-        models 
-    WHERE 
-        id IN (SELECT embeddings_model_id FROM datasets ds WHERE ds.id IN
-        (SELECT dataset_id FROM documents d WHERE d.id = document_id))
-    ) as api_key,
-    (SELECT 
-        name 
-    FROM 
-        models 
-    WHERE 
-        id IN (SELECT embeddings_model_id FROM datasets ds WHERE ds.id IN
-        (SELECT dataset_id FROM documents d WHERE d.id = document_id))
-    ) as model,
-    (SELECT 
-        context_size 
-    FROM 
-        models 
-    WHERE 
-        id IN (SELECT embeddings_model_id FROM datasets ds WHERE ds.id IN
-        (SELECT dataset_id FROM documents d WHERE d.id = document_id))
-    ) as context_size
+    c.id,
+    decrypt_text(c.text) as text,
+    m.id as model_id,
+    m.base_url,
+    m.api_key,
+    m.name as model,
+    m.context_size,
+    m.tokenizer,
+    m.embeddings_batch_size,
+    m.embeddings_concurrency
 FROM
-    chunks
+    chunks c
+JOIN documents d ON d.id = c.document_id
+JOIN datasets ds ON ds.id = d.dataset_id
+JOIN models m ON m.id = ds.embeddings_model_id
 WHERE
-    processed IS NOT TRUE
+    c.embedding_status = 'Pending'
 ORDER BY
-    id
+    c.id
 LIMIT :limit;
 
+--! reindex_chunks : Chunk
+SELECT
+    c.id,
+    decrypt_text(c.text) as text,
+    m.id as model_id,
+    m.base_url,
+    m.api_key,
+    m.name as model,
+    m.context_size,
+    m.tokenizer,
+    m.embeddings_batch_size,
+    m.embeddings_concurrency
+FROM
+    chunks c
+JOIN documents d ON d.id = c.document_id
+JOIN datasets ds ON ds.id = d.dataset_id
+JOIN models m ON m.id = ds.reindex_model_id
+WHERE
+    c.embedding_status = 'Done'
+AND
+    c.reindex_model_id IS DISTINCT FROM ds.reindex_model_id
+ORDER BY
+    c.id
+LIMIT :limit;
+
+--! insert_split_chunk
+INSERT INTO chunks (
+    document_id,
+    page_number,
+    text
+)
+-- The new chunk comes from the same document and page as the one we split
+SELECT
+    document_id,
+    page_number,
+    encrypt_text(:text)
+FROM
+    chunks
+WHERE
+    id = :id;
+
+--! update_chunk_text
+UPDATE chunks SET
+    text = encrypt_text(:text),
+    updated_at = NOW()
+WHERE id = :id;
+
+--! swap_reindexed_chunks
+UPDATE chunks SET
+    embeddings = reindex_embeddings,
+    embeddings_model_id = reindex_model_id,
+    embeddings_dimension = vector_dims(reindex_embeddings),
+    reindex_embeddings = NULL,
+    reindex_model_id = NULL,
+    updated_at = NOW()
+WHERE
+    reindex_model_id IS NOT NULL
+AND
+    document_id IN (SELECT id FROM documents WHERE dataset_id = :dataset_id);
+
+--! retry_dataset_failed_chunks
+UPDATE chunks SET
+    -- After a model change failed chunks get another go with the new model
+    embedding_status = 'Pending',
+    failure_reason = NULL
+WHERE
+    embedding_status = 'Failed'
+AND
+    document_id IN (SELECT id FROM documents WHERE dataset_id = :dataset_id);
+
+--! fail_chunk
+UPDATE chunks SET
+    embedding_status = 'Failed',
+    failure_reason = :failure_reason,
+    updated_at = NOW()
+WHERE id = :id;
+
+--! retry_failed_chunks
+UPDATE chunks SET
+    embedding_status = 'Pending',
+    failure_reason = NULL
+WHERE
+    embedding_status = 'Failed'
+AND
+    document_id = :document_id
+AND
+    document_id IN (SELECT id FROM documents WHERE dataset_id
+        IN (SELECT id FROM datasets WHERE team_id
+            IN (SELECT team_id FROM team_users WHERE user_id = current_app_user())
+        )
+    );
+
 --! delete
-DELETE FROM chunks WHERE id = :embedding_id;
\ No newline at end of file
+DELETE FROM chunks WHERE id = :embedding_id;
@@ -46,6 +46,19 @@ WHERE
 AND 
     id IN (SELECT conversation_id FROM chats WHERE id = :chat_id);
 
+--! get_conversation : Conversation
+SELECT
+    id,
+    user_id,
+    team_id,
+    created_at
+FROM
+    conversations
+WHERE
+    user_id = current_app_user()
+AND
+    id = :id;
+
 --! delete
 DELETE FROM
     conversations
@@ -85,3 +98,31 @@ JOIN models m ON p.model_id = m.id
 WHERE c.id = :conversation_id
   AND c.user_id = current_app_user();
 
+
+--: HistorySummary(summary?, chat_id?)
+-- A summary of the turns on another branch doesn't apply
+--! history_summary : HistorySummary
+SELECT
+    CASE WHEN history_summary_chat_id IN (SELECT chat_id FROM active_branch(id))
+        THEN decrypt_text(history_summary)
+    END as summary,
+    CASE WHEN history_summary_chat_id IN (SELECT chat_id FROM active_branch(id))
+        THEN history_summary_chat_id
+    END as chat_id
+FROM
+    conversations
+WHERE
+    id = :conversation_id
+AND
+    user_id = current_app_user();
+
+--! set_history_summary
+UPDATE
+    conversations
+SET
+    history_summary = encrypt_text(:summary),
+    history_summary_chat_id = :chat_id
+WHERE
+    id = :conversation_id
+AND
+    user_id = current_app_user();
@@ -1,4 +1,6 @@
---! datasets : Dataset()
+--: Dataset(reindex_model_id?)
+
+--! datasets : Dataset
 SELECT
     id,
     team_id, 
@@ -8,6 +10,9 @@ SELECT
     combine_under_n_chars,
     new_after_n_chars,
     multipage_sections,
+    chunk_overlap,
+    embeddings_model_id,
+    reindex_model_id,
     (SELECT COUNT(id) FROM documents WHERE dataset_id = d.id) as count,
     (SELECT name FROM models WHERE id = d.embeddings_model_id) as embeddings_model_name,
     created_at,
@@ -29,7 +34,7 @@ OR
     (visibility = 'Company')
 ORDER BY updated_at;
 
---! dataset_by_pipeline_key : Dataset()
+--! dataset_by_pipeline_key : Dataset
 SELECT
     id,
     team_id, 
@@ -39,6 +44,9 @@ SELECT
     combine_under_n_chars,
     new_after_n_chars,
     multipage_sections,
+    chunk_overlap,
+    embeddings_model_id,
+    reindex_model_id,
     (SELECT COUNT(id) FROM documents WHERE dataset_id = d.id) as count,
     (SELECT name FROM models WHERE id = d.embeddings_model_id) as embeddings_model_name,
     created_at,
@@ -50,7 +58,7 @@ WHERE
         SELECT dataset_id FROM document_pipelines WHERE api_key = :api_key
     ) ORDER BY updated_at;
 
---! dataset : Dataset()
+--! dataset : Dataset
 SELECT
     id,
     team_id, 
@@ -60,6 +68,9 @@ SELECT
     combine_under_n_chars,
     new_after_n_chars,
     multipage_sections,
+    chunk_overlap,
+    embeddings_model_id,
+    reindex_model_id,
     (SELECT COUNT(id) FROM documents WHERE dataset_id = d.id) as count,
     (SELECT name FROM models WHERE id = d.embeddings_model_id) as embeddings_model_name,
     created_at,
@@ -86,7 +97,7 @@ AND
     )
 ORDER BY updated_at;
 
---! pipeline_dataset : Dataset()
+--! pipeline_dataset : Dataset
 SELECT
     id,
     team_id, 
@@ -96,6 +107,9 @@ SELECT
     combine_under_n_chars,
     new_after_n_chars,
     multipage_sections,
+    chunk_overlap,
+    embeddings_model_id,
+    reindex_model_id,
     (SELECT COUNT(id) FROM documents WHERE dataset_id = d.id) as count,
     (SELECT name FROM models WHERE id = d.embeddings_model_id) as embeddings_model_name,
     created_at,
@@ -116,6 +130,7 @@ INSERT INTO
         combine_under_n_chars,
         new_after_n_chars,
         multipage_sections,
+        chunk_overlap,
         visibility,
         created_by
     )
@@ -127,6 +142,7 @@ VALUES(
     :combine_under_n_chars,
     :new_after_n_chars,
     :multipage_sections,
+    :chunk_overlap,
     :visibility,
     current_app_user())
 RETURNING id;
@@ -137,17 +153,90 @@ UPDATE
 SET 
     name = :name, 
     visibility = :visibility,
-    embeddings_model_id = :embeddings_model_id,
+    -- A new embeddings model doesn't take over until the rag-engine has
+    -- re-embedded every chunk, the old vectors are used until then.
+    reindex_model_id = CASE
+        WHEN :embeddings_model_id = COALESCE(reindex_model_id, embeddings_model_id)
+            THEN reindex_model_id
+        WHEN :embeddings_model_id = embeddings_model_id THEN NULL
+        ELSE :embeddings_model_id
+    END,
     chunking_strategy = :chunking_strategy,
     combine_under_n_chars = :combine_under_n_chars,
     new_after_n_chars = :new_after_n_chars,
-    multipage_sections = :multipage_sections
+    multipage_sections = :multipage_sections,
+    chunk_overlap = :chunk_overlap
+WHERE
+    id = :id
+AND
+    team_id
+    IN (SELECT team_id FROM team_users WHERE user_id = current_app_user());
+
+--! request_reindex
+UPDATE
+    datasets
+SET
+    -- Carries on with a model change that is underway, otherwise re-embeds
+    -- with the current model
+    reindex_model_id = COALESCE(reindex_model_id, embeddings_model_id)
 WHERE
     id = :id
 AND
     team_id
     IN (SELECT team_id FROM team_users WHERE user_id = current_app_user());
 
+--! reindex_progress : ReindexProgress
+SELECT
+    d.id as dataset_id,
+    m.name as model_name,
+    (
+        SELECT COUNT(c.id) FROM chunks c
+        JOIN documents doc ON doc.id = c.document_id
+        WHERE doc.dataset_id = d.id AND c.embedding_status = 'Done'
+    ) as total,
+    (
+        SELECT COUNT(c.id) FROM chunks c
+        JOIN documents doc ON doc.id = c.document_id
+        WHERE doc.dataset_id = d.id AND c.embedding_status = 'Done'
+        AND c.reindex_model_id = d.reindex_model_id
+    ) as done
+FROM
+    datasets d
+JOIN models m ON m.id = d.reindex_model_id
+WHERE
+    d.team_id
+    IN (SELECT team_id FROM team_users WHERE user_id = current_app_user());
+
+--! reindexed_datasets
+SELECT
+    d.id
+-- Datasets where every embedded chunk has a vector from the new model
+FROM
+    datasets d
+WHERE
+    d.reindex_model_id IS NOT NULL
+AND NOT EXISTS (
+    SELECT 1 FROM chunks c
+    JOIN documents doc ON doc.id = c.document_id
+    WHERE doc.dataset_id = d.id
+    AND (
+        c.embedding_status = 'Pending'
+        OR (
+            c.embedding_status = 'Done'
+            AND c.reindex_model_id IS DISTINCT FROM d.reindex_model_id
+        )
+    )
+);
+
+--! swap_reindexed_dataset
+UPDATE
+    datasets
+SET
+    embeddings_model_id = reindex_model_id,
+    reindex_model_id = NULL
+WHERE
+    id = :id;
+
 --! delete
 DELETE FROM
     datasets
@@ -25,8 +25,8 @@ SELECT
     failure_reason,
     (SELECT COUNT(id) FROM chunks WHERE document_id = d.id) as batches,
     content_size,
-    (SELECT COUNT(id) FROM chunks WHERE document_id = d.id AND chunks IS NULL AND processed IS TRUE) as fail_count,
-    (SELECT COUNT(id) FROM chunks WHERE document_id = d.id AND processed IS NOT TRUE) as waiting,
+    (SELECT COUNT(id) FROM chunks WHERE document_id = d.id AND embedding_status = 'Failed') as fail_count,
+    (SELECT COUNT(id) FROM chunks WHERE document_id = d.id AND embedding_status = 'Pending') as waiting,
     created_at,
     updated_at
 FROM 
@@ -49,8 +49,8 @@ SELECT
     failure_reason,
     (SELECT COUNT(id) FROM chunks WHERE document_id = d.id) as batches,
     content_size,
-    (SELECT COUNT(id) FROM chunks WHERE document_id = d.id AND chunks IS NULL AND processed IS TRUE) as fail_count,
-    (SELECT COUNT(id) FROM chunks WHERE document_id = d.id AND processed IS NOT TRUE) as waiting,
+    (SELECT COUNT(id) FROM chunks WHERE document_id = d.id AND embedding_status = 'Failed') as fail_count,
+    (SELECT COUNT(id) FROM chunks WHERE document_id = d.id AND embedding_status = 'Pending') as waiting,
     created_at,
     updated_at
 FROM 
@@ -66,6 +66,16 @@ AND
         )
     );
 
+--! retry_document
+UPDATE documents SET failure_reason = NULL
+-- Clearing the failure lets the rag-engine try to chunk the document again
+WHERE
+    id = :document_id
+AND
+    dataset_id IN (SELECT id FROM datasets WHERE team_id
+        IN (SELECT team_id FROM team_users WHERE user_id = current_app_user())
+    );
+
 --! insert
 INSERT INTO documents (
     dataset_id,
@@ -0,0 +1,60 @@
+-- Taken before counting so concurrent requests for a user queue up behind
+-- each other and can't all pass the limit.
+--! lock_for_user
+SELECT id FROM users WHERE id = current_app_user() FOR UPDATE;
+
+-- Holds a place against the daily limit while the image is generated. The
+-- prompt is the user's text so it's encrypted like chat content, read it
+-- back with decrypt_text.
+--! reserve
+INSERT INTO generated_images (
+    user_id,
+    model_id,
+    prompt,
+    size,
+    quality
+) VALUES (
+    current_app_user(),
+    :model_id,
+    encrypt_text(:prompt),
+    :size,
+    :quality
+)
+RETURNING id;
+
+--! set_object
+UPDATE generated_images
+SET
+    object_id = :object_id
+WHERE
+    id = :id
+AND
+    user_id = current_app_user();
+
+-- The image model failed, so the attempt doesn't count
+--! release
+DELETE FROM generated_images
+WHERE
+    id = :id
+AND
+    user_id = current_app_user()
+AND
+    object_id IS NULL;
+
+--! count_for_user_since
+SELECT
+    COUNT(*)
+FROM
+    generated_images
+WHERE
+    user_id = current_app_user()
+AND
+    created_at > NOW() - (:hours || ' hours')::INTERVAL;
+
+-- Tool results are only linked to a chat if the user generated the image
+--! is_users_image
+SELECT EXISTS (
+    SELECT 1 FROM generated_images
+    WHERE object_id = :object_id
+    AND user_id = current_app_user()
+);
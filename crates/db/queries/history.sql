@@ -1,8 +1,10 @@
 --: History(prompt_id?)
 
---! search_history : History
+-- An empty search term matches everything, a rating keeps the conversations
+-- where the user rated an answer that way. One row per conversation.
+--! search_history(rating?) : History
 WITH search_results AS (
-    SELECT
+    SELECT DISTINCT ON (c.conversation_id)
         c.conversation_id,
         c.prompt_id,
         c.created_at,
@@ -17,6 +19,16 @@ WITH search_results AS (
     WHERE
         conv.user_id = :user_id
     AND LOWER(decrypt_text(c.content)) LIKE LOWER('%' || :search_term || '%')
+    AND (
+        c.conversation_id IN (
+            SELECT rated.conversation_id
+            FROM chat_feedback f
+            JOIN chats rated ON rated.id = f.chat_id
+            WHERE f.rating = :rating
+        )
+        OR :rating IS NULL
+    )
+    ORDER BY c.conversation_id, c.created_at DESC
 )
 SELECT
     sr.conversation_id as id,
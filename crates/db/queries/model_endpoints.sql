@@ -0,0 +1,68 @@
+--: ModelEndpoint(api_key?)
+
+--! endpoints : ModelEndpoint
+SELECT
+    id,
+    model_id,
+    base_url,
+    decrypt_text(api_key) as api_key,
+    weight,
+    max_concurrency,
+    created_at
+FROM
+    model_endpoints
+WHERE
+    model_id = :model_id
+ORDER BY
+    id;
+
+--! endpoint_pool
+SELECT
+    m.name AS model_name,
+    e.model_id,
+    e.id AS endpoint_id,
+    e.base_url
+FROM
+    model_endpoints e
+JOIN models m ON m.id = e.model_id
+UNION ALL
+-- Models without a pool use their own base_url, which the proxy calls
+-- endpoint 0
+SELECT
+    m.name AS model_name,
+    m.id AS model_id,
+    0 AS endpoint_id,
+    m.base_url
+FROM
+    models m
+WHERE
+    m.model_type = 'LLM'
+AND NOT EXISTS (
+    SELECT 1 FROM model_endpoints e WHERE e.model_id = m.id
+)
+ORDER BY
+    model_name, endpoint_id;
+
+-- Models belong to the team that owns their Model prompt
+--! is_team_model
+SELECT EXISTS (
+    SELECT 1 FROM prompts
+    WHERE model_id = :model_id
+    AND prompt_type = 'Model'
+    AND team_id = :team_id
+);
+
+--! insert(api_key?)
+INSERT INTO model_endpoints
+    (model_id, base_url, api_key, weight, max_concurrency)
+VALUES
+    (:model_id, :base_url, encrypt_text(:api_key), :weight, :max_concurrency)
+RETURNING id;
+
+--! delete
+DELETE FROM
+    model_endpoints
+WHERE
+    id = :id
+AND
+    model_id = :model_id;
@@ -0,0 +1,84 @@
+--: ChainModel(api_key?)
+--: FallbackOption()
+
+--! model_chain : ChainModel
+SELECT
+    m.id,
+    m.name,
+    m.base_url,
+    m.api_key,
+    m.retry_max_attempts,
+    m.retry_backoff_ms,
+    m.retry_status_codes,
+    m.circuit_breaker_threshold,
+    m.circuit_breaker_cooldown_seconds,
+    m.load_balancing,
+    m.provider,
+    -- The model itself goes first, then its fallbacks in order
+    0 AS priority
+FROM
+    models m
+WHERE
+    m.id = :model_id
+UNION ALL
+SELECT
+    m.id,
+    m.name,
+    m.base_url,
+    m.api_key,
+    m.retry_max_attempts,
+    m.retry_backoff_ms,
+    m.retry_status_codes,
+    m.circuit_breaker_threshold,
+    m.circuit_breaker_cooldown_seconds,
+    m.load_balancing,
+    m.provider,
+    f.priority
+FROM
+    model_fallbacks f
+JOIN models m ON m.id = f.fallback_model_id
+WHERE
+    f.model_id = :model_id
+AND
+    m.model_type = 'LLM'
+ORDER BY
+    priority;
+
+--! fallbacks
+SELECT
+    fallback_model_id
+FROM
+    model_fallbacks
+WHERE
+    model_id = :model_id
+ORDER BY
+    priority;
+
+-- The LLM models a team can fall back to, its own and those shared with everyone
+--! fallback_options : FallbackOption
+SELECT DISTINCT
+    m.id,
+    m.name
+FROM
+    models m
+JOIN
+    prompts p ON p.model_id = m.id AND p.prompt_type = 'Model'
+WHERE
+    m.model_type = 'LLM'
+AND
+    (p.team_id = :team_id OR p.visibility = 'Company')
+ORDER BY
+    m.name;
+
+--! insert
+INSERT INTO model_fallbacks
+    (model_id, fallback_model_id, priority)
+VALUES
+    (:model_id, :fallback_model_id, :priority)
+ON CONFLICT DO NOTHING;
+
+--! delete_all
+DELETE FROM
+    model_fallbacks
+WHERE
+    model_id = :model_id;
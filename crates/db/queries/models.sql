@@ -11,6 +11,7 @@ SELECT
     tpm_limit,
     rpm_limit,
     context_size,
+    tokenizer,
     created_at,
     updated_at
 FROM 
@@ -28,6 +29,16 @@ SELECT DISTINCT
     m.tpm_limit,
     m.rpm_limit,
     m.context_size,
+    m.embeddings_batch_size,
+    m.embeddings_concurrency,
+    m.tokenizer,
+    m.retry_max_attempts,
+    m.retry_backoff_ms,
+    m.retry_status_codes,
+    m.circuit_breaker_threshold,
+    m.circuit_breaker_cooldown_seconds,
+    m.load_balancing,
+    m.provider,
     m.created_at,
     m.updated_at,
     COALESCE(p.name, '') AS display_name,
@@ -55,6 +66,16 @@ SELECT DISTINCT
     m.tpm_limit,
     m.rpm_limit,
     m.context_size,
+    m.embeddings_batch_size,
+    m.embeddings_concurrency,
+    m.tokenizer,
+    m.retry_max_attempts,
+    m.retry_backoff_ms,
+    m.retry_status_codes,
+    m.circuit_breaker_threshold,
+    m.circuit_breaker_cooldown_seconds,
+    m.load_balancing,
+    m.provider,
     m.created_at,
     m.updated_at,
     COALESCE(p.name, '') AS display_name,
@@ -86,6 +107,7 @@ SELECT
     tpm_limit,
     rpm_limit,
     context_size,
+    tokenizer,
     created_at,
     updated_at
 FROM 
@@ -105,6 +127,7 @@ SELECT
     tpm_limit,
     rpm_limit,
     context_size,
+    tokenizer,
     created_at,
     updated_at
 FROM 
@@ -125,6 +148,7 @@ SELECT
     tpm_limit,
     rpm_limit,
     context_size,
+    tokenizer,
     created_at,
     updated_at
 FROM 
@@ -143,6 +167,7 @@ SELECT
     tpm_limit,
     rpm_limit,
     context_size,
+    tokenizer,
     created_at,
     updated_at
 FROM 
@@ -162,7 +187,17 @@ INSERT INTO models (
     api_key,
     tpm_limit,
     rpm_limit,
-    context_size
+    context_size,
+    embeddings_batch_size,
+    embeddings_concurrency,
+    tokenizer,
+    retry_max_attempts,
+    retry_backoff_ms,
+    retry_status_codes,
+    circuit_breaker_threshold,
+    circuit_breaker_cooldown_seconds,
+    load_balancing,
+    provider
 )
 VALUES(
     :name, 
@@ -171,7 +206,17 @@ VALUES(
     :api_key, 
     :tpm_limit,
     :rpm_limit,
-    :context_size
+    :context_size,
+    :embeddings_batch_size,
+    :embeddings_concurrency,
+    :tokenizer,
+    :retry_max_attempts,
+    :retry_backoff_ms,
+    :retry_status_codes,
+    :circuit_breaker_threshold,
+    :circuit_breaker_cooldown_seconds,
+    :load_balancing,
+    :provider
 )
 RETURNING id;
 
@@ -185,7 +230,17 @@ SET
     api_key = :api_key,
     tpm_limit = :tpm_limit,
     rpm_limit = :rpm_limit,
-    context_size = :context_size
+    context_size = :context_size,
+    embeddings_batch_size = :embeddings_batch_size,
+    embeddings_concurrency = :embeddings_concurrency,
+    tokenizer = :tokenizer,
+    retry_max_attempts = :retry_max_attempts,
+    retry_backoff_ms = :retry_backoff_ms,
+    retry_status_codes = :retry_status_codes,
+    circuit_breaker_threshold = :circuit_breaker_threshold,
+    circuit_breaker_cooldown_seconds = :circuit_breaker_cooldown_seconds,
+    load_balancing = :load_balancing,
+    provider = :provider
 WHERE
     id = :id;
 
@@ -1,6 +1,6 @@
 --: Prompt(image_icon_object_id?, temperature?, system_prompt?, api_key?, example1?, example2?, example3?, example4?)
 --: MyPrompt(image_icon_object_id?, api_key?)
---: SinglePrompt(temperature?, system_prompt?, embeddings_base_url?, embeddings_model?, embeddings_api_key?, embeddings_context_size?, api_key?, example1?, example2?, example3?, example4?)
+--: SinglePrompt(temperature?, system_prompt?, embeddings_base_url?, embeddings_model?, embeddings_api_key?, embeddings_context_size?, embeddings_tokenizer?, embeddings_model_id?, api_key?, example1?, example2?, example3?, example4?, summary_model_id?)
 
 --! update_image
 UPDATE 
@@ -141,6 +141,7 @@ SELECT
     (SELECT base_url FROM models WHERE id = p.model_id) as base_url, 
     (SELECT api_key FROM models WHERE id = p.model_id) as api_key, 
     (SELECT context_size FROM models WHERE id = p.model_id) as model_context_size, 
+    (SELECT tokenizer FROM models WHERE id = p.model_id) as tokenizer, 
     (SELECT team_id FROM models WHERE id = p.model_id) as team_id,  
     (SELECT base_url FROM models WHERE id IN 
         (SELECT embeddings_model_id FROM datasets ds WHERE ds.id IN
@@ -154,6 +155,11 @@ SELECT
     (SELECT context_size FROM models WHERE id IN
         (SELECT embeddings_model_id FROM datasets ds WHERE ds.id IN
         (SELECT dataset_id FROM prompt_dataset WHERE prompt_id = p.id LIMIT 1))) as embeddings_context_size,
+    (SELECT tokenizer FROM models WHERE id IN
+        (SELECT embeddings_model_id FROM datasets ds WHERE ds.id IN
+        (SELECT dataset_id FROM prompt_dataset WHERE prompt_id = p.id LIMIT 1))) as embeddings_tokenizer,
+    (SELECT embeddings_model_id FROM datasets ds WHERE ds.id IN
+        (SELECT dataset_id FROM prompt_dataset WHERE prompt_id = p.id LIMIT 1)) as embeddings_model_id,
     p.model_id,
     p.category_id,
     p.name,
@@ -197,8 +203,14 @@ SELECT
     p.system_prompt,
     p.max_history_items,
     p.max_chunks,
+    p.vector_weight,
+    p.lexical_weight,
+    p.rerank_enabled,
+    p.rerank_pool_size,
     p.max_tokens,
     p.trim_ratio,
+    p.history_strategy,
+    p.summary_model_id,
     p.temperature,
     p.prompt_type,
     -- Convert times to ISO 8601 string.
@@ -326,6 +338,46 @@ AND
             (d.visibility = 'Company')
     );
 
+--! update_retrieval_settings
+UPDATE
+    prompts
+SET
+    vector_weight = :vector_weight,
+    lexical_weight = :lexical_weight,
+    rerank_enabled = :rerank_enabled,
+    rerank_pool_size = :rerank_pool_size
+WHERE
+    id = :id
+AND
+    id IN (
+        SELECT id FROM prompts WHERE model_id IN(
+            SELECT id FROM models WHERE team_id IN(
+                SELECT team_id
+                FROM team_users
+                WHERE user_id = current_app_user()
+            )
+        )
+    );
+
+--! update_history_strategy(summary_model_id?)
+UPDATE
+    prompts
+SET
+    history_strategy = :history_strategy,
+    summary_model_id = :summary_model_id
+WHERE
+    id = :id
+AND
+    id IN (
+        SELECT id FROM prompts WHERE model_id IN(
+            SELECT id FROM models WHERE team_id IN(
+                SELECT team_id
+                FROM team_users
+                WHERE user_id = current_app_user()
+            )
+        )
+    );
+
 --! delete_prompt_datasets
 DELETE FROM prompt_dataset
 WHERE
@@ -1,25 +1,128 @@
---: RateLimit()
+--: RateLimit(limits_role?, user_email?, model_id?, model_name?)
+--: MatchingRateLimit(id?, model_id?, reset_seconds?)
 
 --! rate_limits : RateLimit
 SELECT
     l.id,
-    l.api_key_id,
-    l.tpm_limit,
-    l.rpm_limit,
+    l.limits_role,
+    l.user_email,
+    l.model_id,
+    (SELECT name FROM models m WHERE m.id = l.model_id) as model_name,
+    l.tokens_per_hour,
     l.created_at
 FROM
     rate_limits l
 ORDER BY created_at DESC;
 
---! new
+--! new(limits_role?, user_email?, model_id?)
 INSERT INTO rate_limits
-    (api_key_id, tpm_limit, rpm_limit)
+    (limits_role, user_email, model_id, tokens_per_hour)
 VALUES
-    (:api_key_id, :tpm_limit, :rpm_limit)
+    (:limits_role, :user_email, :model_id, :tokens_per_hour)
 RETURNING id;
 
 --! delete
 DELETE FROM
     rate_limits
 WHERE
-    id = :rate_limit_id;
\ No newline at end of file
+    id = :rate_limit_id;
+
+--! matching_rate_limit : MatchingRateLimit
+WITH rule AS (
+    -- The most specific limit wins. A user limit beats a role limit which
+    -- beats a limit for everyone, and a limit for this model beats a limit
+    -- for all models. With no matching rule the model's own tokens per
+    -- minute applies, scaled to the hour.
+    SELECT
+        id,
+        model_id,
+        tokens_per_hour
+    FROM (
+        SELECT
+            l.id,
+            l.model_id,
+            l.tokens_per_hour,
+            CASE
+                WHEN l.user_email IS NOT NULL THEN 0
+                WHEN l.limits_role IS NOT NULL THEN 1
+                ELSE 2
+            END as tier,
+            CASE WHEN l.model_id IS NOT NULL THEN 0 ELSE 1 END as model_tier
+        FROM
+            rate_limits l
+        WHERE
+            (l.model_id IS NULL OR l.model_id = :model_id)
+        AND
+            (l.user_email IS NULL OR LOWER(l.user_email) = (SELECT LOWER(email) FROM users WHERE id = :user_id))
+        AND
+            (l.limits_role IS NULL OR l.limits_role IN (
+                SELECT UNNEST(roles) FROM team_users WHERE user_id = :user_id AND team_id = :team_id
+            ))
+        UNION ALL
+        SELECT
+            NULL,
+            m.id,
+            LEAST(m.tpm_limit::BIGINT * 60, 2147483647)::INT,
+            3,
+            0
+        FROM
+            models m
+        WHERE
+            m.id = :model_id
+        AND
+            m.tpm_limit > 0
+    ) rules
+    ORDER BY
+        tier,
+        model_tier,
+        tokens_per_hour ASC
+    LIMIT 1
+),
+usage AS (
+    -- Everything the user has sent and received over the last hour, from
+    -- the console, transcriptions and their API keys.
+    SELECT
+        tum.tokens,
+        tum.created_at,
+        -- The model that did the work if we know it, otherwise the prompt's model
+        COALESCE(
+            tum.model_id,
+            (SELECT model_id FROM chats c WHERE c.id = tum.chat_id),
+            (SELECT model_id FROM prompts p WHERE p.id IN (
+                SELECT prompt_id FROM chats c WHERE c.id = tum.chat_id)),
+            (SELECT model_id FROM prompts p WHERE p.id IN (
+                SELECT prompt_id FROM api_keys a WHERE a.id = tum.api_key_id))
+        ) as model_id
+    FROM
+        token_usage_metrics tum
+    WHERE
+        tum.created_at >= NOW() - INTERVAL '1 hour'
+    AND
+        (
+            tum.chat_id IN (
+                SELECT id FROM chats WHERE conversation_id IN (
+                    SELECT id FROM conversations WHERE user_id = :user_id
+                )
+            )
+            OR
+            tum.api_key_id IN (SELECT id FROM api_keys WHERE user_id = :user_id)
+            OR
+            tum.user_id = :user_id
+        )
+)
+SELECT
+    rule.id,
+    rule.model_id,
+    rule.tokens_per_hour,
+    COALESCE((
+        SELECT SUM(u.tokens) FROM usage u
+        WHERE rule.model_id IS NULL OR u.model_id = rule.model_id
+    ), 0)::BIGINT as tokens_used,
+    -- When the oldest usage in the window drops out
+    (
+        SELECT CEIL(EXTRACT(EPOCH FROM (MIN(u.created_at) + INTERVAL '1 hour' - NOW())))::INT
+        FROM usage u
+        WHERE rule.model_id IS NULL OR u.model_id = rule.model_id
+    ) as reset_seconds
+FROM
+    rule;
@@ -1,13 +1,45 @@
 --: DailyTokenUsage()
 --: DailyApiRequests()
 
---! create_token_usage_metric(chat_id?, api_key_id?, duration_ms?)
+--! create_token_usage_metric(chat_id?, api_key_id?, duration_ms?, model_id?)
 INSERT INTO token_usage_metrics
-    (chat_id, api_key_id, type, tokens, duration_ms)
+    (chat_id, api_key_id, type, tokens, duration_ms, source, model_id)
 VALUES
-    (:chat_id, :api_key_id, :type, :tokens, :duration_ms)
+    (:chat_id, :api_key_id, :type, :tokens, :duration_ms, :source, :model_id)
 RETURNING id;
 
+-- Console usage outside a chat, i.e. transcriptions, is the current user's
+--! create_user_token_usage_metric(duration_ms?, model_id?)
+INSERT INTO token_usage_metrics
+    (user_id, type, tokens, duration_ms, source, model_id)
+VALUES
+    (current_app_user(), :type, :tokens, :duration_ms, :source, :model_id)
+RETURNING id;
+
+-- We estimate the prompt before sending it, this replaces the estimate with
+-- what the model reported once the response is in.
+--! report_prompt_tokens
+UPDATE token_usage_metrics
+SET
+    tokens = :tokens,
+    source = 'Reported'
+WHERE
+    id = :id
+AND
+    type = 'Prompt';
+
+--! report_chat_prompt_tokens
+UPDATE token_usage_metrics
+SET
+    tokens = :tokens,
+    source = 'Reported'
+WHERE
+    -- A regenerated chat has a prompt for each generation, this is the latest
+    id = (
+        SELECT MAX(id) FROM token_usage_metrics
+        WHERE chat_id = :chat_id AND type = 'Prompt'
+    );
+
 --! get_daily_token_usage_for_team : DailyTokenUsage
 SELECT
     DATE(created_at) as usage_date,
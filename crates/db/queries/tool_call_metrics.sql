@@ -0,0 +1,6 @@
+--! create_tool_call_metric(chat_id?, api_key_id?)
+INSERT INTO tool_call_metrics
+    (chat_id, api_key_id, tool_name, status, duration_ms)
+VALUES
+    (:chat_id, :api_key_id, :tool_name, :status, :duration_ms)
+RETURNING id;
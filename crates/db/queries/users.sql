@@ -20,6 +20,14 @@ FROM
 WHERE
     openid_sub = :openid_sub;
 
+--! openid_sub
+SELECT 
+    openid_sub
+FROM 
+    users
+WHERE
+    id = :id;
+
 --! get_by_email : (first_name?, last_name?)
 SELECT 
     id, email, first_name, last_name
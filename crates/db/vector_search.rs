@@ -1,3 +1,5 @@
+use std::collections::HashMap;
+
 use crate::queries::prompts;
 use crate::TokioPostgresError;
 use crate::Transaction;
@@ -7,13 +9,25 @@ pub struct RelatedContext {
     pub chunk_text: String,
 }
 
-// Query the vector database using a similarity search.
-// The prompt decides how we use the datasets
+// The usual constant for reciprocal rank fusion, it stops the top few
+// results of one retriever from drowning out everything else.
+const RRF_K: f64 = 60.0;
+
+// Each retriever looks at more chunks than we need so the fusion has
+// something to work with.
+const CANDIDATE_MULTIPLIER: i64 = 4;
+
+// Query the vector database using a similarity search combined with a
+// full text search. The prompt decides which datasets we use and how
+// much each search counts. The embeddings must come from
+// `embeddings_model_id`.
 pub async fn get_related_context(
     transaction: &Transaction<'_>,
     prompt_id: i32,
     limit: i32,
+    question: &str,
     embeddings: Vec<f32>,
+    embeddings_model_id: i32,
 ) -> Result<Vec<RelatedContext>, TokioPostgresError> {
     // Which datasets does the prompt use
     let datasets = prompts::prompt_datasets()
@@ -23,38 +37,207 @@ pub async fn get_related_context(
     // We just need the id's
     let datasets: Vec<i32> = datasets.iter().map(|dataset| dataset.dataset_id).collect();
 
+    let weights = transaction
+        .query_one(
+            "SELECT vector_weight, lexical_weight FROM prompts WHERE id = $1",
+            &[&prompt_id],
+        )
+        .await?;
+    let vector_weight: f32 = weights.get(0);
+    let lexical_weight: f32 = weights.get(1);
+
+    let candidates = (limit as i64 * CANDIDATE_MULTIPLIER).max(20);
+
+    let vector_ranked = if vector_weight > 0.0 {
+        vector_search(
+            transaction,
+            &datasets,
+            embeddings,
+            embeddings_model_id,
+            candidates,
+        )
+        .await?
+    } else {
+        Vec::new()
+    };
+
+    let lexical_ranked = if lexical_weight > 0.0 && !question.trim().is_empty() {
+        lexical_search(transaction, &datasets, question, candidates).await?
+    } else {
+        Vec::new()
+    };
+
+    let chunk_ids = reciprocal_rank_fusion(
+        &[
+            (vector_ranked, vector_weight as f64),
+            (lexical_ranked, lexical_weight as f64),
+        ],
+        limit as usize,
+    );
+
+    // Fetch the winners, decrypting if needed, and keep the fused order
+    let rows = transaction
+        .query(
+            "SELECT id, decrypt_text(text) FROM chunks WHERE id = ANY($1)",
+            &[&chunk_ids],
+        )
+        .await?;
+
+    let mut texts: HashMap<i32, String> = rows
+        .into_iter()
+        .map(|row| (row.get(0), row.get(1)))
+        .collect();
+
+    let related_context: Vec<RelatedContext> = chunk_ids
+        .into_iter()
+        .filter_map(|chunk_id| {
+            texts.remove(&chunk_id).map(|chunk_text| RelatedContext {
+                chunk_id,
+                chunk_text,
+            })
+        })
+        .collect();
+
+    Ok(related_context)
+}
+
+// Chunk ids ordered by distance to the question embeddings. Distances
+// between vectors from different models are meaningless, so chunks embedded
+// by any other model, or with another dimension, are left out.
+async fn vector_search(
+    transaction: &Transaction<'_>,
+    datasets: &[i32],
+    embeddings: Vec<f32>,
+    embeddings_model_id: i32,
+    limit: i64,
+) -> Result<Vec<i32>, TokioPostgresError> {
+    let dimension = embeddings.len() as i32;
     // Format the embeddings in PGVector format
-    let embedding_data = pgvector::Vector::from(embeddings.clone());
+    let embedding_data = pgvector::Vector::from(embeddings);
 
-    // Find sections of documents that are related to the users question
-    let related_context = transaction
+    let rows = transaction
         .query(
             "
-                    SELECT 
-                        id,
-                        text 
-                    FROM 
+                    SELECT
+                        id
+                    FROM
                         chunks
                     WHERE
                         document_id IN (
                             SELECT id FROM documents WHERE dataset_id = ANY($1)
                         )
-                    ORDER BY 
-                        embeddings <-> $2 
+                    AND
+                        embeddings IS NOT NULL
+                    AND
+                        embeddings_model_id = $3
+                    AND
+                        embeddings_dimension = $4
+                    ORDER BY
+                        embeddings <-> $2
+                    LIMIT $5;
+                    ",
+            &[
+                &datasets,
+                &embedding_data,
+                &embeddings_model_id,
+                &dimension,
+                &limit,
+            ],
+        )
+        .await?;
+
+    Ok(rows.into_iter().map(|row| row.get(0)).collect())
+}
+
+// Chunk ids ordered by full text rank. Plain text chunks use the indexed
+// search_vector, encrypted chunks have to be decrypted to be searched.
+async fn lexical_search(
+    transaction: &Transaction<'_>,
+    datasets: &[i32],
+    question: &str,
+    limit: i64,
+) -> Result<Vec<i32>, TokioPostgresError> {
+    let rows = transaction
+        .query(
+            "
+                    SELECT
+                        id
+                    FROM (
+                        SELECT
+                            id,
+                            COALESCE(
+                                search_vector,
+                                to_tsvector('english', decrypt_text(text))
+                            ) AS document
+                        FROM
+                            chunks
+                        WHERE
+                            document_id IN (
+                                SELECT id FROM documents WHERE dataset_id = ANY($1)
+                            )
+                    ) c,
+                    websearch_to_tsquery('english', $2) query
+                    WHERE
+                        c.document @@ query
+                    ORDER BY
+                        ts_rank_cd(c.document, query) DESC
                     LIMIT $3;
                     ",
-            &[&datasets, &embedding_data, &(limit as i64)],
+            &[&datasets, &question, &limit],
         )
         .await?;
 
-    // Just get the text from the returned rows
-    let related_context: Vec<RelatedContext> = related_context
-        .into_iter()
-        .map(|content| RelatedContext {
-            chunk_id: content.get(0),
-            chunk_text: content.get(1),
-        })
-        .collect();
+    Ok(rows.into_iter().map(|row| row.get(0)).collect())
+}
 
-    Ok(related_context)
+// Merge ranked lists of chunk ids. Each list contributes weight / (k + rank)
+// for every chunk it contains so a chunk found by both searches wins over
+// one found by only one of them.
+fn reciprocal_rank_fusion(rankings: &[(Vec<i32>, f64)], limit: usize) -> Vec<i32> {
+    let mut scores: Vec<(i32, f64)> = Vec::new();
+
+    for (ranking, weight) in rankings {
+        if *weight <= 0.0 {
+            continue;
+        }
+        for (index, chunk_id) in ranking.iter().enumerate() {
+            let score = weight / (RRF_K + (index + 1) as f64);
+            match scores.iter_mut().find(|(id, _)| id == chunk_id) {
+                Some((_, total)) => *total += score,
+                None => scores.push((*chunk_id, score)),
+            }
+        }
+    }
+
+    // Stable sort keeps the first seen (vector) order on ties
+    scores.sort_by(|a, b| b.1.total_cmp(&a.1));
+    scores.truncate(limit);
+    scores.into_iter().map(|(chunk_id, _)| chunk_id).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_fusion_prefers_chunks_found_by_both() {
+        let vector = vec![1, 2, 3];
+        let lexical = vec![3, 4];
+
+        let fused = reciprocal_rank_fusion(&[(vector, 1.0), (lexical, 1.0)], 3);
+        assert_eq!(fused, vec![3, 1, 2]);
+    }
+
+    #[test]
+    fn test_fusion_weights() {
+        let vector = vec![1, 2];
+        let lexical = vec![3, 4];
+
+        let fused = reciprocal_rank_fusion(&[(vector.clone(), 1.0), (lexical.clone(), 2.0)], 4);
+        assert_eq!(fused, vec![3, 4, 1, 2]);
+
+        // A zero weight turns the retriever off
+        let fused = reciprocal_rank_fusion(&[(vector, 1.0), (lexical, 0.0)], 4);
+        assert_eq!(fused, vec![1, 2]);
+    }
 }
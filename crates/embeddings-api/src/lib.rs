@@ -38,56 +38,50 @@ pub struct Embedding {
     pub text: String,
 }
 
+// Used when a model doesn't say how big its context is
+const DEFAULT_CONTEXT_LENGTH: i32 = 256;
+
+fn effective_context_length(context_length: i32) -> usize {
+    if context_length <= 0 {
+        DEFAULT_CONTEXT_LENGTH as usize
+    } else {
+        context_length as usize
+    }
+}
+
+/// Split the input into pieces that each fit the model's context length,
+/// counted with the model's tokenizer. Use this for document chunks so
+/// nothing is lost.
+pub fn split_to_context_length(input: &str, context_length: i32, tokenizer: &str) -> Vec<String> {
+    openai_api::tokenizer::split_by_tokens(
+        input,
+        tokenizer,
+        effective_context_length(context_length),
+    )
+}
+
 /// Trims the input text to fit within the specified context length.
 /// If context_length is zero or negative, a default value will be used.
-/// Trims the input text to fit within the specified context length.
-/// If context_length is zero or negative, a default value will be used.
-/// Uses a simple 1:1 character-to-token ratio for efficiency.
-fn trim_to_context_length(input: &str, context_length: i32, _model: &str) -> String {
+fn trim_to_context_length(input: &str, context_length: i32, tokenizer: &str) -> String {
     // Handle empty input
     if input.is_empty() {
         return String::new();
     }
 
-    // If context_length is zero or negative, use a default value
-    let effective_context_length = if context_length <= 0 {
-        256 // Default context length
-    } else {
-        context_length
-    };
-
-    // Get the character count
-    let char_count = input.chars().count() as i32;
+    let mut pieces = split_to_context_length(input, context_length, tokenizer);
 
-    // If character count is within context length, return the original input
-    if char_count <= effective_context_length {
+    if pieces.len() > 1 {
         tracing::info!(
-            "Input has {} characters, which is within context length {}",
-            char_count,
-            effective_context_length
+            "Input is longer than the context length {}, trimming",
+            effective_context_length(context_length)
         );
-        return input.to_string();
     }
 
-    // If we need to trim, simply truncate the string
-    tracing::info!(
-        "Input has {} characters, trimming to context length {}",
-        char_count,
-        effective_context_length
-    );
-
-    // Collect characters up to the effective_context_length
-    let result: String = input
-        .chars()
-        .take(effective_context_length as usize)
-        .collect();
-
-    tracing::info!(
-        "Trimmed input from {} to {} characters",
-        char_count,
-        effective_context_length
-    );
-    result
+    if pieces.is_empty() {
+        String::new()
+    } else {
+        pieces.swap_remove(0)
+    }
 }
 
 pub async fn get_embeddings(
@@ -95,13 +89,14 @@ pub async fn get_embeddings(
     api_end_point: &str,
     model: &str,
     context_length: i32,
+    tokenizer: &str,
     api_key: &Option<String>,
 ) -> Result<Vec<f32>, Box<dyn Error>> {
     let client = Client::new();
 
     // Convert input to UTF-8 and trim to context length
     let text = String::from_utf8_lossy(input.as_bytes()).to_string();
-    let trimmed_text = trim_to_context_length(&text, context_length, model);
+    let trimmed_text = trim_to_context_length(&text, context_length, tokenizer);
 
     let calling_json = EmbeddingRequest {
         input: trimmed_text,
@@ -139,28 +134,188 @@ pub async fn get_embeddings(
     }
 }
 
+#[derive(Debug, Serialize)]
+pub struct EmbeddingBatchRequest {
+    pub model: String,
+    pub input: Vec<String>,
+}
+
+/// Embed several inputs with one request.
+/// The embeddings are returned in the same order as the inputs.
+pub async fn get_embeddings_batch(
+    inputs: &[String],
+    api_end_point: &str,
+    model: &str,
+    context_length: i32,
+    tokenizer: &str,
+    api_key: &Option<String>,
+) -> Result<Vec<Vec<f32>>, Box<dyn Error>> {
+    let client = Client::new();
+
+    let calling_json = EmbeddingBatchRequest {
+        input: inputs
+            .iter()
+            .map(|input| trim_to_context_length(input, context_length, tokenizer))
+            .collect(),
+        model: model.to_string(),
+    };
+
+    let request = if let Some(api_key) = api_key {
+        client
+            .post(api_end_point)
+            .header(AUTHORIZATION, format!("Bearer {}", api_key))
+            .json(&calling_json)
+    } else {
+        client.post(api_end_point).json(&calling_json)
+    };
+
+    let response = request.send().await?;
+
+    if !response.status().is_success() {
+        let status = response.status();
+        let response_text = response.text().await?;
+        tracing::error!("Problem with request: {} {}", status, response_text);
+        Err(format!("The embeddings API returned {}", status))?
+    } else {
+        let mut result = response.json::<EmbeddingResponse>().await?;
+
+        if result.data.len() != inputs.len() {
+            tracing::error!(
+                "Asked for {} embeddings and got {}",
+                inputs.len(),
+                result.data.len()
+            );
+            Err("The embeddings API returned the wrong number of embeddings")?
+        }
+
+        result.data.sort_by_key(|data| data.index);
+        Ok(result.data.into_iter().map(|data| data.embedding).collect())
+    }
+}
+
+#[derive(Debug, Serialize)]
+pub struct RerankRequest {
+    pub model: String,
+    pub query: String,
+    pub documents: Vec<String>,
+    pub top_n: usize,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct RerankResult {
+    pub index: usize,
+    pub relevance_score: f32,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct RerankResponse {
+    pub results: Vec<RerankResult>,
+}
+
+/// Score each document against the query using the /rerank endpoint that
+/// Cohere, Jina, vLLM and Text Embeddings Inference all share.
+/// Returns the indexes of the top_n documents, most relevant first.
+pub async fn rerank(
+    query: &str,
+    documents: Vec<String>,
+    base_url: &str,
+    model: &str,
+    top_n: usize,
+    api_key: &Option<String>,
+) -> Result<Vec<usize>, Box<dyn Error>> {
+    let client = Client::new();
+    let document_count = documents.len();
+
+    let calling_json = RerankRequest {
+        model: model.to_string(),
+        query: query.to_string(),
+        documents,
+        top_n,
+    };
+
+    let end_point = format!("{}/rerank", base_url.trim_end_matches('/'));
+
+    let request = if let Some(api_key) = api_key {
+        client
+            .post(end_point)
+            .header(AUTHORIZATION, format!("Bearer {}", api_key))
+            .json(&calling_json)
+    } else {
+        client.post(end_point).json(&calling_json)
+    };
+
+    let response = request.send().await?;
+
+    if !response.status().is_success() {
+        tracing::error!("Problem with rerank request: {:?}", response);
+        let response_text = response.text().await?;
+        tracing::error!("{:?}", response_text);
+        Err("Problem with rerank request")?
+    } else {
+        let result = response.json::<RerankResponse>().await?;
+        Ok(ranked_indexes(result.results, document_count, top_n))
+    }
+}
+
+// Not every server sorts the results or honours top_n so we do it here,
+// ignoring any index that doesn't point at one of our documents.
+fn ranked_indexes(
+    mut results: Vec<RerankResult>,
+    document_count: usize,
+    top_n: usize,
+) -> Vec<usize> {
+    results.retain(|result| result.index < document_count);
+    results.sort_by(|a, b| b.relevance_score.total_cmp(&a.relevance_score));
+    results.truncate(top_n);
+    results.into_iter().map(|result| result.index).collect()
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
 
+    #[test]
+    fn test_ranked_indexes() {
+        let results = vec![
+            RerankResult {
+                index: 0,
+                relevance_score: 0.1,
+            },
+            RerankResult {
+                index: 7,
+                relevance_score: 0.99,
+            },
+            RerankResult {
+                index: 2,
+                relevance_score: 0.8,
+            },
+            RerankResult {
+                index: 1,
+                relevance_score: 0.5,
+            },
+        ];
+
+        assert_eq!(ranked_indexes(results, 3, 2), vec![2, 1]);
+    }
+
     #[test]
     fn test_trim_to_context_length_empty_input() {
         let input = "";
-        let result = trim_to_context_length(input, 100, "gpt-4");
+        let result = trim_to_context_length(input, 100, "cl100k_base");
         assert_eq!(result, "");
     }
 
     #[test]
     fn test_trim_to_context_length_within_limit() {
         let input = "This is a short text that should be within the context length.";
-        let result = trim_to_context_length(input, 100, "gpt-4");
+        let result = trim_to_context_length(input, 100, "cl100k_base");
         assert_eq!(result, input);
     }
 
     #[test]
     fn test_trim_to_context_length_zero_context() {
         let input = "This is a test with zero context length.";
-        let result = trim_to_context_length(input, 0, "gpt-4");
+        let result = trim_to_context_length(input, 0, "cl100k_base");
 
         // With zero context length, it should use the default (256)
         assert_eq!(result, input);
@@ -169,18 +324,29 @@ mod tests {
     #[test]
     fn test_trim_to_context_length_negative_context() {
         let input = "This is a test with negative context length.";
-        let result = trim_to_context_length(input, -10, "gpt-4");
+        let result = trim_to_context_length(input, -10, "cl100k_base");
 
         // With negative context length, it should use the default (256)
         assert_eq!(result, input);
     }
 
     #[test]
-    fn test_trim_to_context_length_invalid_model() {
-        let input = "This is a test with an invalid model name.";
-        let result = trim_to_context_length(input, 100, "invalid-model-name");
+    fn test_trim_to_context_length_invalid_tokenizer() {
+        let input = "This is a test with an invalid tokenizer name.";
+        let result = trim_to_context_length(input, 100, "invalid-tokenizer-name");
 
-        // The model parameter is now ignored, so this should work the same as with a valid model
+        // Unknown tokenizers fall back to the default
         assert_eq!(result, input);
     }
+
+    #[test]
+    fn test_trim_to_context_length_counts_tokens() {
+        // Around 1000 characters but only a few hundred tokens
+        let input = "The cat sat on the mat. ".repeat(40);
+        let result = trim_to_context_length(&input, 50, "cl100k_base");
+
+        assert!(result.len() < input.len());
+        assert!(input.starts_with(&result));
+        assert!(openai_api::tokenizer::count_tokens(&result, "cl100k_base") <= 50);
+    }
 }
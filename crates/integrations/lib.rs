@@ -3,6 +3,7 @@
 //! This crate provides integration with external services and tools.
 
 pub mod bionic_openapi;
+pub mod rerank;
 pub mod token_providers;
 pub mod tool;
 pub mod tool_executor;
@@ -25,9 +26,13 @@ pub use bionic_openapi::{
     create_tools_from_integration, create_tools_from_integrations, BionicOpenAPI, IntegrationTools,
     OAuth2Config,
 };
+pub use rerank::{candidate_pool_size, rerank_related_context};
 pub use token_providers::{OAuth2TokenProvider, StaticTokenProvider, TokenProvider};
 pub use tool::ToolInterface;
-pub use tool_executor::{execute_tool_call_with_tools, execute_tool_calls};
+pub use tool_executor::{
+    execute_tool_call_with_tools, execute_tool_calls, execute_tool_calls_with_tools, get_api_tools,
+    record_tool_call,
+};
 pub use tool_registry::{
     get_chat_tools_user_selected, get_integrations, get_tools, IntegrationTool, ToolScope,
 };
@@ -0,0 +1,87 @@
+use db::queries::models;
+use db::{ModelType, RelatedContext, TokioPostgresError, Transaction};
+
+/// How many chunks to retrieve before reranking. The reranker needs a
+/// bigger pool than we keep so it has something to choose from.
+pub fn candidate_pool_size(rerank_enabled: bool, rerank_pool_size: i32, limit: i32) -> i32 {
+    if rerank_enabled {
+        rerank_pool_size.max(limit)
+    } else {
+        limit
+    }
+}
+
+/// Reorder the retrieved chunks with the Reranker model and keep the best
+/// `limit` of them. If no reranker is set up, or it fails, we fall back to
+/// the retrieval order so the chat still gets its context.
+pub async fn rerank_related_context(
+    transaction: &Transaction<'_>,
+    question: &str,
+    related_context: Vec<RelatedContext>,
+    limit: usize,
+) -> Result<Vec<RelatedContext>, TokioPostgresError> {
+    let mut related_context = related_context;
+
+    if related_context.len() <= 1 || question.trim().is_empty() {
+        related_context.truncate(limit);
+        return Ok(related_context);
+    }
+
+    let Some(reranker) = models::models()
+        .bind(transaction, &ModelType::Reranker)
+        .opt()
+        .await?
+    else {
+        tracing::warn!("Reranking is enabled but no Reranker model is configured");
+        related_context.truncate(limit);
+        return Ok(related_context);
+    };
+
+    let documents: Vec<String> = related_context
+        .iter()
+        .map(|context| context.chunk_text.clone())
+        .collect();
+
+    match embeddings_api::rerank(
+        question,
+        documents,
+        &reranker.base_url,
+        &reranker.name,
+        limit,
+        &reranker.api_key,
+    )
+    .await
+    {
+        Ok(indexes) => {
+            tracing::info!(
+                "Reranked {} chunks with {}",
+                related_context.len(),
+                reranker.name
+            );
+            let mut candidates: Vec<Option<RelatedContext>> =
+                related_context.into_iter().map(Some).collect();
+            Ok(indexes
+                .into_iter()
+                .filter_map(|index| candidates[index].take())
+                .collect())
+        }
+        Err(e) => {
+            tracing::error!("Problem reranking with {}: {}", reranker.name, e);
+            related_context.truncate(limit);
+            Ok(related_context)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_candidate_pool_size() {
+        assert_eq!(candidate_pool_size(false, 50, 5), 5);
+        assert_eq!(candidate_pool_size(true, 50, 5), 50);
+        // Never fetch fewer than we want to keep
+        assert_eq!(candidate_pool_size(true, 3, 5), 5);
+    }
+}
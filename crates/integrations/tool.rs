@@ -5,6 +5,7 @@
 use async_trait::async_trait;
 use openai_api::BionicToolDefinition;
 use serde_json;
+use std::time::Duration;
 
 /// Tool interface trait that defines the common functionality for all tools
 #[async_trait]
@@ -19,4 +20,10 @@ pub trait ToolInterface: Send + Sync {
     fn name(&self) -> String {
         self.get_tool().function.name.clone()
     }
+
+    /// How long the tool may run for, when it needs something other than
+    /// the global default. TOOL_TIMEOUTS overrides this.
+    fn timeout(&self) -> Option<Duration> {
+        None
+    }
 }
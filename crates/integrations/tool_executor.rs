@@ -1,13 +1,21 @@
 use crate::bionic_openapi::create_tools_from_integrations;
 use crate::tool::ToolInterface;
 use crate::tools;
-use db::{queries::prompt_integrations, Pool};
-use openai_api::{ToolCall, ToolCallResult};
+use db::{
+    queries::{prompt_integrations, tool_call_metrics},
+    Pool, Transaction,
+};
+use openai_api::{ToolCall, ToolCallResult, ToolCallStatus};
 use serde_json::json;
-use std::collections::HashSet;
+use std::collections::{HashMap, HashSet};
 use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tokio_util::sync::CancellationToken;
 use tracing::{debug, error, info, trace, warn};
 
+// Tools get this long to run unless TOOL_TIMEOUT_SECS says otherwise
+const DEFAULT_TIMEOUT_SECS: u64 = 30;
+
 /// Get external integration tools using direct database operations
 async fn get_external_integration_tools(
     pool: &Pool,
@@ -44,13 +52,15 @@ async fn get_external_integration_tools(
     Ok(tools)
 }
 
-/// Execute a tool call and return a message with the result
+/// Execute the tool calls at the same time and return the results in the
+/// same order. Calls still running when `cancel` fires are stopped.
 pub async fn execute_tool_calls(
     tool_calls: Vec<ToolCall>,
     pool: &Pool,
     sub: String,
     conversation_id: i64,
     prompt_id: i32,
+    cancel: &CancellationToken,
 ) -> Vec<ToolCallResult> {
     info!("Executing {} tool calls", tool_calls.len());
 
@@ -59,21 +69,149 @@ pub async fn execute_tool_calls(
     let tools = get_tools(pool, sub.clone(), conversation_id, prompt_id).await;
     debug!("Got {} tool instances", tools.len());
 
-    let mut tool_results: Vec<ToolCallResult> = Vec::new();
-    for (i, tool_call) in tool_calls.iter().enumerate() {
-        debug!(
-            "Executing tool call {}/{}: {}",
-            i + 1,
-            tool_calls.len(),
-            tool_call.function.name
-        );
-        tool_results.push(execute_tool_call_with_tools(&tools, tool_call).await);
-    }
+    let tool_results = execute_tool_calls_with_tools(&tools, &tool_calls, cancel).await;
 
     info!("Completed execution of {} tool calls", tool_calls.len());
     tool_results
 }
 
+/// Execute the tool calls concurrently with a specific set of tools, each
+/// one bounded by its timeout.
+pub async fn execute_tool_calls_with_tools(
+    tools: &[Arc<dyn ToolInterface>],
+    tool_calls: &[ToolCall],
+    cancel: &CancellationToken,
+) -> Vec<ToolCallResult> {
+    let timeouts = ToolTimeouts::from_env();
+
+    futures::future::join_all(tool_calls.iter().map(|tool_call| {
+        let tool = tools.iter().find(|t| t.name() == tool_call.function.name);
+        let timeout = timeouts.for_tool(&tool_call.function.name, tool);
+        execute_bounded(tools, tool_call, timeout, cancel)
+    }))
+    .await
+}
+
+async fn execute_bounded(
+    tools: &[Arc<dyn ToolInterface>],
+    tool_call: &ToolCall,
+    timeout: Duration,
+    cancel: &CancellationToken,
+) -> ToolCallResult {
+    let started = Instant::now();
+    let name = &tool_call.function.name;
+
+    let (status, result) = tokio::select! {
+        result = tokio::time::timeout(timeout, execute_tool_call_with_tools(tools, tool_call)) => {
+            match result {
+                Ok(result) => return result,
+                Err(_) => {
+                    warn!("Tool {} timed out after {:?}", name, timeout);
+                    (
+                        ToolCallStatus::Timeout,
+                        json!({
+                            "error": "Tool timed out",
+                            "details": format!("{} did not finish within {} seconds", name, timeout.as_secs()),
+                            "timeout_secs": timeout.as_secs(),
+                        }),
+                    )
+                }
+            }
+        }
+        _ = cancel.cancelled() => {
+            info!("Tool {} cancelled", name);
+            (
+                ToolCallStatus::Cancelled,
+                json!({
+                    "error": "Tool call cancelled",
+                    "details": "The chat was stopped before the tool finished",
+                }),
+            )
+        }
+    };
+
+    ToolCallResult {
+        id: tool_call.id.clone(),
+        name: name.clone(),
+        result,
+        duration_ms: started.elapsed().as_millis() as u64,
+        status,
+    }
+}
+
+// TOOL_TIMEOUT_SECS applies to every tool, TOOL_TIMEOUTS overrides it
+// for single tools i.e. "web=10,generate_image=180"
+struct ToolTimeouts {
+    default: Duration,
+    overrides: HashMap<String, Duration>,
+}
+
+impl ToolTimeouts {
+    fn from_env() -> Self {
+        let default = std::env::var("TOOL_TIMEOUT_SECS")
+            .ok()
+            .and_then(|secs| secs.parse().ok())
+            .unwrap_or(DEFAULT_TIMEOUT_SECS);
+
+        Self {
+            default: Duration::from_secs(default),
+            overrides: std::env::var("TOOL_TIMEOUTS")
+                .map(|timeouts| parse_timeouts(&timeouts))
+                .unwrap_or_default(),
+        }
+    }
+
+    fn for_tool(&self, name: &str, tool: Option<&Arc<dyn ToolInterface>>) -> Duration {
+        self.overrides
+            .get(name)
+            .copied()
+            .or_else(|| tool.and_then(|tool| tool.timeout()))
+            .unwrap_or(self.default)
+    }
+}
+
+fn parse_timeouts(timeouts: &str) -> HashMap<String, Duration> {
+    timeouts
+        .split(',')
+        .filter_map(|timeout| {
+            let (name, secs) = timeout.split_once('=')?;
+            let secs: u64 = secs.trim().parse().ok()?;
+            Some((name.trim().to_string(), Duration::from_secs(secs)))
+        })
+        .collect()
+}
+
+/// Record how long a tool call took against the chat holding its result,
+/// or the API key it was run for.
+pub async fn record_tool_call(
+    transaction: &Transaction<'_>,
+    chat_id: Option<i32>,
+    api_key_id: Option<i32>,
+    result: &ToolCallResult,
+) -> Result<(), db::TokioPostgresError> {
+    let status = match result.status {
+        ToolCallStatus::Success => db::ToolCallStatus::Success,
+        ToolCallStatus::Error => db::ToolCallStatus::Error,
+        ToolCallStatus::Timeout => db::ToolCallStatus::Timeout,
+        ToolCallStatus::Cancelled => db::ToolCallStatus::Cancelled,
+    };
+    let duration_ms = i32::try_from(result.duration_ms).unwrap_or(i32::MAX);
+
+    tool_call_metrics::create_tool_call_metric()
+        .bind(
+            transaction,
+            &chat_id,
+            &api_key_id,
+            &result.name,
+            &status,
+            &duration_ms,
+        )
+        .one()
+        .await?;
+
+    Ok(())
+}
+
 /// Returns a list of available tool instances
 /// This requires a pool for tools that need database access
 pub async fn get_tools(
@@ -102,6 +240,12 @@ pub async fn get_tools(
         conversation_id,
     )));
 
+    tools.push(Arc::new(tools::generate_image::GenerateImageTool::new(
+        pool.clone(),
+        sub.clone(),
+        conversation_id,
+    )));
+
     debug!("Adding dataset tools with database pool");
     tools.push(Arc::new(tools::list_datasets::ListDatasetsTool::new(
         pool.clone(),
@@ -118,6 +262,58 @@ pub async fn get_tools(
         prompt_id,
     )));
 
+    add_external_integration_tools(&mut tools, pool, sub, prompt_id).await;
+
+    info!("Returning {} tool instances", tools.len());
+    tools
+}
+
+/// Returns the tools an API key request can run on the server.
+/// There is no conversation so attachment tools are left out and
+/// searches are scoped to the team of the API key.
+pub async fn get_api_tools(
+    pool: &Pool,
+    sub: String,
+    team_id: i32,
+    prompt_id: i32,
+    context_chunks: i32,
+) -> Vec<Arc<dyn ToolInterface>> {
+    trace!("Getting tool instances for an API key");
+
+    let mut tools: Vec<Arc<dyn ToolInterface>> = vec![
+        Arc::new(tools::time_date::TimeDateTool),
+        Arc::new(tools::web::WebTool),
+        Arc::new(tools::list_datasets::ListDatasetsTool::new(
+            pool.clone(),
+            sub.clone(),
+            prompt_id,
+        )),
+        Arc::new(tools::list_dataset_files::ListDatasetFilesTool::new(
+            pool.clone(),
+            sub.clone(),
+        )),
+        Arc::new(tools::search_context::SearchContextTool::for_team(
+            pool.clone(),
+            sub.clone(),
+            team_id,
+            prompt_id,
+            context_chunks,
+        )),
+    ];
+
+    add_external_integration_tools(&mut tools, pool, sub, prompt_id).await;
+
+    info!("Returning {} API tool instances", tools.len());
+    tools
+}
+
+/// External integrations of the prompt override internal tools with the same name
+async fn add_external_integration_tools(
+    tools: &mut Vec<Arc<dyn ToolInterface>>,
+    pool: &Pool,
+    sub: String,
+    prompt_id: i32,
+) {
     // Get external integration tools
     debug!("Getting external integration tools");
     let external_tools = match get_external_integration_tools(pool, sub, prompt_id).await {
@@ -133,7 +329,7 @@ pub async fn get_tools(
 
         // Check for name conflicts and override internal tools
         let mut tool_names = HashSet::new();
-        for tool in &tools {
+        for tool in tools.iter() {
             tool_names.insert(tool.name());
         }
 
@@ -156,9 +352,6 @@ pub async fn get_tools(
             tools.len()
         );
     }
-
-    info!("Returning {} tool instances", tools.len());
-    tools
 }
 
 /// Execute a tool call with a specific set of tools
@@ -166,6 +359,7 @@ pub async fn execute_tool_call_with_tools(
     tools: &[Arc<dyn ToolInterface>],
     tool_call: &ToolCall,
 ) -> ToolCallResult {
+    let started = Instant::now();
     let tool_name = &tool_call.function.name;
     info!("Executing tool call: {}", tool_name);
     debug!("Tool call arguments: {}", tool_call.function.arguments);
@@ -188,6 +382,8 @@ pub async fn execute_tool_call_with_tools(
                 id: tool_call.id.clone(),
                 name: tool_call.function.name.clone(),
                 result,
+                duration_ms: started.elapsed().as_millis() as u64,
+                status: ToolCallStatus::Success,
             };
         } else if let Err(e) = result {
             error!("Tool execution failed: {}", e);
@@ -201,6 +397,8 @@ pub async fn execute_tool_call_with_tools(
         id: tool_call.id.clone(),
         name: tool_call.function.name.clone(),
         result: json!({"error": "Problem calling tool"}),
+        duration_ms: started.elapsed().as_millis() as u64,
+        status: ToolCallStatus::Error,
     }
 }
 
@@ -208,9 +406,48 @@ pub async fn execute_tool_call_with_tools(
 mod tests {
     use super::*;
     use crate::tools::time_date::TimeDateTool;
+    use async_trait::async_trait;
+    use openai_api::{BionicToolDefinition, ChatCompletionFunctionDefinition};
     use openai_api::{ToolCall, ToolCallFunction};
     use serde_json::json;
 
+    struct SlowTool(Duration);
+
+    #[async_trait]
+    impl ToolInterface for SlowTool {
+        fn get_tool(&self) -> BionicToolDefinition {
+            BionicToolDefinition {
+                r#type: "function".to_string(),
+                function: ChatCompletionFunctionDefinition {
+                    name: "slow".to_string(),
+                    description: "Takes its time".to_string(),
+                    parameters: json!({"type": "object", "properties": {}}),
+                },
+            }
+        }
+
+        fn timeout(&self) -> Option<Duration> {
+            Some(Duration::from_millis(50))
+        }
+
+        async fn execute(&self, _arguments: &str) -> Result<serde_json::Value, serde_json::Value> {
+            tokio::time::sleep(self.0).await;
+            Ok(json!({"done": true}))
+        }
+    }
+
+    fn call(id: &str, name: &str) -> ToolCall {
+        ToolCall {
+            id: id.to_string(),
+            index: None,
+            r#type: "function".to_string(),
+            function: ToolCallFunction {
+                name: name.to_string(),
+                arguments: "{}".to_string(),
+            },
+        }
+    }
+
     #[tokio::test]
     async fn test_execute_tool_call_time_date() {
         let time_date_tool: Arc<dyn ToolInterface> = Arc::new(TimeDateTool);
@@ -230,4 +467,50 @@ mod tests {
         assert_eq!(result.id, "call_123".to_string());
         assert_eq!(result.name, "get_current_time_and_date".to_string());
     }
+
+    #[tokio::test]
+    async fn test_slow_tool_times_out_without_blocking_others() {
+        let tools: Vec<Arc<dyn ToolInterface>> = vec![
+            Arc::new(SlowTool(Duration::from_secs(5))),
+            Arc::new(TimeDateTool),
+        ];
+        let tool_calls = vec![
+            call("call_1", "slow"),
+            call("call_2", "get_current_time_and_date"),
+        ];
+
+        let results =
+            execute_tool_calls_with_tools(&tools, &tool_calls, &CancellationToken::new()).await;
+
+        assert_eq!(results[0].id, "call_1");
+        assert_eq!(results[0].status, ToolCallStatus::Timeout);
+        assert_eq!(results[0].result["error"], "Tool timed out");
+        assert_eq!(results[1].id, "call_2");
+        assert_eq!(results[1].status, ToolCallStatus::Success);
+    }
+
+    #[tokio::test]
+    async fn test_cancelled_tool_calls() {
+        let tools: Vec<Arc<dyn ToolInterface>> = vec![Arc::new(SlowTool(Duration::from_secs(5)))];
+        let cancel = CancellationToken::new();
+        cancel.cancel();
+
+        let result = execute_bounded(
+            &tools,
+            &call("call_1", "slow"),
+            Duration::from_secs(10),
+            &cancel,
+        )
+        .await;
+
+        assert_eq!(result.status, ToolCallStatus::Cancelled);
+    }
+
+    #[test]
+    fn test_parse_timeouts() {
+        let timeouts = parse_timeouts("web=10, generate_image = 180,broken,bad=x");
+        assert_eq!(timeouts.len(), 2);
+        assert_eq!(timeouts["web"], Duration::from_secs(10));
+        assert_eq!(timeouts["generate_image"], Duration::from_secs(180));
+    }
 }
@@ -36,6 +36,15 @@ pub fn get_integrations(scope: Option<ToolScope>) -> Vec<IntegrationTool> {
             definitions_json: serde_json::to_string_pretty(&vec![tools::web::get_open_url_tool()])
                 .expect("Failed to serialize web tools to JSON"),
         },
+        IntegrationTool {
+            scope: ToolScope::UserSelectable,
+            title: "Image generation".into(),
+            definitions: vec![tools::generate_image::get_tool_definition()],
+            definitions_json: serde_json::to_string_pretty(&vec![
+                tools::generate_image::get_tool_definition(),
+            ])
+            .expect("Failed to serialize image generation tools to JSON"),
+        },
         IntegrationTool {
             scope: ToolScope::DocumentIntelligence,
             title: "Tools to retrieve documents and read their contents.".into(),
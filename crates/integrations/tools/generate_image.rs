@@ -0,0 +1,375 @@
+use crate::json_error;
+use crate::tool::ToolInterface;
+use async_trait::async_trait;
+use db::queries::{conversations, generated_images, models};
+use db::{ModelType, Pool};
+use openai_api::{BionicToolDefinition, ChatCompletionFunctionDefinition};
+use serde::Deserialize;
+use serde_json::json;
+use std::time::Duration;
+
+pub const TOOL_NAME: &str = "generate_image";
+pub const SIZES: &[&str] = &["1024x1024", "1792x1024", "1024x1792"];
+pub const QUALITIES: &[&str] = &["standard", "hd"];
+
+// Users can generate this many images in a day unless MAX_GENERATED_IMAGES says otherwise
+const DEFAULT_DAILY_LIMIT: i64 = 20;
+// Image models take a lot longer than most tools
+const TIMEOUT_SECS: u64 = 120;
+
+#[derive(Debug, Deserialize)]
+struct GenerateImageParams {
+    prompt: String,
+    size: Option<String>,
+    quality: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct ImageData {
+    b64_json: Option<String>,
+    url: Option<String>,
+    revised_prompt: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct ImagesResponse {
+    data: Vec<ImageData>,
+    // png, jpeg or webp, only sent by models that support more than one
+    output_format: Option<String>,
+}
+
+pub struct GenerateImageTool {
+    pool: Pool,
+    sub: String,
+    conversation_id: i64,
+}
+
+impl GenerateImageTool {
+    // Links the stored image to the place we reserved
+    async fn record(
+        &self,
+        reservation_id: i32,
+        object_id: i32,
+    ) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+        let mut client = self.pool.get().await?;
+        let transaction = client.transaction().await?;
+        db::authz::set_row_level_security_user_id(&transaction, self.sub.clone()).await?;
+        generated_images::set_object()
+            .bind(&transaction, &object_id, &reservation_id)
+            .await?;
+        transaction.commit().await?;
+        Ok(())
+    }
+
+    pub fn new(pool: Pool, sub: String, conversation_id: i64) -> Self {
+        Self {
+            pool,
+            sub,
+            conversation_id,
+        }
+    }
+}
+
+pub fn get_tool_definition() -> BionicToolDefinition {
+    BionicToolDefinition {
+        r#type: "function".to_string(),
+        function: ChatCompletionFunctionDefinition {
+            name: TOOL_NAME.to_string(),
+            description: "Generates an image from a text description. The image is shown to the user in the chat, so there is no need to repeat or link it in your answer.".to_string(),
+            parameters: json!({
+                "type": "object",
+                "properties": {
+                    "prompt": {"type": "string", "description": "A detailed description of the image to generate."},
+                    "size": {"type": "string", "enum": SIZES, "description": "The size of the image. Default is 1024x1024."},
+                    "quality": {"type": "string", "enum": QUALITIES, "description": "The quality of the image. Default is standard."}
+                },
+                "required": ["prompt"]
+            }),
+        },
+    }
+}
+
+fn daily_limit() -> i64 {
+    std::env::var("MAX_GENERATED_IMAGES")
+        .ok()
+        .and_then(|limit| limit.parse().ok())
+        .unwrap_or(DEFAULT_DAILY_LIMIT)
+}
+
+// Anything the model asks for that we don't support gets the default
+fn pick_option(value: Option<String>, allowed: &[&str]) -> String {
+    value
+        .filter(|v| allowed.contains(&v.as_str()))
+        .unwrap_or_else(|| allowed[0].to_string())
+}
+
+#[async_trait]
+impl ToolInterface for GenerateImageTool {
+    fn get_tool(&self) -> BionicToolDefinition {
+        get_tool_definition()
+    }
+
+    fn timeout(&self) -> Option<Duration> {
+        Some(Duration::from_secs(TIMEOUT_SECS))
+    }
+
+    async fn execute(&self, arguments: &str) -> Result<serde_json::Value, serde_json::Value> {
+        let params: GenerateImageParams =
+            serde_json::from_str(arguments).map_err(|e| json_error("Invalid parameters", e))?;
+        let size = pick_option(params.size, SIZES);
+        let quality = pick_option(params.quality, QUALITIES);
+
+        let mut client = self
+            .pool
+            .get()
+            .await
+            .map_err(|e| json_error("Failed to get DB connection", e))?;
+        let transaction = client
+            .transaction()
+            .await
+            .map_err(|e| json_error("Failed to start transaction", e))?;
+
+        db::authz::set_row_level_security_user_id(&transaction, self.sub.clone())
+            .await
+            .map_err(|e| json_error("Failed to set RLS", e))?;
+
+        // These are returned as results so the model can explain them to the user
+        let Some(model) = models::models()
+            .bind(&transaction, &ModelType::Image)
+            .opt()
+            .await
+            .map_err(|e| json_error("Failed to fetch model", e))?
+        else {
+            return Ok(json!({"error": "No image generation model has been configured"}));
+        };
+
+        // Tools run concurrently, so count and reserve under a lock
+        generated_images::lock_for_user()
+            .bind(&transaction)
+            .one()
+            .await
+            .map_err(|e| json_error("Failed to lock images", e))?;
+        let limit = daily_limit();
+        let generated = generated_images::count_for_user_since()
+            .bind(&transaction, &"24")
+            .one()
+            .await
+            .map_err(|e| json_error("Failed to count images", e))?;
+        if generated >= limit {
+            return Ok(json!({
+                "error": format!("The limit of {} generated images a day has been reached", limit)
+            }));
+        }
+        let reservation_id = generated_images::reserve()
+            .bind(&transaction, &model.id, &params.prompt, &size, &quality)
+            .one()
+            .await
+            .map_err(|e| json_error("Failed to record image", e))?;
+
+        let conversation = conversations::get_conversation()
+            .bind(&transaction, &self.conversation_id)
+            .one()
+            .await
+            .map_err(|e| json_error("Failed to fetch conversation", e))?;
+
+        // Don't hold the transaction while the image model works
+        transaction
+            .commit()
+            .await
+            .map_err(|e| json_error("Failed to commit transaction", e))?;
+        drop(client);
+
+        let image = match generate(&model, &params.prompt, &size, &quality).await {
+            Ok(image) => image,
+            Err(e) => {
+                self.release(reservation_id).await;
+                return Err(e);
+            }
+        };
+
+        // Storage works out the mime type from the extension
+        let file_name = format!(
+            "generated-image-{}.{}",
+            time::OffsetDateTime::now_utc().unix_timestamp(),
+            extension(&image.content_type)
+        );
+        let object_id = match object_storage::upload(
+            self.pool.clone(),
+            conversation.user_id,
+            conversation.team_id,
+            &file_name,
+            &image.bytes,
+        )
+        .await
+        {
+            Ok(object_id) => object_id,
+            Err(e) => {
+                self.release(reservation_id).await;
+                return Err(json_error("Failed to store image", e));
+            }
+        };
+
+        // Without the object the reservation would count against the limit
+        // forever, so give it back if we can't record the image
+        if let Err(e) = self.record(reservation_id, object_id).await {
+            self.release(reservation_id).await;
+            return Err(json_error("Failed to record image", e));
+        }
+
+        Ok(json!({
+            // Picked up when the tool result is saved and linked to the chat
+            "attachment_object_id": object_id,
+            "file_name": file_name,
+            "revised_prompt": image.revised_prompt,
+            "message": "The image has been generated and is shown to the user."
+        }))
+    }
+}
+
+impl GenerateImageTool {
+    // Links the stored image to the place we reserved
+    async fn record(
+        &self,
+        reservation_id: i32,
+        object_id: i32,
+    ) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+        let mut client = self.pool.get().await?;
+        let transaction = client.transaction().await?;
+        db::authz::set_row_level_security_user_id(&transaction, self.sub.clone()).await?;
+        generated_images::set_object()
+            .bind(&transaction, &object_id, &reservation_id)
+            .await?;
+        transaction.commit().await?;
+        Ok(())
+    }
+
+    // Frees the place we reserved against the daily limit
+    async fn release(&self, reservation_id: i32) {
+        let result: Result<(), Box<dyn std::error::Error + Send + Sync>> = async {
+            let mut client = self.pool.get().await?;
+            let transaction = client.transaction().await?;
+            db::authz::set_row_level_security_user_id(&transaction, self.sub.clone()).await?;
+            generated_images::release()
+                .bind(&transaction, &reservation_id)
+                .await?;
+            transaction.commit().await?;
+            Ok(())
+        }
+        .await;
+        if let Err(e) = result {
+            tracing::error!(
+                "Failed to release generated image {}: {:?}",
+                reservation_id,
+                e
+            );
+        }
+    }
+}
+
+struct GeneratedImage {
+    bytes: Vec<u8>,
+    content_type: String,
+    revised_prompt: Option<String>,
+}
+
+fn extension(content_type: &str) -> &'static str {
+    match content_type.split(';').next().unwrap_or_default().trim() {
+        "image/jpeg" | "image/jpg" => "jpg",
+        "image/webp" => "webp",
+        "image/gif" => "gif",
+        _ => "png",
+    }
+}
+
+async fn generate(
+    model: &db::Model,
+    prompt: &str,
+    size: &str,
+    quality: &str,
+) -> Result<GeneratedImage, serde_json::Value> {
+    let mut request = reqwest::Client::new()
+        .post(format!("{}/images/generations", model.base_url))
+        .json(&json!({
+            "model": model.name,
+            "prompt": prompt,
+            "size": size,
+            "quality": quality,
+            "n": 1,
+            "response_format": "b64_json",
+        }));
+    if let Some(api_key) = &model.api_key {
+        request = request.bearer_auth(api_key);
+    }
+
+    let response = request
+        .send()
+        .await
+        .map_err(|e| json_error("Failed to call image model", e))?;
+    if !response.status().is_success() {
+        let status = response.status();
+        let body = response.text().await.unwrap_or_default();
+        tracing::error!("Image model returned {}: {}", status, body);
+        return Err(json_error("Image model returned an error", status));
+    }
+
+    let images: ImagesResponse = response
+        .json()
+        .await
+        .map_err(|e| json_error("Failed to parse image response", e))?;
+    let image = images
+        .data
+        .into_iter()
+        .next()
+        .ok_or_else(|| json!({"error": "No image was generated"}))?;
+
+    // Some servers ignore response_format and give us a URL
+    let (bytes, content_type) = match (image.b64_json, image.url) {
+        (Some(b64_json), _) => (
+            base64::decode(b64_json).map_err(|e| json_error("Invalid image data", e))?,
+            format!("image/{}", images.output_format.as_deref().unwrap_or("png")),
+        ),
+        (None, Some(url)) => {
+            let response = reqwest::get(url)
+                .await
+                .map_err(|e| json_error("Failed to download image", e))?;
+            let content_type = response
+                .headers()
+                .get(reqwest::header::CONTENT_TYPE)
+                .and_then(|value| value.to_str().ok())
+                .unwrap_or("image/png")
+                .to_string();
+            let bytes = response
+                .bytes()
+                .await
+                .map_err(|e| json_error("Failed to download image", e))?
+                .to_vec();
+            (bytes, content_type)
+        }
+        (None, None) => return Err(json!({"error": "No image was generated"})),
+    };
+
+    Ok(GeneratedImage {
+        bytes,
+        content_type,
+        revised_prompt: image.revised_prompt,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_pick_option() {
+        assert_eq!(pick_option(Some("hd".into()), QUALITIES), "hd");
+        assert_eq!(pick_option(Some("ultra".into()), QUALITIES), "standard");
+        assert_eq!(pick_option(None, SIZES), "1024x1024");
+    }
+
+    #[test]
+    fn test_extension() {
+        assert_eq!(extension("image/jpeg"), "jpg");
+        assert_eq!(extension("image/webp; charset=binary"), "webp");
+        assert_eq!(extension("application/octet-stream"), "png");
+    }
+}
@@ -1,9 +1,14 @@
+use crate::rerank::{candidate_pool_size, rerank_related_context};
 use crate::tool::ToolInterface;
 use async_trait::async_trait;
 use db::{queries, Pool, Transaction};
 use openai_api::{BionicToolDefinition, ChatCompletionFunctionDefinition};
 use serde::Deserialize;
 use serde_json::json;
+use std::sync::atomic::{AtomicI32, Ordering};
+use std::sync::Arc;
+
+pub const TOOL_NAME: &str = "search_context";
 
 #[derive(Debug, Deserialize)]
 struct SearchContextParams {
@@ -12,10 +17,19 @@ struct SearchContextParams {
     limit: Option<i32>,
 }
 
+// Where a search happens. API key requests have no conversation to record
+// the chunks against, so they only know the team and keep count of the
+// citations used so far.
+#[derive(Debug, Clone)]
+enum SearchScope {
+    Conversation(i64),
+    Team(i32, Arc<AtomicI32>),
+}
+
 pub struct SearchContextTool {
     pool: Pool,
     sub: String,
-    conversation_id: i64,
+    scope: SearchScope,
     prompt_id: i32,
 }
 
@@ -24,7 +38,23 @@ impl SearchContextTool {
         Self {
             pool,
             sub,
-            conversation_id,
+            scope: SearchScope::Conversation(conversation_id),
+            prompt_id,
+        }
+    }
+
+    // `context_chunks` is how many chunks the prompt already numbered
+    pub fn for_team(
+        pool: Pool,
+        sub: String,
+        team_id: i32,
+        prompt_id: i32,
+        context_chunks: i32,
+    ) -> Self {
+        Self {
+            pool,
+            sub,
+            scope: SearchScope::Team(team_id, Arc::new(AtomicI32::new(context_chunks))),
             prompt_id,
         }
     }
@@ -34,8 +64,8 @@ pub fn get_tool_definition() -> BionicToolDefinition {
     BionicToolDefinition {
         r#type: "function".to_string(),
         function: ChatCompletionFunctionDefinition {
-            name: "search_context".to_string(),
-            description: "Search the knowledge base for text related to the given query and return relevant document chunks.".to_string(),
+            name: TOOL_NAME.to_string(),
+            description: "Search the knowledge base for text related to the given query and return relevant document chunks. When you use a chunk, cite its citation number like this [1].".to_string(),
             parameters: json!({
                 "type": "object",
                 "properties": {
@@ -51,18 +81,21 @@ pub fn get_tool_definition() -> BionicToolDefinition {
 async fn search_context(
     transaction: &Transaction<'_>,
     prompt_id: i32,
-    conversation_id: i64,
+    scope: &SearchScope,
     query: &str,
     limit: i32,
 ) -> Result<serde_json::Value, serde_json::Value> {
-    let team_row = transaction
-        .query_one(
-            "SELECT team_id FROM conversations WHERE id = $1",
-            &[&conversation_id],
-        )
-        .await
-        .map_err(|e| json!({"error": "Failed to get conversation", "details": e.to_string()}))?;
-    let team_id: i32 = team_row.get(0);
+    let team_id: i32 = match scope {
+        SearchScope::Team(team_id, _) => *team_id,
+        SearchScope::Conversation(conversation_id) => transaction
+            .query_one(
+                "SELECT team_id FROM conversations WHERE id = $1",
+                &[conversation_id],
+            )
+            .await
+            .map_err(|e| json!({"error": "Failed to get conversation", "details": e.to_string()}))?
+            .get(0),
+    };
 
     let prompt = queries::prompts::prompt()
         .bind(transaction, &prompt_id, &team_id)
@@ -70,12 +103,13 @@ async fn search_context(
         .await
         .map_err(|e| json!({"error": "Failed to fetch prompt", "details": e.to_string()}))?;
 
-    let (base_url, model, api_key) = match (
+    let (base_url, model, api_key, model_id) = match (
         prompt.embeddings_base_url,
         prompt.embeddings_model,
         prompt.embeddings_api_key,
+        prompt.embeddings_model_id,
     ) {
-        (Some(url), Some(model), Some(key)) => (url, model, key),
+        (Some(url), Some(model), Some(key), Some(model_id)) => (url, model, key, model_id),
         _ => {
             return Err(json!({"error": "Prompt missing embeddings configuration"}));
         }
@@ -86,27 +120,69 @@ async fn search_context(
         &base_url,
         &model,
         prompt.embeddings_context_size.unwrap_or(256),
+        prompt
+            .embeddings_tokenizer
+            .as_deref()
+            .unwrap_or(openai_api::tokenizer::DEFAULT_TOKENIZER),
         &Some(api_key),
     )
     .await
     .map_err(|e| json!({"error": "Failed to get embeddings", "details": e.to_string()}))?;
 
-    let context = db::get_related_context(transaction, prompt_id, limit, embeddings)
-        .await
-        .map_err(|e| json!({"error": "Failed to search context", "details": e.to_string()}))?;
+    let candidates = candidate_pool_size(prompt.rerank_enabled, prompt.rerank_pool_size, limit);
+
+    let mut context = db::get_related_context(
+        transaction,
+        prompt_id,
+        candidates,
+        query,
+        embeddings,
+        model_id,
+    )
+    .await
+    .map_err(|e| json!({"error": "Failed to search context", "details": e.to_string()}))?;
 
-    for chunk in &context {
-        queries::chats_chunks::create_chunks_chats()
-            .bind(transaction, &chunk.chunk_id, &conversation_id)
+    if prompt.rerank_enabled {
+        context = rerank_related_context(transaction, query, context, limit as usize)
             .await
-            .map_err(
-                |e| json!({"error": "Failed to record chunk usage", "details": e.to_string()}),
-            )?;
+            .map_err(|e| json!({"error": "Failed to rerank context", "details": e.to_string()}))?;
     }
 
+    // Carry on from the chunks the model already has, so citations are unique
+    let citation = match scope {
+        SearchScope::Conversation(conversation_id) => {
+            let citation = 1 + queries::chats_chunks::turn_chunk_count()
+                .bind(transaction, conversation_id)
+                .one()
+                .await
+                .map_err(
+                    |e| json!({"error": "Failed to record chunk usage", "details": e.to_string()}),
+                )?;
+            for (index, chunk) in context.iter().enumerate() {
+                queries::chats_chunks::create_chunks_chats()
+                    .bind(
+                        transaction,
+                        &chunk.chunk_id,
+                        conversation_id,
+                        &(citation + index as i32),
+                    )
+                    .await
+                    .map_err(|e| {
+                        json!({"error": "Failed to record chunk usage", "details": e.to_string()})
+                    })?;
+            }
+            citation
+        }
+        // Searches in the same round run at once, so each takes its numbers
+        SearchScope::Team(_, used) => 1 + used.fetch_add(context.len() as i32, Ordering::SeqCst),
+    };
+
     let chunks_json: Vec<_> = context
         .into_iter()
-        .map(|c| json!({"id": c.chunk_id, "text": c.chunk_text}))
+        .enumerate()
+        .map(|(index, c)| {
+            json!({"citation": citation + index as i32, "id": c.chunk_id, "text": c.chunk_text})
+        })
         .collect();
 
     Ok(json!({"chunks": chunks_json}))
@@ -139,7 +215,7 @@ impl ToolInterface for SearchContextTool {
         let result = search_context(
             &transaction,
             self.prompt_id,
-            self.conversation_id,
+            &self.scope,
             &params.query,
             limit,
         )
@@ -1,6 +1,9 @@
+use super::api_tool_loop::{self, ServerTools, ToolLoopEvent};
+use super::citations;
 use super::limits;
 use super::sse_chat_enricher::{enriched_chat, GenerationEvent};
 use crate::errors::CustomError;
+use crate::fallback::{self, ChatRoute, FallbackError};
 use axum::body::Body;
 use axum::extract::Request;
 use axum::response::{sse::Event, Sse};
@@ -8,8 +11,7 @@ use axum::response::{IntoResponse, Response};
 use axum::{Extension, RequestExt};
 use db::{queries, Pool, Transaction};
 use http::{HeaderMap, StatusCode};
-use openai_api::BionicChatCompletionRequest;
-use reqwest::header::{HeaderValue, AUTHORIZATION, CONTENT_TYPE};
+use openai_api::{BionicChatCompletionRequest, ChatCompletion, Citation, Usage};
 use std::sync::Arc;
 use tokio::sync::mpsc;
 use tokio_stream::wrappers::ReceiverStream;
@@ -30,6 +32,7 @@ pub async fn chat_generate(
             .to_str()
             .map_err(|_| CustomError::Authentication("Invalid API Key".to_string()))?
             .replace("Bearer ", "");
+        let server_tools = api_tool_loop::requested(req.headers());
         let mut db_client = pool.get().await?;
         let transaction = db_client.transaction().await?;
 
@@ -40,12 +43,53 @@ pub async fn chat_generate(
         let completion: BionicChatCompletionRequest = serde_json::from_str(&body)?;
         let streaming = completion.stream.unwrap_or(false);
 
-        let (api_key, request) = create_request(&transaction, api_key, completion).await?;
+        let ApiRequest {
+            api_key,
+            completion,
+            routes,
+            prompt_metric_id,
+            citations,
+            context_chunks,
+            caller_tools,
+        } = create_request(&transaction, api_key, completion, server_tools).await?;
+
+        if let Some(limit) = limits::check_limit(
+            &transaction,
+            api_key.model_id,
+            api_key.user_id,
+            api_key.team_id,
+        )
+        .await?
+        {
+            // Dropping the transaction means this request isn't logged
+            return Ok(limit.into_response());
+        }
 
-        if limits::is_limit_exceeded(&transaction, api_key.model_id, api_key.user_id).await? {
-            return Err(CustomError::Limits(
-                "You have exceededs the token limits for this user and model".to_string(),
-            ));
+        if server_tools {
+            let server_tools =
+                ServerTools::new(&transaction, &pool, &api_key, context_chunks, &caller_tools)
+                    .await?;
+            transaction.commit().await?;
+            return if streaming {
+                stream_with_server_tools(
+                    pool,
+                    api_key,
+                    server_tools,
+                    completion,
+                    routes,
+                    prompt_metric_id,
+                    citations,
+                )
+            } else {
+                api_tool_loop::complete(
+                    server_tools,
+                    completion,
+                    routes,
+                    prompt_metric_id,
+                    citations,
+                )
+                .await
+            };
         }
 
         if streaming {
@@ -59,9 +103,7 @@ pub async fn chat_generate(
             tokio::spawn(async move {
                 tracing::debug!("Spawning enriched chat process");
                 // Call your existing function to start generating events
-                if let Err(e) = enriched_chat(request, sender, false).await {
-                    tracing::error!("Error generating SSE stream: {:?}", e);
-                }
+                chat_with_citations(routes, sender, citations).await;
             });
 
             let receiver_stream = ReceiverStream::new(receiver);
@@ -77,15 +119,9 @@ pub async fn chat_generate(
                 let api_key = Arc::clone(&api_key_arc);
                 async move {
                     match item {
-                        Ok(event) => match event {
-                            GenerationEvent::Text(completion_chunk) => {
-                                Ok(Event::default().data(completion_chunk.delta))
-                            }
-                            GenerationEvent::End(completion_chunk) => {
-                                log_end_of_chat(pool, &completion_chunk.snapshot, &api_key).await?;
-                                Ok(Event::default().data(completion_chunk.delta))
-                            }
-                        },
+                        Ok(event) => {
+                            generation_to_sse(event, pool, &api_key, Some(prompt_metric_id)).await
+                        }
                         Err(e) => Err(axum::Error::new(e)),
                     }
                 }
@@ -93,10 +129,19 @@ pub async fn chat_generate(
             Ok(Sse::new(event_stream).into_response())
         } else {
             // Non-streaming logic: generate the full response and return it
-            let response = request.send().await.map_err(|e| {
-                tracing::error!("Error calling model: {:?}", e);
-                CustomError::FaultySetup("Error calling model".to_string())
-            })?;
+            // Hold the endpoint's in flight guard until the body is read
+            let (response, _in_flight) = match fallback::send(routes).await {
+                Ok((response, _model_id, in_flight)) => (response, in_flight),
+                // Pass on what the model said about a request it didn't like
+                Err(FallbackError::Status(status, body)) => {
+                    transaction.commit().await?;
+                    return Ok((status, body).into_response());
+                }
+                Err(e) => {
+                    tracing::error!("Error calling model: {:?}", e);
+                    return Err(CustomError::ExternalApi(e.to_string()));
+                }
+            };
 
             // Commit the transaction, as the request was successful.
             transaction.commit().await?;
@@ -117,6 +162,23 @@ pub async fn chat_generate(
             let body_bytes = response.bytes().await?;
             let body = body_bytes.to_vec(); // Convert body to Vec<u8> (Axum uses hyper)
 
+            // Replace the prompt estimate if the model told us the real number
+            let usage = serde_json::from_slice::<ChatCompletion>(&body)
+                .ok()
+                .and_then(|completion| completion.usage);
+            if usage.is_some() {
+                let mut db_client = pool.get().await?;
+                let transaction = db_client.transaction().await?;
+                super::usage::report_prompt_tokens(&transaction, prompt_metric_id, usage).await?;
+                transaction.commit().await?;
+            }
+
+            // The body changes size when we add the citations
+            if !citations.is_empty() {
+                headers.remove(http::header::CONTENT_LENGTH);
+            }
+            let body = citations::add_citations(body, &citations);
+
             // Build axum response
             let response = (status, headers, body).into_response();
 
@@ -129,11 +191,139 @@ pub async fn chat_generate(
     }
 }
 
+// As the streaming above, with the tool calls and results we run in between
+// sent as SSE comments.
+fn stream_with_server_tools(
+    pool: Pool,
+    api_key: db::ApiKey,
+    server_tools: ServerTools,
+    completion: BionicChatCompletionRequest,
+    routes: Vec<ChatRoute>,
+    prompt_metric_id: i64,
+    citations: Vec<Citation>,
+) -> Result<Response<Body>, CustomError> {
+    let (sender, receiver) = mpsc::channel::<Result<ToolLoopEvent, axum::Error>>(10);
+
+    tokio::spawn(async move {
+        tracing::debug!("Spawning server side tool loop");
+        api_tool_loop::stream(
+            server_tools,
+            completion,
+            routes,
+            prompt_metric_id,
+            citations,
+            sender,
+        )
+        .await;
+    });
+
+    let pool_arc = Arc::new(pool);
+    let api_key_arc = Arc::new(api_key.api_key);
+
+    let event_stream = ReceiverStream::new(receiver).then(move |item| {
+        let pool = Arc::clone(&pool_arc);
+        let api_key = Arc::clone(&api_key_arc);
+        async move {
+            match item {
+                // The loop corrects the prompt of each round itself
+                Ok(ToolLoopEvent::Generation(event)) => {
+                    generation_to_sse(event, pool, &api_key, None).await
+                }
+                Ok(ToolLoopEvent::Comment(comment)) => Ok(Event::default().comment(comment)),
+                // The response has started so the 429 goes in an error event
+                Ok(ToolLoopEvent::Limited(limit)) => Ok(Event::default()
+                    .event("error")
+                    .data(limit.error_body().to_string())),
+                Err(e) => Err(axum::Error::new(e)),
+            }
+        }
+    });
+    Ok(Sse::new(event_stream).into_response())
+}
+
+async fn generation_to_sse(
+    event: GenerationEvent,
+    pool: Arc<Pool>,
+    api_key: &str,
+    prompt_metric_id: Option<i64>,
+) -> Result<Event, axum::Error> {
+    match event {
+        GenerationEvent::Text(completion_chunk) => {
+            Ok(Event::default().data(completion_chunk.delta))
+        }
+        GenerationEvent::End(completion_chunk) | GenerationEvent::Cancelled(completion_chunk) => {
+            let usage = completion_chunk
+                .merged
+                .as_ref()
+                .and_then(|merged| merged.usage);
+            log_end_of_chat(
+                pool,
+                &completion_chunk.snapshot,
+                api_key,
+                completion_chunk.model_id,
+                usage,
+                prompt_metric_id,
+            )
+            .await?;
+            Ok(Event::default().data(completion_chunk.delta))
+        }
+    }
+}
+
+// Sends the citations, if there are any, just before the end of the stream
+async fn chat_with_citations(
+    routes: Vec<ChatRoute>,
+    sender: mpsc::Sender<Result<GenerationEvent, axum::Error>>,
+    citations: Vec<Citation>,
+) {
+    let chat = |sender| async move {
+        if let Err(e) = enriched_chat(routes, sender, false).await {
+            tracing::error!("Error generating SSE stream: {:?}", e);
+        }
+    };
+
+    if citations.is_empty() {
+        return chat(sender).await;
+    }
+
+    let (model_sender, mut receiver) = mpsc::channel::<Result<GenerationEvent, axum::Error>>(10);
+    let forward = async move {
+        while let Some(event) = receiver.recv().await {
+            if let Ok(GenerationEvent::End(end)) = &event {
+                let chunk = citations::citations_event(end, &citations);
+                if sender.send(Ok(chunk)).await.is_err() {
+                    break;
+                }
+            }
+            // Dropping the receiver stops the generation
+            if sender.send(event).await.is_err() {
+                break;
+            }
+        }
+    };
+
+    tokio::join!(chat(model_sender), forward);
+}
+
+struct ApiRequest {
+    api_key: db::ApiKey,
+    completion: BionicChatCompletionRequest,
+    routes: Vec<ChatRoute>,
+    // The estimate of the prompt's tokens, replaced if the model reports them
+    prompt_metric_id: i64,
+    citations: Vec<Citation>,
+    // How many chunks the prompt numbered, the search tool carries on after them
+    context_chunks: usize,
+    // The names of the tools the caller runs
+    caller_tools: Vec<String>,
+}
+
 async fn create_request(
     transaction: &Transaction<'_>,
     api_key: String,
     completion: BionicChatCompletionRequest,
-) -> Result<(db::ApiKey, reqwest::RequestBuilder), CustomError> {
+    server_tools: bool,
+) -> Result<ApiRequest, CustomError> {
     let api_key = queries::api_keys::find_api_key()
         .bind(transaction, &api_key)
         .one()
@@ -164,35 +354,62 @@ async fn create_request(
         .one()
         .await?;
 
-    let messages =
-        super::prompt::execute_prompt(transaction, prompt.clone(), None, completion.messages)
+    let (messages, chunk_ids) =
+        super::prompt::execute_prompt(transaction, prompt.clone(), None, None, completion.messages)
             .await?;
-    let completion = BionicChatCompletionRequest {
+    let citations = citations::citations(transaction, &chunk_ids).await?;
+    let caller_tools = api_tool_loop::tool_names(&completion);
+    let mut completion = BionicChatCompletionRequest {
         messages,
         ..completion
     };
 
+    // The caller's own tools stay, ours are added alongside them unless the
+    // caller already has a tool with that name
+    if server_tools {
+        let capabilities = queries::capabilities::get_model_capabilities()
+            .bind(transaction, &model.id)
+            .all()
+            .await?;
+        if capabilities
+            .iter()
+            .any(|c| c.capability == db::ModelCapability::tool_use)
+        {
+            let tools: Vec<_> = super::prompt::get_prompt_integration_tools(transaction, prompt.id)
+                .await?
+                .into_iter()
+                .filter(|tool| !caller_tools.contains(&tool.function.name))
+                .collect();
+            if !tools.is_empty() {
+                completion.tools.get_or_insert_with(Vec::new).extend(tools);
+            }
+        }
+    }
+
     let completion_json = serde_json::to_string(&completion)?;
 
     tracing::debug!("{:?}", &completion_json);
 
-    log_initial_chat(transaction, api_key.id, &completion_json, &completion).await?;
-
-    let client = reqwest::Client::new();
-    let request = if let Some(api_key) = model.api_key {
-        client
-            .post(format!("{}/chat/completions", model.base_url))
-            .header(AUTHORIZATION, format!("Bearer {}", api_key))
-            .header(CONTENT_TYPE, HeaderValue::from_static("application/json"))
-            .body(completion_json)
-    } else {
-        client
-            .post(format!("{}/chat/completions", model.base_url))
-            .header(CONTENT_TYPE, HeaderValue::from_static("application/json"))
-            .body(completion_json)
-    };
-
-    Ok((api_key, request))
+    let prompt_metric_id = log_initial_chat(
+        transaction,
+        api_key.id,
+        &completion_json,
+        &completion,
+        &model.tokenizer,
+    )
+    .await?;
+
+    let routes = fallback::chat_routes(transaction, model.id, &completion).await?;
+
+    Ok(ApiRequest {
+        api_key,
+        completion,
+        routes,
+        prompt_metric_id,
+        citations,
+        context_chunks: chunk_ids.len(),
+        caller_tools,
+    })
 }
 
 async fn log_initial_chat(
@@ -200,8 +417,9 @@ async fn log_initial_chat(
     api_key_id: i32,
     completion_json: &str,
     completion: &BionicChatCompletionRequest,
-) -> Result<(), CustomError> {
-    let size = openai_api::token_count(completion.messages.clone());
+    tokenizer: &str,
+) -> Result<i64, CustomError> {
+    let size = openai_api::token_count_with(completion.messages.clone(), tokenizer);
 
     // Create the API chat entry with new structure
     queries::api_keys::new_api_chat()
@@ -215,8 +433,9 @@ async fn log_initial_chat(
         .one()
         .await?;
 
-    // Track prompt token usage in token_usage_metrics
-    queries::token_usage_metrics::create_token_usage_metric()
+    // Track prompt token usage in token_usage_metrics, this is an estimate
+    // until the model reports what it used.
+    let prompt_metric_id = queries::token_usage_metrics::create_token_usage_metric()
         .bind(
             transaction,
             &None::<i32>, // chat_id
@@ -224,21 +443,27 @@ async fn log_initial_chat(
             &db::TokenUsageType::Prompt,
             &size,
             &None::<i32>, // duration_ms
+            &db::TokenUsageSource::Estimated,
+            &None::<i32>, // model_id, we don't know which model will answer yet
         )
         .one()
         .await?;
 
-    Ok(())
+    Ok(prompt_metric_id)
 }
 
 async fn log_end_of_chat(
     pool: Arc<Pool>,
     snapshot: &str,
     api_key: &str,
+    model_id: Option<i32>,
+    usage: Option<Usage>,
+    prompt_metric_id: Option<i64>,
 ) -> Result<(), CustomError> {
-    let completion_tokens = openai_api::token_count_from_string(snapshot);
     let mut db_client = pool.get().await?;
     let transaction = db_client.transaction().await?;
+    let (completion_tokens, completion_source) =
+        super::usage::completion_tokens(&transaction, model_id, usage, snapshot).await;
 
     // Get the API key record to get the api_key_id
     let api_key_record = queries::api_keys::find_api_key()
@@ -247,7 +472,7 @@ async fn log_end_of_chat(
         .await?;
 
     // Create a new API chat entry for the assistant's response
-    queries::api_keys::new_api_chat()
+    let api_chat_id = queries::api_keys::new_api_chat()
         .bind(
             &transaction,
             &api_key_record.id,
@@ -258,6 +483,13 @@ async fn log_end_of_chat(
         .one()
         .await?;
 
+    // Record which model answered, it may have been a fallback
+    if let Some(model_id) = model_id {
+        queries::api_keys::set_api_chat_model()
+            .bind(&transaction, &model_id, &api_chat_id)
+            .await?;
+    }
+
     // Track completion token usage in token_usage_metrics
     queries::token_usage_metrics::create_token_usage_metric()
         .bind(
@@ -267,10 +499,16 @@ async fn log_end_of_chat(
             &db::TokenUsageType::Completion,
             &completion_tokens,
             &None::<i32>, // duration_ms - we could add timing here later
+            &completion_source,
+            &model_id,
         )
         .one()
         .await?;
 
+    if let Some(prompt_metric_id) = prompt_metric_id {
+        super::usage::report_prompt_tokens(&transaction, prompt_metric_id, usage).await?;
+    }
+
     transaction.commit().await?;
     Ok(())
 }
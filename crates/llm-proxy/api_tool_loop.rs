@@ -0,0 +1,641 @@
+//! Runs the tools of an assistant on the server for API key requests.
+//!
+//! Callers opt in with the `X-Bionic-Server-Tools: true` header. The
+//! assistant's integrations and RAG tools are added to the request, and
+//! whenever the model calls them we run them and call the model again with
+//! the results, until it answers or we reach the iteration limit.
+//!
+//! When streaming, each tool call and result is sent as an SSE comment so
+//! OpenAI clients skip them. If the model calls a tool the caller supplied
+//! we stop and hand the response back as usual. Each round counts towards
+//! the user's rate limit, once it's reached we stop with a 429.
+
+use crate::citations;
+use crate::errors::CustomError;
+use crate::fallback::{self, ChatRoute, FallbackError};
+use crate::limits::{self, RateLimitStatus};
+use crate::sse_chat_enricher::{enriched_chat, CompletionChunk, GenerationEvent};
+use crate::usage;
+use axum::response::{IntoResponse, Response};
+use db::{queries, Pool, Transaction};
+use http::{HeaderMap, StatusCode};
+use integrations::{execute_tool_calls_with_tools, record_tool_call, ToolInterface};
+use openai_api::{
+    BionicChatCompletionRequest, ChatCompletion, ChatCompletionMessage, ChatCompletionMessageRole,
+    Citation, ToolCall, ToolCallResult, Usage,
+};
+use serde_json::json;
+use std::sync::Arc;
+use tokio::sync::mpsc;
+use tokio_util::sync::CancellationToken;
+
+pub const SERVER_TOOLS_HEADER: &str = "X-Bionic-Server-Tools";
+
+// How many times we call the model unless MAX_API_TOOL_ITERATIONS says otherwise
+const DEFAULT_MAX_ITERATIONS: usize = 5;
+
+#[derive(Debug)]
+pub enum ToolLoopEvent {
+    Generation(GenerationEvent),
+    Comment(String),
+    // The user reached their rate limit before a follow up round
+    Limited(RateLimitStatus),
+}
+
+// The next call to the model, unless the user has used up their limit
+enum Round {
+    Ready(Vec<ChatRoute>, i64),
+    Limited(RateLimitStatus),
+}
+
+pub fn tool_names(completion: &BionicChatCompletionRequest) -> Vec<String> {
+    completion
+        .tools
+        .iter()
+        .flatten()
+        .map(|tool| tool.function.name.clone())
+        .collect()
+}
+
+pub fn requested(headers: &HeaderMap) -> bool {
+    headers
+        .get(SERVER_TOOLS_HEADER)
+        .and_then(|value| value.to_str().ok())
+        .map(|value| value.eq_ignore_ascii_case("true") || value == "1")
+        .unwrap_or(false)
+}
+
+fn max_iterations() -> usize {
+    std::env::var("MAX_API_TOOL_ITERATIONS")
+        .ok()
+        .and_then(|max| max.parse().ok())
+        .unwrap_or(DEFAULT_MAX_ITERATIONS)
+        .max(1)
+}
+
+pub struct ServerTools {
+    pool: Pool,
+    sub: String,
+    api_key_id: i32,
+    user_id: i32,
+    team_id: i32,
+    model_id: i32,
+    tokenizer: String,
+    tools: Vec<Arc<dyn ToolInterface>>,
+}
+
+impl ServerTools {
+    pub async fn new(
+        transaction: &Transaction<'_>,
+        pool: &Pool,
+        api_key: &db::ApiKey,
+        context_chunks: usize,
+        caller_tools: &[String],
+    ) -> Result<Self, CustomError> {
+        let sub = queries::users::openid_sub()
+            .bind(transaction, &api_key.user_id)
+            .one()
+            .await?;
+        let model = queries::models::model()
+            .bind(transaction, &api_key.model_id)
+            .one()
+            .await?;
+
+        let mut tools = integrations::get_api_tools(
+            pool,
+            sub.clone(),
+            api_key.team_id,
+            api_key.prompt_id,
+            context_chunks as i32,
+        )
+        .await;
+        // Calls to a name the caller also uses go back to the caller
+        tools.retain(|tool| !caller_tools.contains(&tool.name()));
+
+        Ok(Self {
+            pool: pool.clone(),
+            sub,
+            api_key_id: api_key.id,
+            user_id: api_key.user_id,
+            team_id: api_key.team_id,
+            model_id: api_key.model_id,
+            tokenizer: model.tokenizer,
+            tools,
+        })
+    }
+
+    fn names(&self) -> Vec<String> {
+        self.tools.iter().map(|tool| tool.name()).collect()
+    }
+
+    async fn execute(
+        &self,
+        tool_calls: &[ToolCall],
+        cancel: &CancellationToken,
+    ) -> Vec<ToolCallResult> {
+        execute_tool_calls_with_tools(&self.tools, tool_calls, cancel).await
+    }
+
+    // Follow up requests count towards the API key's usage like the first one,
+    // returns the routes and the prompt's token usage metric.
+    async fn routes(&self, completion: &BionicChatCompletionRequest) -> Result<Round, CustomError> {
+        let mut db_client = self.pool.get().await?;
+        let transaction = db_client.transaction().await?;
+        db::authz::set_row_level_security_user_id(&transaction, self.sub.clone()).await?;
+
+        // The earlier rounds may have used up the limit
+        if let Some(limit) =
+            limits::check_limit(&transaction, self.model_id, self.user_id, self.team_id).await?
+        {
+            return Ok(Round::Limited(limit));
+        }
+
+        let routes = fallback::chat_routes(&transaction, self.model_id, completion).await?;
+
+        let prompt_metric_id = queries::token_usage_metrics::create_token_usage_metric()
+            .bind(
+                &transaction,
+                &None::<i32>, // chat_id
+                &Some(self.api_key_id),
+                &db::TokenUsageType::Prompt,
+                &openai_api::token_count_with(completion.messages.clone(), &self.tokenizer),
+                &None::<i32>, // duration_ms
+                &db::TokenUsageSource::Estimated,
+                &None::<i32>, // model_id
+            )
+            .one()
+            .await?;
+
+        transaction.commit().await?;
+        Ok(Round::Ready(routes, prompt_metric_id))
+    }
+
+    // The sections the search tool found, so the caller gets them with the answer
+    async fn citations(&self, results: &[ToolCallResult]) -> Vec<Citation> {
+        let result: Result<Vec<Citation>, CustomError> = async {
+            let mut db_client = self.pool.get().await?;
+            let transaction = db_client.transaction().await?;
+            db::authz::set_row_level_security_user_id(&transaction, self.sub.clone()).await?;
+            citations::tool_citations(&transaction, results).await
+        }
+        .await;
+
+        result.unwrap_or_else(|e| {
+            tracing::error!("Failed to fetch tool citations: {:?}", e);
+            Vec::new()
+        })
+    }
+
+    // The last round is logged with the response, we only correct its prompt
+    async fn report_prompt(&self, prompt_metric_id: i64, usage: Option<Usage>) {
+        let result: Result<(), CustomError> = async {
+            let mut db_client = self.pool.get().await?;
+            let transaction = db_client.transaction().await?;
+            db::authz::set_row_level_security_user_id(&transaction, self.sub.clone()).await?;
+            usage::report_prompt_tokens(&transaction, prompt_metric_id, usage).await?;
+            transaction.commit().await?;
+            Ok(())
+        }
+        .await;
+
+        if let Err(e) = result {
+            tracing::error!("Failed to record prompt usage: {:?}", e);
+        }
+    }
+
+    async fn record_round(
+        &self,
+        text: &str,
+        tool_calls: &[ToolCall],
+        results: &[ToolCallResult],
+        prompt_metric_id: i64,
+        usage: Option<Usage>,
+    ) {
+        let (tokens, source) = match usage.and_then(|usage| usage.completion_tokens) {
+            Some(tokens) => (tokens as i32, db::TokenUsageSource::Reported),
+            None => {
+                let tool_calls_json = serde_json::to_string(tool_calls).unwrap_or_default();
+                let tokens = openai_api::token_count_from_string_with(text, &self.tokenizer)
+                    + openai_api::token_count_from_string_with(&tool_calls_json, &self.tokenizer);
+                (tokens, db::TokenUsageSource::Estimated)
+            }
+        };
+
+        let result: Result<(), CustomError> = async {
+            let mut db_client = self.pool.get().await?;
+            let transaction = db_client.transaction().await?;
+            db::authz::set_row_level_security_user_id(&transaction, self.sub.clone()).await?;
+            queries::token_usage_metrics::create_token_usage_metric()
+                .bind(
+                    &transaction,
+                    &None::<i32>, // chat_id
+                    &Some(self.api_key_id),
+                    &db::TokenUsageType::Completion,
+                    &tokens,
+                    &None::<i32>, // duration_ms
+                    &source,
+                    &None::<i32>, // model_id
+                )
+                .one()
+                .await?;
+            usage::report_prompt_tokens(&transaction, prompt_metric_id, usage).await?;
+            for result in results {
+                record_tool_call(&transaction, None, Some(self.api_key_id), result).await?;
+            }
+            transaction.commit().await?;
+            Ok(())
+        }
+        .await;
+
+        if let Err(e) = result {
+            tracing::error!("Failed to record tool call usage: {:?}", e);
+        }
+    }
+}
+
+// We only carry on if every call is to one of our tools, otherwise the
+// caller has to run theirs and the response goes back to them.
+fn server_side_calls(tool_calls: Option<Vec<ToolCall>>, names: &[String]) -> Option<Vec<ToolCall>> {
+    tool_calls
+        .filter(|tool_calls| !tool_calls.is_empty())
+        .filter(|tool_calls| {
+            tool_calls
+                .iter()
+                .all(|tool_call| names.contains(&tool_call.function.name))
+        })
+}
+
+// Add the model's tool calls and our results so the model can carry on
+fn add_tool_round(
+    completion: &mut BionicChatCompletionRequest,
+    text: &str,
+    tool_calls: Vec<ToolCall>,
+    results: Vec<ToolCallResult>,
+) {
+    completion.messages.push(ChatCompletionMessage {
+        role: ChatCompletionMessageRole::Assistant,
+        content: (!text.is_empty()).then(|| text.into()),
+        tool_calls: Some(tool_calls),
+        ..Default::default()
+    });
+
+    for result in results {
+        completion.messages.push(ChatCompletionMessage {
+            role: ChatCompletionMessageRole::Tool,
+            content: Some(result.result.to_string().into()),
+            tool_call_id: Some(result.id),
+            ..Default::default()
+        });
+    }
+}
+
+// The last call has to produce an answer
+fn prepare_round(completion: &mut BionicChatCompletionRequest, iteration: usize, max: usize) {
+    if iteration == max {
+        completion.tool_choice = Some(json!("none"));
+    }
+}
+
+pub async fn stream(
+    server_tools: ServerTools,
+    mut completion: BionicChatCompletionRequest,
+    routes: Vec<ChatRoute>,
+    prompt_metric_id: i64,
+    mut citations: Vec<Citation>,
+    sender: mpsc::Sender<Result<ToolLoopEvent, axum::Error>>,
+) {
+    let names = server_tools.names();
+    let max = max_iterations();
+    let mut first_routes = Some((routes, prompt_metric_id));
+
+    for iteration in 1..=max {
+        let (routes, prompt_metric_id) = match first_routes.take() {
+            Some(first) => first,
+            None => {
+                prepare_round(&mut completion, iteration, max);
+                match server_tools.routes(&completion).await {
+                    Ok(Round::Ready(routes, prompt_metric_id)) => (routes, prompt_metric_id),
+                    Ok(Round::Limited(limit)) => {
+                        sender.send(Ok(ToolLoopEvent::Limited(limit))).await.ok();
+                        return;
+                    }
+                    Err(e) => {
+                        sender.send(Err(axum::Error::new(e))).await.ok();
+                        return;
+                    }
+                }
+            }
+        };
+
+        let Some(end) = stream_round(routes, &sender).await else {
+            return;
+        };
+
+        let tool_calls = end
+            .merged
+            .as_ref()
+            .and_then(|merged| merged.choices.first())
+            .and_then(|choice| choice.delta.tool_calls.clone());
+        let usage = end.merged.as_ref().and_then(|merged| merged.usage);
+
+        let tool_calls = match server_side_calls(tool_calls, &names) {
+            Some(tool_calls) if iteration < max => tool_calls,
+            _ => {
+                server_tools.report_prompt(prompt_metric_id, usage).await;
+                if !citations.is_empty() {
+                    let chunk = citations::citations_event(&end, &citations);
+                    if sender
+                        .send(Ok(ToolLoopEvent::Generation(chunk)))
+                        .await
+                        .is_err()
+                    {
+                        return;
+                    }
+                }
+                sender
+                    .send(Ok(ToolLoopEvent::Generation(GenerationEvent::End(end))))
+                    .await
+                    .ok();
+                return;
+            }
+        };
+
+        for tool_call in &tool_calls {
+            let event = json!({
+                "id": tool_call.id,
+                "name": tool_call.function.name,
+                "arguments": tool_call.function.arguments,
+            });
+            let comment = ToolLoopEvent::Comment(format!("tool_call {}", event));
+            if sender.send(Ok(comment)).await.is_err() {
+                return;
+            }
+        }
+
+        // If the caller goes away we stop the tools, but still record them
+        let cancel = CancellationToken::new();
+        let execution = server_tools.execute(&tool_calls, &cancel);
+        tokio::pin!(execution);
+        let results = tokio::select! {
+            results = &mut execution => results,
+            _ = sender.closed() => {
+                cancel.cancel();
+                let results = execution.await;
+                server_tools
+                    .record_round(&end.snapshot, &tool_calls, &results, prompt_metric_id, usage)
+                    .await;
+                return;
+            }
+        };
+
+        for result in &results {
+            let event = json!({
+                "id": result.id,
+                "name": result.name,
+                "result": result.result,
+            });
+            let comment = ToolLoopEvent::Comment(format!("tool_result {}", event));
+            if sender.send(Ok(comment)).await.is_err() {
+                return;
+            }
+        }
+
+        server_tools
+            .record_round(
+                &end.snapshot,
+                &tool_calls,
+                &results,
+                prompt_metric_id,
+                usage,
+            )
+            .await;
+        citations.extend(server_tools.citations(&results).await);
+        add_tool_round(&mut completion, &end.snapshot, tool_calls, results);
+    }
+}
+
+// Pass the text of one call to the model on to the caller and return the end
+// of it, so we can decide whether to carry on.
+async fn stream_round(
+    routes: Vec<ChatRoute>,
+    sender: &mpsc::Sender<Result<ToolLoopEvent, axum::Error>>,
+) -> Option<CompletionChunk> {
+    let (round_sender, mut receiver) = mpsc::channel::<Result<GenerationEvent, axum::Error>>(10);
+
+    tokio::spawn(async move {
+        if let Err(e) = enriched_chat(routes, round_sender, false).await {
+            tracing::error!("Error generating SSE stream: {:?}", e);
+        }
+    });
+
+    while let Some(event) = receiver.recv().await {
+        match event {
+            Ok(GenerationEvent::End(chunk)) => return Some(chunk),
+            Ok(event) => {
+                if sender
+                    .send(Ok(ToolLoopEvent::Generation(event)))
+                    .await
+                    .is_err()
+                {
+                    return None;
+                }
+            }
+            Err(e) => {
+                sender.send(Err(e)).await.ok();
+                return None;
+            }
+        }
+    }
+
+    None
+}
+
+pub async fn complete(
+    server_tools: ServerTools,
+    mut completion: BionicChatCompletionRequest,
+    routes: Vec<ChatRoute>,
+    prompt_metric_id: i64,
+    mut citations: Vec<Citation>,
+) -> Result<Response, CustomError> {
+    let names = server_tools.names();
+    let max = max_iterations();
+    let mut first_routes = Some((routes, prompt_metric_id));
+
+    let mut iteration = 0;
+
+    // The last round always returns as it answers without our tools
+    loop {
+        iteration += 1;
+        let (routes, prompt_metric_id) = match first_routes.take() {
+            Some(first) => first,
+            None => {
+                prepare_round(&mut completion, iteration, max);
+                match server_tools.routes(&completion).await? {
+                    Round::Ready(routes, prompt_metric_id) => (routes, prompt_metric_id),
+                    Round::Limited(limit) => return Ok(limit.into_response()),
+                }
+            }
+        };
+
+        // Hold the endpoint's in flight guard until the body is read
+        let (response, _in_flight) = match fallback::send(routes).await {
+            Ok((response, _model_id, in_flight)) => (response, in_flight),
+            // Pass on what the model said about a request it didn't like
+            Err(FallbackError::Status(status, body)) => {
+                return Ok((status, body).into_response());
+            }
+            Err(e) => {
+                tracing::error!("Error calling model: {:?}", e);
+                return Err(CustomError::ExternalApi(e.to_string()));
+            }
+        };
+
+        let status = StatusCode::from_u16(response.status().as_u16()).map_err(|e| {
+            tracing::error!("Error generating status code: {:?}", e);
+            CustomError::FaultySetup("Error generating status code".to_string())
+        })?;
+        let mut headers = HeaderMap::new();
+        for (key, value) in response.headers() {
+            headers.insert(key, value.clone());
+        }
+        let body = response.bytes().await?.to_vec();
+
+        let (usage, message) = match serde_json::from_slice::<ChatCompletion>(&body) {
+            Ok(completion) => (
+                completion.usage,
+                completion
+                    .choices
+                    .into_iter()
+                    .next()
+                    .map(|choice| choice.message),
+            ),
+            Err(_) => (None, None),
+        };
+        let tool_calls = message
+            .as_ref()
+            .and_then(|message| server_side_calls(message.tool_calls.clone(), &names));
+
+        let tool_calls = match tool_calls {
+            Some(tool_calls) if iteration < max => tool_calls,
+            _ => {
+                server_tools.report_prompt(prompt_metric_id, usage).await;
+                if !citations.is_empty() {
+                    headers.remove(http::header::CONTENT_LENGTH);
+                }
+                let body = citations::add_citations(body, &citations);
+                return Ok((status, headers, body).into_response());
+            }
+        };
+
+        let text = message
+            .and_then(|message| message.text())
+            .unwrap_or_default();
+        // Axum drops this future if the caller goes away, which stops the tools
+        let results = server_tools
+            .execute(&tool_calls, &CancellationToken::new())
+            .await;
+        server_tools
+            .record_round(&text, &tool_calls, &results, prompt_metric_id, usage)
+            .await;
+        citations.extend(server_tools.citations(&results).await);
+        add_tool_round(&mut completion, &text, tool_calls, results);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use openai_api::{ToolCallFunction, ToolCallStatus};
+
+    fn tool_call(name: &str) -> ToolCall {
+        ToolCall {
+            id: format!("call_{}", name),
+            index: None,
+            r#type: "function".to_string(),
+            function: ToolCallFunction {
+                name: name.to_string(),
+                arguments: "{}".to_string(),
+            },
+        }
+    }
+
+    #[test]
+    fn test_server_side_calls() {
+        let names = vec!["search_context".to_string()];
+
+        let ours = server_side_calls(Some(vec![tool_call("search_context")]), &names);
+        assert_eq!(ours.map(|calls| calls.len()), Some(1));
+
+        // Any call to a tool of the caller hands the response back
+        let mixed = server_side_calls(
+            Some(vec![tool_call("search_context"), tool_call("get_weather")]),
+            &names,
+        );
+        assert!(mixed.is_none());
+
+        assert!(server_side_calls(Some(vec![]), &names).is_none());
+        assert!(server_side_calls(None, &names).is_none());
+    }
+
+    #[test]
+    fn test_tool_names() {
+        let completion: BionicChatCompletionRequest = serde_json::from_value(json!({
+            "model": "model",
+            "messages": [],
+            "tools": [{
+                "type": "function",
+                "function": {
+                    "name": "search_context",
+                    "description": "The caller's own search",
+                    "parameters": {}
+                }
+            }]
+        }))
+        .unwrap();
+
+        assert_eq!(tool_names(&completion), vec!["search_context".to_string()]);
+    }
+
+    #[test]
+    fn test_add_tool_round() {
+        let mut completion = BionicChatCompletionRequest {
+            model: "model".to_string(),
+            stream: None,
+            max_tokens: None,
+            messages: vec![],
+            temperature: None,
+            tools: None,
+            tool_choice: None,
+            stream_options: None,
+        };
+
+        add_tool_round(
+            &mut completion,
+            "",
+            vec![tool_call("search_context")],
+            vec![ToolCallResult {
+                id: "call_search_context".to_string(),
+                result: json!({"chunks": []}),
+                name: "search_context".to_string(),
+                duration_ms: 12,
+                status: ToolCallStatus::Success,
+            }],
+        );
+
+        assert_eq!(completion.messages.len(), 2);
+        assert_eq!(
+            completion.messages[0].role,
+            ChatCompletionMessageRole::Assistant
+        );
+        assert!(completion.messages[0].content.is_none());
+        assert_eq!(completion.messages[1].role, ChatCompletionMessageRole::Tool);
+        assert_eq!(
+            completion.messages[1].tool_call_id.as_deref(),
+            Some("call_search_context")
+        );
+        assert_eq!(
+            completion.messages[1].text().as_deref(),
+            Some(r#"{"chunks":[]}"#)
+        );
+    }
+}
@@ -0,0 +1,473 @@
+//! Executes automations in the background.
+//!
+//! Every few seconds we turn due cron triggers into `automation_runs` and then
+//! pick up pending runs. Several web-server replicas can run this loop at the
+//! same time, a unique index on the run tick and `SKIP LOCKED` when claiming a
+//! run make sure each run only happens once.
+
+use crate::chat_converter;
+use crate::cron::CronSchedule;
+use crate::errors::CustomError;
+use crate::fallback::{self, ChatRoute};
+use crate::limits;
+use crate::sse_chat_enricher::{enriched_chat, GenerationEvent};
+use db::queries::{
+    automation_runs, capabilities, chats, conversations, models, prompts, token_usage_metrics,
+};
+use db::{AutomationRunStatus, ChatRole, ChatStatus, Pool};
+use integrations::{execute_tool_calls, record_tool_call};
+use openai_api::{BionicChatCompletionRequest, ToolCall, Usage};
+use std::sync::Arc;
+use std::time::Duration;
+use time::{format_description::well_known::Rfc3339, OffsetDateTime};
+use tokio::sync::{mpsc, Semaphore};
+use tokio_util::sync::CancellationToken;
+
+// How often we look for due triggers and pending runs
+const POLL_INTERVAL_SECS: u64 = 10;
+// How many runs a single replica will execute at once
+const MAX_CONCURRENT_RUNS: usize = 4;
+// Stop an automation that keeps on calling tools
+const MAX_TOOL_ITERATIONS: usize = 10;
+// Runs left in Running for longer than this are marked as failed
+const STALE_RUN_MINUTES: i32 = 60;
+
+pub async fn run_scheduler(pool: Pool) {
+    tracing::info!("Starting the automation scheduler");
+
+    let semaphore = Arc::new(Semaphore::new(MAX_CONCURRENT_RUNS));
+
+    loop {
+        if let Err(e) = schedule_cron_triggers(&pool).await {
+            tracing::error!("Failed to schedule cron triggers: {:?}", e);
+        }
+
+        if let Err(e) = fail_stale_runs(&pool).await {
+            tracing::error!("Failed to clean up stale automation runs: {:?}", e);
+        }
+
+        // Start as many pending runs as we have capacity for
+        while let Ok(permit) = semaphore.clone().try_acquire_owned() {
+            match claim_pending_run(&pool).await {
+                Ok(Some(run)) => {
+                    let pool = pool.clone();
+                    tokio::spawn(async move {
+                        execute_run(&pool, run).await;
+                        drop(permit);
+                    });
+                }
+                Ok(None) => break,
+                Err(e) => {
+                    tracing::error!("Failed to claim automation run: {:?}", e);
+                    break;
+                }
+            }
+        }
+
+        tokio::time::sleep(Duration::from_secs(POLL_INTERVAL_SECS)).await;
+    }
+}
+
+// Create a pending run for every cron trigger that has fired since we last looked.
+async fn schedule_cron_triggers(pool: &Pool) -> Result<(), CustomError> {
+    let mut db_client = pool.get().await?;
+
+    let triggers = automation_runs::due_cron_triggers()
+        .bind(&db_client)
+        .all()
+        .await?;
+
+    let now = OffsetDateTime::now_utc();
+
+    for trigger in triggers {
+        let schedule = match CronSchedule::parse(&trigger.cron_expression) {
+            Ok(schedule) => schedule,
+            Err(e) => {
+                tracing::warn!("Skipping cron trigger {}: {}", trigger.id, e);
+                continue;
+            }
+        };
+
+        if let Some(tick) = schedule.latest_tick(trigger.scheduled_from, now) {
+            let input = format!(
+                "Scheduled run for {}",
+                tick.format(&Rfc3339).unwrap_or_default()
+            );
+
+            let transaction = db_client.transaction().await?;
+
+            // If another replica got here first this returns nothing
+            let run_id = automation_runs::insert_scheduled_run()
+                .bind(&transaction, &trigger.prompt_id, &trigger.id, &tick, &input)
+                .opt()
+                .await?;
+
+            automation_runs::set_last_scheduled()
+                .bind(&transaction, &tick, &trigger.id)
+                .await?;
+
+            transaction.commit().await?;
+
+            if let Some(run_id) = run_id {
+                tracing::info!(
+                    "Cron trigger {} created automation run {}",
+                    trigger.id,
+                    run_id
+                );
+            }
+        }
+    }
+
+    Ok(())
+}
+
+async fn fail_stale_runs(pool: &Pool) -> Result<(), CustomError> {
+    let db_client = pool.get().await?;
+    automation_runs::fail_stale_runs()
+        .bind(&db_client, &STALE_RUN_MINUTES)
+        .await?;
+    Ok(())
+}
+
+async fn claim_pending_run(
+    pool: &Pool,
+) -> Result<Option<automation_runs::ClaimedRun>, CustomError> {
+    let db_client = pool.get().await?;
+    let run = automation_runs::claim_pending_run()
+        .bind(&db_client)
+        .opt()
+        .await?;
+    Ok(run)
+}
+
+async fn execute_run(pool: &Pool, run: automation_runs::ClaimedRun) {
+    tracing::info!(
+        "Executing automation run {} for prompt {}",
+        run.id,
+        run.prompt_id
+    );
+
+    let (status, error_message) = match run_automation(pool, &run).await {
+        Ok(()) => (AutomationRunStatus::Completed, None),
+        Err(e) => {
+            tracing::error!("Automation run {} failed: {}", run.id, e);
+            // Keep the backtrace in the logs, not in the run history
+            let message = match e {
+                CustomError::Database(cause, _) => format!("Database Error: {}", cause),
+                e => e.to_string(),
+            };
+            (AutomationRunStatus::Failed, Some(message))
+        }
+    };
+
+    match pool.get().await {
+        Ok(db_client) => {
+            if let Err(e) = automation_runs::finish_run()
+                .bind(&db_client, &status, &error_message, &run.id)
+                .await
+            {
+                tracing::error!("Error finishing automation run {}: {:?}", run.id, e);
+            }
+        }
+        Err(e) => tracing::error!("Error getting database client: {:?}", e),
+    }
+}
+
+// Each run gets its own conversation owned by the creator of the automation.
+// We keep calling the model until it stops asking for tools.
+async fn run_automation(pool: &Pool, run: &automation_runs::ClaimedRun) -> Result<(), CustomError> {
+    let input = run
+        .input
+        .clone()
+        .unwrap_or_else(|| "Run the automation".to_string());
+
+    let mut db_client = pool.get().await?;
+    let transaction = db_client.transaction().await?;
+    db::authz::set_row_level_security_user_id(&transaction, run.openid_sub.clone()).await?;
+
+    let conversation_id = conversations::create_conversation()
+        .bind(&transaction, &run.team_id)
+        .one()
+        .await?;
+
+    chats::new_chat()
+        .bind(
+            &transaction,
+            &conversation_id,
+            &run.prompt_id,
+            &None::<String>,
+            &None::<String>,
+            &input,
+            &ChatRole::User,
+            &ChatStatus::Success,
+        )
+        .one()
+        .await?;
+
+    automation_runs::link_chats_to_run()
+        .bind(&transaction, &run.id, &conversation_id)
+        .await?;
+
+    transaction.commit().await?;
+
+    for _ in 0..MAX_TOOL_ITERATIONS {
+        let tool_calls = generate_step(pool, run, conversation_id).await?;
+
+        match tool_calls {
+            Some(tool_calls) => {
+                save_tool_results(pool, run, conversation_id, tool_calls).await?;
+            }
+            None => return Ok(()),
+        }
+    }
+
+    Err(CustomError::FaultySetup(format!(
+        "Automation was still calling tools after {} iterations",
+        MAX_TOOL_ITERATIONS
+    )))
+}
+
+// Build the prompt from the conversation so far, call the model and store
+// the reply. Returns any tool calls the model asked for.
+async fn generate_step(
+    pool: &Pool,
+    run: &automation_runs::ClaimedRun,
+    conversation_id: i64,
+) -> Result<Option<Vec<ToolCall>>, CustomError> {
+    let mut db_client = pool.get().await?;
+    let transaction = db_client.transaction().await?;
+    db::authz::set_row_level_security_user_id(&transaction, run.openid_sub.clone()).await?;
+
+    let prompt = prompts::prompt()
+        .bind(&transaction, &run.prompt_id, &run.team_id)
+        .one()
+        .await?;
+
+    let model = models::model()
+        .bind(&transaction, &prompt.model_id)
+        .one()
+        .await?;
+
+    // Automations share the hourly budget of the user that created them
+    if let Some(limit) =
+        limits::check_limit(&transaction, model.id, prompt.created_by, run.team_id).await?
+    {
+        return Err(CustomError::Limits(format!(
+            "Used {} of {} tokens per hour for this model",
+            limit.tokens_used, limit.tokens_per_hour
+        )));
+    }
+
+    let capabilities = capabilities::get_model_capabilities()
+        .bind(&transaction, &model.id)
+        .all()
+        .await?;
+
+    let chat_history = chats::chat_history()
+        .bind(
+            &transaction,
+            &conversation_id,
+            &(prompt.max_history_items as i64),
+        )
+        .all()
+        .await?;
+
+    let chat_history = chat_converter::convert_chat_to_messages(chat_history);
+
+    let (messages, _chunk_ids) = super::prompt::execute_prompt(
+        &transaction,
+        prompt.clone(),
+        Some(conversation_id),
+        None,
+        chat_history,
+    )
+    .await?;
+
+    let tools = if capabilities
+        .iter()
+        .any(|c| c.capability == db::ModelCapability::tool_use)
+    {
+        let tools = super::prompt::get_prompt_integration_tools(&transaction, prompt.id).await?;
+        if tools.is_empty() {
+            None
+        } else {
+            Some(tools)
+        }
+    } else {
+        None
+    };
+
+    let prompt_tokens = openai_api::token_count_with(messages.clone(), &prompt.tokenizer);
+
+    let completion = BionicChatCompletionRequest {
+        model: model.name,
+        stream: Some(true),
+        max_tokens: Some(prompt.max_tokens),
+        temperature: prompt.temperature,
+        messages,
+        tools,
+        tool_choice: None,
+        stream_options: None,
+    };
+
+    let routes = fallback::chat_routes(&transaction, model.id, &completion).await?;
+
+    transaction.commit().await?;
+
+    let (snapshot, tool_calls, answered_by, usage) = complete(routes).await?;
+
+    let mut db_client = pool.get().await?;
+    let transaction = db_client.transaction().await?;
+    db::authz::set_row_level_security_user_id(&transaction, run.openid_sub.clone()).await?;
+
+    let tool_calls_json = tool_calls
+        .as_ref()
+        .and_then(|tool_calls| serde_json::to_string(tool_calls).ok());
+
+    let chat_id = chats::new_chat()
+        .bind(
+            &transaction,
+            &conversation_id,
+            &run.prompt_id,
+            &None::<String>,
+            &tool_calls_json,
+            &snapshot,
+            &ChatRole::Assistant,
+            &ChatStatus::Success,
+        )
+        .one()
+        .await?;
+
+    if let Some(model_id) = answered_by {
+        chats::set_chat_model()
+            .bind(&transaction, &model_id, &chat_id)
+            .await?;
+    }
+
+    let prompt_tokens = match usage.and_then(|usage| usage.prompt_tokens) {
+        Some(tokens) => (tokens as i32, db::TokenUsageSource::Reported),
+        None => (prompt_tokens, db::TokenUsageSource::Estimated),
+    };
+    let completion_tokens =
+        super::usage::completion_tokens(&transaction, answered_by, usage, &snapshot).await;
+    for (token_type, (tokens, source)) in [
+        (db::TokenUsageType::Prompt, prompt_tokens),
+        (db::TokenUsageType::Completion, completion_tokens),
+    ] {
+        token_usage_metrics::create_token_usage_metric()
+            .bind(
+                &transaction,
+                &Some(chat_id),
+                &None::<i32>, // api_key_id
+                &token_type,
+                &tokens,
+                &None::<i32>, // duration_ms
+                &source,
+                &answered_by,
+            )
+            .one()
+            .await?;
+    }
+
+    automation_runs::link_chats_to_run()
+        .bind(&transaction, &run.id, &conversation_id)
+        .await?;
+
+    transaction.commit().await?;
+
+    Ok(tool_calls)
+}
+
+async fn save_tool_results(
+    pool: &Pool,
+    run: &automation_runs::ClaimedRun,
+    conversation_id: i64,
+    tool_calls: Vec<ToolCall>,
+) -> Result<(), CustomError> {
+    let tool_call_results = execute_tool_calls(
+        tool_calls,
+        pool,
+        run.openid_sub.clone(),
+        conversation_id,
+        run.prompt_id,
+        // Nobody is waiting on an automation, so its tools only stop on timeout
+        &CancellationToken::new(),
+    )
+    .await;
+
+    let mut db_client = pool.get().await?;
+    let transaction = db_client.transaction().await?;
+    db::authz::set_row_level_security_user_id(&transaction, run.openid_sub.clone()).await?;
+
+    for tool_call in tool_call_results {
+        let result_json = serde_json::to_string(&tool_call.result)?;
+
+        let tool_chat_id = chats::new_chat()
+            .bind(
+                &transaction,
+                &conversation_id,
+                &run.prompt_id,
+                &Some(tool_call.id.clone()),
+                &None::<String>,
+                &result_json,
+                &ChatRole::Tool,
+                &ChatStatus::Success,
+            )
+            .one()
+            .await?;
+
+        record_tool_call(&transaction, Some(tool_chat_id), None, &tool_call).await?;
+    }
+
+    automation_runs::link_chats_to_run()
+        .bind(&transaction, &run.id, &conversation_id)
+        .await?;
+
+    transaction.commit().await?;
+
+    Ok(())
+}
+
+// Run the request through the same SSE handling as the console and
+// collect the full response and the model that answered.
+async fn complete(
+    routes: Vec<ChatRoute>,
+) -> Result<(String, Option<Vec<ToolCall>>, Option<i32>, Option<Usage>), CustomError> {
+    let (sender, mut receiver) = mpsc::channel::<Result<GenerationEvent, axum::Error>>(10);
+
+    tokio::spawn(async move {
+        if let Err(e) = enriched_chat(routes, sender, false).await {
+            tracing::error!("Error generating automation response: {:?}", e);
+        }
+    });
+
+    while let Some(event) = receiver.recv().await {
+        match event {
+            Ok(GenerationEvent::Text(_)) => {}
+            Ok(GenerationEvent::End(completion_chunk)) => {
+                let usage = completion_chunk
+                    .merged
+                    .as_ref()
+                    .and_then(|merged| merged.usage);
+                let tool_calls = completion_chunk
+                    .merged
+                    .and_then(|merged| merged.choices.into_iter().next())
+                    .and_then(|choice| choice.delta.tool_calls)
+                    .filter(|tool_calls| !tool_calls.is_empty());
+
+                return Ok((
+                    completion_chunk.snapshot,
+                    tool_calls,
+                    completion_chunk.model_id,
+                    usage,
+                ));
+            }
+            Ok(GenerationEvent::Cancelled(_)) => break,
+            Err(e) => return Err(CustomError::ExternalApi(e.to_string())),
+        }
+    }
+
+    Err(CustomError::ExternalApi(
+        "The model closed the stream before completing".to_string(),
+    ))
+}
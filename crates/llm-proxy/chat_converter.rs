@@ -1,5 +1,12 @@
+use db::queries::attachments::AttachmentImage;
 use db::{Chat, ChatRole};
-use openai_api::{ChatCompletionMessage, ChatCompletionMessageRole};
+use openai_api::{
+    ChatCompletionContent, ChatCompletionContentPart, ChatCompletionMessage,
+    ChatCompletionMessageRole, ImageUrl,
+};
+
+// Images larger than this on either side are scaled down before sending
+const MAX_IMAGE_DIMENSION: u32 = 1024;
 
 /// Converts a database chat role to an OpenAI API chat completion message role
 pub fn convert_chat_role(db_role: &ChatRole) -> ChatCompletionMessageRole {
@@ -12,17 +19,66 @@ pub fn convert_chat_role(db_role: &ChatRole) -> ChatCompletionMessageRole {
     }
 }
 
+/// The chats on the branch that ends with the last chat. Alternatives left
+/// behind by editing or regenerating are dropped, along with their tool calls.
+/// If a chat's parent isn't in the list we keep the chats before it as they are.
+pub fn active_branch(conversation: Vec<Chat>) -> Vec<Chat> {
+    let ids: Vec<i32> = conversation.iter().map(|chat| chat.id).collect();
+    let mut branch = Vec::new();
+    let mut parent_id: Option<i32> = None;
+    for chat in conversation.into_iter().rev() {
+        if let Some(parent_id) = parent_id.filter(|parent_id| ids.contains(parent_id)) {
+            if chat.id != parent_id {
+                continue;
+            }
+        }
+        parent_id = chat.parent_id;
+        branch.push(chat);
+    }
+    branch.reverse();
+    branch
+}
+
 /// Converts a vector of database Chat records to OpenAI API ChatCompletionMessage format
 pub fn convert_chat_to_messages(conversation: Vec<Chat>) -> Vec<ChatCompletionMessage> {
+    convert_chat_to_messages_with_images(conversation, Vec::new(), false)
+}
+
+/// As `convert_chat_to_messages` but image attachments are added to the chat
+/// they were sent with. Vision models get the image inline as a data URL,
+/// other models get a note telling them the image was left out.
+pub fn convert_chat_to_messages_with_images(
+    conversation: Vec<Chat>,
+    images: Vec<AttachmentImage>,
+    vision: bool,
+) -> Vec<ChatCompletionMessage> {
     let mut messages: Vec<ChatCompletionMessage> = Default::default();
-    for chat in conversation {
+    for chat in active_branch(conversation) {
         let tool_calls = chat
             .tool_calls
             .map(|tool_calls| serde_json::from_str(&tool_calls).unwrap_or_default());
 
+        let chat_images: Vec<&AttachmentImage> =
+            images.iter().filter(|i| i.chat_id == chat.id).collect();
+
+        let content = if chat_images.is_empty() {
+            chat.content.map(ChatCompletionContent::Text)
+        } else {
+            let mut parts = Vec::new();
+            if let Some(text) = chat.content {
+                parts.push(ChatCompletionContentPart::Text { text });
+            }
+            parts.extend(
+                chat_images
+                    .into_iter()
+                    .map(|image| image_part(image, vision)),
+            );
+            Some(ChatCompletionContent::Parts(parts))
+        };
+
         messages.push(ChatCompletionMessage {
             role: convert_chat_role(&chat.role),
-            content: chat.content,
+            content,
             tool_call_id: chat.tool_call_id,
             tool_calls,
             name: None,
@@ -30,3 +86,28 @@ pub fn convert_chat_to_messages(conversation: Vec<Chat>) -> Vec<ChatCompletionMe
     }
     messages
 }
+
+fn image_part(image: &AttachmentImage, vision: bool) -> ChatCompletionContentPart {
+    if !vision {
+        return ChatCompletionContentPart::Text {
+            text: format!(
+                "[The image {} was attached but this model can't view images. \
+                Let the user know they need an assistant with a vision capable model.]",
+                image.file_name
+            ),
+        };
+    }
+
+    match object_storage::image_data_url(&image.object_data, &image.mime_type, MAX_IMAGE_DIMENSION)
+    {
+        Ok(url) => ChatCompletionContentPart::ImageUrl {
+            image_url: ImageUrl { url, detail: None },
+        },
+        Err(e) => {
+            tracing::error!("Unable to read image {}: {}", image.file_name, e);
+            ChatCompletionContentPart::Text {
+                text: format!("[The image {} could not be read]", image.file_name),
+            }
+        }
+    }
+}
@@ -0,0 +1,182 @@
+//! The document sections given to the model, numbered as they are in the
+//! context. API clients get them with the response so they can show what a
+//! `[n]` in the answer refers to. Chunks found by the search tool carry on
+//! the numbering and are added as well.
+
+use crate::errors::CustomError;
+use crate::sse_chat_enricher::{CompletionChunk, GenerationEvent};
+use db::queries::chats_chunks;
+use db::Transaction;
+use integrations::tools::search_context::TOOL_NAME as SEARCH_CONTEXT_TOOL;
+use openai_api::{Citation, ToolCallResult};
+use serde_json::{json, Value};
+
+// The chunks are in the order they were numbered, `[1]` is the first
+pub async fn citations(
+    transaction: &Transaction<'_>,
+    chunk_ids: &[i32],
+) -> Result<Vec<Citation>, CustomError> {
+    let numbered: Vec<(i32, i32)> = chunk_ids
+        .iter()
+        .enumerate()
+        .map(|(index, chunk_id)| (index as i32 + 1, *chunk_id))
+        .collect();
+    numbered_citations(transaction, &numbered).await
+}
+
+// The chunks the search tool returned, numbered as the tool numbered them
+pub async fn tool_citations(
+    transaction: &Transaction<'_>,
+    results: &[ToolCallResult],
+) -> Result<Vec<Citation>, CustomError> {
+    numbered_citations(transaction, &tool_chunks(results)).await
+}
+
+async fn numbered_citations(
+    transaction: &Transaction<'_>,
+    numbered: &[(i32, i32)],
+) -> Result<Vec<Citation>, CustomError> {
+    if numbered.is_empty() {
+        return Ok(Vec::new());
+    }
+
+    let chunk_ids: Vec<i32> = numbered.iter().map(|(_, chunk_id)| *chunk_id).collect();
+    let chunks = chats_chunks::citations()
+        .bind(transaction, &chunk_ids)
+        .all()
+        .await?;
+
+    Ok(numbered
+        .iter()
+        .filter_map(|(number, chunk_id)| {
+            let chunk = chunks.iter().find(|chunk| chunk.chunk_id == *chunk_id)?;
+            Some(Citation {
+                number: *number,
+                chunk_id: *chunk_id,
+                file_name: chunk.file_name.clone(),
+                page_number: chunk.page_number,
+                preview: chunk.preview.clone(),
+            })
+        })
+        .collect())
+}
+
+// The citation number and chunk id of each chunk in the search results
+fn tool_chunks(results: &[ToolCallResult]) -> Vec<(i32, i32)> {
+    results
+        .iter()
+        .filter(|result| result.name == SEARCH_CONTEXT_TOOL)
+        .filter_map(|result| result.result.get("chunks")?.as_array())
+        .flatten()
+        .filter_map(|chunk| {
+            Some((
+                chunk.get("citation")?.as_i64()? as i32,
+                chunk.get("id")?.as_i64()? as i32,
+            ))
+        })
+        .collect()
+}
+
+// Streamed as the last chunk before [DONE], like the usage chunk it has no
+// choices so OpenAI clients pass over it.
+pub fn citations_chunk(citations: &[Citation]) -> String {
+    json!({
+        "object": "chat.completion.chunk",
+        "choices": [],
+        "citations": citations,
+    })
+    .to_string()
+}
+
+// The citations chunk as an event, to send before the end of the stream
+pub fn citations_event(end: &CompletionChunk, citations: &[Citation]) -> GenerationEvent {
+    GenerationEvent::Text(CompletionChunk {
+        delta: citations_chunk(citations),
+        merged: None,
+        snapshot: end.snapshot.clone(),
+        model_id: end.model_id,
+    })
+}
+
+// Adds the citations to a completion, anything else is returned as it is
+pub fn add_citations(body: Vec<u8>, citations: &[Citation]) -> Vec<u8> {
+    if citations.is_empty() {
+        return body;
+    }
+
+    match serde_json::from_slice::<Value>(&body) {
+        Ok(Value::Object(mut completion)) if completion.contains_key("choices") => {
+            completion.insert("citations".to_string(), json!(citations));
+            serde_json::to_vec(&completion).unwrap_or(body)
+        }
+        _ => body,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use openai_api::ToolCallStatus;
+
+    fn citation() -> Citation {
+        Citation {
+            number: 1,
+            chunk_id: 42,
+            file_name: "handbook.pdf".to_string(),
+            page_number: 3,
+            preview: "Holidays are booked".to_string(),
+        }
+    }
+
+    #[test]
+    fn test_add_citations() {
+        let body = br#"{"id":"1","choices":[{"index":0}]}"#.to_vec();
+        let body = add_citations(body, &[citation()]);
+
+        let completion: Value = serde_json::from_slice(&body).unwrap();
+        assert_eq!(completion["citations"][0]["file_name"], "handbook.pdf");
+        assert_eq!(completion["citations"][0]["page_number"], 3);
+        assert_eq!(completion["choices"][0]["index"], 0);
+    }
+
+    #[test]
+    fn test_errors_are_left_alone() {
+        let body = br#"{"error":{"message":"Bad request"}}"#.to_vec();
+        assert_eq!(add_citations(body.clone(), &[citation()]), body);
+        assert_eq!(
+            add_citations(b"not json".to_vec(), &[citation()]),
+            b"not json"
+        );
+    }
+
+    #[test]
+    fn test_tool_chunks() {
+        let result = |name: &str, result: Value| ToolCallResult {
+            id: "call_1".to_string(),
+            result,
+            name: name.to_string(),
+            duration_ms: 0,
+            status: ToolCallStatus::Success,
+        };
+        let results = vec![
+            result(
+                SEARCH_CONTEXT_TOOL,
+                json!({"chunks": [
+                    {"citation": 3, "id": 42, "text": "Holidays"},
+                    {"citation": 4, "id": 7, "text": "Sick leave"}
+                ]}),
+            ),
+            result("web", json!({"chunks": [{"citation": 1, "id": 1}]})),
+            result(SEARCH_CONTEXT_TOOL, json!({"error": "Failed to search"})),
+        ];
+
+        assert_eq!(tool_chunks(&results), vec![(3, 42), (4, 7)]);
+    }
+
+    #[test]
+    fn test_citations_chunk() {
+        let chunk: Value = serde_json::from_str(&citations_chunk(&[citation()])).unwrap();
+        assert_eq!(chunk["choices"], json!([]));
+        assert_eq!(chunk["citations"][0]["number"], 1);
+    }
+}
@@ -0,0 +1,221 @@
+//! A minimal parser for the 5 field cron expressions we store in
+//! `automation_cron_triggers` i.e. `minute hour day month weekday`.
+//!
+//! Each field supports `*`, single values, ranges `a-b`, lists `a,b` and
+//! steps `*/n` or `a-b/n`. Weekdays run from 0 (Sunday) to 6, 7 is also
+//! accepted as Sunday.
+
+use time::{Duration, OffsetDateTime};
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CronSchedule {
+    minutes: Vec<u8>,
+    hours: Vec<u8>,
+    days: Vec<u8>,
+    months: Vec<u8>,
+    weekdays: Vec<u8>,
+    // Standard cron treats day and weekday as OR when both are restricted.
+    day_restricted: bool,
+    weekday_restricted: bool,
+}
+
+impl CronSchedule {
+    pub fn parse(expression: &str) -> Result<CronSchedule, String> {
+        let fields: Vec<&str> = expression.split_whitespace().collect();
+        if fields.len() != 5 {
+            return Err(format!(
+                "Expected 5 fields in cron expression '{}' but found {}",
+                expression,
+                fields.len()
+            ));
+        }
+
+        let mut weekdays = parse_field(fields[4], 0, 7)?;
+        // 7 is an alias for Sunday
+        if weekdays.contains(&7) {
+            weekdays.retain(|d| *d != 7);
+            if !weekdays.contains(&0) {
+                weekdays.insert(0, 0);
+            }
+        }
+
+        Ok(CronSchedule {
+            minutes: parse_field(fields[0], 0, 59)?,
+            hours: parse_field(fields[1], 0, 23)?,
+            days: parse_field(fields[2], 1, 31)?,
+            months: parse_field(fields[3], 1, 12)?,
+            weekdays,
+            day_restricted: fields[2] != "*",
+            weekday_restricted: fields[4] != "*",
+        })
+    }
+
+    /// Does this schedule fire at the given minute
+    pub fn matches(&self, time: OffsetDateTime) -> bool {
+        let day_matches = self.days.contains(&time.day());
+        let weekday_matches = self
+            .weekdays
+            .contains(&time.weekday().number_days_from_sunday());
+
+        let day_of_month_or_week = match (self.day_restricted, self.weekday_restricted) {
+            (true, true) => day_matches || weekday_matches,
+            _ => day_matches && weekday_matches,
+        };
+
+        self.minutes.contains(&time.minute())
+            && self.hours.contains(&time.hour())
+            && self.months.contains(&(time.month() as u8))
+            && day_of_month_or_week
+    }
+
+    /// The most recent minute after `after` and up to and including `until`
+    /// that this schedule fires on. We only ever return the latest tick so a
+    /// scheduler that was down for a while doesn't replay every missed run.
+    pub fn latest_tick(
+        &self,
+        after: OffsetDateTime,
+        until: OffsetDateTime,
+    ) -> Option<OffsetDateTime> {
+        let until = truncate_to_minute(until);
+        let after = truncate_to_minute(after);
+
+        // Don't look back further than a day
+        let earliest = std::cmp::max(after, until - Duration::days(1));
+
+        let mut tick = until;
+        while tick > earliest {
+            if self.matches(tick) {
+                return Some(tick);
+            }
+            tick -= Duration::minutes(1);
+        }
+        None
+    }
+}
+
+fn truncate_to_minute(time: OffsetDateTime) -> OffsetDateTime {
+    time.replace_second(0)
+        .and_then(|t| t.replace_nanosecond(0))
+        .unwrap_or(time)
+}
+
+fn parse_field(field: &str, min: u8, max: u8) -> Result<Vec<u8>, String> {
+    let mut values: Vec<u8> = Vec::new();
+
+    for part in field.split(',') {
+        let (range, step) = match part.split_once('/') {
+            Some((range, step)) => {
+                let step: u8 = step
+                    .parse()
+                    .map_err(|_| format!("Invalid step '{}' in '{}'", step, field))?;
+                if step == 0 {
+                    return Err(format!("Step can't be zero in '{}'", field));
+                }
+                (range, step)
+            }
+            None => (part, 1),
+        };
+
+        let (start, end) = if range == "*" {
+            (min, max)
+        } else if let Some((start, end)) = range.split_once('-') {
+            (parse_value(start, min, max)?, parse_value(end, min, max)?)
+        } else {
+            let value = parse_value(range, min, max)?;
+            // `5/10` means starting at 5 every 10
+            if part.contains('/') {
+                (value, max)
+            } else {
+                (value, value)
+            }
+        };
+
+        if start > end {
+            return Err(format!("Invalid range '{}' in '{}'", range, field));
+        }
+
+        let mut value = start;
+        while value <= end {
+            if !values.contains(&value) {
+                values.push(value);
+            }
+            value = match value.checked_add(step) {
+                Some(v) => v,
+                None => break,
+            };
+        }
+    }
+
+    values.sort();
+    Ok(values)
+}
+
+fn parse_value(value: &str, min: u8, max: u8) -> Result<u8, String> {
+    let parsed: u8 = value
+        .parse()
+        .map_err(|_| format!("Invalid value '{}'", value))?;
+    if parsed < min || parsed > max {
+        return Err(format!("Value {} is out of range {}-{}", parsed, min, max));
+    }
+    Ok(parsed)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use time::macros::datetime;
+
+    #[test]
+    fn test_parse_every_minute() {
+        let schedule = CronSchedule::parse("* * * * *").unwrap();
+        assert!(schedule.matches(datetime!(2025-06-22 10:15 UTC)));
+    }
+
+    #[test]
+    fn test_parse_invalid() {
+        assert!(CronSchedule::parse("* * * *").is_err());
+        assert!(CronSchedule::parse("60 * * * *").is_err());
+        assert!(CronSchedule::parse("*/0 * * * *").is_err());
+        assert!(CronSchedule::parse("a * * * *").is_err());
+    }
+
+    #[test]
+    fn test_steps_ranges_and_lists() {
+        let schedule = CronSchedule::parse("*/15 9-17 * * 1-5").unwrap();
+        assert_eq!(schedule.minutes, vec![0, 15, 30, 45]);
+        assert_eq!(schedule.hours, (9..=17).collect::<Vec<u8>>());
+
+        // Monday
+        assert!(schedule.matches(datetime!(2025-06-23 09:30 UTC)));
+        // Sunday
+        assert!(!schedule.matches(datetime!(2025-06-22 09:30 UTC)));
+
+        let schedule = CronSchedule::parse("0,30 0 1 1,6 *").unwrap();
+        assert!(schedule.matches(datetime!(2025-06-01 00:30 UTC)));
+        assert!(!schedule.matches(datetime!(2025-07-01 00:30 UTC)));
+    }
+
+    #[test]
+    fn test_sunday_alias() {
+        let schedule = CronSchedule::parse("0 0 * * 7").unwrap();
+        assert!(schedule.matches(datetime!(2025-06-22 00:00 UTC)));
+    }
+
+    #[test]
+    fn test_latest_tick() {
+        let schedule = CronSchedule::parse("*/5 * * * *").unwrap();
+
+        let tick = schedule.latest_tick(
+            datetime!(2025-06-22 10:00 UTC),
+            datetime!(2025-06-22 10:12:30 UTC),
+        );
+        assert_eq!(tick, Some(datetime!(2025-06-22 10:10 UTC)));
+
+        // The tick we already ran is not returned again
+        let tick = schedule.latest_tick(
+            datetime!(2025-06-22 10:10 UTC),
+            datetime!(2025-06-22 10:12:30 UTC),
+        );
+        assert_eq!(tick, None);
+    }
+}
@@ -0,0 +1,291 @@
+//! Spreads the requests for a model over its pool of endpoints.
+//!
+//! A model without a pool has one endpoint, its own base_url, which we call
+//! endpoint 0. Every endpoint has a weight, an optional concurrency limit and
+//! a circuit breaker. Requests only go to endpoints that are healthy and have
+//! spare capacity, picked by least outstanding requests or by smooth weighted
+//! round robin. State is held per process.
+
+use std::collections::HashMap;
+use std::sync::{Mutex, MutexGuard, OnceLock};
+use std::time::{Duration, Instant};
+
+use db::LoadBalancing;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Endpoint {
+    pub id: i32,
+    pub base_url: String,
+    pub api_key: Option<String>,
+    pub weight: u32,
+    // 0 means no limit
+    pub max_concurrency: u32,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct EndpointKey {
+    pub model_id: i32,
+    pub endpoint_id: i32,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct EndpointStats {
+    pub in_flight: u32,
+    pub healthy: bool,
+}
+
+impl Default for EndpointStats {
+    fn default() -> Self {
+        EndpointStats {
+            in_flight: 0,
+            healthy: true,
+        }
+    }
+}
+
+#[derive(Debug, Default)]
+struct EndpointState {
+    in_flight: u32,
+    failures: u32,
+    open_until: Option<Instant>,
+    // Used by weighted round robin
+    current_weight: i64,
+}
+
+impl EndpointState {
+    fn is_healthy(&self, now: Instant) -> bool {
+        self.open_until.is_none_or(|open_until| now >= open_until)
+    }
+}
+
+fn states() -> MutexGuard<'static, HashMap<EndpointKey, EndpointState>> {
+    static STATES: OnceLock<Mutex<HashMap<EndpointKey, EndpointState>>> = OnceLock::new();
+    STATES
+        .get_or_init(|| Mutex::new(HashMap::new()))
+        .lock()
+        .unwrap_or_else(|e| e.into_inner())
+}
+
+// Counts a request against its endpoint until it's dropped, so hold on to it
+// for as long as the response is being read.
+#[derive(Debug)]
+pub struct InFlight {
+    key: EndpointKey,
+}
+
+impl InFlight {
+    pub fn key(&self) -> EndpointKey {
+        self.key
+    }
+}
+
+impl Drop for InFlight {
+    fn drop(&mut self) {
+        if let Some(state) = states().get_mut(&self.key) {
+            state.in_flight = state.in_flight.saturating_sub(1);
+        }
+    }
+}
+
+// Pick an endpoint for the next request to the model. Returns None when every
+// endpoint is unhealthy or at its concurrency limit.
+pub fn acquire(
+    model_id: i32,
+    endpoints: &[Endpoint],
+    load_balancing: LoadBalancing,
+) -> Option<(&Endpoint, InFlight)> {
+    let mut states = states();
+    let now = Instant::now();
+    let key = |endpoint: &Endpoint| EndpointKey {
+        model_id,
+        endpoint_id: endpoint.id,
+    };
+
+    let candidates: Vec<&Endpoint> = endpoints
+        .iter()
+        .filter(|endpoint| match states.get(&key(endpoint)) {
+            Some(state) => {
+                state.is_healthy(now)
+                    && (endpoint.max_concurrency == 0 || state.in_flight < endpoint.max_concurrency)
+            }
+            None => true,
+        })
+        .collect();
+
+    let chosen = match load_balancing {
+        LoadBalancing::LeastOutstanding => {
+            // Fewest requests per unit of weight, the first endpoint wins a tie
+            candidates.into_iter().min_by(|a, b| {
+                let load = |endpoint: &Endpoint| {
+                    states
+                        .get(&key(endpoint))
+                        .map_or(0, |state| state.in_flight as u64)
+                };
+                (load(a) * b.weight.max(1) as u64).cmp(&(load(b) * a.weight.max(1) as u64))
+            })?
+        }
+        LoadBalancing::WeightedRoundRobin => {
+            // Smooth weighted round robin as nginx does it. Every candidate
+            // gains its weight, the one with the most is picked and pays back
+            // the total, so heavy endpoints are interleaved with light ones.
+            let total: i64 = candidates
+                .iter()
+                .map(|endpoint| endpoint.weight.max(1) as i64)
+                .sum();
+            let mut chosen: Option<(&Endpoint, i64)> = None;
+            for endpoint in candidates {
+                let state = states.entry(key(endpoint)).or_default();
+                state.current_weight += endpoint.weight.max(1) as i64;
+                if chosen.is_none_or(|(_, best)| state.current_weight > best) {
+                    chosen = Some((endpoint, state.current_weight));
+                }
+            }
+            let (endpoint, _) = chosen?;
+            states.entry(key(endpoint)).or_default().current_weight -= total;
+            endpoint
+        }
+    };
+
+    states.entry(key(chosen)).or_default().in_flight += 1;
+    Some((chosen, InFlight { key: key(chosen) }))
+}
+
+pub fn record_success(key: EndpointKey) {
+    if let Some(state) = states().get_mut(&key) {
+        state.failures = 0;
+        state.open_until = None;
+    }
+}
+
+// Returns true if this failure opened the breaker. After the cool down one
+// request gets through, if that fails too the breaker opens again.
+pub fn record_failure(key: EndpointKey, threshold: u32, cooldown: Duration) -> bool {
+    let mut states = states();
+    let state = states.entry(key).or_default();
+    state.failures += 1;
+    if state.failures >= threshold {
+        tracing::warn!(
+            "Opening circuit breaker for model {} endpoint {} for {:?}",
+            key.model_id,
+            key.endpoint_id,
+            cooldown
+        );
+        state.open_until = Some(Instant::now() + cooldown);
+        true
+    } else {
+        false
+    }
+}
+
+// What this process knows about every endpoint it has called. Endpoints
+// missing from the map have nothing in flight and are healthy.
+pub fn endpoint_stats() -> HashMap<EndpointKey, EndpointStats> {
+    let now = Instant::now();
+    states()
+        .iter()
+        .map(|(key, state)| {
+            (
+                *key,
+                EndpointStats {
+                    in_flight: state.in_flight,
+                    healthy: state.is_healthy(now),
+                },
+            )
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn endpoint(id: i32, weight: u32, max_concurrency: u32) -> Endpoint {
+        Endpoint {
+            id,
+            base_url: format!("http://replica-{}", id),
+            api_key: None,
+            weight,
+            max_concurrency,
+        }
+    }
+
+    // Model ids no other test uses as the state is shared
+
+    #[test]
+    fn test_least_outstanding() {
+        let model_id = -10;
+        let endpoints = vec![endpoint(1, 1, 0), endpoint(2, 1, 0)];
+
+        let (first, _first_guard) =
+            acquire(model_id, &endpoints, LoadBalancing::LeastOutstanding).unwrap();
+        let (second, second_guard) =
+            acquire(model_id, &endpoints, LoadBalancing::LeastOutstanding).unwrap();
+        assert_eq!(first.id, 1);
+        assert_eq!(second.id, 2);
+
+        // Once a request finishes its endpoint is the least busy again
+        drop(second_guard);
+        let (third, _third_guard) =
+            acquire(model_id, &endpoints, LoadBalancing::LeastOutstanding).unwrap();
+        assert_eq!(third.id, 2);
+    }
+
+    #[test]
+    fn test_weighted_round_robin() {
+        let model_id = -11;
+        let endpoints = vec![endpoint(1, 3, 0), endpoint(2, 1, 0)];
+
+        let picked: Vec<i32> = (0..8)
+            .map(|_| {
+                acquire(model_id, &endpoints, LoadBalancing::WeightedRoundRobin)
+                    .unwrap()
+                    .0
+                    .id
+            })
+            .collect();
+        assert_eq!(picked, vec![1, 1, 2, 1, 1, 1, 2, 1]);
+    }
+
+    #[test]
+    fn test_concurrency_limit() {
+        let model_id = -12;
+        let endpoints = vec![endpoint(1, 1, 1)];
+
+        let (_, guard) = acquire(model_id, &endpoints, LoadBalancing::LeastOutstanding).unwrap();
+        assert!(acquire(model_id, &endpoints, LoadBalancing::LeastOutstanding).is_none());
+
+        drop(guard);
+        assert!(acquire(model_id, &endpoints, LoadBalancing::LeastOutstanding).is_some());
+    }
+
+    #[test]
+    fn test_circuit_breaker_skips_endpoint() {
+        let model_id = -13;
+        let endpoints = vec![endpoint(1, 1, 0), endpoint(2, 1, 0)];
+        let key = EndpointKey {
+            model_id,
+            endpoint_id: 1,
+        };
+
+        assert!(!record_failure(key, 2, Duration::from_secs(60)));
+        assert!(record_failure(key, 2, Duration::from_secs(60)));
+        assert!(!endpoint_stats()[&key].healthy);
+
+        for _ in 0..3 {
+            let (picked, _) =
+                acquire(model_id, &endpoints, LoadBalancing::WeightedRoundRobin).unwrap();
+            assert_eq!(picked.id, 2);
+        }
+
+        record_success(key);
+        assert!(endpoint_stats()[&key].healthy);
+
+        // A zero cool down closes straight away
+        let key = EndpointKey {
+            model_id: -14,
+            endpoint_id: 0,
+        };
+        assert!(record_failure(key, 1, Duration::ZERO));
+        assert!(endpoint_stats()[&key].healthy);
+    }
+}
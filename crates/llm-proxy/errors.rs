@@ -8,6 +8,7 @@ use std::fmt;
 #[derive(Debug)]
 pub enum CustomError {
     FaultySetup(String),
+    BadRequest(String),
     Database(String, Backtrace),
     ExternalApi(String),
     Authentication(String),
@@ -20,6 +21,7 @@ impl fmt::Display for CustomError {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         match *self {
             CustomError::FaultySetup(ref cause) => write!(f, "Setup Error: {}", cause),
+            CustomError::BadRequest(ref cause) => write!(f, "Bad Request: {}", cause),
             CustomError::ExternalApi(ref cause) => write!(f, "Api Error: {}", cause),
             CustomError::Authentication(ref cause) => write!(f, "Api Error: {}", cause),
             CustomError::Limits(ref cause) => write!(f, "Api Error: {}", cause),
@@ -51,6 +53,7 @@ impl IntoResponse for CustomError {
                 )
             }
             CustomError::FaultySetup(message) => (StatusCode::UNPROCESSABLE_ENTITY, message, None),
+            CustomError::BadRequest(message) => (StatusCode::BAD_REQUEST, message, None),
             CustomError::ExternalApi(message) => (StatusCode::UNPROCESSABLE_ENTITY, message, None),
             CustomError::Authentication(message) => (StatusCode::UNAUTHORIZED, message, None),
             CustomError::Limits(message) => (StatusCode::TOO_MANY_REQUESTS, message, None),
@@ -117,3 +120,9 @@ impl From<CustomError> for axum::Error {
         axum::Error::new(err)
     }
 }
+
+impl From<axum::extract::multipart::MultipartError> for CustomError {
+    fn from(err: axum::extract::multipart::MultipartError) -> CustomError {
+        CustomError::FaultySetup(err.to_string())
+    }
+}
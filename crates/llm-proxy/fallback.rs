@@ -0,0 +1,403 @@
+//! Calls a model, retrying and then moving down its fallback chain when the
+//! endpoint is down.
+//!
+//! Each model has its own retry policy. Connection errors and timeouts are
+//! always retried, error statuses only when the model lists them. Any other
+//! failure moves on to the next model, unless the model rejected the request
+//! itself. The circuit breakers live with the endpoints, see `endpoints`.
+
+use std::fmt;
+use std::future::Future;
+use std::time::Duration;
+
+use db::{
+    queries::{capabilities, model_endpoints, model_fallbacks},
+    LoadBalancing, ModelCapability, ModelProvider, Transaction,
+};
+use openai_api::{BionicChatCompletionRequest, StreamOptions};
+use reqwest::{RequestBuilder, Response, StatusCode};
+use reqwest_eventsource::{Error as EventSourceError, Event, EventSource};
+use tokio::time::{sleep, timeout};
+use tokio_stream::StreamExt;
+
+use crate::endpoints::{self, Endpoint, InFlight};
+use crate::errors::CustomError;
+use crate::providers::{self, ChatStream};
+
+// How long we wait for a model to start responding
+const RESPONSE_TIMEOUT: Duration = Duration::from_secs(60);
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct RetryPolicy {
+    pub max_attempts: u32,
+    pub backoff: Duration,
+    pub retry_status_codes: Vec<u16>,
+    pub breaker_threshold: u32,
+    pub breaker_cooldown: Duration,
+}
+
+impl RetryPolicy {
+    fn should_retry(&self, error: &FallbackError) -> bool {
+        match error {
+            FallbackError::Status(status, _) => self.retry_status_codes.contains(&status.as_u16()),
+            FallbackError::Transport(_) | FallbackError::Timeout => true,
+            FallbackError::Request(_) | FallbackError::Unavailable => false,
+        }
+    }
+
+    // The wait before the next attempt doubles each time
+    fn delay(&self, attempt: u32) -> Duration {
+        self.backoff
+            .saturating_mul(2u32.saturating_pow(attempt.saturating_sub(1)))
+    }
+}
+
+// "429, 503" becomes [429, 503], anything that isn't a status is ignored
+pub fn parse_status_codes(codes: &str) -> Vec<u16> {
+    codes
+        .split(',')
+        .filter_map(|code| code.trim().parse::<u16>().ok())
+        .filter(|code| (100..600).contains(code))
+        .collect()
+}
+
+#[derive(Debug)]
+pub enum FallbackError {
+    // The model answered with an error status, and the body if we got one
+    Status(StatusCode, String),
+    // Connection refused, reset and the like
+    Transport(String),
+    Timeout,
+    Request(String),
+    // No endpoint of any model in the chain is available
+    Unavailable,
+}
+
+impl fmt::Display for FallbackError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            FallbackError::Status(status, body) if body.is_empty() => {
+                write!(f, "The model returned {}", status)
+            }
+            FallbackError::Status(status, body) => {
+                write!(f, "The model returned {}: {}", status, body)
+            }
+            FallbackError::Transport(cause) => write!(f, "Unable to reach the model: {}", cause),
+            FallbackError::Timeout => write!(f, "The model didn't respond in time"),
+            FallbackError::Request(cause) => write!(f, "Unable to call the model: {}", cause),
+            FallbackError::Unavailable => {
+                write!(f, "All models are unavailable, please try again shortly")
+            }
+        }
+    }
+}
+
+impl FallbackError {
+    // The model rejected what we sent, another model won't do any better
+    fn is_bad_request(&self) -> bool {
+        matches!(
+            self,
+            FallbackError::Status(status, _)
+                if *status == StatusCode::BAD_REQUEST
+                    || *status == StatusCode::UNPROCESSABLE_ENTITY
+        )
+    }
+}
+
+impl std::error::Error for FallbackError {}
+
+// One model in a fallback chain, the endpoints that serve it and the
+// completion to send them.
+pub struct ChatRoute {
+    pub model_id: i32,
+    pub provider: ModelProvider,
+    pub endpoints: Vec<Endpoint>,
+    pub load_balancing: LoadBalancing,
+    pub completion: BionicChatCompletionRequest,
+    pub policy: RetryPolicy,
+}
+
+// The model followed by its fallbacks. The completion is sent as is to the
+// model, fallbacks get the same completion with their own model name.
+pub async fn chat_routes(
+    transaction: &Transaction<'_>,
+    model_id: i32,
+    completion: &BionicChatCompletionRequest,
+) -> Result<Vec<ChatRoute>, CustomError> {
+    let chain = model_fallbacks::model_chain()
+        .bind(transaction, &model_id)
+        .all()
+        .await?;
+
+    let mut routes = Vec::new();
+    for (index, model) in chain.into_iter().enumerate() {
+        let mut completion = if index == 0 {
+            completion.clone()
+        } else {
+            BionicChatCompletionRequest {
+                model: model.name.clone(),
+                ..completion.clone()
+            }
+        };
+
+        // Ask for the real token counts. The other providers' streams are
+        // converted by us and always end with the usage.
+        if completion.stream == Some(true)
+            && model.provider == ModelProvider::OpenAI
+            && streams_usage(transaction, model.id).await?
+        {
+            completion.stream_options = Some(StreamOptions {
+                include_usage: true,
+            });
+        }
+
+        let mut endpoints: Vec<Endpoint> = model_endpoints::endpoints()
+            .bind(transaction, &model.id)
+            .all()
+            .await?
+            .into_iter()
+            .map(|endpoint| Endpoint {
+                id: endpoint.id,
+                base_url: endpoint.base_url,
+                api_key: endpoint.api_key,
+                weight: endpoint.weight.max(1) as u32,
+                max_concurrency: endpoint.max_concurrency.max(0) as u32,
+            })
+            .collect();
+        // Without a pool the model's own base_url is the only endpoint
+        if endpoints.is_empty() {
+            endpoints.push(Endpoint {
+                id: 0,
+                base_url: model.base_url,
+                api_key: model.api_key,
+                weight: 1,
+                max_concurrency: 0,
+            });
+        }
+
+        routes.push(ChatRoute {
+            model_id: model.id,
+            provider: model.provider,
+            endpoints,
+            load_balancing: model.load_balancing,
+            completion,
+            policy: RetryPolicy {
+                max_attempts: model.retry_max_attempts.max(1) as u32,
+                backoff: Duration::from_millis(model.retry_backoff_ms.max(0) as u64),
+                retry_status_codes: parse_status_codes(&model.retry_status_codes),
+                breaker_threshold: model.circuit_breaker_threshold.max(1) as u32,
+                breaker_cooldown: Duration::from_secs(
+                    model.circuit_breaker_cooldown_seconds.max(0) as u64,
+                ),
+            },
+        });
+    }
+
+    Ok(routes)
+}
+
+async fn streams_usage(transaction: &Transaction<'_>, model_id: i32) -> Result<bool, CustomError> {
+    Ok(capabilities::get_model_capabilities()
+        .bind(transaction, &model_id)
+        .all()
+        .await?
+        .iter()
+        .any(|c| c.capability == ModelCapability::StreamUsage))
+}
+
+// Open an event stream to the first model that responds. Returns the stream,
+// the id of the model that answered and the in flight guard for its endpoint,
+// keep that until the stream is finished.
+pub async fn open_stream(
+    routes: Vec<ChatRoute>,
+) -> Result<(ChatStream, i32, InFlight), FallbackError> {
+    call_with_fallback(routes, |provider, request| async move {
+        // Ollama streams lines of JSON rather than server sent events
+        if provider == ModelProvider::Ollama {
+            return Ok(ChatStream::from_ollama(send_request(request).await?));
+        }
+
+        let mut stream =
+            EventSource::new(request).map_err(|error| FallbackError::Request(error.to_string()))?;
+        // The first event tells us whether the model accepted the request
+        match timeout(RESPONSE_TIMEOUT, stream.next()).await {
+            Ok(Some(Ok(Event::Open))) => Ok(ChatStream::from_event_source(provider, stream)),
+            Ok(Some(Ok(Event::Message(_)))) => {
+                stream.close();
+                Err(FallbackError::Request("Message before open".into()))
+            }
+            Ok(Some(Err(error))) => {
+                stream.close();
+                Err(from_event_source_error(error).await)
+            }
+            Ok(None) => Err(FallbackError::Transport("The stream closed".into())),
+            Err(_) => {
+                stream.close();
+                Err(FallbackError::Timeout)
+            }
+        }
+    })
+    .await
+}
+
+// Send a non streaming request to the first model that responds. A request
+// the model rejects, or the last error status, comes back as
+// `FallbackError::Status` with the body so the caller can pass on what the
+// model said. Keep the in flight
+// guard until the body has been read. Whatever the provider the response is
+// an OpenAI chat completion.
+pub async fn send(routes: Vec<ChatRoute>) -> Result<(Response, i32, InFlight), FallbackError> {
+    call_with_fallback(routes, |provider, request| async move {
+        let response = send_request(request).await?;
+        providers::chat_response(provider, response).await
+    })
+    .await
+}
+
+async fn send_request(request: RequestBuilder) -> Result<Response, FallbackError> {
+    let response = match timeout(RESPONSE_TIMEOUT, request.send()).await {
+        Ok(Ok(response)) => response,
+        Ok(Err(error)) if error.is_timeout() => return Err(FallbackError::Timeout),
+        Ok(Err(error)) => return Err(FallbackError::Transport(error.to_string())),
+        Err(_) => return Err(FallbackError::Timeout),
+    };
+    if response.status().is_client_error() || response.status().is_server_error() {
+        return Err(FallbackError::Status(
+            response.status(),
+            response.text().await.unwrap_or_default(),
+        ));
+    }
+    Ok(response)
+}
+
+// Each attempt goes to whichever endpoint the load balancer picks, so a retry
+// usually lands on a different replica. When no endpoint of a model is
+// available we move on to the next model.
+async fn call_with_fallback<T, F, Fut>(
+    routes: Vec<ChatRoute>,
+    call: F,
+) -> Result<(T, i32, InFlight), FallbackError>
+where
+    F: Fn(ModelProvider, RequestBuilder) -> Fut,
+    Fut: Future<Output = Result<T, FallbackError>>,
+{
+    let client = reqwest::Client::new();
+    let mut last_error = FallbackError::Unavailable;
+
+    for route in routes {
+        for attempt in 1..=route.policy.max_attempts {
+            let Some((endpoint, in_flight)) =
+                endpoints::acquire(route.model_id, &route.endpoints, route.load_balancing)
+            else {
+                tracing::warn!("Skipping model {}, no endpoint available", route.model_id);
+                break;
+            };
+
+            let request =
+                providers::chat_request(&client, route.provider, endpoint, &route.completion)?;
+
+            match call(route.provider, request).await {
+                Ok(value) => {
+                    endpoints::record_success(in_flight.key());
+                    return Ok((value, route.model_id, in_flight));
+                }
+                // The request itself is the problem, another model won't help
+                Err(error) if error.is_bad_request() => return Err(error),
+                Err(error) => {
+                    let retry = route.policy.should_retry(&error);
+                    tracing::warn!(
+                        "Model {} endpoint {} attempt {} failed: {}",
+                        route.model_id,
+                        endpoint.id,
+                        attempt,
+                        error
+                    );
+                    last_error = error;
+                    endpoints::record_failure(
+                        in_flight.key(),
+                        route.policy.breaker_threshold,
+                        route.policy.breaker_cooldown,
+                    );
+                    drop(in_flight);
+                    // The policy only decides whether to try this model again
+                    if !retry {
+                        break;
+                    }
+                    if attempt < route.policy.max_attempts {
+                        sleep(route.policy.delay(attempt)).await;
+                    }
+                }
+            }
+        }
+    }
+
+    Err(last_error)
+}
+
+async fn from_event_source_error(error: EventSourceError) -> FallbackError {
+    match error {
+        EventSourceError::InvalidStatusCode(status, response) => {
+            FallbackError::Status(status, response.text().await.unwrap_or_default())
+        }
+        EventSourceError::Transport(error) if error.is_timeout() => FallbackError::Timeout,
+        EventSourceError::Transport(error) => FallbackError::Transport(error.to_string()),
+        error => FallbackError::Request(error.to_string()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn policy() -> RetryPolicy {
+        RetryPolicy {
+            max_attempts: 3,
+            backoff: Duration::from_millis(100),
+            retry_status_codes: vec![429, 503],
+            breaker_threshold: 2,
+            breaker_cooldown: Duration::from_secs(60),
+        }
+    }
+
+    #[test]
+    fn test_parse_status_codes() {
+        assert_eq!(parse_status_codes("429, 503,abc,,999"), vec![429, 503]);
+        assert!(parse_status_codes("").is_empty());
+    }
+
+    #[test]
+    fn test_should_retry() {
+        let policy = policy();
+        assert!(policy.should_retry(&FallbackError::Status(
+            StatusCode::SERVICE_UNAVAILABLE,
+            String::new()
+        )));
+        assert!(!policy.should_retry(&FallbackError::Status(
+            StatusCode::BAD_REQUEST,
+            String::new()
+        )));
+        assert!(policy.should_retry(&FallbackError::Timeout));
+        assert!(policy.should_retry(&FallbackError::Transport("refused".into())));
+        assert!(!policy.should_retry(&FallbackError::Unavailable));
+    }
+
+    #[test]
+    fn test_bad_request() {
+        assert!(FallbackError::Status(StatusCode::BAD_REQUEST, String::new()).is_bad_request());
+        assert!(
+            FallbackError::Status(StatusCode::UNPROCESSABLE_ENTITY, String::new()).is_bad_request()
+        );
+        // These are about the model, so the next one is tried
+        assert!(!FallbackError::Status(StatusCode::UNAUTHORIZED, String::new()).is_bad_request());
+        assert!(!FallbackError::Status(StatusCode::NOT_FOUND, String::new()).is_bad_request());
+        assert!(!FallbackError::Timeout.is_bad_request());
+    }
+
+    #[test]
+    fn test_backoff_doubles() {
+        let policy = policy();
+        assert_eq!(policy.delay(1), Duration::from_millis(100));
+        assert_eq!(policy.delay(2), Duration::from_millis(200));
+        assert_eq!(policy.delay(3), Duration::from_millis(400));
+    }
+}
@@ -0,0 +1,96 @@
+//! Keeps track of the console chats that are being generated, so they can
+//! be stopped from the stop endpoint.
+//!
+//! A generation is cancelled when it's stopped or when its `Generation` is
+//! dropped, i.e. when axum drops the SSE stream because the user went away.
+//! Cancelling closes the upstream request and any running tool calls. State is
+//! held per process.
+
+use std::collections::HashMap;
+use std::sync::{Mutex, MutexGuard, OnceLock};
+use tokio_util::sync::CancellationToken;
+
+fn generations() -> MutexGuard<'static, HashMap<i32, CancellationToken>> {
+    static GENERATIONS: OnceLock<Mutex<HashMap<i32, CancellationToken>>> = OnceLock::new();
+    GENERATIONS
+        .get_or_init(|| Mutex::new(HashMap::new()))
+        .lock()
+        .unwrap_or_else(|e| e.into_inner())
+}
+
+#[derive(Debug)]
+pub struct Generation {
+    chat_id: i32,
+    cancel: CancellationToken,
+}
+
+impl Generation {
+    pub fn start(chat_id: i32) -> Self {
+        let cancel = CancellationToken::new();
+        generations().insert(chat_id, cancel.clone());
+        Generation { chat_id, cancel }
+    }
+
+    pub fn token(&self) -> CancellationToken {
+        self.cancel.clone()
+    }
+}
+
+impl Drop for Generation {
+    fn drop(&mut self) {
+        self.cancel.cancel();
+        let mut generations = generations();
+        // Only remove ourselves, the chat may have been generated again
+        if generations
+            .get(&self.chat_id)
+            .is_some_and(|cancel| cancel.is_cancelled())
+        {
+            generations.remove(&self.chat_id);
+        }
+    }
+}
+
+/// Cancels the generation of a chat, returns false if it isn't running here.
+pub fn stop(chat_id: i32) -> bool {
+    match generations().remove(&chat_id) {
+        Some(cancel) => {
+            cancel.cancel();
+            true
+        }
+        None => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_stop_cancels_generation() {
+        let generation = Generation::start(9001);
+        let token = generation.token();
+
+        assert!(stop(9001));
+        assert!(token.is_cancelled());
+        // It's no longer running
+        assert!(!stop(9001));
+    }
+
+    #[test]
+    fn test_drop_cancels_generation() {
+        let token = Generation::start(9002).token();
+
+        assert!(token.is_cancelled());
+        assert!(!stop(9002));
+    }
+
+    #[test]
+    fn test_drop_leaves_newer_generation() {
+        let first = Generation::start(9003);
+        let second = Generation::start(9003);
+        drop(first);
+
+        assert!(!second.token().is_cancelled());
+        assert!(stop(9003));
+    }
+}
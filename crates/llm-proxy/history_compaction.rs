@@ -0,0 +1,402 @@
+//! Compacts long conversations for assistants using the Summarise history
+//! strategy. When the history no longer fits the context the oldest turns are
+//! folded into a summary stored against the conversation, later turns get the
+//! summary as a system message followed by the chats that came after it.
+
+use crate::chat_converter::convert_chat_to_messages;
+use crate::errors::CustomError;
+use crate::fallback::{self, ChatRoute};
+use crate::limits;
+use crate::prompt::size_allowed;
+use crate::usage;
+use db::queries::{chats, conversations, models, prompts, token_usage_metrics};
+use db::{Chat, ChatRole, HistoryStrategy, Pool, TokenUsageSource, TokenUsageType, Transaction};
+use openai_api::{
+    BionicChatCompletionRequest, ChatCompletion, ChatCompletionMessage, ChatCompletionMessageRole,
+};
+
+// After compacting, the turns we keep take up at most this share of the
+// budget. That leaves room for related context and means we don't have to
+// summarise again on the very next turn.
+const KEEP_RATIO: f32 = 0.5;
+
+const SUMMARY_MAX_TOKENS: i32 = 512;
+
+const SUMMARY_PROMPT: &str = "You compact conversations between a user and an assistant. \
+Write a concise summary of the conversation you are given, keeping names, numbers, \
+decisions, open questions and anything the user asked to be remembered. If there is \
+an existing summary, merge it into the new one. Reply with the summary only.";
+
+// The summary of the older turns, if there is one, and the chats after it.
+pub async fn history(
+    transaction: &Transaction<'_>,
+    prompt: &prompts::SinglePrompt,
+    conversation_id: i64,
+) -> Result<(Option<String>, Vec<Chat>), CustomError> {
+    let limit = prompt.max_history_items as i64;
+
+    if prompt.history_strategy == HistoryStrategy::Trim {
+        let chats = chats::chat_history()
+            .bind(transaction, &conversation_id, &limit)
+            .all()
+            .await?;
+        return Ok((None, chats));
+    }
+
+    let summary = conversations::history_summary()
+        .bind(transaction, &conversation_id)
+        .one()
+        .await?;
+    let chats = chats::chat_history_after()
+        .bind(
+            transaction,
+            &conversation_id,
+            &summary.chat_id.unwrap_or(0),
+            &limit,
+        )
+        .all()
+        .await?;
+
+    Ok((summary.summary, chats))
+}
+
+// The older turns to fold into the summary and where to send them
+struct Compaction {
+    conversation_id: i64,
+    last_chat_id: i32,
+    prompt_tokens: i32,
+    routes: Vec<ChatRoute>,
+}
+
+// Summarise the oldest turns of the chat's conversation if the history no
+// longer fits the context. The summary model can take a while so it's called
+// outside a transaction, the chat is created once we're done.
+pub async fn compact(pool: &Pool, sub: &str, chat_id: i32) -> Result<(), CustomError> {
+    let Some(compaction) = plan(pool, sub, chat_id).await? else {
+        return Ok(());
+    };
+
+    tracing::info!(
+        "Summarising conversation {} up to chat {}",
+        compaction.conversation_id,
+        compaction.last_chat_id
+    );
+
+    // Carry on without compacting, the prompt will trim the history instead
+    let (summary, model_id, usage) = match summarise(compaction.routes).await {
+        Ok(summary) => summary,
+        Err(e) => {
+            tracing::error!("Unable to summarise the conversation history: {}", e);
+            return Ok(());
+        }
+    };
+
+    let mut db_client = pool.get().await?;
+    let transaction = db_client.transaction().await?;
+    db::authz::set_row_level_security_user_id(&transaction, sub.to_string()).await?;
+
+    conversations::set_history_summary()
+        .bind(
+            &transaction,
+            &summary,
+            &compaction.last_chat_id,
+            &compaction.conversation_id,
+        )
+        .await?;
+
+    // The summary is usage like any other and counts towards the user's limit
+    let (prompt_tokens, prompt_source) = match usage.and_then(|usage| usage.prompt_tokens) {
+        Some(tokens) => (tokens as i32, TokenUsageSource::Reported),
+        None => (compaction.prompt_tokens, TokenUsageSource::Estimated),
+    };
+    let (completion_tokens, completion_source) =
+        usage::completion_tokens(&transaction, Some(model_id), usage, &summary).await;
+    for (token_type, tokens, source) in [
+        (TokenUsageType::Prompt, prompt_tokens, prompt_source),
+        (
+            TokenUsageType::Completion,
+            completion_tokens,
+            completion_source,
+        ),
+    ] {
+        token_usage_metrics::create_token_usage_metric()
+            .bind(
+                &transaction,
+                &Some(chat_id),
+                &None::<i32>, // api_key_id
+                &token_type,
+                &tokens,
+                &None::<i32>, // duration_ms
+                &source,
+                &Some(model_id),
+            )
+            .one()
+            .await?;
+    }
+
+    transaction.commit().await?;
+    Ok(())
+}
+
+// Work out whether the history needs compacting and build the request that
+// summarises it. None when it fits or the user is over their limit.
+async fn plan(pool: &Pool, sub: &str, chat_id: i32) -> Result<Option<Compaction>, CustomError> {
+    let mut db_client = pool.get().await?;
+    let transaction = db_client.transaction().await?;
+    db::authz::set_row_level_security_user_id(&transaction, sub.to_string()).await?;
+
+    let chat = chats::chat().bind(&transaction, &chat_id).one().await?;
+    let conversation = conversations::get_conversation_from_chat()
+        .bind(&transaction, &chat_id)
+        .one()
+        .await?;
+    let prompt = prompts::prompt()
+        .bind(&transaction, &chat.prompt_id, &conversation.team_id)
+        .one()
+        .await?;
+
+    if prompt.history_strategy == HistoryStrategy::Trim {
+        return Ok(None);
+    }
+
+    let (previous_summary, chats) = history(&transaction, &prompt, conversation.id).await?;
+
+    let budget = size_allowed(
+        prompt.model_context_size as usize,
+        prompt.max_tokens as usize,
+        (prompt.trim_ratio as f32) / 100.0,
+    );
+    let tokenizer = &prompt.tokenizer;
+    let fixed = token_count(prompt.system_prompt.as_deref(), tokenizer)
+        + token_count(previous_summary.as_deref(), tokenizer);
+    let sizes: Vec<usize> = chats
+        .iter()
+        .map(|chat| chat_size(chat, tokenizer))
+        .collect();
+
+    if fixed + sizes.iter().sum::<usize>() < budget {
+        return Ok(None);
+    }
+
+    let keep = (budget.saturating_sub(fixed) as f32 * KEEP_RATIO) as usize;
+    let split = split_point(&chats, &sizes, keep);
+    if split == 0 {
+        return Ok(None);
+    }
+
+    let model_id = prompt.summary_model_id.unwrap_or(prompt.model_id);
+    let limit = limits::check_limit(
+        &transaction,
+        model_id,
+        conversation.user_id,
+        conversation.team_id,
+    )
+    .await?;
+    if limit.is_some() {
+        tracing::info!(
+            "Not summarising conversation {}, the summary model is over its limit",
+            conversation.id
+        );
+        return Ok(None);
+    }
+
+    let model = models::model().bind(&transaction, &model_id).one().await?;
+    let summarised = &chats[..split];
+    let messages = summary_messages(previous_summary.as_deref(), summarised);
+    let prompt_tokens = openai_api::token_count_with(messages.clone(), &model.tokenizer);
+
+    let completion = BionicChatCompletionRequest {
+        model: model.name,
+        stream: Some(false),
+        max_tokens: Some(SUMMARY_MAX_TOKENS),
+        temperature: Some(0.0),
+        messages,
+        tools: None,
+        tool_choice: None,
+        stream_options: None,
+    };
+    let routes = fallback::chat_routes(&transaction, model_id, &completion).await?;
+
+    transaction.commit().await?;
+
+    Ok(Some(Compaction {
+        conversation_id: conversation.id,
+        last_chat_id: summarised.last().map(|chat| chat.id).unwrap_or_default(),
+        prompt_tokens,
+        routes,
+    }))
+}
+
+// The index of the first chat to keep. We keep the newest chats that fit in
+// `keep` tokens, always including the latest user chat, and start on a user
+// chat so tool results aren't separated from the call that asked for them.
+fn split_point(chats: &[Chat], sizes: &[usize], keep: usize) -> usize {
+    let Some(last_user) = chats.iter().rposition(|chat| chat.role == ChatRole::User) else {
+        return 0;
+    };
+
+    let mut split = last_user;
+    let mut size: usize = sizes[last_user..].iter().sum();
+    for index in (0..last_user).rev() {
+        size += sizes[index];
+        if size > keep {
+            break;
+        }
+        if chats[index].role == ChatRole::User {
+            split = index;
+        }
+    }
+    split
+}
+
+fn summary_messages(previous_summary: Option<&str>, chats: &[Chat]) -> Vec<ChatCompletionMessage> {
+    let mut conversation = String::new();
+    if let Some(summary) = previous_summary {
+        conversation.push_str(&format!("Existing summary:\n{}\n\n", summary));
+    }
+    conversation.push_str("Conversation:\n");
+    conversation.push_str(&transcript(chats));
+
+    vec![
+        message(
+            ChatCompletionMessageRole::System,
+            SUMMARY_PROMPT.to_string(),
+        ),
+        message(ChatCompletionMessageRole::User, conversation),
+    ]
+}
+
+// The summary, the model that wrote it and the usage it reported
+async fn summarise(
+    routes: Vec<ChatRoute>,
+) -> Result<(String, i32, Option<openai_api::Usage>), CustomError> {
+    // Hold the endpoint's in flight guard until the body is read
+    let (response, model_id, _in_flight) = fallback::send(routes)
+        .await
+        .map_err(|e| CustomError::ExternalApi(e.to_string()))?;
+    let body = response.bytes().await?;
+
+    let completion = serde_json::from_slice::<ChatCompletion>(&body)
+        .map_err(|e| CustomError::ExternalApi(e.to_string()))?;
+    let usage = completion.usage;
+    completion
+        .choices
+        .into_iter()
+        .next()
+        .and_then(|choice| choice.message.text())
+        .filter(|summary| !summary.trim().is_empty())
+        .map(|summary| (summary, model_id, usage))
+        .ok_or_else(|| CustomError::ExternalApi("The model didn't return a summary".to_string()))
+}
+
+// The chats as plain text, tool calls without a reply are left out
+fn transcript(chats: &[Chat]) -> String {
+    chats
+        .iter()
+        .filter_map(|chat| {
+            let content = chat.content.as_deref()?;
+            let role = match chat.role {
+                ChatRole::User => "User",
+                ChatRole::Assistant => "Assistant",
+                ChatRole::Tool => "Tool",
+                ChatRole::System | ChatRole::Developer => "System",
+            };
+            Some(format!("{}: {}\n", role, content))
+        })
+        .collect()
+}
+
+fn message(role: ChatCompletionMessageRole, content: String) -> ChatCompletionMessage {
+    ChatCompletionMessage {
+        role,
+        content: Some(content.into()),
+        tool_call_id: None,
+        tool_calls: None,
+        name: None,
+    }
+}
+
+fn chat_size(chat: &Chat, tokenizer: &str) -> usize {
+    openai_api::token_count_with(convert_chat_to_messages(vec![chat.clone()]), tokenizer) as usize
+}
+
+fn token_count(text: Option<&str>, tokenizer: &str) -> usize {
+    text.map(|text| openai_api::token_count_from_string_with(text, tokenizer) as usize)
+        .unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use db::ChatStatus;
+    use time::OffsetDateTime;
+
+    fn chat(id: i32, role: ChatRole) -> Chat {
+        Chat {
+            role,
+            id,
+            conversation_id: 0,
+            parent_id: None,
+            content: Some(format!("chat {}", id)),
+            tool_call_id: None,
+            tool_calls: None,
+            prompt_id: 0,
+            model_name: "gpt-4".to_string(),
+            attachments: None,
+            status: ChatStatus::Success,
+            created_at: OffsetDateTime::UNIX_EPOCH,
+            updated_at: OffsetDateTime::UNIX_EPOCH,
+        }
+    }
+
+    #[test]
+    fn test_split_keeps_newest_turns_that_fit() {
+        let chats = vec![
+            chat(1, ChatRole::User),
+            chat(2, ChatRole::Assistant),
+            chat(3, ChatRole::User),
+            chat(4, ChatRole::Assistant),
+            chat(5, ChatRole::User),
+        ];
+        let sizes = vec![10; 5];
+
+        assert_eq!(split_point(&chats, &sizes, 30), 2);
+        assert_eq!(split_point(&chats, &sizes, 100), 0);
+    }
+
+    #[test]
+    fn test_split_starts_on_a_user_chat() {
+        let chats = vec![
+            chat(1, ChatRole::User),
+            chat(2, ChatRole::Assistant),
+            chat(3, ChatRole::Tool),
+            chat(4, ChatRole::Assistant),
+            chat(5, ChatRole::User),
+        ];
+        let sizes = vec![10; 5];
+
+        // The tool round fits but its user chat doesn't, so it goes too
+        assert_eq!(split_point(&chats, &sizes, 40), 4);
+    }
+
+    #[test]
+    fn test_split_always_keeps_the_question() {
+        let chats = vec![
+            chat(1, ChatRole::User),
+            chat(2, ChatRole::Assistant),
+            chat(3, ChatRole::User),
+        ];
+        let sizes = vec![10, 10, 1000];
+
+        assert_eq!(split_point(&chats, &sizes, 10), 2);
+    }
+
+    #[test]
+    fn test_transcript() {
+        let mut tool_call = chat(2, ChatRole::Assistant);
+        tool_call.content = None;
+        let chats = vec![chat(1, ChatRole::User), tool_call, chat(3, ChatRole::Tool)];
+
+        assert_eq!(transcript(&chats), "User: chat 1\nTool: chat 3\n");
+    }
+}
@@ -1,17 +1,28 @@
 pub mod api_chat_stream;
 pub mod api_reverse_proxy;
+pub mod api_tool_loop;
+pub mod automations;
 mod chat_converter;
+mod citations;
+pub mod cron;
+pub mod endpoints;
 mod errors;
+pub mod fallback;
+mod generations;
+mod history_compaction;
 mod jwt;
 pub mod limits;
 pub mod moderation;
 mod prompt;
+pub mod providers;
 pub mod sse_chat_enricher;
 pub mod sse_chat_error;
 pub mod synthesize;
 #[cfg(test)]
 mod tests;
+pub mod transcribe;
 pub mod ui_chat_stream;
+mod usage;
 pub mod user_config;
 use axum::Router;
 use axum_extra::routing::RouterExt;
@@ -32,10 +43,13 @@ pub fn routes() -> Router {
         .typed_get(api_chat_stream::chat_generate)
         .typed_post(api_chat_stream::chat_generate)
         .typed_post(synthesize::synthesize)
+        .typed_post(transcribe::transcribe)
+        .typed_post(transcribe::api_transcribe)
         .typed_get(api_reverse_proxy::handler)
         .typed_post(api_reverse_proxy::handler)
         .typed_post(ui_chat_stream::chat_generate)
         .typed_get(ui_chat_stream::chat_generate)
+        .typed_post(ui_chat_stream::stop_generation)
         .layer(cors) // Apply the CORS layer
 }
 
@@ -45,10 +59,26 @@ pub struct UICompletions {
     pub chat_id: i32,
 }
 
+#[derive(TypedPath, Deserialize)]
+#[typed_path("/completions/{chat_id}/stop")]
+pub struct UIStopCompletion {
+    pub chat_id: i32,
+}
+
 #[derive(TypedPath, Deserialize)]
 #[typed_path("/app/synthesize")]
 pub struct UISynthesize {}
 
+#[derive(TypedPath, Deserialize)]
+#[typed_path("/app/team/{team_id}/transcribe")]
+pub struct UITranscribe {
+    pub team_id: i32,
+}
+
+#[derive(TypedPath, Deserialize)]
+#[typed_path("/v1/audio/transcriptions")]
+pub struct ApiTranscriptionsHandler {}
+
 #[derive(TypedPath, Deserialize)]
 #[typed_path("/v1/{*path}")]
 pub struct LLMHandler {
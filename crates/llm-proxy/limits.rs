@@ -1,42 +1,165 @@
-use db::{
-    queries::{inference_metrics, models},
-    Pool, Transaction,
-};
+use axum::response::{IntoResponse, Response};
+use db::{queries::rate_limits, Pool, Transaction};
+use http::{header::RETRY_AFTER, HeaderMap, HeaderValue, StatusCode};
 
 use crate::errors::CustomError;
 
-// Fetch the usage stats so far and compare with the limits
-// if we have gone over the limits return true
-pub async fn is_limit_exceeded(
+// Usage is measured over a rolling hour
+const WINDOW_SECONDS: i32 = 60 * 60;
+
+// The hourly budget that applies to a user and model and how much of it
+// has been used.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RateLimitStatus {
+    pub tokens_per_hour: i64,
+    pub tokens_used: i64,
+    // Seconds until the oldest usage drops out of the window
+    pub reset_seconds: i32,
+}
+
+impl RateLimitStatus {
+    pub fn is_exceeded(&self) -> bool {
+        self.tokens_used >= self.tokens_per_hour
+    }
+
+    pub fn remaining(&self) -> i64 {
+        (self.tokens_per_hour - self.tokens_used).max(0)
+    }
+
+    // The same headers OpenAI sends so existing clients know when to back off.
+    pub fn headers(&self) -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        let reset = self.reset_seconds.clamp(1, WINDOW_SECONDS);
+
+        headers.insert(
+            "x-ratelimit-limit-tokens",
+            HeaderValue::from(self.tokens_per_hour),
+        );
+        headers.insert(
+            "x-ratelimit-remaining-tokens",
+            HeaderValue::from(self.remaining()),
+        );
+        if let Ok(value) = HeaderValue::from_str(&format!("{}s", reset)) {
+            headers.insert("x-ratelimit-reset-tokens", value);
+        }
+        if self.is_exceeded() {
+            headers.insert(RETRY_AFTER, HeaderValue::from(reset));
+        }
+        headers
+    }
+
+    // The error OpenAI returns, also sent as an event once a stream has started
+    pub fn error_body(&self) -> serde_json::Value {
+        serde_json::json!({
+            "error": {
+                "message": format!(
+                    "Rate limit reached, you have used {} of {} tokens this hour",
+                    self.tokens_used, self.tokens_per_hour
+                ),
+                "type": "tokens",
+                "code": "rate_limit_exceeded"
+            }
+        })
+    }
+}
+
+// A 429 for API clients
+impl IntoResponse for RateLimitStatus {
+    fn into_response(self) -> Response {
+        (
+            StatusCode::TOO_MANY_REQUESTS,
+            self.headers(),
+            axum::Json(self.error_body()),
+        )
+            .into_response()
+    }
+}
+
+// Find the most specific limit for this user and model and work out how
+// much of it has been used. Returns None when no limit applies.
+pub async fn get_limit_status(
     transaction: &Transaction<'_>,
     model_id: i32,
     user_id: i32,
-) -> Result<bool, CustomError> {
-    // Get the inference metrics
-    let inference_metric = inference_metrics::inference_metric()
-        .bind(transaction, &model_id, &user_id)
-        .one()
+    team_id: i32,
+) -> Result<Option<RateLimitStatus>, CustomError> {
+    let limit = rate_limits::matching_rate_limit()
+        .bind(transaction, &model_id, &user_id, &team_id)
+        .opt()
         .await?;
 
-    let total_tokens = inference_metric.tpm_recv + inference_metric.tpm_sent;
+    Ok(limit.map(|limit| RateLimitStatus {
+        tokens_per_hour: limit.tokens_per_hour as i64,
+        tokens_used: limit.tokens_used,
+        reset_seconds: limit.reset_seconds.unwrap_or(WINDOW_SECONDS),
+    }))
+}
 
-    let model = models::model().bind(transaction, &model_id).one().await?;
+// Fetch the usage stats so far and compare with the limits
+// if we have gone over the limits return the status so callers can tell
+// the user when to try again.
+pub async fn check_limit(
+    transaction: &Transaction<'_>,
+    model_id: i32,
+    user_id: i32,
+    team_id: i32,
+) -> Result<Option<RateLimitStatus>, CustomError> {
+    let status = get_limit_status(transaction, model_id, user_id, team_id).await?;
 
-    if (model.tpm_limit as i64) > total_tokens {
-        Ok(false)
-    } else {
-        tracing::warn!("Restricting user {} for model {}", user_id, model_id);
-        Ok(true)
+    match status {
+        Some(status) if status.is_exceeded() => {
+            tracing::warn!("Restricting user {} for model {}", user_id, model_id);
+            Ok(Some(status))
+        }
+        _ => Ok(None),
     }
 }
 
-pub async fn is_limit_exceeded_from_pool(
+pub async fn check_limit_from_pool(
     pool: &Pool,
     model_id: i32,
     user_id: i32,
-) -> Result<bool, CustomError> {
+    team_id: i32,
+) -> Result<Option<RateLimitStatus>, CustomError> {
     let mut db_client = pool.get().await?;
     let transaction = db_client.transaction().await?;
 
-    is_limit_exceeded(&transaction, model_id, user_id).await
+    check_limit(&transaction, model_id, user_id, team_id).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_rate_limit_headers() {
+        let status = RateLimitStatus {
+            tokens_per_hour: 1000,
+            tokens_used: 1200,
+            reset_seconds: 90,
+        };
+        assert!(status.is_exceeded());
+        assert_eq!(status.remaining(), 0);
+
+        let headers = status.headers();
+        assert_eq!(headers["x-ratelimit-limit-tokens"], "1000");
+        assert_eq!(headers["x-ratelimit-remaining-tokens"], "0");
+        assert_eq!(headers["x-ratelimit-reset-tokens"], "90s");
+        assert_eq!(headers[RETRY_AFTER], "90");
+    }
+
+    #[test]
+    fn test_under_limit_has_no_retry_after() {
+        let status = RateLimitStatus {
+            tokens_per_hour: 1000,
+            tokens_used: 250,
+            reset_seconds: 0,
+        };
+        assert!(!status.is_exceeded());
+
+        let headers = status.headers();
+        assert_eq!(headers["x-ratelimit-remaining-tokens"], "750");
+        assert_eq!(headers["x-ratelimit-reset-tokens"], "1s");
+        assert!(headers.get(RETRY_AFTER).is_none());
+    }
 }
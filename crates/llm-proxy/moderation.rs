@@ -7,9 +7,10 @@ use reqwest::{
 use serde::Deserialize;
 
 /// Remove tool call related data from chat messages and drop any
-/// messages that were solely used for tool calling.
+/// messages that were solely used for tool calling. Images are left out as
+/// guard models only read text.
 pub fn strip_tool_data(messages: &[ChatCompletionMessage]) -> Vec<ChatCompletionMessage> {
-    use openai_api::ChatCompletionMessageRole;
+    use openai_api::{ChatCompletionContent, ChatCompletionMessageRole};
 
     messages
         .iter()
@@ -27,7 +28,7 @@ pub fn strip_tool_data(messages: &[ChatCompletionMessage]) -> Vec<ChatCompletion
             // Drop assistant messages that had only tool calls and no content.
             if had_tool_calls
                 && m.role == ChatCompletionMessageRole::Assistant
-                && m.content.as_deref().map(str::trim).unwrap_or("").is_empty()
+                && m.text().as_deref().map(str::trim).unwrap_or("").is_empty()
             {
                 return None;
             }
@@ -36,6 +37,10 @@ pub fn strip_tool_data(messages: &[ChatCompletionMessage]) -> Vec<ChatCompletion
                 m.role = ChatCompletionMessageRole::User;
             }
 
+            if let Some(ChatCompletionContent::Parts(_)) = m.content {
+                m.content = m.text().map(ChatCompletionContent::Text);
+            }
+
             Some(m)
         })
         .collect()
@@ -65,6 +70,7 @@ pub async fn moderate_chat(
         temperature: None,
         tools: None,
         tool_choice: None,
+        stream_options: None,
     };
 
     let client = reqwest::Client::new();
@@ -97,7 +103,7 @@ pub async fn moderate_chat(
 
     let content = choices
         .first()
-        .and_then(|c| c.message.content.clone())
+        .and_then(|c| c.message.text())
         .unwrap_or_default();
     let content = content.trim();
 
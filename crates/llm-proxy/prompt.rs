@@ -1,26 +1,41 @@
 use crate::errors::CustomError;
 use db::queries::{chats_chunks, prompt_integrations, prompts};
 use db::{RelatedContext, Transaction};
-use integrations::{create_tools_from_integrations, get_tools, ToolScope};
+use integrations::{
+    candidate_pool_size, create_tools_from_integrations, get_tools, rerank_related_context,
+    ToolScope,
+};
 use openai_api::{BionicToolDefinition, ChatCompletionMessage, ChatCompletionMessageRole};
 use std::env;
 
+const SUMMARY_INTRODUCTION: &str = "Summary of the earlier part of this conversation:";
+
+// Each section of context is numbered so the model can cite where its answer
+// came from.
+const CONTEXT_INSTRUCTIONS: &str = "Context information is below. Each section starts with its \
+number in square brackets. When you use a section, cite its number like this [1].
+--------------------
+{context_str}
+--------------------";
+
 // If we are getting called from the API we'll possible have a buch of chat messaages
 // that's why chat is a Vec<Message>
 // For the UI they'll be just one.
+// Returns the messages and the chunks in the context, `[1]` is the first chunk.
 pub async fn execute_prompt(
     transaction: &Transaction<'_>,
     prompt: prompts::SinglePrompt,
     conversation_id: Option<i64>,
+    history_summary: Option<String>,
     chat_history: Vec<ChatCompletionMessage>,
-) -> Result<Vec<ChatCompletionMessage>, CustomError> {
+) -> Result<(Vec<ChatCompletionMessage>, Vec<i32>), CustomError> {
     // Find the most recent user message. The last message may be a tool
     // response, so we search backwards for a message from the user.
     let question = chat_history
         .iter()
         .rev()
         .find(|m| m.role == ChatCompletionMessageRole::User)
-        .and_then(|m| m.content.clone())
+        .and_then(|m| m.text())
         .unwrap_or_default();
 
     // Determine if we should use legacy RAG vector search
@@ -37,7 +52,9 @@ pub async fn execute_prompt(
         prompt.model_context_size as usize,
         prompt.max_tokens as usize,
         trim_ratio,
+        &prompt.tokenizer,
         prompt.system_prompt,
+        history_summary,
         chat_history,
         related_context,
     )
@@ -46,14 +63,14 @@ pub async fn execute_prompt(
     // Store the id's of the chunks we used for this particular chat
     // We assume, given a list that the last item is the one used for lookup
     if let Some(id) = conversation_id {
-        for chunk_id in chunk_ids {
+        for (index, chunk_id) in chunk_ids.iter().enumerate() {
             chats_chunks::create_chunks_chats()
-                .bind(transaction, &chunk_id, &id)
+                .bind(transaction, chunk_id, &id, &(index as i32 + 1))
                 .await?;
         }
     }
 
-    Ok(messages)
+    Ok((messages, chunk_ids))
 }
 
 /// Get integration tools for a specific prompt
@@ -106,7 +123,9 @@ pub async fn generate_prompt(
     model_context_size: usize,
     max_tokens: usize,
     trim_ratio: f32,
+    tokenizer: &str,
     system_prompt: Option<String>,
+    history_summary: Option<String>,
     history: Vec<ChatCompletionMessage>,
     related_context: Vec<RelatedContext>,
 ) -> (Vec<ChatCompletionMessage>, Vec<i32>) {
@@ -115,26 +134,14 @@ pub async fn generate_prompt(
     let mut chunk_ids: Vec<i32> = Default::default();
 
     let system_prompt = match (system_prompt, related_context.is_empty()) {
-        (Some(prompt), false) => {
-            Some(format!("{}\n\nContext information is below.\n--------------------\n{{context_str}}\n--------------------", prompt))
-        }
-        (Some(prompt), true) => {
-            Some(prompt)
-        }
-        (None, false) => {
-            Some("Context information is below.\n--------------------\n{{context_str}}\n--------------------".to_string())
-        }
-        (None, true) => {
-            None
-        }
+        (Some(prompt), false) => Some(format!("{}\n\n{}", prompt, CONTEXT_INSTRUCTIONS)),
+        (Some(prompt), true) => Some(prompt),
+        (None, false) => Some(CONTEXT_INSTRUCTIONS.to_string()),
+        (None, true) => None,
     };
 
     // This is the space we have to fill
-    let size_allowed = if max_tokens < model_context_size {
-        ((model_context_size - max_tokens) as f32 * trim_ratio) as usize
-    } else {
-        model_context_size
-    };
+    let size_allowed = size_allowed(model_context_size, max_tokens, trim_ratio);
 
     tracing::info!("Using context size of {}", size_allowed);
 
@@ -146,13 +153,31 @@ pub async fn generate_prompt(
             &mut messages,
             ChatCompletionMessage {
                 role: ChatCompletionMessageRole::System,
-                content: Some(system_prompt.clone()),
+                content: Some(system_prompt.clone().into()),
+                tool_call_id: None,
+                tool_calls: None,
+                name: None,
+            },
+            size_so_far,
+            size_allowed,
+            tokenizer,
+        );
+    }
+
+    // The turns that were compacted away come before what's left of the history
+    if let Some(summary) = history_summary {
+        size_so_far = add_message(
+            &mut messages,
+            ChatCompletionMessage {
+                role: ChatCompletionMessageRole::System,
+                content: Some(format!("{}\n\n{}", SUMMARY_INTRODUCTION, summary).into()),
                 tool_call_id: None,
                 tool_calls: None,
                 name: None,
             },
             size_so_far,
             size_allowed,
+            tokenizer,
         );
     }
 
@@ -168,15 +193,19 @@ pub async fn generate_prompt(
         // Add some relevant context
         if let Some(rel_context) = related_context.pop() {
             let size_rel_context =
-                openai_api::token_count_from_string(&rel_context.chunk_text) as usize;
+                openai_api::token_count_from_string_with(&rel_context.chunk_text, tokenizer)
+                    as usize;
 
             if size_so_far + size_rel_context < size_allowed {
-                context_so_far.push_str(&rel_context.chunk_text);
                 chunk_ids.push(rel_context.chunk_id);
-                context_so_far += "\n";
+                context_so_far.push_str(&format!(
+                    "[{}] {}\n",
+                    chunk_ids.len(),
+                    rel_context.chunk_text
+                ));
                 if let Some(prompt) = &system_prompt {
                     let replaced = prompt.replace("{context_str}", &context_so_far);
-                    messages[0].content = Some(replaced);
+                    messages[0].content = Some(replaced.into());
                 }
                 size_so_far += size_rel_context;
             }
@@ -184,7 +213,13 @@ pub async fn generate_prompt(
 
         // Expand all the chats we have into the corresponding Messages
         if let Some(hist) = history.pop() {
-            size_so_far = add_message(&mut history_messages, hist, size_so_far, size_allowed);
+            size_so_far = add_message(
+                &mut history_messages,
+                hist,
+                size_so_far,
+                size_allowed,
+                tokenizer,
+            );
         }
 
         if history.is_empty() && related_context.is_empty() {
@@ -200,14 +235,25 @@ pub async fn generate_prompt(
     (messages, chunk_ids)
 }
 
+// The number of tokens the prompt and history can take up
+pub fn size_allowed(model_context_size: usize, max_tokens: usize, trim_ratio: f32) -> usize {
+    if max_tokens < model_context_size {
+        ((model_context_size - max_tokens) as f32 * trim_ratio) as usize
+    } else {
+        model_context_size
+    }
+}
+
 // Only add a message if the context doesn't overflow
 fn add_message(
     messages: &mut Vec<ChatCompletionMessage>,
     message_to_add: ChatCompletionMessage,
     size_so_far: usize,
     size_allowed: usize,
+    tokenizer: &str,
 ) -> usize {
-    let size: usize = openai_api::token_count(vec![message_to_add.clone()]) as usize;
+    let size: usize =
+        openai_api::token_count_with(vec![message_to_add.clone()], tokenizer) as usize;
 
     if (size + size_so_far) < size_allowed {
         messages.push(message_to_add);
@@ -223,15 +269,20 @@ async fn legacy_related_context(
     question: &str,
 ) -> Result<Vec<RelatedContext>, CustomError> {
     let mut related_context = Vec::new();
-    if let (Some(embeddings_base_url), Some(embeddings_model)) = (
+    if let (Some(embeddings_base_url), Some(embeddings_model), Some(embeddings_model_id)) = (
         prompt.embeddings_base_url.clone(),
         prompt.embeddings_model.clone(),
+        prompt.embeddings_model_id,
     ) {
         let embeddings = embeddings_api::get_embeddings(
             question,
             &embeddings_base_url,
             &embeddings_model,
             prompt.embeddings_context_size.unwrap_or(256),
+            prompt
+                .embeddings_tokenizer
+                .as_deref()
+                .unwrap_or(openai_api::tokenizer::DEFAULT_TOKENIZER),
             &prompt.embeddings_api_key,
         )
         .await
@@ -245,8 +296,30 @@ async fn legacy_related_context(
         })?;
 
         tracing::info!(prompt.name);
-        related_context =
-            db::get_related_context(transaction, prompt.id, prompt.max_chunks, embeddings).await?;
+        let candidates = candidate_pool_size(
+            prompt.rerank_enabled,
+            prompt.rerank_pool_size,
+            prompt.max_chunks,
+        );
+        related_context = db::get_related_context(
+            transaction,
+            prompt.id,
+            candidates,
+            question,
+            embeddings,
+            embeddings_model_id,
+        )
+        .await?;
+
+        if prompt.rerank_enabled {
+            related_context = rerank_related_context(
+                transaction,
+                question,
+                related_context,
+                prompt.max_chunks as usize,
+            )
+            .await?;
+        }
         tracing::info!("Retrieved {} chunks", related_context.len());
     }
 
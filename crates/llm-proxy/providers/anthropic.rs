@@ -0,0 +1,595 @@
+//! The Anthropic Messages API.
+//!
+//! System messages move to the top level `system` field, tool calls become
+//! `tool_use` blocks and tool results `tool_result` blocks in a user turn.
+//! The stream is a series of named events which we turn back into OpenAI
+//! chunks, tool input arrives as partial JSON just like OpenAI arguments.
+
+use std::collections::HashMap;
+
+use openai_api::{
+    BionicChatCompletionRequest, ChatCompletionContent, ChatCompletionContentPart,
+    ChatCompletionMessage, ChatCompletionMessageRole,
+};
+use reqwest::{Client, RequestBuilder};
+use serde::Serialize;
+use serde_json::{json, Value};
+
+use super::{completion_chunk, to_json, unix_now, usage};
+use crate::endpoints::Endpoint;
+use crate::fallback::FallbackError;
+
+const ANTHROPIC_VERSION: &str = "2023-06-01";
+
+// Anthropic won't take a request without max_tokens
+const DEFAULT_MAX_TOKENS: i32 = 4096;
+
+// Anthropic wants the user to go first, which they don't when the history was
+// trimmed back to an answer
+const FIRST_USER_TURN: &str = "Continue the conversation.";
+
+#[derive(Serialize, Debug)]
+pub struct MessagesRequest {
+    pub model: String,
+    pub max_tokens: i32,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub system: Option<String>,
+    pub messages: Vec<Value>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub temperature: Option<f32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub stream: Option<bool>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub tools: Vec<Value>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub tool_choice: Option<Value>,
+}
+
+pub fn chat_request(
+    client: &Client,
+    endpoint: &Endpoint,
+    completion: &BionicChatCompletionRequest,
+) -> Result<RequestBuilder, FallbackError> {
+    let mut request = client
+        .post(format!("{}/messages", endpoint.base_url))
+        .header("anthropic-version", ANTHROPIC_VERSION)
+        .body(to_json(&messages_request(completion))?);
+    if let Some(api_key) = &endpoint.api_key {
+        request = request.header("x-api-key", api_key);
+    }
+    Ok(request)
+}
+
+pub fn messages_request(completion: &BionicChatCompletionRequest) -> MessagesRequest {
+    let mut system: Vec<String> = Vec::new();
+    let mut messages: Vec<Value> = Vec::new();
+
+    for message in &completion.messages {
+        let (role, blocks) = match message.role {
+            ChatCompletionMessageRole::System | ChatCompletionMessageRole::Developer => {
+                if let Some(content) = message.text() {
+                    system.push(content);
+                }
+                continue;
+            }
+            ChatCompletionMessageRole::Assistant => ("assistant", assistant_blocks(message)),
+            ChatCompletionMessageRole::Tool => (
+                "user",
+                vec![json!({
+                    "type": "tool_result",
+                    "tool_use_id": message.tool_call_id.clone().unwrap_or_default(),
+                    "content": message.text().unwrap_or_default(),
+                })],
+            ),
+            ChatCompletionMessageRole::User | ChatCompletionMessageRole::Function => {
+                ("user", text_blocks(&message.content))
+            }
+        };
+        if blocks.is_empty() {
+            continue;
+        }
+
+        // The roles have to alternate so join up turns from the same side,
+        // i.e. the results of parallel tool calls.
+        match messages.last_mut() {
+            Some(last) if last["role"] == role => {
+                if let Some(content) = last["content"].as_array_mut() {
+                    content.extend(blocks);
+                }
+            }
+            _ => messages.push(json!({ "role": role, "content": blocks })),
+        }
+    }
+
+    if messages.first().is_none_or(|first| first["role"] != "user") {
+        messages.insert(
+            0,
+            json!({ "role": "user", "content": [{ "type": "text", "text": FIRST_USER_TURN }] }),
+        );
+    }
+
+    let tools: Vec<Value> = completion
+        .tools
+        .iter()
+        .flatten()
+        .map(|tool| {
+            json!({
+                "name": tool.function.name,
+                "description": tool.function.description,
+                "input_schema": tool.function.parameters,
+            })
+        })
+        .collect();
+
+    // A tool choice without tools is an error
+    let tool_choice = if tools.is_empty() {
+        None
+    } else {
+        completion.tool_choice.as_ref().and_then(tool_choice)
+    };
+
+    MessagesRequest {
+        model: completion.model.clone(),
+        max_tokens: completion.max_tokens.unwrap_or(DEFAULT_MAX_TOKENS),
+        system: (!system.is_empty()).then(|| system.join("\n\n")),
+        messages,
+        temperature: completion.temperature,
+        stream: completion.stream,
+        tools,
+        tool_choice,
+    }
+}
+
+fn text_blocks(content: &Option<ChatCompletionContent>) -> Vec<Value> {
+    match content {
+        Some(ChatCompletionContent::Text(text)) => text_block(text).into_iter().collect(),
+        Some(ChatCompletionContent::Parts(parts)) => parts
+            .iter()
+            .filter_map(|part| match part {
+                ChatCompletionContentPart::Text { text } => text_block(text),
+                ChatCompletionContentPart::ImageUrl { image_url } => {
+                    // Anthropic wants the base64 split out, remote URLs are passed through
+                    Some(match image_url.base64_data() {
+                        Some((media_type, data)) => json!({
+                            "type": "image",
+                            "source": { "type": "base64", "media_type": media_type, "data": data },
+                        }),
+                        None => json!({
+                            "type": "image",
+                            "source": { "type": "url", "url": image_url.url },
+                        }),
+                    })
+                }
+            })
+            .collect(),
+        None => Vec::new(),
+    }
+}
+
+fn text_block(text: &str) -> Option<Value> {
+    // Empty text blocks are rejected
+    (!text.trim().is_empty()).then(|| json!({ "type": "text", "text": text }))
+}
+
+fn assistant_blocks(message: &ChatCompletionMessage) -> Vec<Value> {
+    let mut blocks = text_blocks(&message.content);
+    for tool_call in message.tool_calls.iter().flatten() {
+        // Models don't always produce valid JSON and the input must be an object
+        let input = serde_json::from_str::<Value>(&tool_call.function.arguments)
+            .ok()
+            .filter(Value::is_object)
+            .unwrap_or_else(|| json!({}));
+        blocks.push(json!({
+            "type": "tool_use",
+            "id": tool_call.id,
+            "name": tool_call.function.name,
+            "input": input,
+        }));
+    }
+    blocks
+}
+
+fn tool_choice(choice: &Value) -> Option<Value> {
+    match choice {
+        Value::String(choice) => match choice.as_str() {
+            "auto" => Some(json!({ "type": "auto" })),
+            "required" => Some(json!({ "type": "any" })),
+            "none" => Some(json!({ "type": "none" })),
+            _ => None,
+        },
+        _ => choice["function"]["name"]
+            .as_str()
+            .map(|name| json!({ "type": "tool", "name": name })),
+    }
+}
+
+fn finish_reason(stop_reason: Option<&str>) -> &'static str {
+    match stop_reason {
+        Some("tool_use") => "tool_calls",
+        Some("max_tokens") => "length",
+        _ => "stop",
+    }
+}
+
+// What we need to remember between stream events
+#[derive(Default, Debug)]
+pub struct StreamState {
+    id: String,
+    model: String,
+    created: u64,
+    prompt_tokens: u64,
+    // Content block index to tool call index
+    tool_calls: HashMap<u64, u64>,
+}
+
+impl StreamState {
+    // Translate one event into zero or more OpenAI chunks
+    pub fn translate(&mut self, event: &str, data: &str) -> Result<Vec<String>, FallbackError> {
+        let data: Value = serde_json::from_str(data).map_err(|error| {
+            FallbackError::Request(format!("Unexpected event from Anthropic: {}", error))
+        })?;
+        // The event name is repeated in the data
+        let event = data["type"].as_str().unwrap_or(event);
+
+        let chunks = match event {
+            "message_start" => {
+                let message = &data["message"];
+                self.id = message["id"].as_str().unwrap_or_default().to_string();
+                self.model = message["model"].as_str().unwrap_or_default().to_string();
+                self.created = unix_now();
+                self.prompt_tokens = message["usage"]["input_tokens"].as_u64().unwrap_or(0);
+                vec![self.chunk(json!({ "role": "assistant", "content": "" }), None, None)]
+            }
+            "content_block_start" if data["content_block"]["type"] == "tool_use" => {
+                let index = self.tool_calls.len() as u64;
+                self.tool_calls
+                    .insert(data["index"].as_u64().unwrap_or(0), index);
+                vec![self.chunk(
+                    json!({
+                        "tool_calls": [{
+                            "index": index,
+                            "id": data["content_block"]["id"],
+                            "type": "function",
+                            "function": {
+                                "name": data["content_block"]["name"],
+                                "arguments": "",
+                            },
+                        }],
+                    }),
+                    None,
+                    None,
+                )]
+            }
+            "content_block_delta" => {
+                let delta = &data["delta"];
+                match delta["type"].as_str() {
+                    Some("text_delta") => {
+                        vec![self.chunk(json!({ "content": delta["text"] }), None, None)]
+                    }
+                    Some("input_json_delta") => {
+                        match self.tool_calls.get(&data["index"].as_u64().unwrap_or(0)) {
+                            Some(index) => vec![self.chunk(
+                                json!({
+                                    "tool_calls": [{
+                                        "index": index,
+                                        "type": "function",
+                                        "function": { "arguments": delta["partial_json"] },
+                                    }],
+                                }),
+                                None,
+                                None,
+                            )],
+                            None => Vec::new(),
+                        }
+                    }
+                    _ => Vec::new(),
+                }
+            }
+            "message_delta" => {
+                let completion_tokens = data["usage"]["output_tokens"].as_u64().unwrap_or(0);
+                vec![self.chunk(
+                    json!({}),
+                    Some(finish_reason(data["delta"]["stop_reason"].as_str())),
+                    Some(usage(self.prompt_tokens, completion_tokens)),
+                )]
+            }
+            "message_stop" => vec!["[DONE]".to_string()],
+            "error" => {
+                return Err(FallbackError::Request(
+                    data["error"]["message"]
+                        .as_str()
+                        .unwrap_or("Anthropic returned an error")
+                        .to_string(),
+                ))
+            }
+            // ping, the start and end of text blocks and anything new
+            _ => Vec::new(),
+        };
+
+        Ok(chunks)
+    }
+
+    fn chunk(&self, delta: Value, finish_reason: Option<&str>, usage: Option<Value>) -> String {
+        completion_chunk(
+            &self.id,
+            &self.model,
+            self.created,
+            delta,
+            finish_reason,
+            usage,
+        )
+    }
+}
+
+// A whole (non streaming) message as an OpenAI chat completion
+pub fn chat_completion(body: &str) -> Result<String, FallbackError> {
+    let message: Value = serde_json::from_str(body).map_err(|error| {
+        FallbackError::Request(format!("Unexpected response from Anthropic: {}", error))
+    })?;
+
+    let mut content = String::new();
+    let mut tool_calls = Vec::new();
+    for block in message["content"].as_array().into_iter().flatten() {
+        match block["type"].as_str() {
+            Some("text") => content.push_str(block["text"].as_str().unwrap_or_default()),
+            Some("tool_use") => tool_calls.push(json!({
+                "id": block["id"],
+                "type": "function",
+                "function": {
+                    "name": block["name"],
+                    "arguments": block["input"].to_string(),
+                },
+            })),
+            _ => {}
+        }
+    }
+
+    let mut assistant = json!({ "role": "assistant", "content": content });
+    if !tool_calls.is_empty() {
+        assistant["tool_calls"] = Value::Array(tool_calls);
+    }
+
+    Ok(json!({
+        "id": message["id"],
+        "object": "chat.completion",
+        "created": unix_now(),
+        "model": message["model"],
+        "choices": [{
+            "index": 0,
+            "message": assistant,
+            "finish_reason": finish_reason(message["stop_reason"].as_str()),
+        }],
+        "usage": usage(
+            message["usage"]["input_tokens"].as_u64().unwrap_or(0),
+            message["usage"]["output_tokens"].as_u64().unwrap_or(0),
+        ),
+    })
+    .to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use openai_api::{BionicToolDefinition, ChatCompletionFunctionDefinition, ToolCall};
+    use openai_api::{ChatCompletionDelta, ToolCallFunction};
+
+    fn message(role: ChatCompletionMessageRole, content: &str) -> ChatCompletionMessage {
+        ChatCompletionMessage {
+            role,
+            content: Some(content.into()),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_messages_request() {
+        let completion = BionicChatCompletionRequest {
+            model: "claude-sonnet-4".to_string(),
+            stream: Some(true),
+            max_tokens: None,
+            messages: vec![
+                message(ChatCompletionMessageRole::System, "Be brief"),
+                message(
+                    ChatCompletionMessageRole::User,
+                    "Weather in Paris and Rome?",
+                ),
+                ChatCompletionMessage {
+                    role: ChatCompletionMessageRole::Assistant,
+                    tool_calls: Some(vec![
+                        ToolCall {
+                            id: "call_1".to_string(),
+                            function: ToolCallFunction {
+                                name: "weather".to_string(),
+                                arguments: r#"{"city":"Paris"}"#.to_string(),
+                            },
+                            ..Default::default()
+                        },
+                        ToolCall {
+                            id: "call_2".to_string(),
+                            function: ToolCallFunction {
+                                name: "weather".to_string(),
+                                arguments: "not json".to_string(),
+                            },
+                            ..Default::default()
+                        },
+                    ]),
+                    ..Default::default()
+                },
+                ChatCompletionMessage {
+                    role: ChatCompletionMessageRole::Tool,
+                    content: Some("Sunny".into()),
+                    tool_call_id: Some("call_1".to_string()),
+                    ..Default::default()
+                },
+                ChatCompletionMessage {
+                    role: ChatCompletionMessageRole::Tool,
+                    content: Some("Rain".into()),
+                    tool_call_id: Some("call_2".to_string()),
+                    ..Default::default()
+                },
+            ],
+            temperature: None,
+            tools: Some(vec![BionicToolDefinition {
+                r#type: "function".to_string(),
+                function: ChatCompletionFunctionDefinition {
+                    name: "weather".to_string(),
+                    description: "Get the weather".to_string(),
+                    parameters: json!({ "type": "object" }),
+                },
+            }]),
+            tool_choice: Some(json!("required")),
+            stream_options: None,
+        };
+
+        let request = serde_json::to_value(messages_request(&completion)).unwrap();
+
+        assert_eq!(request["system"], "Be brief");
+        assert_eq!(request["max_tokens"], DEFAULT_MAX_TOKENS);
+        assert_eq!(request["tool_choice"], json!({ "type": "any" }));
+        assert_eq!(
+            request["tools"][0]["input_schema"],
+            json!({ "type": "object" })
+        );
+
+        let messages = request["messages"].as_array().unwrap();
+        assert_eq!(messages.len(), 3);
+        assert_eq!(
+            messages[1]["content"][0]["input"],
+            json!({ "city": "Paris" })
+        );
+        assert_eq!(messages[1]["content"][1]["input"], json!({}));
+        // Both tool results end up in one user turn
+        assert_eq!(messages[2]["role"], "user");
+        assert_eq!(messages[2]["content"][1]["tool_use_id"], "call_2");
+    }
+
+    #[test]
+    fn test_first_turn_is_user() {
+        let completion = BionicChatCompletionRequest {
+            model: "claude-sonnet-4".to_string(),
+            stream: None,
+            max_tokens: None,
+            messages: vec![
+                message(ChatCompletionMessageRole::System, "Be brief"),
+                message(ChatCompletionMessageRole::Assistant, "Hello"),
+                message(ChatCompletionMessageRole::User, "Hi"),
+            ],
+            temperature: None,
+            tools: None,
+            tool_choice: None,
+            stream_options: None,
+        };
+
+        let request = serde_json::to_value(messages_request(&completion)).unwrap();
+
+        let messages = request["messages"].as_array().unwrap();
+        assert_eq!(messages.len(), 3);
+        assert_eq!(messages[0]["role"], "user");
+        assert_eq!(messages[0]["content"][0]["text"], FIRST_USER_TURN);
+        assert_eq!(messages[1]["role"], "assistant");
+    }
+
+    #[test]
+    fn test_image_blocks() {
+        let content: ChatCompletionContent = serde_json::from_value(json!([
+            { "type": "text", "text": "What is this?" },
+            { "type": "image_url", "image_url": { "url": "data:image/png;base64,iVBORw0KGgo=" } },
+        ]))
+        .unwrap();
+
+        let blocks = text_blocks(&Some(content));
+
+        assert_eq!(blocks.len(), 2);
+        assert_eq!(blocks[1]["source"]["media_type"], "image/png");
+        assert_eq!(blocks[1]["source"]["data"], "iVBORw0KGgo=");
+    }
+
+    #[test]
+    fn test_stream_merges_into_tool_calls() {
+        let events = [
+            (
+                "message_start",
+                r#"{"type":"message_start","message":{"id":"msg_1","model":"claude-sonnet-4","usage":{"input_tokens":12}}}"#,
+            ),
+            (
+                "content_block_start",
+                r#"{"type":"content_block_start","index":0,"content_block":{"type":"text","text":""}}"#,
+            ),
+            (
+                "content_block_delta",
+                r#"{"type":"content_block_delta","index":0,"delta":{"type":"text_delta","text":"Let me check."}}"#,
+            ),
+            ("ping", r#"{"type":"ping"}"#),
+            (
+                "content_block_start",
+                r#"{"type":"content_block_start","index":1,"content_block":{"type":"tool_use","id":"toolu_1","name":"weather","input":{}}}"#,
+            ),
+            (
+                "content_block_delta",
+                r#"{"type":"content_block_delta","index":1,"delta":{"type":"input_json_delta","partial_json":"{\"city\":"}}"#,
+            ),
+            (
+                "content_block_delta",
+                r#"{"type":"content_block_delta","index":1,"delta":{"type":"input_json_delta","partial_json":" \"Paris\"}"}}"#,
+            ),
+            (
+                "message_delta",
+                r#"{"type":"message_delta","delta":{"stop_reason":"tool_use"},"usage":{"output_tokens":30}}"#,
+            ),
+            ("message_stop", r#"{"type":"message_stop"}"#),
+        ];
+
+        let mut state = StreamState::default();
+        let mut chunks = Vec::new();
+        for (event, data) in events {
+            chunks.extend(state.translate(event, data).unwrap());
+        }
+        assert_eq!(chunks.last().unwrap(), "[DONE]");
+
+        let mut merged: Option<ChatCompletionDelta> = None;
+        for chunk in &chunks[..chunks.len() - 1] {
+            let delta: ChatCompletionDelta = serde_json::from_str(chunk).unwrap();
+            match merged.as_mut() {
+                Some(merged) => merged.merge(delta).unwrap(),
+                None => merged = Some(delta),
+            }
+        }
+
+        let merged = merged.unwrap();
+        let choice = &merged.choices[0];
+        assert_eq!(choice.delta.content.as_deref(), Some("Let me check."));
+        let tool_calls = choice.delta.tool_calls.as_ref().unwrap();
+        assert_eq!(tool_calls.len(), 1);
+        assert_eq!(tool_calls[0].id, "toolu_1");
+        assert_eq!(tool_calls[0].function.name, "weather");
+        assert_eq!(tool_calls[0].function.arguments, r#"{"city": "Paris"}"#);
+
+        let last: ChatCompletionDelta = serde_json::from_str(&chunks[chunks.len() - 2]).unwrap();
+        assert_eq!(last.choices[0].finish_reason.as_deref(), Some("tool_calls"));
+        assert_eq!(last.usage.unwrap().total_tokens, Some(42));
+    }
+
+    #[test]
+    fn test_chat_completion() {
+        let body = r#"{
+            "id": "msg_1",
+            "model": "claude-sonnet-4",
+            "content": [
+                {"type": "text", "text": "Hello"},
+                {"type": "tool_use", "id": "toolu_1", "name": "weather", "input": {"city": "Rome"}}
+            ],
+            "stop_reason": "tool_use",
+            "usage": {"input_tokens": 5, "output_tokens": 7}
+        }"#;
+
+        let completion: Value = serde_json::from_str(&chat_completion(body).unwrap()).unwrap();
+        let choice = &completion["choices"][0];
+        assert_eq!(choice["message"]["content"], "Hello");
+        assert_eq!(choice["finish_reason"], "tool_calls");
+        assert_eq!(
+            choice["message"]["tool_calls"][0]["function"]["arguments"],
+            r#"{"city":"Rome"}"#
+        );
+        assert_eq!(completion["usage"]["total_tokens"], 12);
+    }
+}
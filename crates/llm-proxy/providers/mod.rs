@@ -0,0 +1,397 @@
+//! Adapters for model APIs that aren't OpenAI compatible.
+//!
+//! The request goes out in the provider's own format and whatever comes back
+//! is translated into OpenAI chat completions, so the rest of the proxy only
+//! ever sees `ChatCompletionDelta`s and API callers always get OpenAI JSON.
+
+pub mod anthropic;
+pub mod ollama;
+
+use std::collections::VecDeque;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use db::ModelProvider;
+use openai_api::BionicChatCompletionRequest;
+use reqwest::{
+    header::{HeaderValue, AUTHORIZATION, CONTENT_TYPE},
+    Client, RequestBuilder, Response,
+};
+use reqwest_eventsource::{Event, EventSource};
+use serde_json::{json, Value};
+use tokio_stream::StreamExt;
+
+use crate::endpoints::Endpoint;
+use crate::fallback::FallbackError;
+
+// Build the chat request for one endpoint in the provider's format
+pub fn chat_request(
+    client: &Client,
+    provider: ModelProvider,
+    endpoint: &Endpoint,
+    completion: &BionicChatCompletionRequest,
+) -> Result<RequestBuilder, FallbackError> {
+    let request = match provider {
+        ModelProvider::OpenAI => {
+            let request = client
+                .post(format!("{}/chat/completions", endpoint.base_url))
+                .body(to_json(completion)?);
+            match &endpoint.api_key {
+                Some(api_key) => request.header(AUTHORIZATION, format!("Bearer {}", api_key)),
+                None => request,
+            }
+        }
+        ModelProvider::Anthropic => anthropic::chat_request(client, endpoint, completion)?,
+        ModelProvider::Ollama => ollama::chat_request(client, endpoint, completion)?,
+    };
+
+    Ok(request.header(CONTENT_TYPE, HeaderValue::from_static("application/json")))
+}
+
+// Turn a successful non streaming response into an OpenAI chat completion
+pub async fn chat_response(
+    provider: ModelProvider,
+    response: Response,
+) -> Result<Response, FallbackError> {
+    let translate = match provider {
+        ModelProvider::OpenAI => return Ok(response),
+        ModelProvider::Anthropic => anthropic::chat_completion,
+        ModelProvider::Ollama => ollama::chat_completion,
+    };
+
+    let status = response.status();
+    let body = response
+        .text()
+        .await
+        .map_err(|error| FallbackError::Transport(error.to_string()))?;
+    let completion = translate(&body)?;
+
+    let response = http::Response::builder()
+        .status(status)
+        .header(CONTENT_TYPE, HeaderValue::from_static("application/json"))
+        .body(completion)
+        .map_err(|error| FallbackError::Request(error.to_string()))?;
+
+    Ok(Response::from(response))
+}
+
+// The chunks of a streamed chat completion as the JSON an OpenAI compatible
+// model would have sent, ending with "[DONE]".
+pub struct ChatStream {
+    source: Source,
+    pending: VecDeque<String>,
+}
+
+enum Source {
+    OpenAI(EventSource),
+    Anthropic(EventSource, anthropic::StreamState),
+    // Ollama streams a JSON object per line, the buffer holds a partial line
+    Ollama(Response, ollama::StreamState, Vec<u8>),
+}
+
+impl ChatStream {
+    // An event stream that has already been opened
+    pub fn from_event_source(provider: ModelProvider, stream: EventSource) -> Self {
+        let source = match provider {
+            ModelProvider::Anthropic => Source::Anthropic(stream, Default::default()),
+            _ => Source::OpenAI(stream),
+        };
+        ChatStream {
+            source,
+            pending: VecDeque::new(),
+        }
+    }
+
+    pub fn from_ollama(response: Response) -> Self {
+        ChatStream {
+            source: Source::Ollama(response, Default::default(), Vec::new()),
+            pending: VecDeque::new(),
+        }
+    }
+
+    pub async fn next(&mut self) -> Option<Result<String, FallbackError>> {
+        loop {
+            if let Some(data) = self.pending.pop_front() {
+                return Some(Ok(data));
+            }
+
+            match &mut self.source {
+                Source::OpenAI(stream) => match stream.next().await? {
+                    Ok(Event::Open) => {}
+                    Ok(Event::Message(message)) => self.pending.push_back(message.data),
+                    Err(error) => return Some(Err(FallbackError::Transport(error.to_string()))),
+                },
+                Source::Anthropic(stream, state) => match stream.next().await? {
+                    Ok(Event::Open) => {}
+                    Ok(Event::Message(message)) => {
+                        match state.translate(&message.event, &message.data) {
+                            Ok(chunks) => self.pending.extend(chunks),
+                            Err(error) => return Some(Err(error)),
+                        }
+                    }
+                    Err(error) => return Some(Err(FallbackError::Transport(error.to_string()))),
+                },
+                Source::Ollama(response, state, buffer) => {
+                    if state.is_done() {
+                        return None;
+                    }
+                    match response.chunk().await {
+                        Ok(Some(bytes)) => buffer.extend_from_slice(&bytes),
+                        // Whatever is left is the last line
+                        Ok(None) if !buffer.is_empty() => buffer.push(b'\n'),
+                        Ok(None) => {
+                            return Some(Err(FallbackError::Transport(
+                                "The stream ended before the model was done".into(),
+                            )))
+                        }
+                        Err(error) => {
+                            return Some(Err(FallbackError::Transport(error.to_string())))
+                        }
+                    }
+                    while let Some(end) = buffer.iter().position(|byte| *byte == b'\n') {
+                        let line: Vec<u8> = buffer.drain(..=end).collect();
+                        let line = String::from_utf8_lossy(&line);
+                        if line.trim().is_empty() {
+                            continue;
+                        }
+                        match state.translate(line.trim()) {
+                            Ok(chunks) => self.pending.extend(chunks),
+                            Err(error) => return Some(Err(error)),
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    pub fn close(&mut self) {
+        match &mut self.source {
+            Source::OpenAI(stream) | Source::Anthropic(stream, _) => stream.close(),
+            // Dropping the response closes the connection
+            Source::Ollama(..) => {}
+        }
+    }
+}
+
+fn to_json<T: serde::Serialize>(value: &T) -> Result<String, FallbackError> {
+    serde_json::to_string(value).map_err(|error| FallbackError::Request(error.to_string()))
+}
+
+// One chunk of an OpenAI chat completion stream
+fn completion_chunk(
+    id: &str,
+    model: &str,
+    created: u64,
+    delta: Value,
+    finish_reason: Option<&str>,
+    usage: Option<Value>,
+) -> String {
+    let mut chunk = json!({
+        "id": id,
+        "object": "chat.completion.chunk",
+        "created": created,
+        "model": model,
+        "choices": [{
+            "index": 0,
+            "delta": delta,
+            "finish_reason": finish_reason,
+        }],
+    });
+    if let Some(usage) = usage {
+        chunk["usage"] = usage;
+    }
+    chunk.to_string()
+}
+
+fn usage(prompt_tokens: u64, completion_tokens: u64) -> Value {
+    json!({
+        "prompt_tokens": prompt_tokens,
+        "completion_tokens": completion_tokens,
+        "total_tokens": prompt_tokens + completion_tokens,
+    })
+}
+
+fn unix_now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|duration| duration.as_secs())
+        .unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::endpoints::Endpoint;
+    use crate::fallback::{self, ChatRoute, RetryPolicy};
+    use db::LoadBalancing;
+    use openai_api::{ChatCompletionDelta, ChatCompletionMessage};
+    use std::time::Duration;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio::net::TcpListener;
+    use tokio::task::JoinHandle;
+
+    // Answer one request with a canned body and hand back the raw request
+    async fn mock_server(content_type: &'static str, body: String) -> (String, JoinHandle<String>) {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let base_url = format!("http://{}", listener.local_addr().unwrap());
+
+        let handle = tokio::spawn(async move {
+            let (mut socket, _) = listener.accept().await.unwrap();
+            let mut request = Vec::new();
+            let mut buffer = [0u8; 4096];
+            loop {
+                let read = socket.read(&mut buffer).await.unwrap();
+                request.extend_from_slice(&buffer[..read]);
+                let text = String::from_utf8_lossy(&request).to_string();
+                if let Some(end) = text.find("\r\n\r\n") {
+                    let content_length = text[..end]
+                        .lines()
+                        .filter_map(|line| line.split_once(':'))
+                        .find(|(name, _)| name.eq_ignore_ascii_case("content-length"))
+                        .and_then(|(_, value)| value.trim().parse::<usize>().ok())
+                        .unwrap_or(0);
+                    if request.len() >= end + 4 + content_length {
+                        break;
+                    }
+                }
+                if read == 0 {
+                    break;
+                }
+            }
+
+            let response = format!(
+                "HTTP/1.1 200 OK\r\ncontent-type: {}\r\ncontent-length: {}\r\nconnection: close\r\n\r\n{}",
+                content_type,
+                body.len(),
+                body
+            );
+            socket.write_all(response.as_bytes()).await.unwrap();
+            socket.shutdown().await.ok();
+            String::from_utf8_lossy(&request).to_string()
+        });
+
+        (base_url, handle)
+    }
+
+    fn route(model_id: i32, provider: ModelProvider, base_url: String, stream: bool) -> ChatRoute {
+        ChatRoute {
+            model_id,
+            provider,
+            endpoints: vec![Endpoint {
+                id: 0,
+                base_url,
+                api_key: Some("secret".to_string()),
+                weight: 1,
+                max_concurrency: 0,
+            }],
+            load_balancing: LoadBalancing::LeastOutstanding,
+            completion: BionicChatCompletionRequest {
+                model: "test-model".to_string(),
+                stream: Some(stream),
+                max_tokens: None,
+                messages: vec![ChatCompletionMessage {
+                    content: Some("Hello".into()),
+                    ..Default::default()
+                }],
+                temperature: None,
+                tools: None,
+                tool_choice: None,
+                stream_options: None,
+            },
+            policy: RetryPolicy {
+                max_attempts: 1,
+                backoff: Duration::ZERO,
+                retry_status_codes: Vec::new(),
+                breaker_threshold: 5,
+                breaker_cooldown: Duration::from_secs(1),
+            },
+        }
+    }
+
+    // Read the stream to the end and merge the chunks like the enricher does
+    async fn merged(mut stream: ChatStream) -> ChatCompletionDelta {
+        let mut merged: Option<ChatCompletionDelta> = None;
+        while let Some(data) = stream.next().await {
+            let data = data.unwrap();
+            if data == "[DONE]" {
+                stream.close();
+                break;
+            }
+            let delta: ChatCompletionDelta = serde_json::from_str(&data).unwrap();
+            match merged.as_mut() {
+                Some(merged) => merged.merge(delta).unwrap(),
+                None => merged = Some(delta),
+            }
+        }
+        merged.unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_anthropic_stream() {
+        let events = [
+            r#"{"type":"message_start","message":{"id":"msg_1","model":"test-model","usage":{"input_tokens":3}}}"#,
+            r#"{"type":"content_block_delta","index":0,"delta":{"type":"text_delta","text":"Hi "}}"#,
+            r#"{"type":"content_block_delta","index":0,"delta":{"type":"text_delta","text":"there"}}"#,
+            r#"{"type":"message_delta","delta":{"stop_reason":"end_turn"},"usage":{"output_tokens":2}}"#,
+            r#"{"type":"message_stop"}"#,
+        ];
+        let body: String = events
+            .iter()
+            .map(|data| {
+                let event: Value = serde_json::from_str(data).unwrap();
+                format!(
+                    "event: {}\ndata: {}\n\n",
+                    event["type"].as_str().unwrap(),
+                    data
+                )
+            })
+            .collect();
+        let (base_url, server) = mock_server("text/event-stream", body).await;
+
+        let routes = vec![route(-30, ModelProvider::Anthropic, base_url, true)];
+        let (stream, model_id, _in_flight) = fallback::open_stream(routes).await.unwrap();
+        assert_eq!(model_id, -30);
+
+        let merged = merged(stream).await;
+        assert_eq!(merged.choices[0].delta.content.as_deref(), Some("Hi there"));
+
+        let request = server.await.unwrap();
+        assert!(request.starts_with("POST /messages "));
+        assert!(request.contains("x-api-key: secret"));
+        assert!(request.contains(r#""max_tokens":4096"#));
+    }
+
+    #[tokio::test]
+    async fn test_ollama_stream() {
+        let body = [
+            r#"{"model":"test-model","message":{"role":"assistant","content":"Hi"},"done":false}"#,
+            r#"{"model":"test-model","message":{"role":"assistant","content":"","tool_calls":[{"function":{"name":"time","arguments":{}}}]},"done":false}"#,
+            r#"{"model":"test-model","message":{"role":"assistant","content":""},"done":true,"done_reason":"stop"}"#,
+        ]
+        .join("\n");
+        let (base_url, server) = mock_server("application/x-ndjson", body).await;
+
+        let routes = vec![route(-31, ModelProvider::Ollama, base_url, true)];
+        let (stream, _, _in_flight) = fallback::open_stream(routes).await.unwrap();
+
+        let merged = merged(stream).await;
+        let tool_calls = merged.choices[0].delta.tool_calls.clone().unwrap();
+        assert_eq!(tool_calls[0].function.name, "time");
+
+        let request = server.await.unwrap();
+        assert!(request.starts_with("POST /api/chat "));
+        assert!(request.contains(r#""stream":true"#));
+    }
+
+    #[tokio::test]
+    async fn test_anthropic_send() {
+        let body = r#"{"id":"msg_1","model":"test-model","content":[{"type":"text","text":"Hello"}],"stop_reason":"end_turn","usage":{"input_tokens":1,"output_tokens":1}}"#;
+        let (base_url, _server) = mock_server("application/json", body.to_string()).await;
+
+        let routes = vec![route(-32, ModelProvider::Anthropic, base_url, false)];
+        let (response, _, _in_flight) = fallback::send(routes).await.unwrap();
+
+        let completion: Value = response.json().await.unwrap();
+        assert_eq!(completion["object"], "chat.completion");
+        assert_eq!(completion["choices"][0]["message"]["content"], "Hello");
+    }
+}
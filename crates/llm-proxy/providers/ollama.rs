@@ -0,0 +1,345 @@
+//! Ollama's native `/api/chat`.
+//!
+//! The messages are close to OpenAI's but tool call arguments are objects
+//! rather than strings and the stream is a JSON object per line instead of
+//! server sent events. Tool calls arrive whole so they get an id here.
+
+use openai_api::{BionicChatCompletionRequest, ChatCompletionMessage, ChatCompletionMessageRole};
+use reqwest::{Client, RequestBuilder};
+use serde::Serialize;
+use serde_json::{json, Value};
+
+use super::{completion_chunk, to_json, unix_now, usage};
+use crate::endpoints::Endpoint;
+use crate::fallback::FallbackError;
+
+#[derive(Serialize, Debug)]
+pub struct ChatRequest {
+    pub model: String,
+    pub messages: Vec<Value>,
+    // Ollama streams unless told not to
+    pub stream: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub tools: Option<Vec<openai_api::BionicToolDefinition>>,
+    #[serde(skip_serializing_if = "Options::is_empty")]
+    pub options: Options,
+}
+
+#[derive(Serialize, Debug, Default)]
+pub struct Options {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub temperature: Option<f32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub num_predict: Option<i32>,
+}
+
+impl Options {
+    fn is_empty(&self) -> bool {
+        self.temperature.is_none() && self.num_predict.is_none()
+    }
+}
+
+pub fn chat_request(
+    client: &Client,
+    endpoint: &Endpoint,
+    completion: &BionicChatCompletionRequest,
+) -> Result<RequestBuilder, FallbackError> {
+    let mut request = client
+        .post(format!("{}/api/chat", endpoint.base_url))
+        .body(to_json(&ollama_request(completion))?);
+    // Ollama doesn't need a key but it's often behind a proxy that does
+    if let Some(api_key) = &endpoint.api_key {
+        request = request.bearer_auth(api_key);
+    }
+    Ok(request)
+}
+
+pub fn ollama_request(completion: &BionicChatCompletionRequest) -> ChatRequest {
+    ChatRequest {
+        model: completion.model.clone(),
+        messages: completion.messages.iter().map(message).collect(),
+        stream: completion.stream.unwrap_or(false),
+        tools: completion.tools.clone().filter(|tools| !tools.is_empty()),
+        options: Options {
+            temperature: completion.temperature,
+            num_predict: completion.max_tokens,
+        },
+    }
+}
+
+fn message(message: &ChatCompletionMessage) -> Value {
+    let role = match message.role {
+        ChatCompletionMessageRole::System | ChatCompletionMessageRole::Developer => "system",
+        ChatCompletionMessageRole::Assistant => "assistant",
+        ChatCompletionMessageRole::Tool | ChatCompletionMessageRole::Function => "tool",
+        ChatCompletionMessageRole::User => "user",
+    };
+    let mut ollama = json!({
+        "role": role,
+        "content": message.text().unwrap_or_default(),
+    });
+
+    // Ollama takes images as bare base64 next to the text
+    let images: Vec<&str> = message
+        .content
+        .iter()
+        .flat_map(|content| content.images())
+        .filter_map(|image| image.base64_data().map(|(_, data)| data))
+        .collect();
+    if !images.is_empty() {
+        ollama["images"] = json!(images);
+    }
+
+    if let Some(tool_calls) = message
+        .tool_calls
+        .as_ref()
+        .filter(|calls| !calls.is_empty())
+    {
+        ollama["tool_calls"] = tool_calls
+            .iter()
+            .map(|tool_call| {
+                let arguments = serde_json::from_str::<Value>(&tool_call.function.arguments)
+                    .ok()
+                    .filter(Value::is_object)
+                    .unwrap_or_else(|| json!({}));
+                json!({
+                    "function": {
+                        "name": tool_call.function.name,
+                        "arguments": arguments,
+                    },
+                })
+            })
+            .collect();
+    }
+
+    ollama
+}
+
+// Ollama sends the whole call at once without ids, we number them for OpenAI
+// and give them ids that stay unique across the turns of a conversation
+fn tool_calls(message: &Value, first_index: usize) -> Vec<Value> {
+    message["tool_calls"]
+        .as_array()
+        .into_iter()
+        .flatten()
+        .enumerate()
+        .map(|(offset, tool_call)| {
+            let index = first_index + offset;
+            json!({
+                "index": index,
+                "id": format!("call_{}", uuid::Uuid::new_v4().simple()),
+                "type": "function",
+                "function": {
+                    "name": tool_call["function"]["name"],
+                    "arguments": tool_call["function"]["arguments"].to_string(),
+                },
+            })
+        })
+        .collect()
+}
+
+fn finish_reason(response: &Value, called_tools: bool) -> &'static str {
+    if called_tools {
+        "tool_calls"
+    } else if response["done_reason"] == "length" {
+        "length"
+    } else {
+        "stop"
+    }
+}
+
+fn response_usage(response: &Value) -> Value {
+    usage(
+        response["prompt_eval_count"].as_u64().unwrap_or(0),
+        response["eval_count"].as_u64().unwrap_or(0),
+    )
+}
+
+// What we need to remember between lines
+#[derive(Default, Debug)]
+pub struct StreamState {
+    id: String,
+    created: u64,
+    tool_calls: usize,
+    done: bool,
+}
+
+impl StreamState {
+    pub fn is_done(&self) -> bool {
+        self.done
+    }
+
+    // Translate one line into zero or more OpenAI chunks
+    pub fn translate(&mut self, line: &str) -> Result<Vec<String>, FallbackError> {
+        let response: Value = serde_json::from_str(line).map_err(|error| {
+            FallbackError::Request(format!("Unexpected response from Ollama: {}", error))
+        })?;
+        if let Some(error) = response["error"].as_str() {
+            return Err(FallbackError::Request(error.to_string()));
+        }
+
+        let model = response["model"].as_str().unwrap_or_default();
+        let mut chunks = Vec::new();
+        if self.id.is_empty() {
+            self.id = format!("chatcmpl-ollama-{}", unix_now());
+            self.created = unix_now();
+            chunks.push(completion_chunk(
+                &self.id,
+                model,
+                self.created,
+                json!({ "role": "assistant", "content": "" }),
+                None,
+                None,
+            ));
+        }
+
+        let message = &response["message"];
+        let mut delta = json!({});
+        if let Some(content) = message["content"].as_str().filter(|c| !c.is_empty()) {
+            delta["content"] = json!(content);
+        }
+        let tool_calls = tool_calls(message, self.tool_calls);
+        if !tool_calls.is_empty() {
+            self.tool_calls += tool_calls.len();
+            delta["tool_calls"] = Value::Array(tool_calls);
+        }
+        if delta != json!({}) {
+            chunks.push(completion_chunk(
+                &self.id,
+                model,
+                self.created,
+                delta,
+                None,
+                None,
+            ));
+        }
+
+        if response["done"] == true {
+            self.done = true;
+            chunks.push(completion_chunk(
+                &self.id,
+                model,
+                self.created,
+                json!({}),
+                Some(finish_reason(&response, self.tool_calls > 0)),
+                Some(response_usage(&response)),
+            ));
+            chunks.push("[DONE]".to_string());
+        }
+
+        Ok(chunks)
+    }
+}
+
+// A whole (non streaming) response as an OpenAI chat completion
+pub fn chat_completion(body: &str) -> Result<String, FallbackError> {
+    let response: Value = serde_json::from_str(body).map_err(|error| {
+        FallbackError::Request(format!("Unexpected response from Ollama: {}", error))
+    })?;
+
+    let tool_calls = tool_calls(&response["message"], 0);
+    let mut assistant = json!({
+        "role": "assistant",
+        "content": response["message"]["content"].as_str().unwrap_or_default(),
+    });
+    let called_tools = !tool_calls.is_empty();
+    if called_tools {
+        assistant["tool_calls"] = Value::Array(tool_calls);
+    }
+
+    Ok(json!({
+        "id": format!("chatcmpl-ollama-{}", unix_now()),
+        "object": "chat.completion",
+        "created": unix_now(),
+        "model": response["model"],
+        "choices": [{
+            "index": 0,
+            "message": assistant,
+            "finish_reason": finish_reason(&response, called_tools),
+        }],
+        "usage": response_usage(&response),
+    })
+    .to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use openai_api::{ChatCompletionDelta, ToolCall, ToolCallFunction};
+
+    #[test]
+    fn test_ollama_request() {
+        let completion = BionicChatCompletionRequest {
+            model: "llama3.1".to_string(),
+            stream: None,
+            max_tokens: Some(100),
+            messages: vec![ChatCompletionMessage {
+                role: ChatCompletionMessageRole::Assistant,
+                tool_calls: Some(vec![ToolCall {
+                    id: "call_0".to_string(),
+                    function: ToolCallFunction {
+                        name: "weather".to_string(),
+                        arguments: r#"{"city":"Paris"}"#.to_string(),
+                    },
+                    ..Default::default()
+                }]),
+                ..Default::default()
+            }],
+            temperature: None,
+            tools: Some(Vec::new()),
+            tool_choice: None,
+            stream_options: None,
+        };
+
+        let request = serde_json::to_value(ollama_request(&completion)).unwrap();
+        assert_eq!(request["stream"], false);
+        assert_eq!(request["options"], json!({ "num_predict": 100 }));
+        assert!(request.get("tools").is_none());
+        assert_eq!(
+            request["messages"][0]["tool_calls"][0]["function"]["arguments"],
+            json!({ "city": "Paris" })
+        );
+    }
+
+    #[test]
+    fn test_stream_lines() {
+        let lines = [
+            r#"{"model":"llama3.1","message":{"role":"assistant","content":"Hi"},"done":false}"#,
+            r#"{"model":"llama3.1","message":{"role":"assistant","content":"","tool_calls":[{"function":{"name":"weather","arguments":{"city":"Rome"}}}]},"done":false}"#,
+            r#"{"model":"llama3.1","message":{"role":"assistant","content":""},"done":true,"done_reason":"stop","prompt_eval_count":3,"eval_count":4}"#,
+        ];
+
+        let mut state = StreamState::default();
+        let mut chunks = Vec::new();
+        for line in lines {
+            chunks.extend(state.translate(line).unwrap());
+        }
+        assert!(state.is_done());
+        assert_eq!(chunks.last().unwrap(), "[DONE]");
+
+        let mut merged: Option<ChatCompletionDelta> = None;
+        for chunk in &chunks[..chunks.len() - 1] {
+            let delta: ChatCompletionDelta = serde_json::from_str(chunk).unwrap();
+            match merged.as_mut() {
+                Some(merged) => merged.merge(delta).unwrap(),
+                None => merged = Some(delta),
+            }
+        }
+
+        let choice = &merged.unwrap().choices[0];
+        assert_eq!(choice.delta.content.as_deref(), Some("Hi"));
+        let tool_calls = choice.delta.tool_calls.as_ref().unwrap();
+        assert!(tool_calls[0].id.starts_with("call_"));
+        assert_eq!(tool_calls[0].function.arguments, r#"{"city":"Rome"}"#);
+
+        let last: ChatCompletionDelta = serde_json::from_str(&chunks[chunks.len() - 2]).unwrap();
+        assert_eq!(last.choices[0].finish_reason.as_deref(), Some("tool_calls"));
+        assert_eq!(last.usage.unwrap().total_tokens, Some(7));
+    }
+
+    #[test]
+    fn test_error_line() {
+        let mut state = StreamState::default();
+        assert!(state.translate(r#"{"error":"model not found"}"#).is_err());
+    }
+}
@@ -4,62 +4,117 @@
 //! cargo run -p example-reqwest-response
 //! ```
 
+use crate::fallback::{self, ChatRoute};
 use axum::Error;
 use openai_api::ChatCompletionDelta;
-use reqwest::RequestBuilder;
-use reqwest_eventsource::{Event as ReqwestEvent, EventSource as ReqwestEventSource};
 use tokio::sync::mpsc;
-use tokio_stream::StreamExt;
+use tokio_util::sync::CancellationToken;
 
 #[derive(Debug)]
 pub struct CompletionChunk {
     pub delta: String,
     pub merged: Option<ChatCompletionDelta>,
     pub snapshot: String,
+    // The model that answered, which may be a fallback
+    pub model_id: Option<i32>,
 }
 
 #[derive(Debug)]
 pub enum GenerationEvent {
     Text(CompletionChunk),
     End(CompletionChunk),
+    // The generation was stopped, this carries what we had so far
+    Cancelled(CompletionChunk),
 }
 
 pub async fn enriched_chat(
-    request: RequestBuilder,
+    routes: Vec<ChatRoute>,
     sender: mpsc::Sender<Result<GenerationEvent, Error>>,
     convert_errors_to_chat: bool,
 ) -> Result<(), Box<dyn std::error::Error>> {
-    tracing::debug!("{:?}", &request);
-    let mut stream = ReqwestEventSource::new(request)?;
+    cancellable_chat(
+        routes,
+        sender,
+        convert_errors_to_chat,
+        CancellationToken::new(),
+    )
+    .await
+    .map(|_| ())
+}
+
+/// As `enriched_chat`, but closes the upstream request when `cancel` fires
+/// and sends what we have so far as `GenerationEvent::Cancelled`. If nobody
+/// is listening any more the partial response is returned instead, so the
+/// caller can still save it.
+pub async fn cancellable_chat(
+    routes: Vec<ChatRoute>,
+    sender: mpsc::Sender<Result<GenerationEvent, Error>>,
+    convert_errors_to_chat: bool,
+    cancel: CancellationToken,
+) -> Result<Option<CompletionChunk>, Box<dyn std::error::Error>> {
     let mut snapshot = String::new();
     let mut merged: Option<ChatCompletionDelta> = None;
 
-    while let Some(event) = stream.next().await {
+    // Retries and falls back to other models before we give up
+    // The endpoint counts us as in flight until the guard drops at the end
+    let connected = tokio::select! {
+        connected = fallback::open_stream(routes) => connected,
+        _ = cancel.cancelled() => {
+            return Ok(send_cancelled(cancelled_chunk(&snapshot, None, None), &sender).await);
+        }
+    };
+    let (mut stream, model_id, _in_flight) = match connected {
+        Ok(connected) => connected,
+        Err(err) => {
+            return handle_chat_error(err, convert_errors_to_chat, &mut snapshot, &sender, None)
+                .await
+                .map(|_| None);
+        }
+    };
+
+    // Whatever the provider the stream carries OpenAI chunks
+    loop {
+        let event = tokio::select! {
+            event = stream.next() => event,
+            _ = cancel.cancelled() => {
+                stream.close();
+                let chunk = cancelled_chunk(&snapshot, merged, Some(model_id));
+                return Ok(send_cancelled(chunk, &sender).await);
+            }
+        };
+        let Some(event) = event else {
+            break;
+        };
+
         match event {
-            Ok(ReqwestEvent::Open) => tracing::debug!("Connection Open!"),
-            Ok(ReqwestEvent::Message(message)) => {
-                if message.data.trim() == "[DONE]" {
+            Ok(data) => {
+                if data.trim() == "[DONE]" {
                     let chunk = CompletionChunk {
-                        delta: message.data.clone(),
+                        delta: data.clone(),
                         merged: merged.clone(),
                         snapshot: snapshot.clone(),
+                        model_id: Some(model_id),
                     };
                     stream.close();
-                    sender.send(Ok(GenerationEvent::End(chunk))).await.ok();
+                    if let Err(mpsc::error::SendError(Ok(GenerationEvent::End(chunk)))) =
+                        sender.send(Ok(GenerationEvent::End(chunk))).await
+                    {
+                        return Ok(Some(chunk));
+                    }
                     break;
                 }
 
-                tracing::debug!("{}", &message.data);
-                let delta: ChatCompletionDelta = match serde_json::from_str(&message.data) {
+                tracing::debug!("{}", &data);
+                let delta: ChatCompletionDelta = match serde_json::from_str(&data) {
                     Ok(delta) => delta,
                     Err(e) => {
+                        stream.close();
                         handle_chat_error(
                             e,
                             convert_errors_to_chat,
                             &mut snapshot,
                             &sender,
-                            &mut stream,
-                            Some(&message.data),
+                            Some(&data),
                         )
                         .await?;
                         break;
@@ -76,34 +131,62 @@ pub async fn enriched_chat(
                     None => merged = Some(delta.clone()),
                 }
 
-                if let Some(text) = &delta.choices[0].delta.content {
+                // The usage chunk at the end of a stream has no choices
+                let text = delta
+                    .choices
+                    .first()
+                    .and_then(|choice| choice.delta.content.as_ref());
+                if let Some(text) = text {
                     snapshot.push_str(text);
                     let chunk = CompletionChunk {
-                        delta: message.data.clone(),
+                        delta: data.clone(),
                         merged: merged.clone(),
                         snapshot: snapshot.clone(),
+                        model_id: Some(model_id),
                     };
+                    // Nobody is listening, so stop and hand back what we have
                     if sender.send(Ok(GenerationEvent::Text(chunk))).await.is_err() {
-                        break;
+                        stream.close();
+                        let chunk = cancelled_chunk(&snapshot, merged, Some(model_id));
+                        return Ok(Some(chunk));
                     }
                 }
             }
             Err(err) => {
-                handle_chat_error(
-                    err,
-                    convert_errors_to_chat,
-                    &mut snapshot,
-                    &sender,
-                    &mut stream,
-                    None,
-                )
-                .await?;
+                stream.close();
+                handle_chat_error(err, convert_errors_to_chat, &mut snapshot, &sender, None)
+                    .await?;
                 break;
             }
         }
     }
 
-    Ok(())
+    Ok(None)
+}
+
+fn cancelled_chunk(
+    snapshot: &str,
+    merged: Option<ChatCompletionDelta>,
+    model_id: Option<i32>,
+) -> CompletionChunk {
+    CompletionChunk {
+        // Ends the stream in the browser like any other generation
+        delta: "[DONE]".into(),
+        merged,
+        snapshot: snapshot.to_string(),
+        model_id,
+    }
+}
+
+async fn send_cancelled(
+    chunk: CompletionChunk,
+    sender: &mpsc::Sender<Result<GenerationEvent, Error>>,
+) -> Option<CompletionChunk> {
+    match sender.send(Ok(GenerationEvent::Cancelled(chunk))).await {
+        Ok(()) => None,
+        Err(mpsc::error::SendError(Ok(GenerationEvent::Cancelled(chunk)))) => Some(chunk),
+        Err(_) => None,
+    }
 }
 
 fn convert_error_to_chats(
@@ -144,11 +227,9 @@ async fn handle_chat_error<E: std::error::Error + Send + Sync + 'static>(
     convert_errors_to_chat: bool,
     snapshot: &mut String,
     sender: &mpsc::Sender<Result<GenerationEvent, Error>>,
-    stream: &mut ReqwestEventSource,
     context_message: Option<&str>,
 ) -> Result<(), Box<dyn std::error::Error>> {
     tracing::error!("Chat error: {:?}", err);
-    stream.close();
 
     if convert_errors_to_chat {
         for (chunk, markdown) in convert_error_to_chats(err, context_message) {
@@ -160,6 +241,7 @@ async fn handle_chat_error<E: std::error::Error + Send + Sync + 'static>(
                 delta: "[DONE]".into(),
                 merged: None,
                 snapshot: snapshot.clone(),
+                model_id: None,
             })))
             .await?;
     } else {
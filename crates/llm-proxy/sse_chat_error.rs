@@ -19,6 +19,7 @@ pub async fn error_to_chat(
             delta: "[DONE]".to_string(),
             merged: None,
             snapshot: error.to_string(),
+            model_id: None,
         })))
         .await?;
 
@@ -70,5 +71,6 @@ pub fn string_to_chunk(content: &str) -> CompletionChunk {
         delta: serde_json::to_string(&delta).unwrap(),
         merged: None,
         snapshot: "".to_string(),
+        model_id: None,
     }
 }
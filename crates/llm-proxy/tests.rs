@@ -1,4 +1,4 @@
-use db::{Chat, ChatRole, ChatStatus};
+use db::{Chat, ChatRole, ChatStatus, RelatedContext};
 use openai_api::{ChatCompletionMessage, ChatCompletionMessageRole};
 use time::OffsetDateTime;
 
@@ -12,6 +12,7 @@ async fn test_convert_chat_to_messages_tool_calling_fallback() {
         role: ChatRole::User,
         id: 0,
         conversation_id: 0,
+        parent_id: None,
         content: Some("What's the current time in San Francisco?".to_string()),
         tool_call_id: None,
         tool_calls: Some("[invalid json]".to_string()),
@@ -36,10 +37,12 @@ async fn test_generate_prompt() {
         2048,
         1024,
         1.0,
+        openai_api::tokenizer::DEFAULT_TOKENIZER,
         Some("You are a helpful asistant".to_string()),
+        None,
         vec![ChatCompletionMessage {
             role: ChatCompletionMessageRole::User,
-            content: Some("How are you today?".to_string()),
+            content: Some("How are you today?".into()),
             tool_call_id: None,
             tool_calls: None,
             name: None,
@@ -52,8 +55,43 @@ async fn test_generate_prompt() {
 
     assert!(messages.len() == 2);
 
-    assert!(messages[0].content == Some("You are a helpful asistant".to_string()));
-    assert!(messages[1].content == Some("How are you today?".to_string()));
+    assert!(messages[0].text() == Some("You are a helpful asistant".to_string()));
+    assert!(messages[1].text() == Some("How are you today?".to_string()));
+}
+
+#[tokio::test]
+async fn test_context_is_numbered_for_citations() {
+    let (messages, chunk_ids) = generate_prompt(
+        2048,
+        1024,
+        1.0,
+        openai_api::tokenizer::DEFAULT_TOKENIZER,
+        Some("You are a helpful asistant".to_string()),
+        None,
+        vec![ChatCompletionMessage {
+            role: ChatCompletionMessageRole::User,
+            content: Some("Where are Paris and Berlin?".into()),
+            tool_call_id: None,
+            tool_calls: None,
+            name: None,
+        }],
+        vec![
+            RelatedContext {
+                chunk_id: 7,
+                chunk_text: "Paris is in France".to_string(),
+            },
+            RelatedContext {
+                chunk_id: 3,
+                chunk_text: "Berlin is in Germany".to_string(),
+            },
+        ],
+    )
+    .await;
+
+    assert_eq!(chunk_ids, vec![7, 3]);
+    let system_prompt = messages[0].text().unwrap_or_default();
+    assert!(system_prompt.starts_with("You are a helpful asistant"));
+    assert!(system_prompt.contains("[1] Paris is in France\n[2] Berlin is in Germany\n"));
 }
 
 // ============================================================================
@@ -72,6 +110,7 @@ fn create_test_chat(
     Chat {
         id,
         conversation_id: 1,
+        parent_id: None,
         role,
         content,
         tool_call_id,
@@ -105,7 +144,7 @@ fn assert_message_properties(
     has_tool_calls: bool,
 ) {
     assert_eq!(message.role, expected_role);
-    assert_eq!(message.content.as_deref(), expected_content);
+    assert_eq!(message.text().as_deref(), expected_content);
     assert_eq!(message.tool_call_id.as_deref(), expected_tool_call_id);
     assert_eq!(message.tool_calls.is_some(), has_tool_calls);
     assert_eq!(message.name, None); // Always None in current implementation
@@ -387,6 +426,37 @@ async fn test_tool_call_id_linking() {
     assert_eq!(messages[1].role, ChatCompletionMessageRole::Tool);
 }
 
+#[tokio::test]
+async fn test_only_the_active_branch_is_converted() {
+    let chat = |id, parent_id, role, content: &str| Chat {
+        parent_id,
+        ..create_test_chat(id, role, Some(content.to_string()), None, None)
+    };
+    // The first reply called a tool, it was regenerated without one and then
+    // the question was edited.
+    let mut tool_call = chat(2, Some(1), ChatRole::Assistant, "");
+    tool_call.content = None;
+    tool_call.tool_calls = Some(create_tool_call_json("call_old", "old_function", "{}"));
+    let conversation = vec![
+        chat(1, None, ChatRole::User, "Question"),
+        tool_call,
+        chat(3, Some(2), ChatRole::Tool, "Old result"),
+        chat(4, Some(3), ChatRole::Assistant, "Old answer"),
+        chat(5, Some(1), ChatRole::Assistant, "New answer"),
+        chat(6, Some(5), ChatRole::User, "Follow up"),
+        chat(7, Some(5), ChatRole::User, "Edited follow up"),
+    ];
+
+    let messages = convert_chat_to_messages(conversation);
+
+    let texts: Vec<String> = messages
+        .iter()
+        .filter_map(|message| message.text())
+        .collect();
+    assert_eq!(texts, vec!["Question", "New answer", "Edited follow up"]);
+    assert!(messages.iter().all(|message| message.tool_calls.is_none()));
+}
+
 #[tokio::test]
 async fn test_history_truncation_keeps_latest() {
     use openai_api::token_count::token_count;
@@ -394,7 +464,7 @@ async fn test_history_truncation_keeps_latest() {
     fn mk_msg(content: &str) -> ChatCompletionMessage {
         ChatCompletionMessage {
             role: ChatCompletionMessageRole::User,
-            content: Some(content.to_string()),
+            content: Some(content.into()),
             tool_call_id: None,
             tool_calls: None,
             name: None,
@@ -421,10 +491,19 @@ async fn test_history_truncation_keeps_latest() {
         large_msg.clone(),
     ];
 
-    let (messages, _chunk_ids) =
-        generate_prompt(context_size, 0, 1.0, None, history, Default::default()).await;
+    let (messages, _chunk_ids) = generate_prompt(
+        context_size,
+        0,
+        1.0,
+        openai_api::tokenizer::DEFAULT_TOKENIZER,
+        None,
+        None,
+        history,
+        Default::default(),
+    )
+    .await;
 
-    let contents: Vec<_> = messages.iter().map(|m| m.content.clone()).collect();
+    let contents: Vec<_> = messages.iter().map(|m| m.text()).collect();
 
     dbg!(&contents);
 
@@ -442,7 +521,7 @@ fn test_strip_tool_data_removes_tool_messages() {
     let messages = vec![
         ChatCompletionMessage {
             role: ChatCompletionMessageRole::User,
-            content: Some("hi".to_string()),
+            content: Some("hi".into()),
             tool_call_id: None,
             tool_calls: None,
             name: None,
@@ -464,7 +543,7 @@ fn test_strip_tool_data_removes_tool_messages() {
         },
         ChatCompletionMessage {
             role: ChatCompletionMessageRole::Tool,
-            content: Some("{}".to_string()),
+            content: Some("{}".into()),
             tool_call_id: Some("call1".to_string()),
             tool_calls: None,
             name: None,
@@ -475,7 +554,7 @@ fn test_strip_tool_data_removes_tool_messages() {
 
     assert_eq!(sanitized.len(), 1);
     assert_eq!(sanitized[0].role, ChatCompletionMessageRole::User);
-    assert_eq!(sanitized[0].content, Some("hi".to_string()));
+    assert_eq!(sanitized[0].text(), Some("hi".to_string()));
     assert!(sanitized[0].tool_calls.is_none());
     assert!(sanitized[0].tool_call_id.is_none());
 }
@@ -486,7 +565,7 @@ fn test_strip_tool_data_changes_system_to_user() {
 
     let messages = vec![ChatCompletionMessage {
         role: ChatCompletionMessageRole::System,
-        content: Some("hi".to_string()),
+        content: Some("hi".into()),
         tool_call_id: None,
         tool_calls: None,
         name: None,
@@ -496,5 +575,63 @@ fn test_strip_tool_data_changes_system_to_user() {
 
     assert_eq!(sanitized.len(), 1);
     assert_eq!(sanitized[0].role, ChatCompletionMessageRole::User);
-    assert_eq!(sanitized[0].content, Some("hi".to_string()));
+    assert_eq!(sanitized[0].text(), Some("hi".to_string()));
+}
+
+#[test]
+fn test_strip_tool_data_removes_images() {
+    use openai_api::{ChatCompletionContent, ChatCompletionContentPart, ImageUrl};
+
+    let messages = vec![ChatCompletionMessage {
+        role: ChatCompletionMessageRole::User,
+        content: Some(ChatCompletionContent::Parts(vec![
+            ChatCompletionContentPart::Text {
+                text: "What is this?".to_string(),
+            },
+            ChatCompletionContentPart::ImageUrl {
+                image_url: ImageUrl {
+                    url: "data:image/png;base64,AAEC".to_string(),
+                    detail: None,
+                },
+            },
+        ])),
+        tool_call_id: None,
+        tool_calls: None,
+        name: None,
+    }];
+
+    let sanitized = strip_tool_data(&messages);
+
+    assert_eq!(
+        sanitized[0].content,
+        Some(ChatCompletionContent::Text("What is this?".to_string()))
+    );
+}
+
+#[test]
+fn test_images_left_out_for_non_vision_models() {
+    use crate::chat_converter::convert_chat_to_messages_with_images;
+    use db::queries::attachments::AttachmentImage;
+
+    let chat = create_test_chat(
+        7,
+        ChatRole::User,
+        Some("What is this?".to_string()),
+        None,
+        None,
+    );
+    let images = vec![AttachmentImage {
+        chat_id: 7,
+        object_data: vec![0, 1, 2],
+        file_name: "cat.png".to_string(),
+        mime_type: "image/png".to_string(),
+    }];
+
+    let messages = convert_chat_to_messages_with_images(vec![chat], images, false);
+
+    assert_eq!(messages.len(), 1);
+    let content = messages[0].content.as_ref().unwrap();
+    assert!(content.images().is_empty());
+    assert!(content.text().starts_with("What is this?"));
+    assert!(content.text().contains("cat.png"));
 }
@@ -0,0 +1,319 @@
+//! Speech to text. Audio is forwarded to the team's SpeechToText model which
+//! needs to support the OpenAI `/audio/transcriptions` endpoint. The console
+//! uses this for voice input and API key holders get the same endpoint.
+
+use crate::errors::CustomError;
+use crate::jwt::Jwt;
+use axum::body::{Body, Bytes};
+use axum::extract::Multipart;
+use axum::response::{IntoResponse, Response};
+use axum::Extension;
+use db::queries::{api_keys, models, teams, token_usage_metrics};
+use db::{Model, ModelType, Pool};
+use http::HeaderMap;
+use reqwest::multipart::{Form, Part};
+use serde::Deserialize;
+use std::time::Instant;
+
+use super::{limits, ApiTranscriptionsHandler, UITranscribe};
+
+// Called from the console's microphone button
+pub async fn transcribe(
+    UITranscribe { team_id }: UITranscribe,
+    current_user: Jwt,
+    Extension(pool): Extension<Pool>,
+    multipart: Multipart,
+) -> Result<Response<Body>, CustomError> {
+    let mut db_client = pool.get().await?;
+    let transaction = db_client.transaction().await?;
+    let user_id =
+        db::authz::set_row_level_security_user_id(&transaction, current_user.sub.to_string())
+            .await?;
+    // Make sure the user is in the team whose limits apply
+    teams::team().bind(&transaction, &team_id).one().await?;
+    let model = models::models()
+        .bind(&transaction, &ModelType::SpeechToText)
+        .one()
+        .await?;
+    transaction.commit().await?;
+
+    if let Some(limit) = limits::check_limit_from_pool(&pool, model.id, user_id, team_id).await? {
+        return Ok(limit.into_response());
+    }
+
+    let form = transcription_form(multipart, &model.name).await?;
+    let (status, body) = send_transcription(&model, form).await?;
+
+    if status.is_success() {
+        let usage = TranscriptionUsage::from_response(&body);
+
+        let transaction = db_client.transaction().await?;
+        db::authz::set_row_level_security_user_id(&transaction, current_user.sub.to_string())
+            .await?;
+
+        if let Some(prompt_tokens) = usage.prompt_tokens {
+            token_usage_metrics::create_user_token_usage_metric()
+                .bind(
+                    &transaction,
+                    &db::TokenUsageType::Prompt,
+                    &prompt_tokens,
+                    &None::<i32>, // duration_ms
+                    &db::TokenUsageSource::Reported,
+                    &Some(model.id),
+                )
+                .one()
+                .await?;
+        }
+
+        token_usage_metrics::create_user_token_usage_metric()
+            .bind(
+                &transaction,
+                &db::TokenUsageType::Completion,
+                &usage.completion_tokens,
+                &usage.audio_ms,
+                &usage.completion_source,
+                &Some(model.id),
+            )
+            .one()
+            .await?;
+
+        transaction.commit().await?;
+    }
+
+    Ok(Response::builder()
+        .status(status)
+        .header(http::header::CONTENT_TYPE, "application/json")
+        .body(Body::from(body))?)
+}
+
+// Handles calls to /v1/audio/transcriptions
+pub async fn api_transcribe(
+    ApiTranscriptionsHandler {}: ApiTranscriptionsHandler,
+    Extension(pool): Extension<Pool>,
+    headers: HeaderMap,
+    multipart: Multipart,
+) -> Result<Response<Body>, CustomError> {
+    let api_key = headers
+        .get("Authorization")
+        .ok_or_else(|| CustomError::Authentication("You need an API key".to_string()))?
+        .to_str()
+        .map_err(|_| CustomError::Authentication("Invalid API Key".to_string()))?
+        .replace("Bearer ", "");
+
+    let mut db_client = pool.get().await?;
+    let transaction = db_client.transaction().await?;
+
+    let api_key = api_keys::find_api_key()
+        .bind(&transaction, &api_key)
+        .one()
+        .await
+        .map_err(|_| CustomError::Authentication("Invalid API Key".to_string()))?;
+
+    let model = models::models()
+        .bind(&transaction, &ModelType::SpeechToText)
+        .one()
+        .await
+        .map_err(|_| CustomError::FaultySetup("No speech to text model configured".into()))?;
+
+    if let Some(limit) =
+        limits::check_limit(&transaction, model.id, api_key.user_id, api_key.team_id).await?
+    {
+        return Ok(limit.into_response());
+    }
+
+    let form = transcription_form(multipart, &model.name).await?;
+    let started = Instant::now();
+    let (status, body) = send_transcription(&model, form).await?;
+
+    if status.is_success() {
+        let usage = TranscriptionUsage::from_response(&body);
+        tracing::info!(
+            "Transcribed {}ms of audio in {}ms",
+            usage.audio_ms.unwrap_or_default(),
+            started.elapsed().as_millis()
+        );
+
+        if let Some(prompt_tokens) = usage.prompt_tokens {
+            token_usage_metrics::create_token_usage_metric()
+                .bind(
+                    &transaction,
+                    &None::<i32>, // chat_id
+                    &Some(api_key.id),
+                    &db::TokenUsageType::Prompt,
+                    &prompt_tokens,
+                    &None::<i32>, // duration_ms
+                    &db::TokenUsageSource::Reported,
+                    &Some(model.id),
+                )
+                .one()
+                .await?;
+        }
+
+        token_usage_metrics::create_token_usage_metric()
+            .bind(
+                &transaction,
+                &None::<i32>, // chat_id
+                &Some(api_key.id),
+                &db::TokenUsageType::Completion,
+                &usage.completion_tokens,
+                &usage.audio_ms,
+                &usage.completion_source,
+                &Some(model.id),
+            )
+            .one()
+            .await?;
+
+        transaction.commit().await?;
+    }
+
+    Ok(Response::builder()
+        .status(status)
+        .header(http::header::CONTENT_TYPE, "application/json")
+        .body(Body::from(body))?)
+}
+
+// Copy the incoming form across, the model is always the one we have configured.
+async fn transcription_form(
+    mut multipart: Multipart,
+    model_name: &str,
+) -> Result<Form, CustomError> {
+    let mut form = Form::new().text("model", model_name.to_string());
+    let mut has_file = false;
+
+    while let Some(field) = multipart.next_field().await? {
+        let name = field.name().unwrap_or_default().to_string();
+        match name.as_str() {
+            "model" => {}
+            "file" => {
+                let file_name = field.file_name().unwrap_or("audio.webm").to_string();
+                let content_type = field
+                    .content_type()
+                    .unwrap_or("application/octet-stream")
+                    .to_string();
+                let bytes = field.bytes().await?;
+                let part = Part::bytes(bytes.to_vec())
+                    .file_name(file_name)
+                    .mime_str(&content_type)?;
+                form = form.part("file", part);
+                has_file = true;
+            }
+            _ => {
+                let value = field.text().await?;
+                form = form.text(name, value);
+            }
+        }
+    }
+
+    if !has_file {
+        return Err(CustomError::BadRequest(
+            "An audio file is required".to_string(),
+        ));
+    }
+
+    Ok(form)
+}
+
+async fn send_transcription(
+    model: &Model,
+    form: Form,
+) -> Result<(http::StatusCode, Bytes), CustomError> {
+    let mut request = reqwest::Client::new()
+        .post(format!("{}/audio/transcriptions", model.base_url))
+        .multipart(form);
+    if let Some(api_key) = &model.api_key {
+        request = request.bearer_auth(api_key);
+    }
+
+    let response = request.send().await.map_err(|e| {
+        tracing::error!("Error calling transcription model: {:?}", e);
+        CustomError::ExternalApi("Error calling transcription model".to_string())
+    })?;
+    let status = http::StatusCode::from_u16(response.status().as_u16())
+        .unwrap_or(http::StatusCode::BAD_GATEWAY);
+    let body = response.bytes().await?;
+
+    Ok((status, body))
+}
+
+/// What we record for a transcription. Newer models report token usage,
+/// older ones the length of the audio, otherwise we count the transcript.
+#[derive(Debug, PartialEq)]
+pub struct TranscriptionUsage {
+    pub prompt_tokens: Option<i32>,
+    pub completion_tokens: i32,
+    pub completion_source: db::TokenUsageSource,
+    pub audio_ms: Option<i32>,
+}
+
+impl TranscriptionUsage {
+    pub fn from_response(body: &[u8]) -> Self {
+        #[derive(Deserialize, Default)]
+        struct Usage {
+            input_tokens: Option<i32>,
+            output_tokens: Option<i32>,
+            seconds: Option<f64>,
+        }
+
+        #[derive(Deserialize, Default)]
+        struct Transcription {
+            #[serde(default)]
+            text: String,
+            duration: Option<f64>,
+            usage: Option<Usage>,
+        }
+
+        let transcription: Transcription = serde_json::from_slice(body).unwrap_or_default();
+        let usage = transcription.usage.unwrap_or_default();
+
+        let (completion_tokens, completion_source) = match usage.output_tokens {
+            Some(tokens) => (tokens, db::TokenUsageSource::Reported),
+            None => (
+                openai_api::token_count_from_string(&transcription.text),
+                db::TokenUsageSource::Estimated,
+            ),
+        };
+
+        TranscriptionUsage {
+            prompt_tokens: usage.input_tokens,
+            completion_tokens,
+            completion_source,
+            audio_ms: usage
+                .seconds
+                .or(transcription.duration)
+                .map(|seconds| (seconds * 1000.0) as i32),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_usage_from_tokens() {
+        let body =
+            br#"{"text":"Hello","usage":{"type":"tokens","input_tokens":14,"output_tokens":2}}"#;
+        assert_eq!(
+            TranscriptionUsage::from_response(body),
+            TranscriptionUsage {
+                prompt_tokens: Some(14),
+                completion_tokens: 2,
+                completion_source: db::TokenUsageSource::Reported,
+                audio_ms: None,
+            }
+        );
+    }
+
+    #[test]
+    fn test_usage_from_duration() {
+        let body = br#"{"text":"Hello there","duration":2.5}"#;
+        let usage = TranscriptionUsage::from_response(body);
+        assert_eq!(usage.prompt_tokens, None);
+        assert_eq!(
+            usage.completion_tokens,
+            openai_api::token_count_from_string("Hello there")
+        );
+        assert_eq!(usage.completion_source, db::TokenUsageSource::Estimated);
+        assert_eq!(usage.audio_ms, Some(2500));
+    }
+}
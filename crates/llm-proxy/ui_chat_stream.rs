@@ -3,10 +3,13 @@
 //! ```not_rust
 //! cargo run -p example-reqwest-response
 //! ```
-use super::sse_chat_enricher::{enriched_chat, GenerationEvent};
+use super::generations::{self, Generation};
+use super::sse_chat_enricher::{cancellable_chat, GenerationEvent};
 use super::sse_chat_error::error_to_chat;
 use crate::chat_converter;
 use crate::errors::CustomError;
+use crate::fallback::{self, ChatRoute};
+use crate::history_compaction;
 use crate::jwt::Jwt;
 use crate::moderation::{moderate_chat, strip_tool_data, ModerationVerdict};
 use crate::user_config::UserConfig;
@@ -14,18 +17,19 @@ use axum::response::{sse::Event, Sse};
 use axum::Extension;
 use db::{queries, Pool};
 use db::{ChatRole, ChatStatus};
-use integrations::{execute_tool_calls, get_chat_tools_user_selected, get_tools, ToolScope};
-use openai_api::{BionicChatCompletionRequest, ToolCall};
-use reqwest::{
-    header::{HeaderValue, AUTHORIZATION, CONTENT_TYPE},
-    RequestBuilder,
+use http::StatusCode;
+use integrations::tools::generate_image::TOOL_NAME as GENERATE_IMAGE_TOOL;
+use integrations::{
+    execute_tool_calls, get_chat_tools_user_selected, get_tools, record_tool_call, ToolScope,
 };
+use openai_api::{BionicChatCompletionRequest, ToolCall, Usage};
 use std::sync::Arc;
 use tokio::sync::mpsc;
 use tokio_stream::wrappers::ReceiverStream;
 use tokio_stream::StreamExt;
+use tokio_util::sync::CancellationToken;
 
-use super::{limits, UICompletions};
+use super::{limits, UICompletions, UIStopCompletion};
 
 // Called from the front end to generate a streaming chat with the model
 pub async fn chat_generate(
@@ -35,27 +39,53 @@ pub async fn chat_generate(
     Extension(pool): Extension<Pool>,
 ) -> Result<Sse<impl tokio_stream::Stream<Item = Result<Event, axum::Error>>>, CustomError> {
     match create_request(&pool, &current_user, chat_id, &user_config).await {
-        Ok((request, model_id, user_id)) => {
-            let is_limit_breached =
-                limits::is_limit_exceeded_from_pool(&pool, model_id, user_id).await?;
+        Ok((routes, model_id, user_id, team_id)) => {
+            let limit_breached =
+                limits::check_limit_from_pool(&pool, model_id, user_id, team_id).await?;
 
             // Create a channel for sending SSE events
             let (sender, receiver) = mpsc::channel::<Result<GenerationEvent, axum::Error>>(10);
 
+            // The stop endpoint or axum dropping the stream when the user goes
+            // away cancels the generation and any tool calls still running.
+            let generation = Arc::new(Generation::start(chat_id));
+            let cancel = generation.token();
+            let task_pool = pool.clone();
+            let task_sub = current_user.sub.clone();
+
             // Spawn a task that generates SSE events and sends them into the channel
             tokio::spawn(async move {
-                if is_limit_breached {
-                    // Call your existing function to start generating events
-                    if let Err(e) =
-                        error_to_chat("You have exceeded your token limit for this model", sender)
-                            .await
-                    {
+                if let Some(limit) = limit_breached {
+                    let message = format!(
+                        "You have used {} of your {} tokens per hour for this model, please try again in {} minutes",
+                        limit.tokens_used,
+                        limit.tokens_per_hour,
+                        (limit.reset_seconds + 59) / 60
+                    );
+                    if let Err(e) = error_to_chat(&message, sender).await {
                         tracing::warn!("Limits exceeded: {:?}", e);
                     }
                 } else {
-                    // Call your existing function to start generating events
-                    if let Err(e) = enriched_chat(request, sender, true).await {
-                        tracing::error!("Error generating SSE stream: {:?}", e);
+                    match cancellable_chat(routes, sender, true, cancel).await {
+                        // Nobody was listening, so we save what we have here
+                        Ok(Some(unsent)) => {
+                            save_results(
+                                &task_pool,
+                                &unsent.snapshot,
+                                None,
+                                chat_id,
+                                &task_sub,
+                                ChatStatus::Cancelled,
+                                unsent.model_id,
+                                unsent.merged.and_then(|merged| merged.usage),
+                                &CancellationToken::new(),
+                            )
+                            .await;
+                        }
+                        Ok(None) => {}
+                        Err(e) => {
+                            tracing::error!("Error generating SSE stream: {:?}", e);
+                        }
                     }
                 }
             });
@@ -67,7 +97,9 @@ pub async fn chat_generate(
             let event_stream = receiver_stream.then(move |item| {
                 let pool = Arc::clone(&pool_arc);
                 let sub = Arc::clone(&sub_arc);
+                let generation = Arc::clone(&generation);
                 async move {
+                    let cancel = generation.token();
                     match item {
                         Ok(event) => match event {
                             GenerationEvent::Text(completion_chunk) => {
@@ -75,6 +107,10 @@ pub async fn chat_generate(
                             }
                             GenerationEvent::End(completion_chunk) => {
                                 let mut tool_calls: Option<Vec<ToolCall>> = None;
+                                let usage = completion_chunk
+                                    .merged
+                                    .as_ref()
+                                    .and_then(|merged| merged.usage);
                                 if let Some(merged) = completion_chunk.merged {
                                     if let Some(tcs) = &merged.choices[0].delta.tool_calls {
                                         tracing::info!("Detected tool calls: {:?}", tcs);
@@ -83,13 +119,42 @@ pub async fn chat_generate(
                                 }
 
                                 tracing::debug!("End of stream saving data");
+                                // Saving carries on if the user goes away, so cancelled
+                                // tool calls are still recorded.
+                                let snapshot = completion_chunk.snapshot.clone();
+                                let model_id = completion_chunk.model_id;
+                                let saving = tokio::spawn(async move {
+                                    save_results(
+                                        &pool,
+                                        &snapshot,
+                                        tool_calls,
+                                        chat_id,
+                                        &sub,
+                                        ChatStatus::Success,
+                                        model_id,
+                                        usage,
+                                        &cancel,
+                                    )
+                                    .await;
+                                });
+                                if let Err(e) = saving.await {
+                                    tracing::error!("Error saving chat results: {:?}", e);
+                                }
+
+                                Ok(Event::default().data(completion_chunk.delta))
+                            }
+                            GenerationEvent::Cancelled(completion_chunk) => {
+                                tracing::debug!("Generation stopped saving what we have");
                                 save_results(
                                     &pool,
                                     &completion_chunk.snapshot,
-                                    tool_calls,
+                                    None,
                                     chat_id,
                                     &sub,
-                                    ChatStatus::Success,
+                                    ChatStatus::Cancelled,
+                                    completion_chunk.model_id,
+                                    completion_chunk.merged.and_then(|merged| merged.usage),
+                                    &cancel,
                                 )
                                 .await;
 
@@ -104,6 +169,9 @@ pub async fn chat_generate(
                                 chat_id,
                                 &sub,
                                 ChatStatus::Error,
+                                None,
+                                None,
+                                &cancel,
                             )
                             .await;
                             Err(axum::Error::new(e))
@@ -122,6 +190,9 @@ pub async fn chat_generate(
                 chat_id,
                 &current_user.sub,
                 ChatStatus::Error,
+                None,
+                None,
+                &CancellationToken::new(),
             )
             .await;
             Err(CustomError::FaultySetup(err.to_string()))
@@ -129,7 +200,39 @@ pub async fn chat_generate(
     }
 }
 
+// Called from the stop button in the console while a chat is streaming
+pub async fn stop_generation(
+    UIStopCompletion { chat_id }: UIStopCompletion,
+    current_user: Jwt,
+    Extension(pool): Extension<Pool>,
+) -> Result<StatusCode, CustomError> {
+    let mut db_client = pool.get().await?;
+    let transaction = db_client.transaction().await?;
+    db::authz::set_row_level_security_user_id(&transaction, current_user.sub).await?;
+
+    // Also makes sure the chat belongs to the user
+    let chat = queries::chats::chat()
+        .bind(&transaction, &chat_id)
+        .one()
+        .await?;
+
+    // The generation saves what it has when it stops. If it isn't running
+    // here we don't leave the chat waiting for a response.
+    if !generations::stop(chat.id)
+        && (chat.status == ChatStatus::Pending || chat.status == ChatStatus::InProgress)
+    {
+        queries::chats::set_chat_status()
+            .bind(&transaction, &ChatStatus::Cancelled, &chat.id)
+            .await?;
+    }
+
+    transaction.commit().await?;
+
+    Ok(StatusCode::NO_CONTENT)
+}
+
 // When the chat has completed, store the results in the database.
+#[allow(clippy::too_many_arguments)]
 async fn save_results(
     pool: &Pool,
     snapshot: &str,
@@ -137,6 +240,9 @@ async fn save_results(
     chat_id: i32,
     sub: &str,
     status: ChatStatus, // New parameter
+    model_id: Option<i32>,
+    usage: Option<Usage>,
+    cancel: &CancellationToken,
 ) {
     let mut db_client = match pool.get().await {
         Ok(client) => client,
@@ -179,7 +285,8 @@ async fn save_results(
     let tool_calls_json = serde_json::to_string(&tool_calls).ok();
 
     // Calculate completion tokens from the response
-    let completion_tokens = openai_api::token_count_from_string(snapshot);
+    let (completion_tokens, completion_source) =
+        super::usage::completion_tokens(&transaction, model_id, usage, snapshot).await;
 
     tracing::debug!(
         "save_results: Executing chat query with chat_id: {}",
@@ -199,7 +306,14 @@ async fn save_results(
             return;
         }
 
-        if let Err(e) = queries::chats::new_chat()
+        // A stopped generation keeps what it had so far
+        let assistant_status = if status == ChatStatus::Cancelled {
+            ChatStatus::Cancelled
+        } else {
+            ChatStatus::Success
+        };
+
+        let assistant_chat_id = match queries::chats::new_chat()
             .bind(
                 &transaction,
                 &chat.conversation_id,
@@ -208,13 +322,26 @@ async fn save_results(
                 &tool_calls_json,
                 &snapshot,
                 &ChatRole::Assistant,
-                &ChatStatus::Success,
+                &assistant_status,
             )
             .one()
             .await
         {
-            tracing::error!("Error creating chat: {:?}", e);
-            return;
+            Ok(id) => id,
+            Err(e) => {
+                tracing::error!("Error creating chat: {:?}", e);
+                return;
+            }
+        };
+
+        // Record which model answered, it may have been a fallback
+        if let Some(model_id) = model_id {
+            if let Err(e) = queries::chats::set_chat_model()
+                .bind(&transaction, &model_id, &assistant_chat_id)
+                .await
+            {
+                tracing::error!("Error recording chat model: {:?}", e);
+            }
         }
 
         // Track completion token usage in token_usage_metrics
@@ -226,6 +353,8 @@ async fn save_results(
                 &db::TokenUsageType::Completion,
                 &completion_tokens,
                 &None::<i32>, // duration_ms - could add timing here later
+                &completion_source,
+                &model_id,
             )
             .one()
             .await
@@ -234,6 +363,12 @@ async fn save_results(
             // Don't return here, continue with the rest of the function
         }
 
+        // Replace the prompt estimate if the model told us the real number
+        if let Err(e) = super::usage::report_chat_prompt_tokens(&transaction, chat_id, usage).await
+        {
+            tracing::error!("Error tracking prompt tokens: {:?}", e);
+        }
+
         if let Some(tool_calls) = tool_calls {
             let tool_call_results = execute_tool_calls(
                 tool_calls.clone(),
@@ -241,6 +376,7 @@ async fn save_results(
                 sub.to_string(),
                 chat.conversation_id,
                 chat.prompt_id,
+                cancel,
             )
             .await;
             for tool_call in tool_call_results {
@@ -252,12 +388,12 @@ async fn save_results(
                     }
                 };
 
-                if let Err(e) = queries::chats::new_chat()
+                let tool_chat_id = match queries::chats::new_chat()
                     .bind(
                         &transaction,
                         &chat.conversation_id,
                         &chat.prompt_id,
-                        &Some(tool_call.id),
+                        &Some(tool_call.id.clone()),
                         &None::<String>,
                         &result_json,
                         &ChatRole::Tool,
@@ -266,8 +402,46 @@ async fn save_results(
                     .one()
                     .await
                 {
-                    tracing::error!("Error creating tool call results chat: {:?}", e);
-                    return;
+                    Ok(id) => id,
+                    Err(e) => {
+                        tracing::error!("Error creating tool call results chat: {:?}", e);
+                        return;
+                    }
+                };
+
+                if let Err(e) =
+                    record_tool_call(&transaction, Some(tool_chat_id), None, &tool_call).await
+                {
+                    tracing::error!("Error recording tool call metric: {:?}", e);
+                }
+
+                // Generated images are shown with the tool's chat. Other tools,
+                // i.e. integrations, return JSON we don't control so are ignored.
+                let object_id = tool_call
+                    .result
+                    .get("attachment_object_id")
+                    .and_then(serde_json::Value::as_i64)
+                    .filter(|_| tool_call.name == GENERATE_IMAGE_TOOL);
+                if let Some(object_id) = object_id {
+                    let object_id = object_id as i32;
+                    match queries::generated_images::is_users_image()
+                        .bind(&transaction, &object_id)
+                        .one()
+                        .await
+                    {
+                        Ok(true) => {
+                            if let Err(e) = queries::attachments::insert()
+                                .bind(&transaction, &tool_chat_id, &object_id)
+                                .await
+                            {
+                                tracing::error!("Error linking tool attachment: {:?}", e);
+                            }
+                        }
+                        Ok(false) => {
+                            tracing::warn!("Not linking image {} to chat", object_id);
+                        }
+                        Err(e) => tracing::error!("Error checking generated image: {:?}", e),
+                    }
                 }
             }
         }
@@ -289,7 +463,10 @@ async fn create_request(
     current_user: &Jwt,
     chat_id: i32,
     user_config: &UserConfig,
-) -> Result<(RequestBuilder, i32, i32), CustomError> {
+) -> Result<(Vec<ChatRoute>, i32, i32, i32), CustomError> {
+    // Summarising the history calls a model, so do it before we start
+    history_compaction::compact(pool, &current_user.sub, chat_id).await?;
+
     let mut db_client = pool.get().await?;
     let transaction = db_client.transaction().await?;
     db::authz::set_row_level_security_user_id(&transaction, current_user.sub.to_string()).await?;
@@ -345,29 +522,48 @@ async fn create_request(
         .bind(&transaction, &chat.prompt_id, &conversation.team_id)
         .one()
         .await?;
-    // Get the maximum required amount of chat history
-    let chat_history = queries::chats::chat_history()
-        .bind(
-            &transaction,
-            &conversation.id,
-            &(prompt.max_history_items as i64),
-        )
-        .all()
-        .await?;
+    // Get the maximum required amount of chat history, after the summary if
+    // the assistant summarises
+    let (history_summary, chat_history) =
+        history_compaction::history(&transaction, &prompt, conversation.id).await?;
 
     tracing::debug!("{:?}", &chat_history);
 
-    let chat_history = chat_converter::convert_chat_to_messages(chat_history);
+    let vision = capabilities
+        .iter()
+        .any(|c| c.capability == db::ModelCapability::vision);
+
+    // Only load images for the chats that made it into the history
+    let images = if attachment_count > 0 {
+        let chat_ids: Vec<i32> = chat_history.iter().map(|chat| chat.id).collect();
+        queries::attachments::get_images_by_chats()
+            .bind(&transaction, &chat_ids)
+            .all()
+            .await?
+    } else {
+        Vec::new()
+    };
 
-    let messages = super::prompt::execute_prompt(
+    if !images.is_empty() && !vision {
+        tracing::info!(
+            "{} has no vision capability, leaving out images",
+            model.name
+        );
+    }
+
+    let chat_history =
+        chat_converter::convert_chat_to_messages_with_images(chat_history, images, vision);
+
+    let (messages, _chunk_ids) = super::prompt::execute_prompt(
         &transaction,
         prompt.clone(),
         Some(conversation.id),
+        history_summary,
         chat_history,
     )
     .await?;
 
-    let size = openai_api::token_count(messages.clone());
+    let size = openai_api::token_count_with(messages.clone(), &prompt.tokenizer);
 
     // Track prompt tokens in the new token_usage_metrics table
     queries::token_usage_metrics::create_token_usage_metric()
@@ -378,6 +574,8 @@ async fn create_request(
             &db::TokenUsageType::Prompt,
             &size,
             &None::<i32>, // duration_ms
+            &db::TokenUsageSource::Estimated,
+            &None::<i32>, // model_id, the chat records it
         )
         .one()
         .await?;
@@ -472,8 +670,6 @@ async fn create_request(
         }
     }
 
-    transaction.commit().await?;
-
     let completion = BionicChatCompletionRequest {
         model: model.name,
         stream: Some(true),
@@ -482,23 +678,14 @@ async fn create_request(
         messages,
         tools,
         tool_choice: None,
+        stream_options: None,
     };
-    let completion_json = serde_json::to_string(&completion)?;
 
-    tracing::debug!(completion_json);
+    tracing::debug!("{:?}", &completion);
 
-    let client = reqwest::Client::new();
-    let request = if let Some(api_key) = model.api_key {
-        client
-            .post(format!("{}/chat/completions", model.base_url))
-            .header(AUTHORIZATION, format!("Bearer {}", api_key))
-            .header(CONTENT_TYPE, HeaderValue::from_static("application/json"))
-            .body(completion_json.to_string())
-    } else {
-        client
-            .post(format!("{}/chat/completions", model.base_url))
-            .header(CONTENT_TYPE, HeaderValue::from_static("application/json"))
-            .body(completion_json.to_string())
-    };
-    Ok((request, model.id, conversation.user_id))
+    let routes = fallback::chat_routes(&transaction, model.id, &completion).await?;
+
+    transaction.commit().await?;
+
+    Ok((routes, model.id, conversation.user_id, conversation.team_id))
 }
@@ -0,0 +1,67 @@
+//! The token counts we record in `token_usage_metrics`. When the model sends a
+//! usage block we record its numbers, otherwise we estimate with the model's
+//! tokenizer. Each row says which it was.
+
+use crate::errors::CustomError;
+use db::queries::{models, token_usage_metrics};
+use db::{TokenUsageSource, Transaction};
+use openai_api::Usage;
+
+// The tokens in a reply, counted with the tokenizer of the model that
+// answered if it didn't tell us.
+pub async fn completion_tokens(
+    transaction: &Transaction<'_>,
+    model_id: Option<i32>,
+    usage: Option<Usage>,
+    text: &str,
+) -> (i32, TokenUsageSource) {
+    if let Some(tokens) = usage.and_then(|usage| usage.completion_tokens) {
+        return (tokens as i32, TokenUsageSource::Reported);
+    }
+
+    let tokenizer = match model_id {
+        Some(model_id) => models::model()
+            .bind(transaction, &model_id)
+            .one()
+            .await
+            .map(|model| model.tokenizer)
+            .ok(),
+        None => None,
+    };
+    let tokens = openai_api::token_count_from_string_with(
+        text,
+        tokenizer
+            .as_deref()
+            .unwrap_or(openai_api::tokenizer::DEFAULT_TOKENIZER),
+    );
+    (tokens, TokenUsageSource::Estimated)
+}
+
+// The prompt is recorded as an estimate before it's sent, swap in what the
+// model reported.
+pub async fn report_prompt_tokens(
+    transaction: &Transaction<'_>,
+    metric_id: i64,
+    usage: Option<Usage>,
+) -> Result<(), CustomError> {
+    if let Some(tokens) = usage.and_then(|usage| usage.prompt_tokens) {
+        token_usage_metrics::report_prompt_tokens()
+            .bind(transaction, &(tokens as i32), &metric_id)
+            .await?;
+    }
+    Ok(())
+}
+
+// As `report_prompt_tokens` for the prompt of a console chat
+pub async fn report_chat_prompt_tokens(
+    transaction: &Transaction<'_>,
+    chat_id: i32,
+    usage: Option<Usage>,
+) -> Result<(), CustomError> {
+    if let Some(tokens) = usage.and_then(|usage| usage.prompt_tokens) {
+        token_usage_metrics::report_chat_prompt_tokens()
+            .bind(transaction, &(tokens as i32), &chat_id)
+            .await?;
+    }
+    Ok(())
+}
@@ -109,6 +109,32 @@ pub fn resize_image(bytes: &[u8], size: Option<(u32, u32)>) -> Result<Vec<u8>, S
     }
 }
 
+/// Turns an image into a data URL for a vision model, shrinking it first
+/// if either side is larger than `max_dimension`.
+pub fn image_data_url(
+    bytes: &[u8],
+    mime_type: &str,
+    max_dimension: u32,
+) -> Result<String, StorageError> {
+    let (width, height) = image::ImageReader::new(std::io::Cursor::new(bytes))
+        .with_guessed_format()
+        .map_err(|e| StorageError::InvalidInput(e.to_string()))?
+        .into_dimensions()
+        .map_err(|e| StorageError::InvalidInput(e.to_string()))?;
+
+    let bytes = if width > max_dimension || height > max_dimension {
+        resize_image(bytes, Some((max_dimension, max_dimension)))?
+    } else {
+        bytes.to_vec()
+    };
+
+    Ok(format!(
+        "data:{};base64,{}",
+        mime_type,
+        base64::encode(bytes)
+    ))
+}
+
 pub async fn get(pool: Pool, id: i32) -> Result<ObjectStorage, StorageError> {
     let mut client = pool.get().await?;
     let transaction = client.transaction().await?;
@@ -1,10 +1,13 @@
 use serde::{Deserialize, Serialize};
 use serde_json::Value;
 pub mod token_count;
+pub mod tokenizer;
 
-pub use token_count::{token_count, token_count_from_string};
+pub use token_count::{
+    token_count, token_count_from_string, token_count_from_string_with, token_count_with,
+};
 
-#[derive(Serialize, Deserialize, Debug)]
+#[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct BionicChatCompletionRequest {
     pub model: String,
     #[serde(skip_serializing_if = "Option::is_none")]
@@ -18,6 +21,14 @@ pub struct BionicChatCompletionRequest {
     pub tools: Option<Vec<BionicToolDefinition>>,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub tool_choice: Option<serde_json::Value>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub stream_options: Option<StreamOptions>,
+}
+
+/// Asks for a final chunk with the usage when streaming.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, Eq, PartialEq)]
+pub struct StreamOptions {
+    pub include_usage: bool,
 }
 
 #[derive(Deserialize, Serialize, Clone, Debug, Eq, PartialEq)]
@@ -41,6 +52,9 @@ pub struct ChatCompletionFunctionDefinition {
 /// A delta chat completion, which is streamed token by token.
 pub type ChatCompletionDelta = ChatCompletionGeneric<ChatCompletionChoiceDelta>;
 
+/// A full chat completion, as returned when not streaming.
+pub type ChatCompletion = ChatCompletionGeneric<ChatCompletionChoice>;
+
 #[derive(Deserialize, Clone, Debug, Eq, PartialEq)]
 pub struct ChatCompletionGeneric<C> {
     pub id: String,
@@ -58,6 +72,25 @@ pub struct Usage {
     pub total_tokens: Option<u32>,
 }
 
+/// A section of a document given to the model as context. The model cites
+/// it as `[number]`, we return these alongside the response.
+#[derive(Deserialize, Serialize, Clone, Debug, Eq, PartialEq)]
+pub struct Citation {
+    pub number: i32,
+    pub chunk_id: i32,
+    pub file_name: String,
+    pub page_number: i32,
+    /// The start of the section's text.
+    pub preview: String,
+}
+
+#[derive(Deserialize, Clone, Debug, Eq, PartialEq)]
+pub struct ChatCompletionChoice {
+    pub index: u64,
+    pub finish_reason: Option<String>,
+    pub message: ChatCompletionMessage,
+}
+
 #[derive(Deserialize, Clone, Debug, Eq, PartialEq)]
 pub struct ChatCompletionChoiceDelta {
     pub index: u64,
@@ -71,6 +104,10 @@ impl ChatCompletionDelta {
         &mut self,
         other: ChatCompletionDelta,
     ) -> Result<(), ChatCompletionDeltaMergeError> {
+        // Usage comes with the last chunk, when the upstream sends it
+        if other.usage.is_some() {
+            self.usage = other.usage;
+        }
         for other_choice in other.choices.iter() {
             for choice in self.choices.iter_mut() {
                 if choice.index != other_choice.index {
@@ -236,11 +273,11 @@ pub struct ChatCompletionMessageDelta {
 pub struct ChatCompletionMessage {
     /// The role of the author of this message.
     pub role: ChatCompletionMessageRole,
-    /// The contents of the message
+    /// The contents of the message, either plain text or an array of parts
     ///
     /// This is always required for all messages, except for when ChatGPT calls
     /// a function.
-    pub content: Option<String>,
+    pub content: Option<ChatCompletionContent>,
     /// The name of the user in a multi-user chat
     #[serde(skip_serializing_if = "Option::is_none")]
     pub name: Option<String>,
@@ -255,6 +292,88 @@ pub struct ChatCompletionMessage {
     pub tool_calls: Option<Vec<ToolCall>>,
 }
 
+impl ChatCompletionMessage {
+    /// The text of the message with any image parts left out.
+    pub fn text(&self) -> Option<String> {
+        self.content.as_ref().map(ChatCompletionContent::text)
+    }
+}
+
+/// Message content is a plain string or, for vision models, a list of parts.
+#[derive(Deserialize, Serialize, Debug, Clone, Eq, PartialEq)]
+#[serde(untagged)]
+pub enum ChatCompletionContent {
+    Text(String),
+    Parts(Vec<ChatCompletionContentPart>),
+}
+
+impl ChatCompletionContent {
+    /// Joins up the text parts.
+    pub fn text(&self) -> String {
+        match self {
+            ChatCompletionContent::Text(text) => text.clone(),
+            ChatCompletionContent::Parts(parts) => parts
+                .iter()
+                .filter_map(|part| match part {
+                    ChatCompletionContentPart::Text { text } => Some(text.as_str()),
+                    ChatCompletionContentPart::ImageUrl { .. } => None,
+                })
+                .collect::<Vec<&str>>()
+                .join("\n"),
+        }
+    }
+
+    pub fn images(&self) -> Vec<&ImageUrl> {
+        match self {
+            ChatCompletionContent::Text(_) => Vec::new(),
+            ChatCompletionContent::Parts(parts) => parts
+                .iter()
+                .filter_map(|part| match part {
+                    ChatCompletionContentPart::ImageUrl { image_url } => Some(image_url),
+                    ChatCompletionContentPart::Text { .. } => None,
+                })
+                .collect(),
+        }
+    }
+}
+
+impl From<String> for ChatCompletionContent {
+    fn from(text: String) -> Self {
+        ChatCompletionContent::Text(text)
+    }
+}
+
+impl From<&str> for ChatCompletionContent {
+    fn from(text: &str) -> Self {
+        ChatCompletionContent::Text(text.to_string())
+    }
+}
+
+#[derive(Deserialize, Serialize, Debug, Clone, Eq, PartialEq)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum ChatCompletionContentPart {
+    Text { text: String },
+    ImageUrl { image_url: ImageUrl },
+}
+
+#[derive(Deserialize, Serialize, Debug, Clone, Eq, PartialEq)]
+pub struct ImageUrl {
+    /// Either a https URL or a `data:image/png;base64,...` data URL.
+    pub url: String,
+    /// One of `low`, `high` or `auto`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub detail: Option<String>,
+}
+
+impl ImageUrl {
+    /// Splits a base64 data URL into its mime type and data.
+    pub fn base64_data(&self) -> Option<(&str, &str)> {
+        let rest = self.url.strip_prefix("data:")?;
+        let (mime_type, data) = rest.split_once(";base64,")?;
+        Some((mime_type, data))
+    }
+}
+
 #[derive(Deserialize, Serialize, Clone, Debug, Eq, PartialEq)]
 pub struct ToolCall {
     /// The ID of the tool call.
@@ -277,6 +396,22 @@ pub struct ToolCallResult {
     pub result: serde_json::Value,
     /// The name of the function that was called.
     pub name: String,
+    /// How long the tool ran for in milliseconds.
+    #[serde(default)]
+    pub duration_ms: u64,
+    /// Whether the tool finished or was stopped.
+    #[serde(default)]
+    pub status: ToolCallStatus,
+}
+
+#[derive(Deserialize, Serialize, Clone, Copy, Debug, Eq, PartialEq, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum ToolCallStatus {
+    #[default]
+    Success,
+    Error,
+    Timeout,
+    Cancelled,
 }
 
 #[derive(Deserialize, Serialize, Clone, Debug, Eq, PartialEq, Default)]
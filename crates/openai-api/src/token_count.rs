@@ -1,34 +1,75 @@
+use crate::tokenizer::{count_tokens, DEFAULT_TOKENIZER};
 use crate::{ChatCompletionMessage, ChatCompletionMessageRole};
-use tiktoken_rs::{num_tokens_from_messages, ChatCompletionRequestMessage};
+
+// OpenAI charges 85 tokens for a low detail image. High detail images are
+// scaled to fit 768px on the short side then cost 170 per 512px tile on top.
+// We don't decode images to get their size, the UI sends images at up to
+// 1024px which is at most 4 tiles, so we count that and overestimate smaller
+// images rather than run over the context.
+const LOW_DETAIL_IMAGE_TOKENS: i32 = 85;
+const HIGH_DETAIL_IMAGE_TOKENS: i32 = LOW_DETAIL_IMAGE_TOKENS + 4 * 170;
+
+// Chat formats wrap every message in a few tokens, and a few more prime the
+// reply. These are OpenAI's numbers, other models are close enough.
+const TOKENS_PER_MESSAGE: i32 = 3;
+const TOKENS_PER_REPLY: i32 = 3;
 
 pub fn token_count(messages: Vec<ChatCompletionMessage>) -> i32 {
-    let messages: Vec<ChatCompletionRequestMessage> = messages
+    token_count_with(messages, DEFAULT_TOKENIZER)
+}
+
+/// As `token_count` using the model's tokenizer, see `tokenizer::available`.
+pub fn token_count_with(messages: Vec<ChatCompletionMessage>, tokenizer: &str) -> i32 {
+    let message_tokens: i32 = messages
         .iter()
-        .map(|msg| ChatCompletionRequestMessage {
-            role: match msg.role {
+        .map(|msg| {
+            let role = match msg.role {
                 ChatCompletionMessageRole::System => "system",
                 ChatCompletionMessageRole::User => "user",
                 ChatCompletionMessageRole::Assistant => "assistant",
                 ChatCompletionMessageRole::Function => "function",
                 ChatCompletionMessageRole::Tool => "tool",
                 ChatCompletionMessageRole::Developer => "developer",
-            }
-            .to_string(),
-            content: msg.content.clone(),
-            name: None,
-            function_call: None,
+            };
+            let text = msg.text().unwrap_or_default();
+            let images: i32 = msg
+                .content
+                .as_ref()
+                .map(|content| {
+                    content
+                        .images()
+                        .iter()
+                        .map(|image| match image.detail.as_deref() {
+                            Some("low") => LOW_DETAIL_IMAGE_TOKENS,
+                            _ => HIGH_DETAIL_IMAGE_TOKENS,
+                        })
+                        .sum()
+                })
+                .unwrap_or_default();
+
+            TOKENS_PER_MESSAGE
+                + count_tokens(role, tokenizer) as i32
+                + count_tokens(&text, tokenizer) as i32
+                + images
         })
-        .collect();
+        .sum();
 
-    num_tokens_from_messages("gpt-4", &messages).unwrap_or(0) as i32
+    message_tokens + TOKENS_PER_REPLY
 }
 
 pub fn token_count_from_string(message: &str) -> i32 {
-    token_count(vec![ChatCompletionMessage {
-        role: ChatCompletionMessageRole::User,
-        content: Some(message.to_string()),
-        tool_call_id: None,
-        tool_calls: None,
-        name: None,
-    }])
+    token_count_from_string_with(message, DEFAULT_TOKENIZER)
+}
+
+pub fn token_count_from_string_with(message: &str, tokenizer: &str) -> i32 {
+    token_count_with(
+        vec![ChatCompletionMessage {
+            role: ChatCompletionMessageRole::User,
+            content: Some(message.into()),
+            tool_call_id: None,
+            tool_calls: None,
+            name: None,
+        }],
+        tokenizer,
+    )
 }
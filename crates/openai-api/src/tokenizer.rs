@@ -0,0 +1,251 @@
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex, OnceLock};
+use tiktoken_rs::{cl100k_base, o200k_base, p50k_base, r50k_base, CoreBPE};
+use tokenizers::Tokenizer;
+
+// The tokenizers a model can declare in the models table. HuggingFace
+// tokenizers found in the tokenizer directory can be declared as well.
+pub const DEFAULT_TOKENIZER: &str = "cl100k_base";
+pub const TOKENIZERS: &[&str] = &["cl100k_base", "o200k_base", "p50k_base", "r50k_base"];
+
+// Where HuggingFace tokenizers live, either as `<name>.json` or
+// `<name>/tokenizer.json`.
+const DEFAULT_TOKENIZER_DIR: &str = "/tokenizers";
+
+static CL100K: OnceLock<Option<CoreBPE>> = OnceLock::new();
+static O200K: OnceLock<Option<CoreBPE>> = OnceLock::new();
+static P50K: OnceLock<Option<CoreBPE>> = OnceLock::new();
+static R50K: OnceLock<Option<CoreBPE>> = OnceLock::new();
+
+enum Encoding {
+    Tiktoken(&'static CoreBPE),
+    HuggingFace(Arc<Tokenizer>),
+}
+
+// Loading an encoding is slow so each one is loaded once. Anything we
+// don't recognise or can't load gets the default.
+fn encoding(tokenizer: &str) -> Option<Encoding> {
+    let bpe = match tokenizer {
+        "cl100k_base" => CL100K.get_or_init(|| cl100k_base().ok()).as_ref(),
+        "o200k_base" => O200K.get_or_init(|| o200k_base().ok()).as_ref(),
+        "p50k_base" => P50K.get_or_init(|| p50k_base().ok()).as_ref(),
+        "r50k_base" => R50K.get_or_init(|| r50k_base().ok()).as_ref(),
+        _ => match hugging_face(tokenizer) {
+            Some(tokenizer) => return Some(Encoding::HuggingFace(tokenizer)),
+            None => CL100K.get_or_init(|| cl100k_base().ok()).as_ref(),
+        },
+    };
+    bpe.map(Encoding::Tiktoken)
+}
+
+fn tokenizer_dir() -> PathBuf {
+    std::env::var("TOKENIZER_DIR")
+        .unwrap_or_else(|_| DEFAULT_TOKENIZER_DIR.to_string())
+        .into()
+}
+
+fn tokenizer_file(dir: &Path, name: &str) -> Option<PathBuf> {
+    // Names come from the models table, don't let them leave the directory
+    if name.is_empty() || name.contains(['/', '\\']) || name.starts_with('.') {
+        return None;
+    }
+    [
+        dir.join(format!("{}.json", name)),
+        dir.join(name).join("tokenizer.json"),
+    ]
+    .into_iter()
+    .find(|path| path.is_file())
+}
+
+// Failures are remembered too, so we only look on disk once per name
+fn hugging_face(name: &str) -> Option<Arc<Tokenizer>> {
+    static LOADED: OnceLock<Mutex<HashMap<String, Option<Arc<Tokenizer>>>>> = OnceLock::new();
+    let mut loaded = LOADED
+        .get_or_init(|| Mutex::new(HashMap::new()))
+        .lock()
+        .unwrap_or_else(|e| e.into_inner());
+
+    loaded
+        .entry(name.to_string())
+        .or_insert_with(|| {
+            let file = tokenizer_file(&tokenizer_dir(), name)?;
+            Tokenizer::from_file(file).ok().map(Arc::new)
+        })
+        .clone()
+}
+
+/// Every tokenizer a model can use, the tiktoken encodings followed by the
+/// HuggingFace tokenizers in the tokenizer directory.
+pub fn available() -> Vec<String> {
+    let dir = tokenizer_dir();
+    let mut hugging_face: Vec<String> = std::fs::read_dir(&dir)
+        .into_iter()
+        .flatten()
+        .filter_map(|entry| {
+            let path = entry.ok()?.path();
+            let name = if path.is_dir() {
+                path.file_name()?.to_str()?.to_string()
+            } else if path.extension()?.to_str() == Some("json") {
+                path.file_stem()?.to_str()?.to_string()
+            } else {
+                return None;
+            };
+            tokenizer_file(&dir, &name).map(|_| name)
+        })
+        .collect();
+    hugging_face.sort();
+    hugging_face.dedup();
+
+    TOKENIZERS
+        .iter()
+        .map(|tokenizer| tokenizer.to_string())
+        .chain(hugging_face)
+        .collect()
+}
+
+pub fn count_tokens(text: &str, tokenizer: &str) -> usize {
+    match encoding(tokenizer) {
+        Some(Encoding::Tiktoken(bpe)) => bpe.encode_ordinary(text).len(),
+        Some(Encoding::HuggingFace(tokenizer)) => match tokenizer.encode(text, false) {
+            Ok(encoding) => encoding.len(),
+            Err(_) => text.chars().count(),
+        },
+        None => text.chars().count(),
+    }
+}
+
+/// Split text into pieces of at most `max_tokens` tokens, nothing is lost.
+pub fn split_by_tokens(text: &str, tokenizer: &str, max_tokens: usize) -> Vec<String> {
+    let max_tokens = max_tokens.max(1);
+
+    let bpe = match encoding(tokenizer) {
+        Some(Encoding::Tiktoken(bpe)) => bpe,
+        Some(Encoding::HuggingFace(tokenizer)) => {
+            return split_hugging_face(&tokenizer, text, max_tokens)
+        }
+        None => return split_chars(text, max_tokens),
+    };
+
+    let tokens = bpe.encode_ordinary(text);
+    if tokens.len() <= max_tokens {
+        return vec![text.to_string()];
+    }
+
+    let mut pieces = Vec::new();
+    let mut start = 0;
+
+    while start < tokens.len() {
+        let limit = (start + max_tokens).min(tokens.len());
+
+        // A character can be spread over several tokens so a window may
+        // not decode. Try shorter windows first, then slightly longer ones.
+        let ends = (start + 1..=limit)
+            .rev()
+            .chain(limit + 1..=(limit + 4).min(tokens.len()));
+
+        let mut decoded = None;
+        for end in ends {
+            if let Ok(piece) = bpe.decode(tokens[start..end].to_vec()) {
+                decoded = Some((piece, end));
+                break;
+            }
+        }
+
+        match decoded {
+            Some((piece, end)) => {
+                pieces.push(piece);
+                start = end;
+            }
+            // Shouldn't happen, but don't loop forever
+            None => break,
+        }
+    }
+
+    pieces
+}
+
+// Without a tokenizer one character per token is the safe guess
+fn split_chars(text: &str, max_tokens: usize) -> Vec<String> {
+    let chars: Vec<char> = text.chars().collect();
+    chars
+        .chunks(max_tokens)
+        .map(|piece| piece.iter().collect())
+        .collect()
+}
+
+// HuggingFace tells us where each token starts, so we cut the text there
+// rather than decoding tokens, which isn't lossless for every tokenizer.
+fn split_hugging_face(tokenizer: &Tokenizer, text: &str, max_tokens: usize) -> Vec<String> {
+    let Ok(encoding) = tokenizer.encode_char_offsets(text, false) else {
+        return split_chars(text, max_tokens);
+    };
+    if encoding.len() <= max_tokens {
+        return vec![text.to_string()];
+    }
+
+    // Offsets count characters, we need byte positions to slice
+    let bytes: Vec<usize> = text
+        .char_indices()
+        .map(|(index, _)| index)
+        .chain(std::iter::once(text.len()))
+        .collect();
+
+    let mut pieces = Vec::new();
+    let mut start = 0;
+    for (offset, _) in encoding.get_offsets().iter().step_by(max_tokens).skip(1) {
+        let end = bytes[(*offset).min(bytes.len() - 1)];
+        if end > start {
+            pieces.push(text[start..end].to_string());
+            start = end;
+        }
+    }
+    pieces.push(text[start..].to_string());
+    pieces
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_split_by_tokens_keeps_everything() {
+        let text = "The quick brown fox jumps over the lazy dog. ".repeat(20);
+        let pieces = split_by_tokens(&text, DEFAULT_TOKENIZER, 16);
+
+        assert!(pieces.len() > 1);
+        assert!(pieces
+            .iter()
+            .all(|piece| count_tokens(piece, DEFAULT_TOKENIZER) <= 16));
+        assert_eq!(pieces.concat(), text);
+    }
+
+    #[test]
+    fn test_split_multi_byte_characters() {
+        let text = "Grüße aus Köln 😀 ".repeat(10);
+        let pieces = split_by_tokens(&text, "o200k_base", 5);
+        assert_eq!(pieces.concat(), text);
+    }
+
+    #[test]
+    fn test_unknown_tokenizer_uses_default() {
+        assert_eq!(
+            count_tokens("hello world", "not-a-tokenizer"),
+            count_tokens("hello world", DEFAULT_TOKENIZER)
+        );
+    }
+
+    #[test]
+    fn test_tokenizer_names_stay_in_the_directory() {
+        let dir = Path::new("/tmp");
+        assert_eq!(tokenizer_file(dir, "../etc/passwd"), None);
+        assert_eq!(tokenizer_file(dir, ".hidden"), None);
+        assert_eq!(tokenizer_file(dir, ""), None);
+    }
+
+    #[test]
+    fn test_available_starts_with_tiktoken() {
+        let tiktoken: Vec<String> = TOKENIZERS.iter().map(|t| t.to_string()).collect();
+        assert!(available().starts_with(&tiktoken));
+    }
+}
@@ -0,0 +1,71 @@
+use openai_api::{ChatCompletionContent, ChatCompletionMessage, ChatCompletionMessageRole};
+use serde_json::json;
+
+#[test]
+fn plain_text_content_round_trips() {
+    let message: ChatCompletionMessage =
+        serde_json::from_value(json!({ "role": "user", "content": "Hello" })).unwrap();
+
+    assert_eq!(message.content, Some(ChatCompletionContent::from("Hello")));
+    assert_eq!(serde_json::to_value(&message).unwrap()["content"], "Hello");
+}
+
+#[test]
+fn content_parts_with_images() {
+    let message: ChatCompletionMessage = serde_json::from_value(json!({
+        "role": "user",
+        "content": [
+            { "type": "text", "text": "What is in this picture?" },
+            { "type": "image_url", "image_url": { "url": "data:image/jpeg;base64,/9j/4AAQ", "detail": "low" } }
+        ]
+    }))
+    .unwrap();
+
+    assert_eq!(message.role, ChatCompletionMessageRole::User);
+    assert_eq!(message.text().as_deref(), Some("What is in this picture?"));
+
+    let content = message.content.as_ref().unwrap();
+    let images = content.images();
+    assert_eq!(images.len(), 1);
+    assert_eq!(images[0].detail.as_deref(), Some("low"));
+    assert_eq!(images[0].base64_data(), Some(("image/jpeg", "/9j/4AAQ")));
+
+    let value = serde_json::to_value(&message).unwrap();
+    assert_eq!(value["content"][1]["type"], "image_url");
+}
+
+#[test]
+fn images_add_to_token_count() {
+    let text = ChatCompletionMessage {
+        role: ChatCompletionMessageRole::User,
+        content: Some("Describe this".into()),
+        ..Default::default()
+    };
+    let with_image: ChatCompletionMessage = serde_json::from_value(json!({
+        "role": "user",
+        "content": [
+            { "type": "text", "text": "Describe this" },
+            { "type": "image_url", "image_url": { "url": "https://example.com/cat.png" } }
+        ]
+    }))
+    .unwrap();
+
+    assert!(openai_api::token_count(vec![with_image]) > openai_api::token_count(vec![text]));
+}
+
+#[test]
+fn low_detail_images_count_less() {
+    let image = |detail: &str| -> ChatCompletionMessage {
+        serde_json::from_value(json!({
+            "role": "user",
+            "content": [
+                { "type": "image_url", "image_url": { "url": "https://example.com/cat.png", "detail": detail } }
+            ]
+        }))
+        .unwrap()
+    };
+
+    assert!(
+        openai_api::token_count(vec![image("low")]) < openai_api::token_count(vec![image("high")])
+    );
+}
@@ -0,0 +1,610 @@
+//! In process chunking for text based documents so small deployments can
+//! run without the Unstructured container. PDFs and Office documents are
+//! still sent to Unstructured.
+
+use db::types::public::ChunkingStrategy;
+use serde_json::Value;
+
+// Separators tried in order, from the largest structure down to characters.
+const SEPARATORS: &[&str] = &["\n\n", "\n", ". ", " ", ""];
+const MARKDOWN_SEPARATORS: &[&str] = &[
+    "\n# ", "\n## ", "\n### ", "\n#### ", "\n\n", "\n", ". ", " ", "",
+];
+
+// Tags that start a new block of text when we flatten HTML
+const BLOCK_TAGS: &[&str] = &[
+    "p",
+    "div",
+    "section",
+    "article",
+    "header",
+    "footer",
+    "main",
+    "nav",
+    "aside",
+    "table",
+    "ul",
+    "ol",
+    "blockquote",
+    "pre",
+    "hr",
+    "h1",
+    "h2",
+    "h3",
+    "h4",
+    "h5",
+    "h6",
+    "title",
+];
+const LINE_TAGS: &[&str] = &["br", "li", "tr", "dt", "dd"];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Format {
+    Text,
+    Markdown,
+    Html,
+    Csv,
+    Json,
+}
+
+impl Format {
+    pub fn from_file_name(file_name: &str) -> Option<Format> {
+        let (_, extension) = file_name.rsplit_once('.')?;
+        match extension.to_lowercase().as_str() {
+            "txt" | "text" | "log" => Some(Format::Text),
+            "md" | "markdown" => Some(Format::Markdown),
+            "html" | "htm" => Some(Format::Html),
+            "csv" => Some(Format::Csv),
+            "json" => Some(Format::Json),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Strategy {
+    // Split on paragraphs, then lines, then sentences, then words.
+    Recursive,
+    // Pack whole sentences into each chunk.
+    Sentence,
+    // Fixed windows of tokens, sizes are counted in tokens not characters.
+    TokenWindow,
+}
+
+impl Strategy {
+    // ByTitle is an Unstructured strategy so we don't handle it here.
+    pub fn from_chunking_strategy(chunking_strategy: ChunkingStrategy) -> Option<Strategy> {
+        match chunking_strategy {
+            ChunkingStrategy::Recursive => Some(Strategy::Recursive),
+            ChunkingStrategy::Sentence => Some(Strategy::Sentence),
+            ChunkingStrategy::TokenWindow => Some(Strategy::TokenWindow),
+            ChunkingStrategy::ByTitle => None,
+        }
+    }
+}
+
+// Returns None if we can't handle the format and the document should go
+// to Unstructured instead.
+pub fn document_to_chunks(
+    file: &[u8],
+    file_name: &str,
+    strategy: Strategy,
+    chunk_size: usize,
+    overlap: usize,
+) -> Option<Vec<String>> {
+    let format = Format::from_file_name(file_name)?;
+    let content = String::from_utf8_lossy(file);
+
+    let text = match format {
+        Format::Text | Format::Markdown => content.replace("\r\n", "\n"),
+        Format::Html => html_to_text(&content),
+        Format::Csv => csv_to_text(&content),
+        Format::Json => json_to_text(&content),
+    };
+
+    let chunk_size = chunk_size.max(1);
+    // An overlap as big as the chunk would never move forward
+    let overlap = overlap.min(chunk_size / 2);
+
+    let separators = if format == Format::Markdown {
+        MARKDOWN_SEPARATORS
+    } else {
+        SEPARATORS
+    };
+
+    let chunks = match strategy {
+        Strategy::Recursive => merge_pieces(
+            recursive_split(&text, separators, chunk_size),
+            chunk_size,
+            overlap,
+        ),
+        Strategy::Sentence => {
+            let pieces = split_sentences(&text)
+                .into_iter()
+                .flat_map(|sentence| recursive_split(&sentence, SEPARATORS, chunk_size))
+                .collect();
+            merge_pieces(pieces, chunk_size, overlap)
+        }
+        Strategy::TokenWindow => token_windows(&text, chunk_size, overlap),
+    };
+
+    Some(chunks)
+}
+
+fn char_count(text: &str) -> usize {
+    text.chars().count()
+}
+
+// Break text into pieces no bigger than chunk_size, using the largest
+// separator that works and falling back to smaller ones.
+fn recursive_split(text: &str, separators: &[&str], chunk_size: usize) -> Vec<String> {
+    if char_count(text) <= chunk_size {
+        return vec![text.to_string()];
+    }
+
+    let Some((separator, smaller_separators)) = separators.split_first() else {
+        return split_chars(text, chunk_size);
+    };
+
+    if separator.is_empty() {
+        return split_chars(text, chunk_size);
+    }
+
+    let mut pieces = Vec::new();
+    for part in split_keeping_separator(text, separator) {
+        if char_count(part) <= chunk_size {
+            pieces.push(part.to_string());
+        } else {
+            pieces.extend(recursive_split(part, smaller_separators, chunk_size));
+        }
+    }
+    pieces
+}
+
+// Headings belong to the section that follows them, any other separator
+// stays with the text before it.
+fn split_keeping_separator<'a>(text: &'a str, separator: &str) -> Vec<&'a str> {
+    let leading = separator.trim_start().starts_with('#');
+    let mut pieces = Vec::new();
+    let mut start = 0;
+
+    for (index, _) in text.match_indices(separator) {
+        let end = if leading {
+            index
+        } else {
+            index + separator.len()
+        };
+        if end > start {
+            pieces.push(&text[start..end]);
+            start = end;
+        }
+    }
+    if start < text.len() {
+        pieces.push(&text[start..]);
+    }
+    pieces
+}
+
+fn split_chars(text: &str, chunk_size: usize) -> Vec<String> {
+    let chars: Vec<char> = text.chars().collect();
+    chars
+        .chunks(chunk_size)
+        .map(|chunk| chunk.iter().collect())
+        .collect()
+}
+
+// Join small pieces back together up to chunk_size, carrying the last
+// `overlap` characters worth of pieces into the next chunk.
+fn merge_pieces(pieces: Vec<String>, chunk_size: usize, overlap: usize) -> Vec<String> {
+    let mut chunks = Vec::new();
+    let mut current: Vec<String> = Vec::new();
+    let mut current_size = 0;
+
+    for piece in pieces {
+        let size = char_count(&piece);
+
+        if current_size + size > chunk_size && !current.is_empty() {
+            chunks.push(current.concat());
+
+            while !current.is_empty()
+                && (current_size > overlap || current_size + size > chunk_size)
+            {
+                let removed = current.remove(0);
+                current_size -= char_count(&removed);
+            }
+        }
+
+        current_size += size;
+        current.push(piece);
+    }
+
+    if !current.is_empty() {
+        chunks.push(current.concat());
+    }
+
+    chunks
+        .into_iter()
+        .map(|chunk| chunk.trim().to_string())
+        .filter(|chunk| !chunk.is_empty())
+        .collect()
+}
+
+fn split_sentences(text: &str) -> Vec<String> {
+    let mut sentences = Vec::new();
+    let mut current = String::new();
+    let mut chars = text.chars().peekable();
+
+    while let Some(c) = chars.next() {
+        current.push(c);
+
+        let boundary = match c {
+            '.' | '!' | '?' => !matches!(chars.peek(), Some(next) if !next.is_whitespace()),
+            // A blank line ends a sentence even without punctuation
+            '\n' => chars.peek() == Some(&'\n'),
+            _ => false,
+        };
+
+        if boundary {
+            while let Some(next) = chars.next_if(|next| next.is_whitespace()) {
+                current.push(next);
+            }
+            sentences.push(std::mem::take(&mut current));
+        }
+    }
+
+    if !current.trim().is_empty() {
+        sentences.push(current);
+    }
+    sentences
+}
+
+fn token_windows(text: &str, chunk_size: usize, overlap: usize) -> Vec<String> {
+    let bpe = match tiktoken_rs::cl100k_base() {
+        Ok(bpe) => bpe,
+        Err(e) => {
+            tracing::error!("Unable to load tokenizer {}", e);
+            return Vec::new();
+        }
+    };
+
+    let tokens = bpe.encode_ordinary(text);
+    let step = chunk_size - overlap;
+    let mut chunks = Vec::new();
+    let mut start = 0;
+
+    while start < tokens.len() {
+        let mut end = (start + chunk_size).min(tokens.len());
+
+        // A window can cut a multi byte character in half, so back off
+        // until the tokens decode.
+        let mut decoded = bpe.decode(tokens[start..end].to_vec());
+        while decoded.is_err() && end > start + 1 {
+            end -= 1;
+            decoded = bpe.decode(tokens[start..end].to_vec());
+        }
+
+        if let Ok(chunk) = decoded {
+            let chunk = chunk.trim();
+            if !chunk.is_empty() {
+                chunks.push(chunk.to_string());
+            }
+        }
+
+        if end == tokens.len() {
+            break;
+        }
+        start += step.min(end - start).max(1);
+    }
+
+    chunks
+}
+
+fn html_to_text(html: &str) -> String {
+    let mut text = String::new();
+    let mut rest = html;
+
+    while let Some(start) = rest.find('<') {
+        text.push_str(&rest[..start]);
+        rest = &rest[start..];
+
+        if rest.starts_with("<!--") {
+            rest = rest.find("-->").map_or("", |end| &rest[end + 3..]);
+            continue;
+        }
+
+        let Some(end) = rest.find('>') else {
+            rest = "";
+            break;
+        };
+
+        let inner = &rest[1..end];
+        let closing = inner.starts_with('/');
+        let tag = inner
+            .trim_start_matches('/')
+            .split(|c: char| c.is_whitespace() || c == '/')
+            .next()
+            .unwrap_or_default()
+            .to_lowercase();
+        rest = &rest[end + 1..];
+
+        // Skip everything up to the closing tag, it isn't text
+        if !closing && (tag == "script" || tag == "style") {
+            let closing_tag = format!("</{}", tag);
+            rest = rest
+                .to_ascii_lowercase()
+                .find(&closing_tag)
+                .map_or("", |index| &rest[index..]);
+            continue;
+        }
+
+        if BLOCK_TAGS.contains(&tag.as_str()) {
+            text.push_str("\n\n");
+        } else if !closing && LINE_TAGS.contains(&tag.as_str()) {
+            text.push('\n');
+        } else if tag == "td" || tag == "th" {
+            text.push(' ');
+        }
+    }
+    text.push_str(rest);
+
+    tidy_whitespace(&decode_entities(&text))
+}
+
+fn decode_entities(text: &str) -> String {
+    let mut decoded = String::new();
+    let mut rest = text;
+
+    while let Some(start) = rest.find('&') {
+        decoded.push_str(&rest[..start]);
+        rest = &rest[start..];
+
+        let entity = rest.find(';').filter(|end| *end <= 10).and_then(|end| {
+            let name = &rest[1..end];
+            let c = match name {
+                "amp" => Some('&'),
+                "lt" => Some('<'),
+                "gt" => Some('>'),
+                "quot" => Some('"'),
+                "apos" => Some('\''),
+                "nbsp" => Some(' '),
+                _ => name
+                    .strip_prefix("#x")
+                    .or_else(|| name.strip_prefix("#X"))
+                    .and_then(|hex| u32::from_str_radix(hex, 16).ok())
+                    .or_else(|| name.strip_prefix('#').and_then(|dec| dec.parse().ok()))
+                    .and_then(char::from_u32),
+            };
+            c.map(|c| (c, end))
+        });
+
+        match entity {
+            Some((c, end)) => {
+                decoded.push(c);
+                rest = &rest[end + 1..];
+            }
+            None => {
+                decoded.push('&');
+                rest = &rest[1..];
+            }
+        }
+    }
+    decoded.push_str(rest);
+    decoded
+}
+
+// Collapse runs of spaces in each line and runs of blank lines
+fn tidy_whitespace(text: &str) -> String {
+    let mut tidy = String::new();
+    let mut blank_lines = 0;
+
+    for line in text.lines() {
+        let line = line.split_whitespace().collect::<Vec<&str>>().join(" ");
+        if line.is_empty() {
+            blank_lines += 1;
+            continue;
+        }
+        if !tidy.is_empty() {
+            tidy.push_str(if blank_lines > 0 { "\n\n" } else { "\n" });
+        }
+        tidy.push_str(&line);
+        blank_lines = 0;
+    }
+    tidy
+}
+
+fn parse_csv(text: &str) -> Vec<Vec<String>> {
+    let mut rows = Vec::new();
+    let mut row = Vec::new();
+    let mut field = String::new();
+    let mut in_quotes = false;
+    let mut chars = text.chars().peekable();
+
+    while let Some(c) = chars.next() {
+        if in_quotes {
+            match c {
+                '"' if chars.peek() == Some(&'"') => {
+                    field.push('"');
+                    chars.next();
+                }
+                '"' => in_quotes = false,
+                _ => field.push(c),
+            }
+        } else {
+            match c {
+                '"' => in_quotes = true,
+                ',' => row.push(std::mem::take(&mut field)),
+                '\r' => {}
+                '\n' => {
+                    row.push(std::mem::take(&mut field));
+                    rows.push(std::mem::take(&mut row));
+                }
+                _ => field.push(c),
+            }
+        }
+    }
+
+    if !field.is_empty() || !row.is_empty() {
+        row.push(field);
+        rows.push(row);
+    }
+    rows
+}
+
+// One line per row with the column names repeated so every chunk makes
+// sense on its own.
+fn csv_to_text(text: &str) -> String {
+    let mut rows = parse_csv(text)
+        .into_iter()
+        .filter(|row| row.iter().any(|field| !field.trim().is_empty()));
+
+    let Some(headers) = rows.next() else {
+        return String::new();
+    };
+
+    rows.map(|row| {
+        row.iter()
+            .enumerate()
+            .map(|(index, value)| match headers.get(index) {
+                Some(header) if !header.trim().is_empty() => {
+                    format!("{}: {}", header.trim(), value.trim())
+                }
+                _ => value.trim().to_string(),
+            })
+            .collect::<Vec<String>>()
+            .join(", ")
+    })
+    .collect::<Vec<String>>()
+    .join("\n")
+}
+
+// Flatten JSON into `path: value` lines. The items of a top level array
+// are separated by blank lines so they stay together when chunked.
+fn json_to_text(text: &str) -> String {
+    match serde_json::from_str::<Value>(text) {
+        Ok(Value::Array(items)) => items
+            .iter()
+            .map(|item| {
+                let mut lines = Vec::new();
+                flatten_json("", item, &mut lines);
+                lines.join("\n")
+            })
+            .filter(|item| !item.is_empty())
+            .collect::<Vec<String>>()
+            .join("\n\n"),
+        Ok(value) => {
+            let mut lines = Vec::new();
+            flatten_json("", &value, &mut lines);
+            lines.join("\n")
+        }
+        Err(_) => text.to_string(),
+    }
+}
+
+fn flatten_json(path: &str, value: &Value, lines: &mut Vec<String>) {
+    let line = |value: &str| {
+        if path.is_empty() {
+            value.to_string()
+        } else {
+            format!("{}: {}", path, value)
+        }
+    };
+
+    match value {
+        Value::Object(map) => {
+            for (key, value) in map {
+                let path = if path.is_empty() {
+                    key.clone()
+                } else {
+                    format!("{}.{}", path, key)
+                };
+                flatten_json(&path, value, lines);
+            }
+        }
+        Value::Array(items) => {
+            for (index, value) in items.iter().enumerate() {
+                flatten_json(&format!("{}[{}]", path, index), value, lines);
+            }
+        }
+        Value::String(value) => lines.push(line(value)),
+        Value::Null => {}
+        value => lines.push(line(&value.to_string())),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_unsupported_formats_go_to_unstructured() {
+        assert!(document_to_chunks(b"%PDF", "report.pdf", Strategy::Recursive, 100, 0).is_none());
+        assert!(document_to_chunks(b"", "slides.pptx", Strategy::Sentence, 100, 0).is_none());
+        assert_eq!(Format::from_file_name("README.MD"), Some(Format::Markdown));
+    }
+
+    #[test]
+    fn test_recursive_prefers_paragraphs() {
+        let text = "First paragraph here.\n\nSecond paragraph here.\n\nThird one.";
+        let chunks =
+            document_to_chunks(text.as_bytes(), "notes.txt", Strategy::Recursive, 30, 0).unwrap();
+        assert_eq!(
+            chunks,
+            vec![
+                "First paragraph here.",
+                "Second paragraph here.",
+                "Third one."
+            ]
+        );
+        assert!(chunks.iter().all(|chunk| chunk.chars().count() <= 30));
+    }
+
+    #[test]
+    fn test_markdown_keeps_headings_with_their_section() {
+        let text = "# Intro\nSome words.\n## Install\nRun the installer.";
+        let chunks =
+            document_to_chunks(text.as_bytes(), "guide.md", Strategy::Recursive, 30, 0).unwrap();
+        assert_eq!(
+            chunks,
+            vec!["# Intro\nSome words.", "## Install\nRun the installer."]
+        );
+    }
+
+    #[test]
+    fn test_sentence_overlap() {
+        let text = "One is first. Two is second. Three is third.";
+        let chunks =
+            document_to_chunks(text.as_bytes(), "a.txt", Strategy::Sentence, 30, 15).unwrap();
+        assert_eq!(
+            chunks,
+            vec![
+                "One is first. Two is second.",
+                "Two is second. Three is third."
+            ]
+        );
+    }
+
+    #[test]
+    fn test_html_to_text() {
+        let html = "<html><head><title>Hi</title><style>p { color: red; }</style></head>\
+            <body><p>Fish &amp; chips</p><script>alert('x')</script><ul><li>One</li><li>Two</li></ul></body></html>";
+        assert_eq!(html_to_text(html), "Hi\n\nFish & chips\n\nOne\nTwo");
+    }
+
+    #[test]
+    fn test_csv_rows_carry_headers() {
+        let csv = "name,comment\nAlice,\"Likes \"\"tea\"\", and cake\"\r\nBob,Coffee\n";
+        assert_eq!(
+            csv_to_text(csv),
+            "name: Alice, comment: Likes \"tea\", and cake\nname: Bob, comment: Coffee"
+        );
+    }
+
+    #[test]
+    fn test_json_is_flattened() {
+        let json = r#"[{"id": 1, "owner": {"name": "Ann"}, "tags": ["a", "b"]}, {"id": 2}]"#;
+        assert_eq!(
+            json_to_text(json),
+            "id: 1\nowner.name: Ann\ntags[0]: a\ntags[1]: b\n\nid: 2"
+        );
+    }
+}
@@ -5,6 +5,9 @@ pub struct Config {
     pub app_database_url: String,
     pub unstructured_endpoint: String,
     pub batch_size: i64,
+    // How many pending chunks we pick up at a time, they are then split
+    // into batches for each embeddings model.
+    pub embeddings_fetch_size: i64,
 }
 
 impl Default for Config {
@@ -28,10 +31,16 @@ impl Config {
             .and_then(|v| v.parse().ok())
             .unwrap_or(10);
 
+        let embeddings_fetch_size = std::env::var("RAG_EMBEDDINGS_FETCH_SIZE")
+            .ok()
+            .and_then(|v| v.parse().ok())
+            .unwrap_or(500);
+
         Config {
             app_database_url,
             unstructured_endpoint,
             batch_size,
+            embeddings_fetch_size,
         }
     }
 }
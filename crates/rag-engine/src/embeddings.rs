@@ -0,0 +1,157 @@
+use std::collections::BTreeMap;
+use std::error::Error;
+use std::future::Future;
+
+use db::queries::chunks::Chunk;
+use futures::stream::{self, StreamExt};
+use tokio::time::{sleep, Duration};
+
+// Attempts per batch before we give up on it
+const MAX_ATTEMPTS: u32 = 4;
+const BASE_DELAY_MS: u64 = 500;
+
+pub struct EmbeddingResult {
+    pub chunk_id: i32,
+    pub model_id: i32,
+    pub result: Result<Vec<f32>, String>,
+}
+
+// Embed the chunks in batches, respecting the batch size and concurrency
+// of each chunk's embeddings model.
+pub async fn embed_chunks(chunks: Vec<Chunk>) -> Vec<EmbeddingResult> {
+    let mut by_model: BTreeMap<i32, Vec<Chunk>> = BTreeMap::new();
+    for chunk in chunks {
+        by_model.entry(chunk.model_id).or_default().push(chunk);
+    }
+
+    let mut results = Vec::new();
+    for (_, chunks) in by_model {
+        let batch_size = chunks[0].embeddings_batch_size.max(1) as usize;
+        let concurrency = chunks[0].embeddings_concurrency.max(1) as usize;
+
+        let embedded: Vec<Vec<EmbeddingResult>> = stream::iter(batches(chunks, batch_size))
+            .map(embed_batch)
+            .buffer_unordered(concurrency)
+            .collect()
+            .await;
+
+        results.extend(embedded.into_iter().flatten());
+    }
+    results
+}
+
+fn batches<T>(items: Vec<T>, batch_size: usize) -> Vec<Vec<T>> {
+    let mut items = items.into_iter().peekable();
+    let mut batches = Vec::new();
+    while items.peek().is_some() {
+        batches.push(items.by_ref().take(batch_size).collect());
+    }
+    batches
+}
+
+async fn embed_batch(batch: Vec<Chunk>) -> Vec<EmbeddingResult> {
+    let first = &batch[0];
+    let inputs: Vec<String> = batch.iter().map(|chunk| chunk.text.clone()).collect();
+
+    let embeddings = with_backoff(|| {
+        embeddings_api::get_embeddings_batch(
+            &inputs,
+            &first.base_url,
+            &first.model,
+            first.context_size,
+            &first.tokenizer,
+            &first.api_key,
+        )
+    })
+    .await;
+
+    match embeddings {
+        Ok(embeddings) => batch
+            .iter()
+            .zip(embeddings)
+            .map(|(chunk, embeddings)| EmbeddingResult {
+                chunk_id: chunk.id,
+                model_id: chunk.model_id,
+                result: Ok(embeddings),
+            })
+            .collect(),
+        // One bad input fails the whole request, so try them one at a time
+        // to find out which chunks are the problem.
+        Err(error) if batch.len() > 1 => {
+            tracing::warn!("Batch of {} failed, retrying singly {}", batch.len(), error);
+            let mut results = Vec::new();
+            for chunk in &batch {
+                let result = with_backoff(|| {
+                    embeddings_api::get_embeddings_batch(
+                        std::slice::from_ref(&chunk.text),
+                        &chunk.base_url,
+                        &chunk.model,
+                        chunk.context_size,
+                        &chunk.tokenizer,
+                        &chunk.api_key,
+                    )
+                })
+                .await
+                .map(|mut embeddings| embeddings.remove(0));
+                results.push(EmbeddingResult {
+                    chunk_id: chunk.id,
+                    model_id: chunk.model_id,
+                    result,
+                });
+            }
+            results
+        }
+        Err(error) => vec![EmbeddingResult {
+            chunk_id: first.id,
+            model_id: first.model_id,
+            result: Err(error),
+        }],
+    }
+}
+
+async fn with_backoff<F, Fut, T>(mut call: F) -> Result<T, String>
+where
+    F: FnMut() -> Fut,
+    Fut: Future<Output = Result<T, Box<dyn Error>>>,
+{
+    let mut attempt = 1;
+    loop {
+        match call().await {
+            Ok(value) => return Ok(value),
+            Err(error) if attempt < MAX_ATTEMPTS => {
+                let delay = backoff_delay(attempt);
+                tracing::warn!(
+                    "Embeddings attempt {} failed, retrying in {:?}: {}",
+                    attempt,
+                    delay,
+                    error
+                );
+                sleep(delay).await;
+                attempt += 1;
+            }
+            Err(error) => return Err(error.to_string()),
+        }
+    }
+}
+
+fn backoff_delay(attempt: u32) -> Duration {
+    Duration::from_millis(BASE_DELAY_MS * 2u64.pow(attempt.saturating_sub(1)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_batches() {
+        let batched = batches((1..=7).collect(), 3);
+        assert_eq!(batched, vec![vec![1, 2, 3], vec![4, 5, 6], vec![7]]);
+    }
+
+    #[test]
+    fn test_backoff_doubles() {
+        assert_eq!(backoff_delay(1), Duration::from_millis(500));
+        assert_eq!(backoff_delay(2), Duration::from_millis(1000));
+        assert_eq!(backoff_delay(3), Duration::from_millis(2000));
+    }
+}
@@ -1,4 +1,6 @@
 //! RAG Engine library for document processing and embedding
 
+pub mod chunker;
 pub mod config;
+pub mod embeddings;
 pub mod unstructured;
@@ -1,4 +1,6 @@
+mod chunker;
 mod config;
+mod embeddings;
 mod unstructured;
 
 use db::queries;
@@ -12,7 +14,7 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
     let config = config::Config::new();
     dbg!(&config);
     let pool = db::create_pool(&config.app_database_url);
-    let client = pool.get().await?;
+    let mut client = pool.get().await?;
 
     loop {
         // Process unprocessed documents in batches until none remain
@@ -32,19 +34,53 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
                     .one()
                     .await?;
 
-                let structured_data = crate::unstructured::document_to_chunks(
-                    document.content,
-                    &document.file_name,
-                    dataset.combine_under_n_chars as u32,
-                    dataset.new_after_n_chars as u32,
-                    dataset.multipage_sections,
-                    &config.unstructured_endpoint,
+                // Text based formats can be chunked here, everything else
+                // goes to Unstructured.
+                let native_chunks = chunker::Strategy::from_chunking_strategy(
+                    dataset.chunking_strategy,
                 )
-                .await;
+                .and_then(|strategy| {
+                    chunker::document_to_chunks(
+                        &document.content,
+                        &document.file_name,
+                        strategy,
+                        dataset.new_after_n_chars as usize,
+                        dataset.chunk_overlap as usize,
+                    )
+                });
+
+                // (page_number, text) for each chunk
+                let structured_data: Result<Vec<(i32, String)>, Box<dyn std::error::Error>> =
+                    match native_chunks {
+                        Some(chunks) => Ok(chunks.into_iter().map(|text| (0, text)).collect()),
+                        None => crate::unstructured::document_to_chunks(
+                            document.content,
+                            &document.file_name,
+                            dataset.combine_under_n_chars as u32,
+                            dataset.new_after_n_chars as u32,
+                            dataset.multipage_sections,
+                            &config.unstructured_endpoint,
+                        )
+                        .await
+                        .map(|sections| {
+                            sections
+                                .into_iter()
+                                .map(|section| {
+                                    (section.metadata.page_number.unwrap_or(0), section.text)
+                                })
+                                .collect()
+                        }),
+                    };
 
                 match structured_data {
+                    Ok(structured_data) if structured_data.is_empty() => {
+                        // Otherwise we'd pick the document up again on the next pass
+                        queries::documents::fail_document()
+                            .bind(&client, &"No text found in document", &document.id)
+                            .await?;
+                    }
                     Ok(structured_data) => {
-                        for text in structured_data {
+                        for (page_number, text) in structured_data {
                             client
                                 .execute(
                                     "
@@ -55,11 +91,7 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
                                 )
                                 VALUES
                                     ($1, $2, encrypt_text($3))",
-                                    &[
-                                        &document.id,
-                                        &text.metadata.page_number.unwrap_or(0),
-                                        &text.text,
-                                    ],
+                                    &[&document.id, &page_number, &text],
                                 )
                                 .await?;
                         }
@@ -78,8 +110,8 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
 
         // Process embeddings in batches until none remain
         loop {
-            let unprocessed = queries::chunks::unprocessed_chunks()
-                .bind(&client, &config.batch_size)
+            let unprocessed = queries::chunks::pending_chunks()
+                .bind(&client, &config.embeddings_fetch_size)
                 .all()
                 .await?;
 
@@ -87,49 +119,138 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
                 break;
             }
 
-            for embedding in unprocessed {
-                match embeddings_api::get_embeddings(
-                    &embedding.text,
-                    &embedding.base_url,
-                    &embedding.model,
-                    embedding.context_size,
-                    &embedding.api_key,
-                )
-                .await
-                {
+            // Chunks that don't fit the model's context are split rather than
+            // trimmed so none of the document becomes unsearchable. The new
+            // chunks are picked up on the next pass.
+            let mut pending = Vec::new();
+            for mut chunk in unprocessed {
+                let mut pieces = embeddings_api::split_to_context_length(
+                    &chunk.text,
+                    chunk.context_size,
+                    &chunk.tokenizer,
+                );
+                if pieces.len() > 1 {
+                    tracing::info!("Splitting chunk {} into {}", chunk.id, pieces.len());
+                    for piece in pieces.drain(1..) {
+                        queries::chunks::insert_split_chunk()
+                            .bind(&client, &piece, &chunk.id)
+                            .await?;
+                    }
+                    chunk.text = pieces.swap_remove(0);
+                    queries::chunks::update_chunk_text()
+                        .bind(&client, &chunk.text, &chunk.id)
+                        .await?;
+                }
+                pending.push(chunk);
+            }
+
+            for embedding in embeddings::embed_chunks(pending).await {
+                match embedding.result {
                     Ok(embeddings) => {
+                        let dimension = embeddings.len() as i32;
                         let embedding_data = pgvector::Vector::from(embeddings);
                         client
                             .execute(
                                 "
-                                UPDATE chunks SET (processed, embeddings) = (TRUE, $1)
-                                WHERE id = $2
+                                UPDATE chunks SET
+                                    embeddings = $1,
+                                    embeddings_model_id = $2,
+                                    embeddings_dimension = $3,
+                                    embedding_status = 'Done',
+                                    failure_reason = NULL,
+                                    updated_at = NOW()
+                                WHERE id = $4
                                 ",
-                                &[&embedding_data, &embedding.id],
+                                &[
+                                    &embedding_data,
+                                    &embedding.model_id,
+                                    &dimension,
+                                    &embedding.chunk_id,
+                                ],
                             )
                             .await?;
-                        tracing::info!("Processing embedding id {:?}", embedding.id);
+                        tracing::info!("Processing embedding id {:?}", embedding.chunk_id);
                     }
                     Err(error) => {
                         tracing::error!(
                             "Failed to process embedding id {:?}: {:?}",
-                            embedding.id,
+                            embedding.chunk_id,
                             error
                         );
+                        queries::chunks::fail_chunk()
+                            .bind(&client, &error, &embedding.chunk_id)
+                            .await?;
+                    }
+                }
+            }
+        }
+
+        // Re-embed datasets that are moving to another embeddings model. The
+        // new vectors are staged so searches keep using the old ones.
+        loop {
+            let reindex = queries::chunks::reindex_chunks()
+                .bind(&client, &config.embeddings_fetch_size)
+                .all()
+                .await?;
+
+            if reindex.is_empty() {
+                break;
+            }
+
+            for embedding in embeddings::embed_chunks(reindex).await {
+                match embedding.result {
+                    Ok(embeddings) => {
+                        let embedding_data = pgvector::Vector::from(embeddings);
                         client
                             .execute(
                                 "
-                                UPDATE chunks SET processed = TRUE
-                                WHERE id = $1
+                                UPDATE chunks SET
+                                    reindex_embeddings = $1,
+                                    reindex_model_id = $2
+                                WHERE id = $3
                                 ",
-                                &[&embedding.id],
+                                &[&embedding_data, &embedding.model_id, &embedding.chunk_id],
                             )
                             .await?;
                     }
+                    Err(error) => {
+                        // Mark the chunk as failed so the rest of the dataset
+                        // carries on, it's retried with the new model once the
+                        // dataset has switched over.
+                        tracing::error!(
+                            "Failed to re-embed chunk id {:?}: {:?}",
+                            embedding.chunk_id,
+                            error
+                        );
+                        queries::chunks::fail_chunk()
+                            .bind(&client, &error, &embedding.chunk_id)
+                            .await?;
+                    }
                 }
             }
         }
 
+        // Swap in the new vectors for datasets that are fully re-embedded
+        let reindexed = queries::datasets::reindexed_datasets()
+            .bind(&client)
+            .all()
+            .await?;
+
+        for dataset_id in reindexed {
+            let transaction = client.transaction().await?;
+            queries::chunks::swap_reindexed_chunks()
+                .bind(&transaction, &dataset_id)
+                .await?;
+            queries::datasets::swap_reindexed_dataset()
+                .bind(&transaction, &dataset_id)
+                .await?;
+            queries::chunks::retry_dataset_failed_chunks()
+                .bind(&transaction, &dataset_id)
+                .await?;
+            transaction.commit().await?;
+            tracing::info!("Dataset {} now uses its new embeddings model", dataset_id);
+        }
+
         tokio::time::sleep(tokio::time::Duration::from_millis(5000)).await;
     }
 }
@@ -7,6 +7,13 @@ export const speechToText = (): void => {
     const button = document.querySelector<HTMLButtonElement>('#speech-to-text-button');
     const textArea = document.querySelector<HTMLTextAreaElement>('.pt-3.auto-expand');
   
+    // When the team has a speech to text model we record audio and transcribe it server side
+    const transcribeUrl = document.querySelector<HTMLInputElement>('#transcribe-url')?.value;
+    if (button && transcribeUrl) {
+      recordAndTranscribe(button, textArea, transcribeUrl);
+      return;
+    }
+
     const SpeechRecognitionConstructor =
       (window as unknown as WindowWithSpeechRecognition).SpeechRecognition ||
       (window as unknown as WindowWithSpeechRecognition).webkitSpeechRecognition;
@@ -136,6 +143,74 @@ export const speechToText = (): void => {
     });
   };
   
+  /**
+   * Records from the microphone until the button is clicked again, then posts
+   * the audio to the transcribe endpoint and appends the text to the message box.
+   */
+  function recordAndTranscribe(
+    button: HTMLButtonElement,
+    textArea: HTMLTextAreaElement | null,
+    transcribeUrl: string
+  ): void {
+    let recorder: MediaRecorder | null = null;
+    let chunks: Blob[] = [];
+
+    const toggleButtonImages = (state: 'idle' | 'listening') => {
+      const prefixImage = button.children.item(0) as HTMLElement | null;
+      const suffixImage = button.children.item(1) as HTMLElement | null;
+      prefixImage?.classList.toggle('hidden', state === 'listening');
+      suffixImage?.classList.toggle('hidden', state === 'idle');
+    };
+    toggleButtonImages('idle');
+
+    const transcribe = async (audio: Blob) => {
+      const form = new FormData();
+      form.append('file', audio, 'recording.webm');
+      button.disabled = true;
+      try {
+        const response = await fetch(transcribeUrl, { method: 'POST', body: form });
+        if (!response.ok) {
+          throw new Error(`Transcription failed with status ${response.status}`);
+        }
+        const { text } = await response.json();
+        if (textArea && text) {
+          textArea.value = textArea.value ? `${textArea.value} ${text}` : text;
+          textArea.dispatchEvent(new Event('input'));
+          textArea.focus();
+        }
+      } catch (error) {
+        console.error('Transcription error:', error);
+        alert('An error occurred while trying to transcribe the audio.');
+      } finally {
+        button.disabled = false;
+      }
+    };
+
+    button.addEventListener('click', async () => {
+      if (recorder && recorder.state === 'recording') {
+        recorder.stop();
+        return;
+      }
+
+      try {
+        const stream = await navigator.mediaDevices.getUserMedia({ audio: true });
+        recorder = new MediaRecorder(stream);
+        chunks = [];
+        recorder.addEventListener('dataavailable', (event) => chunks.push(event.data));
+        recorder.addEventListener('stop', () => {
+          stream.getTracks().forEach((track) => track.stop());
+          toggleButtonImages('idle');
+          transcribe(new Blob(chunks, { type: recorder?.mimeType || 'audio/webm' }));
+        });
+        recorder.start();
+        toggleButtonImages('listening');
+      } catch (error) {
+        console.error('Error starting recording:', error);
+        toggleButtonImages('idle');
+      }
+    });
+  }
+
   // --- Custom interfaces for Web Speech API support ---
   
   interface WindowWithSpeechRecognition extends Window {
@@ -19,8 +19,22 @@ async function streamResult(chatId: string, element: HTMLElement) {
     let result = '';
 
     const stopButton = document.getElementById('streaming-button');
-    const stopListener = (event: Event) => {
-        console.log('Attempting to abort stream.');
+    // The server stops the model and sends what we have so far followed by
+    // [DONE], so the stream ends as usual. If that fails we abort it here.
+    const stopListener = async (event: Event) => {
+        console.log('Attempting to stop generation.');
+        if (stopButton instanceof HTMLButtonElement) {
+            stopButton.disabled = true;
+        }
+        try {
+            const res = await fetch(`/completions/${chatId}/stop`, { method: 'POST' });
+            if (res.ok) {
+                return;
+            }
+            console.error('Stop request failed', res.status);
+        } catch (error) {
+            console.error('Error stopping generation:', error);
+        }
         abortController.abort("User aborted");
     };
 
@@ -16,6 +16,7 @@ pub enum SideBar {
     Console,
     Datasets,
     DocumentPipelines,
+    Feedback,
     Guardrails,
     History,
     Integrations,
@@ -113,6 +114,15 @@ pub fn Layout(props: LayoutProps) -> Element {
                                 icon: nav_ccsds_data_svg.name,
                                 title: "Datasets & Documents"
                             }
+                            if props.rbac.can_view_feedback() {
+                                NavItem {
+                                    id: SideBar::Feedback.to_string(),
+                                    selected_item_id: props.selected_item.to_string(),
+                                    href: super::routes::feedback::Index{team_id: props.team_id},
+                                    icon: nav_feedback_svg.name,
+                                    title: "Answer Feedback"
+                                }
+                            }
                         )
                     }
                 }
@@ -185,12 +195,15 @@ pub fn Layout(props: LayoutProps) -> Element {
                                 icon: nav_audit_svg.name,
                                 title: "Audit Trail"
                             }
-                            NavItem {
-                                id: SideBar::RateLimits.to_string(),
-                                selected_item_id: props.selected_item.to_string(),
-                                href: super::routes::rate_limits::Index { team_id: props.team_id },
-                                icon: limits_svg.name,
-                                title: "Rate Limits"
+                            // Limits apply across every team
+                            if props.rbac.can_manage_limits() {
+                                NavItem {
+                                    id: SideBar::RateLimits.to_string(),
+                                    selected_item_id: props.selected_item.to_string(),
+                                    href: super::routes::rate_limits::Index { team_id: props.team_id },
+                                    icon: limits_svg.name,
+                                    title: "Rate Limits"
+                                }
                             }
                             if props.rbac.is_sys_admin {
                                 NavItem {
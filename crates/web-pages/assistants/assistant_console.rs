@@ -21,6 +21,7 @@ pub fn AssistantConsole(
     title: String,
     header: Element,
     is_tts_disabled: bool,
+    is_stt_disabled: bool,
     capabilities: Vec<Capability>,
     enabled_tools: Vec<String>,
     available_tools: Vec<BionicToolDefinition>,
@@ -60,6 +61,7 @@ pub fn AssistantConsole(
                         team_id: team_id,
                         prompt_id: prompt.id,
                         lock_console: has_pending_chat,
+                        is_stt_disabled,
                         conversation_id,
                         disclaimer: prompt.disclaimer,
                         capabilities,
@@ -20,6 +20,7 @@ pub fn page(
     prompt: SinglePrompt,
     conversation_id: i64,
     is_tts_disabled: bool,
+    is_stt_disabled: bool,
     capabilities: Vec<Capability>,
     enabled_tools: Vec<String>,
     available_tools: Vec<BionicToolDefinition>,
@@ -37,6 +38,7 @@ pub fn page(
             prompt: prompt.clone(),
             conversation_id,
             is_tts_disabled,
+            is_stt_disabled,
             capabilities,
             enabled_tools,
             available_tools,
@@ -31,6 +31,7 @@ pub fn AutomationCard(team_id: i32, prompt: MyPrompt) -> Element {
                     DropDownLink { href: crate::routes::automations::Edit{team_id, prompt_id: prompt.id}.to_string(), "Edit" }
                     DropDownLink { href: crate::routes::automations::ManageIntegrations{team_id, prompt_id: prompt.id}.to_string(), "Manage Integrations" }
                     DropDownLink { href: crate::routes::automations::ManageTriggers{team_id, prompt_id: prompt.id}.to_string(), "Manage Triggers" }
+                    DropDownLink { href: crate::routes::automations::Runs{team_id, prompt_id: prompt.id}.to_string(), "Run History" }
                     DropDownLink { popover_target: format!("delete-trigger-{}-{}", prompt.id, team_id), href: "#", target: "_top", "Delete" }
                 }
             ))
@@ -1,5 +1,7 @@
 pub mod automation_card;
 pub mod integrations;
 pub mod page;
+pub mod run_detail;
+pub mod runs;
 pub mod triggers;
 pub mod upsert;
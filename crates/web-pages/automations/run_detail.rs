@@ -0,0 +1,199 @@
+#![allow(non_snake_case)]
+use super::runs::{format_duration, RerunForm, RunStatus};
+use crate::app_layout::{Layout, SideBar};
+use daisy_rsx::*;
+use db::authz::Rbac;
+use db::queries::automation_runs::{AutomationRun, RunChat};
+use db::ChatRole;
+use dioxus::prelude::*;
+use openai_api::ToolCall;
+
+pub fn page(
+    team_id: i32,
+    prompt_id: i32,
+    prompt_name: String,
+    rbac: Rbac,
+    run: AutomationRun,
+    chats: Vec<RunChat>,
+) -> String {
+    // The last thing the model said is the result of the run
+    let output = chats
+        .iter()
+        .rev()
+        .find(|chat| chat.role == ChatRole::Assistant)
+        .and_then(|chat| chat.content.clone())
+        .filter(|content| !content.is_empty());
+
+    let page = rsx! {
+        Layout {
+            section_class: "p-4",
+            selected_item: SideBar::Prompts,
+            team_id: team_id,
+            rbac: rbac.clone(),
+            title: "Automation Run",
+            header: rsx!(
+                Breadcrumb {
+                    items: vec![
+                        BreadcrumbItem { text: "Automations".into(), href: Some(crate::routes::automations::Index { team_id }.to_string()) },
+                        BreadcrumbItem { text: prompt_name.clone(), href: Some(crate::routes::automations::Runs { team_id, prompt_id }.to_string()) },
+                        BreadcrumbItem { text: format!("Run #{}", run.id), href: None },
+                    ]
+                }
+                div {
+                    RerunForm { team_id, prompt_id, run_id: run.id }
+                }
+            ),
+
+            div {
+                class: "p-4 max-w-4xl w-full mx-auto space-y-6",
+
+                Card {
+                    CardHeader { title: "Summary" }
+                    CardBody {
+                        table {
+                            class: "table table-sm",
+                            tbody {
+                                tr {
+                                    th { "Status" }
+                                    td { RunStatus { status: run.status } }
+                                }
+                                tr {
+                                    th { "Trigger" }
+                                    td { "{run.trigger_type}" }
+                                }
+                                tr {
+                                    th { "Created" }
+                                    td {
+                                        RelativeTime {
+                                            format: RelativeTimeFormat::Datetime,
+                                            datetime: run.created_at.clone()
+                                        }
+                                    }
+                                }
+                                tr {
+                                    th { "Duration" }
+                                    td { {format_duration(run.duration_ms)} }
+                                }
+                                tr {
+                                    th { "Prompt Tokens" }
+                                    td { "{run.prompt_tokens}" }
+                                }
+                                tr {
+                                    th { "Completion Tokens" }
+                                    td { "{run.completion_tokens}" }
+                                }
+                            }
+                        }
+                        if let Some(error_message) = &run.error_message {
+                            div {
+                                class: "alert alert-error mt-4",
+                                "{error_message}"
+                            }
+                        }
+                    }
+                }
+
+                if let Some(input) = &run.input {
+                    Card {
+                        CardHeader { title: "Input" }
+                        CardBody {
+                            pre {
+                                class: "bg-base-200 p-4 rounded overflow-auto max-h-96 whitespace-pre-wrap",
+                                "{input}"
+                            }
+                        }
+                    }
+                }
+
+                Card {
+                    CardHeader { title: "Steps" }
+                    CardBody {
+                        if chats.is_empty() {
+                            p {
+                                class: "text-sm text-base-content/70",
+                                "No steps have been recorded for this run."
+                            }
+                        }
+                        for chat in &chats {
+                            RunStep { chat: chat.clone() }
+                        }
+                    }
+                }
+
+                if let Some(output) = output {
+                    Card {
+                        CardHeader { title: "Output" }
+                        CardBody {
+                            pre {
+                                class: "bg-base-200 p-4 rounded overflow-auto whitespace-pre-wrap",
+                                "{output}"
+                            }
+                        }
+                    }
+                }
+            }
+        }
+    };
+    crate::render(page)
+}
+
+#[component]
+fn RunStep(chat: RunChat) -> Element {
+    let title = match chat.role {
+        ChatRole::User => "Input",
+        ChatRole::Assistant => "Model",
+        ChatRole::Tool => "Tool Result",
+        ChatRole::System | ChatRole::Developer => "System",
+    };
+
+    let tool_calls: Vec<ToolCall> = chat
+        .tool_calls
+        .as_ref()
+        .and_then(|tool_calls| serde_json::from_str(tool_calls).ok())
+        .unwrap_or_default();
+
+    // Tool results are stored as JSON, pretty print them if we can
+    let content = chat.content.clone().map(|content| {
+        if chat.role == ChatRole::Tool {
+            serde_json::from_str::<serde_json::Value>(&content)
+                .and_then(|value| serde_json::to_string_pretty(&value))
+                .unwrap_or(content)
+        } else {
+            content
+        }
+    });
+
+    rsx!(
+        div {
+            class: "border-b border-base-300 py-3",
+            div {
+                class: "flex justify-between text-sm mb-2",
+                strong { "{title}" }
+                if let Some(tool_call_id) = &chat.tool_call_id {
+                    span { class: "text-base-content/70", "{tool_call_id}" }
+                }
+                if chat.tokens > 0 {
+                    span { class: "text-base-content/70", "{chat.tokens} tokens" }
+                }
+            }
+            if let Some(content) = content {
+                if !content.is_empty() {
+                    pre {
+                        class: "bg-base-200 p-2 rounded overflow-auto max-h-96 text-xs whitespace-pre-wrap",
+                        "{content}"
+                    }
+                }
+            }
+            for tool_call in tool_calls {
+                div {
+                    class: "mt-2",
+                    span { class: "text-sm", "Called " code { "{tool_call.function.name}" } }
+                    pre {
+                        class: "bg-base-200 p-2 rounded overflow-auto max-h-96 text-xs whitespace-pre-wrap",
+                        "{tool_call.function.arguments}"
+                    }
+                }
+            }
+        }
+    )
+}
@@ -0,0 +1,134 @@
+#![allow(non_snake_case)]
+use crate::app_layout::{Layout, SideBar};
+use daisy_rsx::*;
+use db::authz::Rbac;
+use db::queries::automation_runs::AutomationRun;
+use db::AutomationRunStatus;
+use dioxus::prelude::*;
+
+pub fn page(
+    team_id: i32,
+    prompt_id: i32,
+    prompt_name: String,
+    rbac: Rbac,
+    runs: Vec<AutomationRun>,
+) -> String {
+    let page = rsx! {
+        Layout {
+            section_class: "p-4",
+            selected_item: SideBar::Prompts,
+            team_id: team_id,
+            rbac: rbac.clone(),
+            title: "Automation Runs",
+            header: rsx!(
+                Breadcrumb {
+                    items: vec![
+                        BreadcrumbItem { text: "Automations".into(), href: Some(crate::routes::automations::Index { team_id }.to_string()) },
+                        BreadcrumbItem { text: prompt_name.clone(), href: None },
+                    ]
+                }
+            ),
+
+            if runs.is_empty() {
+                div {
+                    class: "p-4 text-center text-base-content/70",
+                    "This automation hasn't run yet. Add a trigger to schedule it."
+                }
+            } else {
+                Card {
+                    class: "has-data-table",
+                    CardHeader { title: "Run History" }
+                    CardBody {
+                        table {
+                            class: "table table-sm",
+                            thead {
+                                th { "Run" }
+                                th { "Trigger" }
+                                th { "Status" }
+                                th { "Started" }
+                                th { "Duration" }
+                                th { "Tokens" }
+                                th {
+                                    class: "text-right",
+                                    "Action"
+                                }
+                            }
+                            tbody {
+                                for run in runs {
+                                    tr {
+                                        td {
+                                            a {
+                                                href: crate::routes::automations::RunDetail { team_id, prompt_id, run_id: run.id }.to_string(),
+                                                "#{run.id}"
+                                            }
+                                        }
+                                        td { "{run.trigger_type}" }
+                                        td {
+                                            RunStatus { status: run.status }
+                                        }
+                                        td {
+                                            RelativeTime {
+                                                format: RelativeTimeFormat::Relative,
+                                                datetime: run.started_at.clone().unwrap_or(run.created_at.clone())
+                                            }
+                                        }
+                                        td { {format_duration(run.duration_ms)} }
+                                        td { "{run.prompt_tokens + run.completion_tokens}" }
+                                        td {
+                                            class: "text-right",
+                                            RerunForm { team_id, prompt_id, run_id: run.id }
+                                        }
+                                    }
+                                }
+                            }
+                        }
+                    }
+                }
+            }
+        }
+    };
+    crate::render(page)
+}
+
+#[component]
+pub fn RunStatus(status: AutomationRunStatus) -> Element {
+    let (badge_color, text) = match status {
+        AutomationRunStatus::Pending => (BadgeColor::Neutral, "Pending"),
+        AutomationRunStatus::Running => (BadgeColor::Info, "Running"),
+        AutomationRunStatus::Completed => (BadgeColor::Success, "Completed"),
+        AutomationRunStatus::Failed => (BadgeColor::Error, "Failed"),
+    };
+
+    rsx!(
+        Badge {
+            class: "truncate",
+            badge_color,
+            badge_style: BadgeStyle::Outline,
+            badge_size: BadgeSize::Sm,
+            "{text}"
+        }
+    )
+}
+
+#[component]
+pub fn RerunForm(team_id: i32, prompt_id: i32, run_id: i32) -> Element {
+    rsx!(
+        form {
+            method: "post",
+            action: crate::routes::automations::Rerun { team_id, prompt_id, run_id }.to_string(),
+            Button {
+                button_type: ButtonType::Submit,
+                button_size: ButtonSize::Small,
+                "Re-run"
+            }
+        }
+    )
+}
+
+pub fn format_duration(duration_ms: Option<i64>) -> String {
+    match duration_ms {
+        Some(ms) if ms < 1000 => format!("{}ms", ms),
+        Some(ms) => format!("{:.1}s", ms as f64 / 1000.0),
+        None => "-".to_string(),
+    }
+}
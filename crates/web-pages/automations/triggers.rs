@@ -2,7 +2,7 @@
 use crate::app_layout::{Layout, SideBar};
 use daisy_rsx::*;
 use db::authz::Rbac;
-use db::queries::automation_triggers::CronTrigger;
+use db::queries::automation_triggers::{CronTrigger, WebhookTrigger};
 use dioxus::prelude::*;
 
 pub fn page(
@@ -11,6 +11,8 @@ pub fn page(
     prompt_name: String,
     rbac: Rbac,
     triggers: Vec<CronTrigger>,
+    webhooks: Vec<WebhookTrigger>,
+    base_url: String,
 ) -> String {
     let page = rsx! {
         Layout {
@@ -95,6 +97,56 @@ pub fn page(
                     }
                 }
 
+                Card {
+                    CardHeader { title: "Webhook Triggers" }
+                    CardBody {
+                        p {
+                            class: "text-sm text-base-content/70 mb-2",
+                            "POST a JSON payload to the URL below. Either send the secret as a Bearer token or sign the body with HMAC SHA256 and pass the hex digest in the X-Bionic-Signature header."
+                        }
+                        for webhook in &webhooks {
+                            div {
+                                class: "flex flex-col gap-2 border-b border-base-300 py-3",
+                                Fieldset {
+                                    legend: "URL",
+                                    Input {
+                                        input_type: InputType::Text,
+                                        name: "url",
+                                        value: format!("{}{}", base_url, crate::routes::automations::Webhook { trigger_id: webhook.id })
+                                    }
+                                }
+                                Fieldset {
+                                    legend: "Secret",
+                                    Input {
+                                        input_type: InputType::Password,
+                                        name: "secret",
+                                        value: webhook.secret.clone()
+                                    }
+                                }
+                                div {
+                                    class: "flex gap-2 justify-end",
+                                    form {
+                                        method: "post",
+                                        action: crate::routes::automations::RotateWebhookTrigger { team_id, prompt_id, trigger_id: webhook.id }.to_string(),
+                                        Button { button_type: ButtonType::Submit, button_size: ButtonSize::Small, "Rotate Secret" }
+                                    }
+                                    form {
+                                        method: "post",
+                                        action: crate::routes::automations::RemoveWebhookTrigger { team_id, prompt_id, trigger_id: webhook.id }.to_string(),
+                                        Button { button_type: ButtonType::Submit, button_scheme: ButtonScheme::Error, button_size: ButtonSize::Small, "Delete" }
+                                    }
+                                }
+                            }
+                        }
+                        form {
+                            class: "mt-4",
+                            method: "post",
+                            action: crate::routes::automations::AddWebhookTrigger { team_id, prompt_id }.to_string(),
+                            Button { button_type: ButtonType::Submit, button_scheme: ButtonScheme::Primary, "Add Webhook" }
+                        }
+                    }
+                }
+
                 if !triggers.is_empty() {
                     Card {
                         CardHeader { title: "Existing Triggers" }
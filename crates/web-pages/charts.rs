@@ -1,5 +1,6 @@
 #![allow(non_snake_case)]
 use daisy_rsx::*;
+use db::queries::chat_feedback::DailySatisfaction;
 use db::queries::token_usage_metrics::{DailyApiRequests, DailyTokenUsage};
 use dioxus::prelude::*;
 use std::collections::HashMap;
@@ -158,3 +159,94 @@ pub fn ApiRequestChartCard(data: Vec<DailyApiRequests>, title: String) -> Elemen
         }
     }
 }
+
+#[component]
+pub fn SatisfactionChart(data: Vec<DailySatisfaction>) -> Element {
+    let max_ratings = data
+        .iter()
+        .map(|day| day.positive + day.negative)
+        .max()
+        .unwrap_or(1)
+        .max(1);
+    // Narrower bars when there are more days than fit
+    let step = (380 / data.len().max(1)).min(50);
+    let bar = step * 4 / 5;
+
+    rsx! {
+        div {
+            class: "w-full h-64",
+            svg {
+                width: "100%",
+                height: "100%",
+                view_box: "0 0 400 200",
+                // Stacked bars, helpful at the bottom
+                for (i, day) in data.iter().enumerate() {
+                    g {
+                        rect {
+                            x: "{i * step + 20}",
+                            y: "{200 - (day.positive * 180 / max_ratings)}",
+                            width: "{bar}",
+                            height: "{day.positive * 180 / max_ratings}",
+                            fill: "var(--color-success)",
+                            class: "hover:opacity-80 cursor-pointer"
+                        }
+                        rect {
+                            x: "{i * step + 20}",
+                            y: "{200 - ((day.positive + day.negative) * 180 / max_ratings)}",
+                            width: "{bar}",
+                            height: "{day.negative * 180 / max_ratings}",
+                            fill: "var(--color-error)",
+                            class: "hover:opacity-80 cursor-pointer"
+                        }
+                        text {
+                            x: "{i * step + 20 + bar / 2}",
+                            y: "195",
+                            text_anchor: "middle",
+                            font_size: "10",
+                            "{day.feedback_date.month()}/{day.feedback_date.day()}"
+                        }
+                    }
+                }
+            }
+        }
+    }
+}
+
+#[component]
+pub fn SatisfactionChartCard(data: Vec<DailySatisfaction>, title: String) -> Element {
+    rsx! {
+        Card {
+            CardHeader {
+                title: "{title}"
+            }
+            CardBody {
+                SatisfactionChart {
+                    data: data
+                }
+                div {
+                    class: "flex justify-center mt-4 space-x-4",
+                    div {
+                        class: "flex items-center",
+                        div {
+                            class: "w-4 h-4 bg-success mr-2"
+                        }
+                        span {
+                            class: "text-sm",
+                            "Helpful"
+                        }
+                    }
+                    div {
+                        class: "flex items-center",
+                        div {
+                            class: "w-4 h-4 bg-error mr-2"
+                        }
+                        span {
+                            class: "text-sm",
+                            "Not helpful"
+                        }
+                    }
+                }
+            }
+        }
+    }
+}
@@ -2,12 +2,15 @@
 use crate::routes;
 
 use assets::files::*;
+use comrak::nodes::{Ast, AstNode, NodeLink, NodeValue};
 use daisy_rsx::*;
-use db::{authz::Rbac, ChatRole};
+use db::queries::{chat_feedback::ChatFeedback, chats_chunks::ChatChunks};
+use db::{authz::Rbac, ChatRole, ChatStatus, FeedbackRating};
 use dioxus::prelude::*;
 use openai_api::ToolCall;
+use std::cell::RefCell;
 
-use super::{ChatWithChunks, PendingChatState};
+use super::{ChatWithChunks, PendingChatState, FEEDBACK_REASONS};
 
 // Main ConsoleStream Component
 #[component]
@@ -33,7 +36,8 @@ pub fn ConsoleStream(
                                 name: get_function_name_from_tool_calls(&tool_chat.tool_call_id, &chat_history.clone()),
                                 chat_id: tool_chat.id as i64,
                                 team_id,
-                                pending: true
+                                pending: true,
+                                images: vec![]
                             }
                         }
                         // This component has an id of 'streaming-chat' which
@@ -75,13 +79,29 @@ pub fn ConsoleStream(
                 div {
                     class: "flex flex-col-reverse pl-2 pr-2 md:pr-0 md:pl-0 md:min-w-[65ch] max-w-prose mx-auto",
 
+                    // Reversed, so this goes under the chat
+                    if chat_with_chunks.siblings.len() > 1 {
+                        BranchSwitcher {
+                            team_id,
+                            chat_id: chat_with_chunks.chat.id,
+                            siblings: chat_with_chunks.siblings.clone()
+                        }
+                    }
+
                     match chat_with_chunks.chat.role {
-                        ChatRole::Assistant => rsx! {
-                            if let Some(content) = chat_with_chunks.chat.content.clone() {
-                                if !content.is_empty() {
+                        ChatRole::Assistant => {
+                            let content = chat_with_chunks.chat.content.clone().unwrap_or_default();
+                            let stopped = chat_with_chunks.chat.status == ChatStatus::Cancelled;
+                            rsx! {
+                                if !content.is_empty() || stopped {
                                     ResponseTimeline {
                                         response: content,
-                                        is_tts_disabled
+                                        is_tts_disabled,
+                                        stopped,
+                                        chat_id: chat_with_chunks.chat.id,
+                                        team_id,
+                                        citations: chat_with_chunks.citations.clone(),
+                                        feedback: chat_with_chunks.feedback.clone()
                                     }
                                 }
                             }
@@ -96,11 +116,19 @@ pub fn ConsoleStream(
                                     name: function_name,
                                     chat_id: chat_with_chunks.chat.id as i64,
                                     team_id,
-                                    pending: false
+                                    pending: false,
+                                    images: image_attachments(team_id, &chat_with_chunks.chat.attachments)
                                 }
                             }
                         },
                         _ => rsx! {
+                            if chat_with_chunks.chat.role == ChatRole::User {
+                                EditMessage {
+                                    team_id,
+                                    chat_id: chat_with_chunks.chat.id,
+                                    message: chat_with_chunks.chat.content.clone().unwrap_or_default()
+                                }
+                            }
                             UserRequestTimeline {
                                 user_request: chat_with_chunks.chat.content.clone().unwrap_or_default()
                             }
@@ -140,9 +168,38 @@ fn get_function_name_from_tool_calls(
     format!("Tool Call {}", tool_call_id.as_deref().unwrap_or("Unknown"))
 }
 
+// Links to any images attached to a chat, i.e. from the image generation tool
+fn image_attachments(team_id: i32, attachments: &Option<serde_json::Value>) -> Vec<String> {
+    attachments
+        .iter()
+        .filter_map(|a| a.as_array())
+        .flatten()
+        .filter(|a| {
+            a["type"]
+                .as_str()
+                .map(|t| t.starts_with("image/"))
+                .unwrap_or(false)
+        })
+        .filter_map(|a| a["id"].as_i64())
+        .map(|id| {
+            routes::console::Attachment {
+                team_id,
+                id: id as i32,
+            }
+            .to_string()
+        })
+        .collect()
+}
+
 // Function Call Timeline Component
 #[component]
-fn FunctionCallTimeline(name: String, chat_id: i64, team_id: i32, pending: bool) -> Element {
+fn FunctionCallTimeline(
+    name: String,
+    chat_id: i64,
+    team_id: i32,
+    pending: bool,
+    images: Vec<String>,
+) -> Element {
     rsx! {
         TimeLine {
             TimeLineBadge {
@@ -158,6 +215,17 @@ fn FunctionCallTimeline(name: String, chat_id: i64, team_id: i32, pending: bool)
                         "{name}"
                     }
                 }
+                for image in images {
+                    a {
+                        href: "{image}",
+                        target: "_blank",
+                        img {
+                            class: "mt-2 rounded-lg max-w-full",
+                            src: "{image}",
+                            loading: "lazy"
+                        }
+                    }
+                }
             }
         }
     }
@@ -165,7 +233,15 @@ fn FunctionCallTimeline(name: String, chat_id: i64, team_id: i32, pending: bool)
 
 // Response Timeline Component
 #[component]
-fn ResponseTimeline(response: String, is_tts_disabled: bool) -> Element {
+fn ResponseTimeline(
+    response: String,
+    is_tts_disabled: bool,
+    stopped: bool,
+    chat_id: i32,
+    team_id: i32,
+    citations: Vec<ChatChunks>,
+    feedback: Option<ChatFeedback>,
+) -> Element {
     // Set up the markdown with the needed extensions
     let mut options = comrak::Options::default();
     options.extension.table = true;
@@ -184,7 +260,11 @@ fn ResponseTimeline(response: String, is_tts_disabled: bool) -> Element {
     options.extension.underline = true;
     options.extension.subscript = true;
     let markdown = response;
-    let html = comrak::markdown_to_html(&markdown, &options);
+    let numbers: Vec<i32> = citations
+        .iter()
+        .filter_map(|citation| citation.position)
+        .collect();
+    let html = markdown_to_html_with_citations(&markdown, &options, chat_id, &numbers);
 
     rsx! {
         TimeLine {
@@ -201,6 +281,20 @@ fn ResponseTimeline(response: String, is_tts_disabled: bool) -> Element {
                     class: "hidden markdown-response",
                     "{markdown}"
                 }
+                if !citations.is_empty() {
+                    Sources {
+                        chat_id,
+                        citations
+                    }
+                }
+                if stopped {
+                    Badge {
+                        class: "mb-2",
+                        badge_color: BadgeColor::Warning,
+                        badge_style: BadgeStyle::Outline,
+                        "Generation stopped"
+                    }
+                }
                 div {
                     if !is_tts_disabled {
                         ToolTip {
@@ -227,6 +321,302 @@ fn ResponseTimeline(response: String, is_tts_disabled: bool) -> Element {
                             height: "16"
                         }
                     }
+                    ToolTip {
+                        text: "Regenerate",
+                        class: "ml-2",
+                        form {
+                            class: "inline",
+                            method: "post",
+                            action: routes::console::Regenerate{team_id}.to_string(),
+                            input {
+                                name: "chat_id",
+                                value: "{chat_id}",
+                                "type": "hidden"
+                            }
+                            button {
+                                "type": "submit",
+                                class: "cursor-pointer",
+                                img {
+                                    class: "svg-icon mt-0 mb-0",
+                                    src: regenerate_svg.name,
+                                    width: "16",
+                                    height: "16"
+                                }
+                            }
+                        }
+                    }
+                    RateResponse {
+                        team_id,
+                        chat_id,
+                        feedback
+                    }
+                }
+            }
+        }
+    }
+}
+
+// Thumbs up saves straight away, thumbs down asks what went wrong
+#[component]
+fn RateResponse(team_id: i32, chat_id: i32, feedback: Option<ChatFeedback>) -> Element {
+    let rating = feedback.as_ref().map(|feedback| feedback.rating);
+    let reasons = feedback
+        .as_ref()
+        .map(|feedback| feedback.reasons.clone())
+        .unwrap_or_default();
+    let comment = feedback
+        .and_then(|feedback| feedback.comment)
+        .unwrap_or_default();
+    // Show which way the answer was rated
+    let selected = |this: FeedbackRating| {
+        if rating == Some(this) {
+            "bg-base-300 rounded"
+        } else {
+            ""
+        }
+    };
+    let positive_class = selected(FeedbackRating::Positive);
+    let negative_class = selected(FeedbackRating::Negative);
+
+    rsx! {
+        ToolTip {
+            text: "Helpful",
+            class: "ml-2",
+            form {
+                class: "inline",
+                method: "post",
+                action: routes::console::Feedback{team_id}.to_string(),
+                input {
+                    name: "chat_id",
+                    value: "{chat_id}",
+                    "type": "hidden"
+                }
+                input {
+                    name: "rating",
+                    value: "Positive",
+                    "type": "hidden"
+                }
+                Button {
+                    button_type: ButtonType::Submit,
+                    class: "btn-ghost btn-xs {positive_class}",
+                    img {
+                        class: "svg-icon mt-0 mb-0",
+                        src: thumbs_up_svg.name,
+                        width: "16",
+                        height: "16"
+                    }
+                }
+            }
+        }
+        ToolTip {
+            text: "Not helpful",
+            class: "ml-2",
+            Button {
+                class: "btn-ghost btn-xs {negative_class}",
+                popover_target: "feedback-{chat_id}",
+                img {
+                    class: "svg-icon mt-0 mb-0",
+                    src: thumbs_down_svg.name,
+                    width: "16",
+                    height: "16"
+                }
+            }
+        }
+        form {
+            class: "not-prose",
+            method: "post",
+            action: routes::console::Feedback{team_id}.to_string(),
+            Modal {
+                trigger_id: "feedback-{chat_id}",
+                ModalBody {
+                    h3 {
+                        class: "font-bold text-lg mb-4",
+                        "What was wrong with this answer?"
+                    }
+                    input {
+                        name: "chat_id",
+                        value: "{chat_id}",
+                        "type": "hidden"
+                    }
+                    input {
+                        name: "rating",
+                        value: "Negative",
+                        "type": "hidden"
+                    }
+                    div {
+                        class: "flex flex-wrap gap-4 mb-4",
+                        for reason in FEEDBACK_REASONS {
+                            label {
+                                class: "label cursor-pointer",
+                                input {
+                                    "type": "checkbox",
+                                    class: "checkbox checkbox-sm",
+                                    name: "reasons",
+                                    value: reason,
+                                    checked: reasons.iter().any(|picked| picked == reason)
+                                }
+                                span {
+                                    class: "label-text ml-2",
+                                    "{reason}"
+                                }
+                            }
+                        }
+                    }
+                    Fieldset {
+                        legend: "Comment",
+                        help_text: "Optional, anything that would help us improve the answer",
+                        TextArea {
+                            class: "w-full",
+                            name: "comment",
+                            rows: "4",
+                            "{comment}"
+                        }
+                    }
+                    ModalAction {
+                        Button {
+                            class: "cancel-modal",
+                            button_scheme: ButtonScheme::Warning,
+                            "Cancel"
+                        }
+                        Button {
+                            button_type: ButtonType::Submit,
+                            button_scheme: ButtonScheme::Primary,
+                            "Send Feedback"
+                        }
+                    }
+                }
+            }
+        }
+    }
+}
+
+// Render the reply, turning the [n] the model wrote into links to the sources
+// under it. We change the syntax tree rather than the HTML so code and the
+// text of existing links are left alone.
+fn markdown_to_html_with_citations(
+    markdown: &str,
+    options: &comrak::Options,
+    chat_id: i32,
+    numbers: &[i32],
+) -> String {
+    let arena = comrak::Arena::new();
+    let root = comrak::parse_document(&arena, markdown, options);
+
+    // Code has its own node types, so it never shows up as text
+    let texts: Vec<&AstNode> = root
+        .descendants()
+        .filter(|node| matches!(node.data.borrow().value, NodeValue::Text(_)))
+        .filter(|node| {
+            !node
+                .ancestors()
+                .any(|ancestor| matches!(ancestor.data.borrow().value, NodeValue::Link(_)))
+        })
+        .collect();
+
+    for node in texts {
+        let (text, start) = {
+            let ast = node.data.borrow();
+            match &ast.value {
+                NodeValue::Text(text) => (text.clone(), ast.sourcepos.start),
+                _ => continue,
+            }
+        };
+        let pieces = split_citations(&text, numbers);
+        if pieces.iter().all(|(_, number)| number.is_none()) {
+            continue;
+        }
+
+        for (piece, number) in pieces {
+            let text = arena.alloc(AstNode::new(RefCell::new(Ast::new(
+                NodeValue::Text(piece),
+                start,
+            ))));
+            match number {
+                Some(number) => {
+                    let link = arena.alloc(AstNode::new(RefCell::new(Ast::new(
+                        NodeValue::Link(NodeLink {
+                            url: format!("#citation-{chat_id}-{number}"),
+                            title: String::new(),
+                        }),
+                        start,
+                    ))));
+                    link.append(text);
+                    node.insert_before(link);
+                }
+                None => node.insert_before(text),
+            }
+        }
+        node.detach();
+    }
+
+    let mut html = Vec::new();
+    if comrak::format_html(root, options, &mut html).is_err() {
+        return comrak::markdown_to_html(markdown, options);
+    }
+    String::from_utf8(html).unwrap_or_default()
+}
+
+// The text split around the citations, with the number of each citation
+fn split_citations(text: &str, numbers: &[i32]) -> Vec<(String, Option<i32>)> {
+    let mut pieces = Vec::new();
+    let mut plain = String::new();
+    let mut rest = text;
+
+    while let Some(open) = rest.find('[') {
+        let after = &rest[open + 1..];
+        let citation = after.find(']').and_then(|close| {
+            let number = after[..close].parse::<i32>().ok()?;
+            numbers.contains(&number).then_some((number, close))
+        });
+        match citation {
+            Some((number, close)) => {
+                plain.push_str(&rest[..open]);
+                if !plain.is_empty() {
+                    pieces.push((std::mem::take(&mut plain), None));
+                }
+                pieces.push((format!("[{number}]"), Some(number)));
+                rest = &after[close + 1..];
+            }
+            None => {
+                plain.push_str(&rest[..=open]);
+                rest = after;
+            }
+        }
+    }
+    plain.push_str(rest);
+    if !plain.is_empty() {
+        pieces.push((plain, None));
+    }
+    pieces
+}
+
+// The document sections behind the citations in a reply
+#[component]
+fn Sources(chat_id: i32, citations: Vec<ChatChunks>) -> Element {
+    rsx! {
+        details {
+            class: "mb-2 not-prose text-sm",
+            summary {
+                class: "cursor-pointer",
+                "Sources"
+            }
+            ol {
+                class: "mt-2 space-y-2",
+                for citation in citations {
+                    li {
+                        id: "citation-{chat_id}-{citation.position.unwrap_or_default()}",
+                        strong {
+                            "[{citation.position.unwrap_or_default()}] {citation.file_name}"
+                        }
+                        span {
+                            class: "ml-2 opacity-70",
+                            "Page {citation.page_number}"
+                        }
+                        p {
+                            class: "opacity-70",
+                            "{citation.preview}"
+                        }
+                    }
                 }
             }
         }
@@ -298,3 +688,163 @@ fn UserRequestTimeline(user_request: String) -> Element {
         }
     }
 }
+
+// Edit a message we sent, the edit starts a new branch from that point
+#[component]
+fn EditMessage(team_id: i32, chat_id: i32, message: String) -> Element {
+    rsx! {
+        div {
+            class: "flex flex-row justify-end mb-2",
+            ToolTip {
+                text: "Edit",
+                Button {
+                    class: "btn-ghost btn-xs",
+                    popover_target: "edit-chat-{chat_id}",
+                    img {
+                        class: "svg-icon mt-0 mb-0",
+                        src: button_edit_svg.name,
+                        width: "16",
+                        height: "16"
+                    }
+                }
+            }
+        }
+        form {
+            action: routes::console::EditMessage{team_id}.to_string(),
+            method: "post",
+            Modal {
+                trigger_id: "edit-chat-{chat_id}",
+                ModalBody {
+                    h3 {
+                        class: "font-bold text-lg mb-4",
+                        "Edit Message"
+                    }
+                    input {
+                        name: "chat_id",
+                        value: "{chat_id}",
+                        "type": "hidden"
+                    }
+                    TextArea {
+                        class: "w-full",
+                        name: "message",
+                        rows: "6",
+                        required: true,
+                        "{message}"
+                    }
+                    ModalAction {
+                        Button {
+                            class: "cancel-modal",
+                            button_scheme: ButtonScheme::Warning,
+                            "Cancel"
+                        }
+                        Button {
+                            button_type: ButtonType::Submit,
+                            button_scheme: ButtonScheme::Primary,
+                            "Send"
+                        }
+                    }
+                }
+            }
+        }
+    }
+}
+
+// Flips between the alternatives to a chat, i.e. "< 2/3 >"
+#[component]
+fn BranchSwitcher(team_id: i32, chat_id: i32, siblings: Vec<i32>) -> Element {
+    let position = siblings
+        .iter()
+        .position(|id| *id == chat_id)
+        .unwrap_or_default();
+    let previous = position
+        .checked_sub(1)
+        .and_then(|position| siblings.get(position))
+        .copied();
+    let next = siblings.get(position + 1).copied();
+    let count = siblings.len();
+
+    rsx! {
+        div {
+            class: "flex flex-row items-center gap-1 ml-12 mb-2 text-sm",
+            BranchButton {
+                team_id,
+                chat_id: previous,
+                label: "<"
+            }
+            span {
+                "{position + 1}/{count}"
+            }
+            BranchButton {
+                team_id,
+                chat_id: next,
+                label: ">"
+            }
+        }
+    }
+}
+
+#[component]
+fn BranchButton(team_id: i32, chat_id: Option<i32>, label: String) -> Element {
+    rsx! {
+        form {
+            method: "post",
+            action: routes::console::SwitchBranch{team_id}.to_string(),
+            input {
+                name: "chat_id",
+                value: "{chat_id.unwrap_or_default()}",
+                "type": "hidden"
+            }
+            button {
+                "type": "submit",
+                class: "btn btn-ghost btn-xs",
+                disabled: chat_id.is_none(),
+                "{label}"
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_citations_are_linked() {
+        let html = markdown_to_html_with_citations(
+            "Holidays are booked [1], see [2] and [7].",
+            &comrak::Options::default(),
+            5,
+            &[1, 2],
+        );
+
+        assert_eq!(
+            html,
+            "<p>Holidays are booked <a href=\"#citation-5-1\">[1]</a>, see \
+             <a href=\"#citation-5-2\">[2]</a> and [7].</p>\n"
+        );
+    }
+
+    #[test]
+    fn test_code_is_left_alone() {
+        let markdown = "Use `arr[1]` [1]\n\n```\nx = arr[1]\n```\n\n[see [1]](https://example.com)";
+        let html = markdown_to_html_with_citations(markdown, &comrak::Options::default(), 5, &[1]);
+
+        assert!(html.contains("<code>arr[1]</code> <a href=\"#citation-5-1\">[1]</a>"));
+        assert!(html.contains("<pre><code>x = arr[1]\n</code></pre>"));
+        assert!(html.contains("<a href=\"https://example.com\">see [1]</a>"));
+    }
+
+    #[test]
+    fn test_split_citations() {
+        assert_eq!(
+            split_citations("a [1] [x] [[2]]", &[1, 2]),
+            vec![
+                ("a ".to_string(), None),
+                ("[1]".to_string(), Some(1)),
+                (" [x] [".to_string(), None),
+                ("[2]".to_string(), Some(2)),
+                ("]".to_string(), None),
+            ]
+        );
+    }
+}
@@ -21,6 +21,7 @@ pub fn page(
     prompt: SinglePrompt,
     conversation_id: i64,
     is_tts_disabled: bool,
+    is_stt_disabled: bool,
     capabilities: Vec<Capability>,
     enabled_tools: Vec<String>,
     available_tools: Vec<BionicToolDefinition>,
@@ -38,6 +39,7 @@ pub fn page(
             pending_chat_state,
             conversation_id,
             is_tts_disabled,
+            is_stt_disabled,
             capabilities,
             enabled_tools,
             available_tools,
@@ -20,6 +20,7 @@ pub fn ConsoleLayout(
     title: String,
     header: Element,
     is_tts_disabled: bool,
+    is_stt_disabled: bool,
     capabilities: Vec<Capability>,
     enabled_tools: Vec<String>,
     available_tools: Vec<BionicToolDefinition>,
@@ -50,6 +51,7 @@ pub fn ConsoleLayout(
                             team_id: team_id,
                             prompt_id: prompt.id,
                             lock_console: has_pending_chat,
+                            is_stt_disabled,
                             conversation_id,
                             disclaimer: prompt.disclaimer,
                             capabilities: capabilities.clone(),
@@ -69,6 +71,7 @@ pub fn ConsoleLayout(
                                 team_id: team_id,
                                 prompt_id: prompt.id,
                                 lock_console: has_pending_chat,
+                                is_stt_disabled,
                                 conversation_id,
                                 disclaimer: prompt.disclaimer,
                                 capabilities,
@@ -9,15 +9,30 @@ pub mod prompt_drawer;
 pub mod prompt_form;
 pub mod tools_modal;
 
-use db::queries::{chats::Chat, chats_chunks::ChatChunks};
+use db::queries::{chat_feedback::ChatFeedback, chats::Chat, chats_chunks::ChatChunks};
 use openai_api::ToolCall;
 
 #[derive(PartialEq, Clone, Debug)]
 pub struct ChatWithChunks {
     pub chat: Chat,
     pub chunks: Vec<ChatChunks>,
+    // The alternatives to this chat, including itself, oldest first
+    pub siblings: Vec<i32>,
+    // For replies, the numbered chunks the model could cite as [n]
+    pub citations: Vec<ChatChunks>,
+    pub feedback: Option<ChatFeedback>,
 }
 
+// The tags someone can pick when an answer wasn't helpful
+pub const FEEDBACK_REASONS: [&str; 6] = [
+    "Inaccurate",
+    "Incomplete",
+    "Wrong sources",
+    "Didn't follow instructions",
+    "Too long",
+    "Other",
+];
+
 #[derive(PartialEq, Clone, Debug)]
 pub struct PendingChat {
     pub chat: Chat,
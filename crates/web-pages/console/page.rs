@@ -12,6 +12,7 @@ pub fn new_conversation(
     prompts: Vec<Prompt>,
     prompt: SinglePrompt,
     rbac: Rbac,
+    is_stt_disabled: bool,
     capabilities: Vec<Capability>,
     enabled_tools: Vec<String>,
     available_tools: Vec<BionicToolDefinition>,
@@ -27,6 +28,7 @@ pub fn new_conversation(
             chat_history: vec![],
             pending_chat_state: super::PendingChatState::None,
             is_tts_disabled: true,
+            is_stt_disabled,
             capabilities,
             enabled_tools,
             available_tools,
@@ -38,10 +38,13 @@ pub fn PromptDrawer(
                         "Context provided to the prompt from your documents"
                     }
 
-                    ol {
+                    ul {
                         class: "space-y-1",
                         for chunk in chunks {
                             li {
+                                if let Some(position) = chunk.position {
+                                    "[{position}] "
+                                }
                                 "A section from Page {chunk.page_number} in file {chunk.file_name}."
                             }
                         }
@@ -15,6 +15,7 @@ pub fn Form(
     prompt_id: i32,
     conversation_id: Option<i64>,
     lock_console: bool,
+    is_stt_disabled: bool,
     disclaimer: String,
     capabilities: Vec<Capability>,
     enabled_tools: Vec<String>,
@@ -83,7 +84,9 @@ pub fn Form(
                         div {
                             class: "flex flex-row gap-2",
                             SpeechToTextButton {
-                                lock_console
+                                team_id,
+                                lock_console,
+                                is_stt_disabled
                             }
 
                             SendMessageButton {
@@ -121,11 +124,22 @@ fn ToolsButton(lock_console: bool) -> Element {
 }
 
 #[component]
-fn SpeechToTextButton(lock_console: bool) -> Element {
+fn SpeechToTextButton(team_id: i32, lock_console: bool, is_stt_disabled: bool) -> Element {
+    let class = if is_stt_disabled { "hidden" } else { "" };
     rsx! {
+        if !is_stt_disabled {
+            // Records audio and sends it to the speech to text model
+            input {
+                "type": "hidden",
+                id: "transcribe-url",
+                value: routes::console::Transcribe { team_id }.to_string()
+            }
+        }
+        // Without a speech to text model this falls back to the browser's
+        // speech recognition, and stays hidden if it has none
         Button {
             id: "speech-to-text-button",
-            class: "hidden",
+            class: class,
             disabled: lock_console,
             button_style: ButtonStyle::Outline,
             button_shape: ButtonShape::Circle,
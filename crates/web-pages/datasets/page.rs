@@ -6,13 +6,17 @@ use crate::SectionIntroduction;
 use assets::files::*;
 use daisy_rsx::*;
 use db::authz::Rbac;
-use db::queries::{datasets::Dataset, models::Model};
+use db::queries::{
+    datasets::{Dataset, ReindexProgress},
+    models::Model,
+};
 use dioxus::prelude::*;
 
 pub fn page(
     rbac: Rbac,
     team_id: i32,
     datasets: Vec<Dataset>,
+    reindex_progress: Vec<ReindexProgress>,
     models: Vec<Model>,
     can_set_visibility_to_company: bool,
 ) -> String {
@@ -84,6 +88,11 @@ pub fn page(
                                                     href: crate::routes::documents::Index{team_id, dataset_id: dataset.id}.to_string(),
                                                     "{dataset.name}"
                                                 }
+                                                if let Some(progress) = reindex_progress.iter().find(|progress| progress.dataset_id == dataset.id) {
+                                                    ReindexBadge {
+                                                        progress: progress.clone()
+                                                    }
+                                                }
                                             }
                                             td {
                                                 crate::assistants::visibility::VisLabel {
@@ -122,6 +131,13 @@ pub fn page(
                                                             target: "_top",
                                                             "Edit"
                                                         }
+                                                        DropDownLink {
+                                                            popover_target: format!("reindex-trigger-{}-{}",
+                                                                dataset.id, team_id),
+                                                            href: "#",
+                                                            target: "_top",
+                                                            "Re-index"
+                                                        }
                                                     }
                                                     DropDownLink {
                                                         popover_target: format!("delete-trigger-{}-{}",
@@ -151,14 +167,29 @@ pub fn page(
                                 ],
                             }
 
+                            ConfirmModal {
+                                action: crate::routes::datasets::Reindex{team_id, id: dataset.id}.to_string(),
+                                trigger_id: format!("reindex-trigger-{}-{}", dataset.id, team_id),
+                                submit_label: "Re-index".to_string(),
+                                heading: "Re-index this Dataset?".to_string(),
+                                warning: "Every document will be embedded again. Searches use the existing embeddings until this finishes.".to_string(),
+                                hidden_fields: vec![
+                                    ("team_id".into(), team_id.to_string()),
+                                    ("id".into(), dataset.id.to_string()),
+                                ],
+                            }
+
                             super::upsert::Upsert {
                                 id: dataset.id,
                                 trigger_id: format!("edit-trigger-{}-{}", dataset.id, team_id),
                                 name: dataset.name,
                                 models: models.clone(),
+                                embeddings_model_id: dataset.reindex_model_id.unwrap_or(dataset.embeddings_model_id),
                                 team_id: team_id,
                                 combine_under_n_chars: dataset.combine_under_n_chars,
                                 new_after_n_chars: dataset.new_after_n_chars,
+                                chunk_overlap: dataset.chunk_overlap,
+                                chunking_strategy: dataset.chunking_strategy,
                                 _multipage_sections: true,
                                 visibility: dataset.visibility,
                                 can_set_visibility_to_company
@@ -171,9 +202,12 @@ pub fn page(
                     trigger_id: "new-dataset-form",
                     name: "".to_string(),
                     models: models.clone(),
+                    embeddings_model_id: None,
                     team_id: team_id,
                     combine_under_n_chars: 500,
                     new_after_n_chars: 1000,
+                    chunk_overlap: 0,
+                    chunking_strategy: db::types::public::ChunkingStrategy::ByTitle,
                     _multipage_sections: true,
                     visibility: db::Visibility::Private,
                     can_set_visibility_to_company
@@ -184,3 +218,15 @@ pub fn page(
 
     crate::render(page)
 }
+
+#[component]
+fn ReindexBadge(progress: ReindexProgress) -> Element {
+    rsx! {
+        Badge {
+            class: "ml-2",
+            badge_color: BadgeColor::Warning,
+            badge_size: BadgeSize::Sm,
+            "Re-indexing to {progress.model_name} ({progress.done} of {progress.total})"
+        }
+    }
+}
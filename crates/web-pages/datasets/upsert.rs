@@ -1,6 +1,7 @@
 #![allow(non_snake_case)]
 use daisy_rsx::*;
 use db::queries::models;
+use db::types::public::ChunkingStrategy;
 use db::Visibility;
 use dioxus::prelude::*;
 
@@ -9,10 +10,13 @@ pub fn Upsert(
     id: Option<i32>,
     trigger_id: String,
     models: Vec<models::Model>,
+    embeddings_model_id: Option<i32>,
     name: String,
     team_id: i32,
     combine_under_n_chars: i32,
     new_after_n_chars: i32,
+    chunk_overlap: i32,
+    chunking_strategy: ChunkingStrategy,
     _multipage_sections: bool,
     visibility: Visibility,
     can_set_visibility_to_company: bool,
@@ -95,12 +99,13 @@ pub fn Upsert(
                             Fieldset {
                                 legend: "Select the Embedding Model to use",
                                 legend_class: "mt-4",
-                                help_text: "Embeddings are vector stored in the database",
+                                help_text: "Embeddings are vector stored in the database. Changing the model re-embeds every document, the current model is used until that finishes.",
                                 Select {
                                     name: "embeddings_model_id",
                                     for model in &models {
                                         option {
                                             value: "{model.id}",
+                                            selected: Some(model.id) == embeddings_model_id,
                                             "{model.name}"
                                         }
                                     }
@@ -110,13 +115,21 @@ pub fn Upsert(
                             Fieldset {
                                 legend: "Select the Chunking Strategy",
                                 legend_class: "mt-4",
-                                help_text: "These are the chunking strategies supported by unstructured.",
+                                help_text: "By Title uses Unstructured. The others split text, Markdown, HTML, CSV and JSON files without it, other files still go to Unstructured.",
                                 Select {
                                     name: "chunking_strategy",
-                                    value: "By Title",
-                                    option {
-                                        value: "By Title",
-                                        "By Title"
+                                    value: "{crate::chunking_strategy_to_string(chunking_strategy)}",
+                                    for strategy in [
+                                        ChunkingStrategy::ByTitle,
+                                        ChunkingStrategy::Recursive,
+                                        ChunkingStrategy::Sentence,
+                                        ChunkingStrategy::TokenWindow,
+                                    ] {
+                                        SelectOption {
+                                            value: "{crate::chunking_strategy_to_string(strategy)}",
+                                            selected_value: "{crate::chunking_strategy_to_string(chunking_strategy)}",
+                                            {crate::chunking_strategy_to_string(strategy)}
+                                        }
                                     }
                                 }
                             }
@@ -135,7 +148,7 @@ pub fn Upsert(
                             Fieldset {
                                 legend: "New After N Chars",
                                 legend_class: "mt-4",
-                                help_text: "Start a new section if the length of a section exceeds this value",
+                                help_text: "Start a new section if the length of a section exceeds this value. For Token Window this is a number of tokens.",
                                 Input {
                                     input_type: InputType::Text,
                                     value: "{new_after_n_chars}",
@@ -143,6 +156,17 @@ pub fn Upsert(
                                     name: "new_after_n_chars"
                                 }
                             }
+                            Fieldset {
+                                legend: "Chunk Overlap",
+                                legend_class: "mt-4",
+                                help_text: "How much of the end of one chunk to repeat at the start of the next. Not used by By Title.",
+                                Input {
+                                    input_type: InputType::Number,
+                                    value: "{chunk_overlap}",
+                                    required: true,
+                                    name: "chunk_overlap"
+                                }
+                            }
 
                             Fieldset {
                                 legend: "Multipage Sections",
@@ -83,6 +83,16 @@ pub fn page(rbac: Rbac, team_id: i32, dataset: Dataset, documents: Vec<Document>
                     }
 
                     for doc in documents {
+                        if doc.failure_reason.is_some() || doc.fail_count > 0 {
+                            ConfirmModal {
+                                action: crate::routes::documents::Retry{team_id, document_id: doc.id}.to_string(),
+                                trigger_id: format!("retry-doc-trigger-{}-{}", doc.id, team_id),
+                                submit_label: "Retry".to_string(),
+                                heading: "Retry this document?".to_string(),
+                                warning: "We'll try to process the parts of this document that failed again.".to_string(),
+                                hidden_fields: vec![],
+                            }
+                        }
                         ConfirmModal {
                             action: crate::routes::documents::Delete{team_id, document_id: doc.id}.to_string(),
                             trigger_id: format!("delete-doc-trigger-{}-{}", doc.id, team_id),
@@ -146,18 +156,7 @@ pub fn Row(document: Document, team_id: i32, first_time: bool) -> Element {
              }
             td { "{document.content_size}" }
             td {
-                if document.waiting > 0 || document.batches == 0 {
-                    turbo-frame {
-                        id,
-                        src,
-                        Badge {
-                            class: class,
-                            badge_style: BadgeStyle::Outline,
-                            badge_size: BadgeSize::Sm,
-                            "Processing ({document.waiting} remaining)"
-                        }
-                    }
-                } else if document.failure_reason.is_some() {
+                if document.failure_reason.is_some() {
                     turbo-frame {
                         id,
                         src,
@@ -172,26 +171,19 @@ pub fn Row(document: Document, team_id: i32, first_time: bool) -> Element {
                             }
                         }
                     }
-                } else if document.batches == 0 {
+                } else if document.waiting > 0 || document.batches == 0 {
                     turbo-frame {
                         id,
                         src,
-
-                        Badge { badge_style: BadgeStyle::Outline, badge_size: BadgeSize::Sm, "Queued" }
-                    }
-                } else if document.fail_count > 0 {
-                    turbo-frame {
-                        id,
-                        src,
-
                         Badge {
-                            badge_color: BadgeColor::Error,
+                            class: class,
                             badge_style: BadgeStyle::Outline,
                             badge_size: BadgeSize::Sm,
-                            "Processed ({document.fail_count} failed)"
+                            "Processing ({document.waiting} remaining)"
                         }
                     }
-                } else if document.failure_reason.is_some() {
+                } else if document.fail_count > 0 {
+                    // Only done when every chunk has its embeddings
                     turbo-frame {
                         id,
                         src,
@@ -200,7 +192,7 @@ pub fn Row(document: Document, team_id: i32, first_time: bool) -> Element {
                             badge_color: BadgeColor::Error,
                             badge_style: BadgeStyle::Outline,
                             badge_size: BadgeSize::Sm,
-                            "Failed"
+                            "Failed ({document.fail_count} of {document.batches} chunks)"
                         }
                     }
                 } else {
@@ -222,6 +214,15 @@ pub fn Row(document: Document, team_id: i32, first_time: bool) -> Element {
                 DropDown {
                     direction: Direction::Left,
                     button_text: "...",
+                    if document.failure_reason.is_some() || document.fail_count > 0 {
+                        DropDownLink {
+                            popover_target: format!("retry-doc-trigger-{}-{}",
+                                document.id, team_id),
+                            href: "#",
+                            target: "_top",
+                            "Retry"
+                        }
+                    }
                     DropDownLink {
                         popover_target: format!("delete-doc-trigger-{}-{}",
                             document.id, team_id),
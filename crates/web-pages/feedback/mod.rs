@@ -0,0 +1,7 @@
+pub mod page;
+pub mod satisfaction_table;
+
+pub use satisfaction_table::SatisfactionTable;
+
+// The periods the dashboard can cover, in days
+pub const PERIODS: [i32; 3] = [7, 30, 90];
@@ -0,0 +1,111 @@
+#![allow(non_snake_case)]
+use crate::app_layout::{Layout, SideBar};
+use crate::charts::SatisfactionChartCard;
+use crate::SectionIntroduction;
+use daisy_rsx::*;
+use db::authz::Rbac;
+use db::queries::chat_feedback::{DailySatisfaction, Satisfaction};
+use dioxus::prelude::*;
+
+pub struct Dashboard {
+    pub days: i32,
+    pub daily: Vec<DailySatisfaction>,
+    pub by_assistant: Vec<Satisfaction>,
+    pub by_model: Vec<Satisfaction>,
+    pub by_dataset: Vec<Satisfaction>,
+}
+
+pub fn page(rbac: Rbac, team_id: i32, dashboard: Dashboard) -> String {
+    let days = dashboard.days;
+    let page = rsx! {
+        Layout {
+            section_class: "p-4",
+            selected_item: SideBar::Feedback,
+            team_id: team_id,
+            rbac: rbac,
+            title: "Answer Feedback",
+            header: rsx! {
+                Breadcrumb {
+                    items: vec![BreadcrumbItem {
+                        text: "Answer Feedback".into(),
+                        href: None
+                    }]
+                }
+                div {
+                    class: "flex flex-row gap-2",
+                    form {
+                        method: "post",
+                        action: crate::routes::feedback::Index{team_id}.to_string(),
+                        class: "flex flex-row gap-2",
+                        Select {
+                            name: "days",
+                            for period in super::PERIODS {
+                                option {
+                                    value: "{period}",
+                                    selected: period == days,
+                                    "Last {period} days"
+                                }
+                            }
+                        }
+                        Button {
+                            button_type: ButtonType::Submit,
+                            button_size: ButtonSize::Small,
+                            "Apply"
+                        }
+                    }
+                    form {
+                        method: "post",
+                        action: crate::routes::feedback::Export{team_id}.to_string(),
+                        input {
+                            "type": "hidden",
+                            name: "days",
+                            value: "{days}"
+                        }
+                        Button {
+                            button_type: ButtonType::Submit,
+                            button_scheme: ButtonScheme::Primary,
+                            button_size: ButtonSize::Small,
+                            "Export Low Rated Answers"
+                        }
+                    }
+                }
+            },
+            div {
+                class: "p-4 max-w-5xl w-full mx-auto",
+                SectionIntroduction {
+                    header: "Answer Feedback".to_string(),
+                    subtitle: "How helpful the team found the answers from each assistant, model and dataset. The export pairs answers rated not helpful with the question, prompt and document sections behind them.".to_string(),
+                    is_empty: dashboard.daily.is_empty(),
+                    empty_text: format!("Nobody has rated an answer in the last {days} days."),
+                }
+
+                if !dashboard.daily.is_empty() {
+                    SatisfactionChartCard {
+                        data: dashboard.daily,
+                        title: format!("Ratings (Last {days} Days)")
+                    }
+
+                    super::SatisfactionTable {
+                        title: "By Assistant".to_string(),
+                        heading: "Assistant".to_string(),
+                        rows: dashboard.by_assistant
+                    }
+
+                    super::SatisfactionTable {
+                        title: "By Model".to_string(),
+                        heading: "Model".to_string(),
+                        rows: dashboard.by_model
+                    }
+
+                    super::SatisfactionTable {
+                        title: "By Dataset".to_string(),
+                        heading: "Dataset".to_string(),
+                        rows: dashboard.by_dataset
+                    }
+                }
+            }
+        }
+    };
+
+    crate::render(page)
+}
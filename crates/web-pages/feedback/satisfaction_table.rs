@@ -0,0 +1,66 @@
+#![allow(non_snake_case)]
+use daisy_rsx::*;
+use db::queries::chat_feedback::Satisfaction;
+use dioxus::prelude::*;
+
+#[component]
+pub fn SatisfactionTable(title: String, heading: String, rows: Vec<Satisfaction>) -> Element {
+    rsx!(
+        Card {
+            class: "has-data-table mt-6",
+            CardHeader {
+                title: "{title}"
+            }
+            CardBody {
+                if rows.is_empty() {
+                    p {
+                        class: "p-4 text-sm opacity-70",
+                        "No feedback yet"
+                    }
+                } else {
+                    table {
+                        class: "table table-sm",
+                        thead {
+                            th { "{heading}" }
+                            th { "Helpful" }
+                            th { "Not helpful" }
+                            th { "Satisfaction" }
+                        }
+                        tbody {
+                            for row in rows {
+                                tr {
+                                    td { "{row.name}" }
+                                    td { "{row.positive}" }
+                                    td { "{row.negative}" }
+                                    td {
+                                        Badge {
+                                            badge_style: BadgeStyle::Outline,
+                                            badge_color: satisfaction_color(row.positive, row.negative),
+                                            "{satisfaction(row.positive, row.negative)}%"
+                                        }
+                                    }
+                                }
+                            }
+                        }
+                    }
+                }
+            }
+        }
+    )
+}
+
+// The share of ratings that were helpful
+fn satisfaction(positive: i64, negative: i64) -> i64 {
+    match positive + negative {
+        0 => 0,
+        total => positive * 100 / total,
+    }
+}
+
+fn satisfaction_color(positive: i64, negative: i64) -> BadgeColor {
+    match satisfaction(positive, negative) {
+        80.. => BadgeColor::Success,
+        50.. => BadgeColor::Warning,
+        _ => BadgeColor::Error,
+    }
+}
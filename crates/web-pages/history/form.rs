@@ -19,14 +19,32 @@ pub fn Form(team_id: i32) -> Element {
                         class: "flex flex-col",
                         Fieldset {
                             legend: "Search",
-                            help_text: "What do you want to look for?",
+                            help_text: "What do you want to look for? Leave it empty to see every chat.",
                             Input {
                                 input_type: InputType::Text,
                                 placeholder: "Your Search",
-                                required: true,
                                 name: "search"
                             }
                         }
+                        Fieldset {
+                            legend: "Feedback",
+                            help_text: "Only show chats where you rated an answer",
+                            Select {
+                                name: "rating",
+                                option {
+                                    value: "",
+                                    "Any"
+                                }
+                                option {
+                                    value: "Positive",
+                                    "Rated helpful"
+                                }
+                                option {
+                                    value: "Negative",
+                                    "Rated not helpful"
+                                }
+                            }
+                        }
                     }
                     ModalAction {
                         Button {
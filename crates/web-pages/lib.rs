@@ -1,4 +1,6 @@
+use db::types::public::ChunkingStrategy;
 use db::Visibility;
+use db::{HistoryStrategy, LoadBalancing, ModelProvider};
 use dioxus::prelude::Element;
 
 pub mod api_keys;
@@ -13,6 +15,7 @@ pub mod components;
 pub mod console;
 pub mod datasets;
 pub mod documents;
+pub mod feedback;
 pub mod history;
 pub mod integrations;
 pub mod licence;
@@ -52,3 +55,65 @@ pub fn string_to_visibility(visibility: &str) -> Visibility {
         _ => Visibility::Private,
     }
 }
+
+pub fn chunking_strategy_to_string(chunking_strategy: ChunkingStrategy) -> String {
+    match chunking_strategy {
+        ChunkingStrategy::ByTitle => "By Title".to_string(),
+        ChunkingStrategy::Recursive => "Recursive".to_string(),
+        ChunkingStrategy::Sentence => "Sentence".to_string(),
+        ChunkingStrategy::TokenWindow => "Token Window".to_string(),
+    }
+}
+
+pub fn string_to_chunking_strategy(chunking_strategy: &str) -> ChunkingStrategy {
+    match chunking_strategy {
+        "Recursive" => ChunkingStrategy::Recursive,
+        "Sentence" => ChunkingStrategy::Sentence,
+        "Token Window" => ChunkingStrategy::TokenWindow,
+        _ => ChunkingStrategy::ByTitle,
+    }
+}
+
+pub fn history_strategy_to_string(history_strategy: HistoryStrategy) -> String {
+    match history_strategy {
+        HistoryStrategy::Trim => "Trim".to_string(),
+        HistoryStrategy::Summarise => "Summarise".to_string(),
+    }
+}
+
+pub fn string_to_history_strategy(history_strategy: &str) -> HistoryStrategy {
+    match history_strategy {
+        "Summarise" => HistoryStrategy::Summarise,
+        _ => HistoryStrategy::Trim,
+    }
+}
+
+pub fn load_balancing_to_string(load_balancing: LoadBalancing) -> String {
+    match load_balancing {
+        LoadBalancing::LeastOutstanding => "Least Outstanding".to_string(),
+        LoadBalancing::WeightedRoundRobin => "Weighted Round Robin".to_string(),
+    }
+}
+
+pub fn string_to_load_balancing(load_balancing: &str) -> LoadBalancing {
+    match load_balancing {
+        "Weighted Round Robin" => LoadBalancing::WeightedRoundRobin,
+        _ => LoadBalancing::LeastOutstanding,
+    }
+}
+
+pub fn provider_to_string(provider: ModelProvider) -> String {
+    match provider {
+        ModelProvider::OpenAI => "OpenAI".to_string(),
+        ModelProvider::Anthropic => "Anthropic".to_string(),
+        ModelProvider::Ollama => "Ollama".to_string(),
+    }
+}
+
+pub fn string_to_provider(provider: &str) -> ModelProvider {
+    match provider {
+        "Anthropic" => ModelProvider::Anthropic,
+        "Ollama" => ModelProvider::Ollama,
+        _ => ModelProvider::OpenAI,
+    }
+}
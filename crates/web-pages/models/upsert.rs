@@ -14,11 +14,28 @@ pub struct ModelForm {
     pub name: String,
     pub display_name: String,
     pub model_type: String,
+    pub provider: String,
     pub base_url: String,
     pub api_key: String,
     pub tpm_limit: i32,
     pub rpm_limit: i32,
     pub context_size_bytes: i32,
+    pub embeddings_batch_size: i32,
+    pub embeddings_concurrency: i32,
+    pub tokenizer: String,
+    pub retry_max_attempts: i32,
+    pub retry_backoff_ms: i32,
+    pub retry_status_codes: String,
+    pub circuit_breaker_threshold: i32,
+    pub circuit_breaker_cooldown_seconds: i32,
+    pub load_balancing: String,
+    // The pool of endpoints, only loaded when editing
+    #[serde(skip)]
+    pub endpoints: Vec<EndpointRow>,
+    pub fallback_model_ids: Vec<i32>,
+    // The models that can be picked as fallbacks
+    #[serde(skip)]
+    pub fallback_options: Vec<(i32, String)>,
     pub visibility: String,
     pub disclaimer: String,
     pub description: String,
@@ -30,10 +47,22 @@ pub struct ModelForm {
     pub has_capability_vision: bool,
     pub has_capability_tool_use: bool,
     pub has_capability_guard: bool,
+    pub has_capability_stream_usage: bool,
     #[serde(skip)]
     pub error: Option<String>,
 }
 
+#[derive(Default, Debug, Clone, PartialEq)]
+pub struct EndpointRow {
+    pub id: i32,
+    pub base_url: String,
+    pub weight: i32,
+    pub max_concurrency: i32,
+    // As seen by this server
+    pub in_flight: u32,
+    pub healthy: bool,
+}
+
 pub fn page(team_id: i32, rbac: Rbac, form: ModelForm) -> String {
     let page = rsx! {
         Layout {
@@ -144,7 +173,24 @@ pub fn page(team_id: i32, rbac: Rbac, form: ModelForm) -> String {
                                         SelectOption { value: "Embeddings", selected_value: form.model_type.clone(), "Embeddings Model" }
                                         SelectOption { value: "Image", selected_value: form.model_type.clone(), "Image Generation" }
                                         SelectOption { value: "TextToSpeech", selected_value: form.model_type.clone(), "Text To Speech" }
+                                        SelectOption { value: "SpeechToText", selected_value: form.model_type.clone(), "Speech To Text" }
                                         SelectOption { value: "Guard", selected_value: form.model_type.clone(), "Guard" }
+                                        SelectOption { value: "Reranker", selected_value: form.model_type.clone(), "Reranker" }
+                                    }
+                                }
+                            }
+                            div {
+                                class: "flex flex-col",
+                                Fieldset {
+                                    legend: "API Provider",
+                                    legend_class: "mt-4",
+                                    help_text: "Anything OpenAI compatible (vLLM, TGI, LiteLLM...) is OpenAI. For Anthropic the base URL ends in /v1, for Ollama it's the server i.e. http://localhost:11434",
+                                    Select {
+                                        name: "provider",
+                                        value: form.provider.clone(),
+                                        SelectOption { value: "OpenAI", selected_value: form.provider.clone(), "OpenAI Compatible" }
+                                        SelectOption { value: "Anthropic", selected_value: form.provider.clone(), "Anthropic" }
+                                        SelectOption { value: "Ollama", selected_value: form.provider.clone(), "Ollama" }
                                     }
                                 }
                             }
@@ -216,7 +262,7 @@ pub fn page(team_id: i32, rbac: Rbac, form: ModelForm) -> String {
                             Fieldset {
                                 legend: "Set the maximum tokens per minute for each user.",
                                 legend_class: "mt-4",
-                                help_text: "If users exceed this limit there access to the model will be limited.",
+                                help_text: "Applies over an hour to users without a rate limit rule. Set to 0 for no default limit.",
                                 Input {
                                     input_type: InputType::Number,
                                     name: "tpm_limit",
@@ -246,6 +292,44 @@ pub fn page(team_id: i32, rbac: Rbac, form: ModelForm) -> String {
                                     required: true
                                 }
                             }
+                            Fieldset {
+                                legend: "Embeddings Batch Size",
+                                legend_class: "mt-4",
+                                help_text: "How many chunks to send in each embeddings request. Only used by embeddings models.",
+                                Input {
+                                    input_type: InputType::Number,
+                                    name: "embeddings_batch_size",
+                                    value: "{form.embeddings_batch_size}",
+                                    required: true
+                                }
+                            }
+                            Fieldset {
+                                legend: "Embeddings Concurrency",
+                                legend_class: "mt-4",
+                                help_text: "How many embeddings requests to run at the same time. Only used by embeddings models.",
+                                Input {
+                                    input_type: InputType::Number,
+                                    name: "embeddings_concurrency",
+                                    value: "{form.embeddings_concurrency}",
+                                    required: true
+                                }
+                            }
+                            Fieldset {
+                                legend: "Tokenizer",
+                                legend_class: "mt-4",
+                                help_text: "Used to count tokens for prompt budgets, usage and embeddings input. HuggingFace tokenizer.json files are read from TOKENIZER_DIR.",
+                                Select {
+                                    name: "tokenizer",
+                                    value: form.tokenizer.clone(),
+                                    for tokenizer in openai_api::tokenizer::available() {
+                                        SelectOption {
+                                            value: "{tokenizer}",
+                                            selected_value: form.tokenizer.clone(),
+                                            "{tokenizer}"
+                                        }
+                                    }
+                                }
+                            }
                         }
                     }
 
@@ -288,12 +372,114 @@ pub fn page(team_id: i32, rbac: Rbac, form: ModelForm) -> String {
                                         input { "type": "checkbox", name: "capability_guard", class: "checkbox", checked: form.has_capability_guard }
                                     }
                                 }
+                                div {
+                                    class: "form-control",
+                                    label {
+                                        class: "label cursor-pointer",
+                                        span { class: "label-text", "Stream Usage" }
+                                        input { "type": "checkbox", name: "capability_stream_usage", class: "checkbox", checked: form.has_capability_stream_usage }
+                                    }
+                                }
                             } else {
                                 p { "Capabilities are only available for LLM models." }
                             }
                         }
                     }
 
+                    // Retries and fallbacks
+                    Card {
+                        class: "mb-6",
+                        CardHeader { title: "Reliability" }
+                        CardBody {
+                            class: "flex flex-col gap-6",
+                            p {
+                                "Used by LLM models. When the model can't be reached, times out or returns one of the retry status codes we try again, then move on to the fallback models in order."
+                            }
+                            div {
+                                class: "grid grid-cols-1 md:grid-cols-2 gap-4",
+                                Fieldset {
+                                    legend: "Max Attempts",
+                                    help_text: "How many times to call this model before falling back.",
+                                    Input {
+                                        input_type: InputType::Number,
+                                        name: "retry_max_attempts",
+                                        value: "{form.retry_max_attempts}",
+                                        required: true
+                                    }
+                                }
+                                Fieldset {
+                                    legend: "Backoff (ms)",
+                                    help_text: "The wait before the first retry, it doubles after each attempt.",
+                                    Input {
+                                        input_type: InputType::Number,
+                                        name: "retry_backoff_ms",
+                                        value: "{form.retry_backoff_ms}",
+                                        required: true
+                                    }
+                                }
+                                Fieldset {
+                                    legend: "Retry Status Codes",
+                                    help_text: "Comma separated, i.e. 429, 500, 502, 503, 504",
+                                    Input {
+                                        input_type: InputType::Text,
+                                        name: "retry_status_codes",
+                                        value: "{form.retry_status_codes}"
+                                    }
+                                }
+                                Fieldset {
+                                    legend: "Circuit Breaker Threshold",
+                                    help_text: "Failures in a row before an endpoint is skipped.",
+                                    Input {
+                                        input_type: InputType::Number,
+                                        name: "circuit_breaker_threshold",
+                                        value: "{form.circuit_breaker_threshold}",
+                                        required: true
+                                    }
+                                }
+                                Fieldset {
+                                    legend: "Circuit Breaker Cool Down (seconds)",
+                                    help_text: "How long the endpoint is skipped for.",
+                                    Input {
+                                        input_type: InputType::Number,
+                                        name: "circuit_breaker_cooldown_seconds",
+                                        value: "{form.circuit_breaker_cooldown_seconds}",
+                                        required: true
+                                    }
+                                }
+                                Fieldset {
+                                    legend: "Load Balancing",
+                                    help_text: "How requests are spread over the model's endpoints.",
+                                    Select {
+                                        name: "load_balancing",
+                                        value: form.load_balancing.clone(),
+                                        SelectOption { value: "Least Outstanding", selected_value: form.load_balancing.clone(), "Least Outstanding Requests" }
+                                        SelectOption { value: "Weighted Round Robin", selected_value: form.load_balancing.clone(), "Weighted Round Robin" }
+                                    }
+                                }
+                            }
+                            for (index, legend) in ["First Fallback", "Second Fallback", "Third Fallback"].into_iter().enumerate() {
+                                Fieldset {
+                                    legend: "{legend}",
+                                    help_text: "Tried when the models above are unavailable.",
+                                    Select {
+                                        name: "fallback_model_ids",
+                                        option {
+                                            value: "",
+                                            "None"
+                                        }
+                                        for (id, name) in &form.fallback_options {
+                                            option {
+                                                value: "{id}",
+                                                selected: form.fallback_model_ids.get(index) == Some(id),
+                                                "{name}"
+                                            }
+                                        }
+                                    }
+                                }
+                            }
+                        }
+                    }
+
                     // Examples & Disclaimer
                     Card {
                         class: "mb-6",
@@ -377,8 +563,149 @@ pub fn page(team_id: i32, rbac: Rbac, form: ModelForm) -> String {
                         }
                     }
                 }
+
+                // The pool is saved as it's edited so it lives outside the
+                // model form
+                if let Some(model_id) = form.id {
+                    if form.model_type == "LLM" {
+                        Endpoints {
+                            team_id,
+                            model_id,
+                            endpoints: form.endpoints.clone()
+                        }
+                    }
+                }
             }
         }
     };
     crate::render(page)
 }
+
+#[component]
+fn Endpoints(team_id: i32, model_id: i32, endpoints: Vec<EndpointRow>) -> Element {
+    rsx! {
+        Card {
+            class: "mt-6",
+            CardHeader { title: "Endpoints" }
+            CardBody {
+                class: "flex flex-col gap-6",
+                p {
+                    "Replicas or provider keys serving this model. When there are any they are used instead of the base URL and API secret above."
+                }
+                if !endpoints.is_empty() {
+                    table {
+                        class: "table table-sm",
+                        thead {
+                            th { "Base URL" }
+                            th { "Weight" }
+                            th { "Max Concurrency" }
+                            th { "In Flight" }
+                            th { "Health" }
+                            th {
+                                class: "text-right",
+                                "Action"
+                            }
+                        }
+                        tbody {
+                            for endpoint in endpoints {
+                                tr {
+                                    td { "{endpoint.base_url}" }
+                                    td { "{endpoint.weight}" }
+                                    td {
+                                        if endpoint.max_concurrency == 0 {
+                                            "Unlimited"
+                                        } else {
+                                            "{endpoint.max_concurrency}"
+                                        }
+                                    }
+                                    td { "{endpoint.in_flight}" }
+                                    td {
+                                        if endpoint.healthy {
+                                            Badge {
+                                                badge_color: BadgeColor::Success,
+                                                badge_size: BadgeSize::Sm,
+                                                "Healthy"
+                                            }
+                                        } else {
+                                            Badge {
+                                                badge_color: BadgeColor::Error,
+                                                badge_size: BadgeSize::Sm,
+                                                "Circuit Open"
+                                            }
+                                        }
+                                    }
+                                    td {
+                                        class: "text-right",
+                                        form {
+                                            action: crate::routes::models::DeleteEndpoint { team_id, model_id, id: endpoint.id }.to_string(),
+                                            method: "post",
+                                            Button {
+                                                button_type: ButtonType::Submit,
+                                                button_scheme: ButtonScheme::Error,
+                                                button_size: ButtonSize::Small,
+                                                "Delete"
+                                            }
+                                        }
+                                    }
+                                }
+                            }
+                        }
+                    }
+                }
+                form {
+                    action: crate::routes::models::AddEndpoint { team_id, model_id }.to_string(),
+                    method: "post",
+                    class: "flex flex-col gap-4",
+                    div {
+                        class: "grid grid-cols-1 md:grid-cols-2 gap-4",
+                        Fieldset {
+                            legend: "Base URL",
+                            help_text: "The URL location of the OpenAI compatible API",
+                            Input {
+                                input_type: InputType::Text,
+                                name: "base_url",
+                                required: true
+                            }
+                        }
+                        Fieldset {
+                            legend: "API Secret",
+                            help_text: "Leave empty if the endpoint doesn't need one",
+                            Input {
+                                input_type: InputType::Text,
+                                name: "api_key"
+                            }
+                        }
+                        Fieldset {
+                            legend: "Weight",
+                            help_text: "Endpoints with more weight get more requests.",
+                            Input {
+                                input_type: InputType::Number,
+                                name: "weight",
+                                value: "1",
+                                required: true
+                            }
+                        }
+                        Fieldset {
+                            legend: "Max Concurrency",
+                            help_text: "Requests the endpoint can take at once, 0 for no limit.",
+                            Input {
+                                input_type: InputType::Number,
+                                name: "max_concurrency",
+                                value: "0",
+                                required: true
+                            }
+                        }
+                    }
+                    div {
+                        class: "flex justify-end",
+                        Button {
+                            button_type: ButtonType::Submit,
+                            button_scheme: ButtonScheme::Primary,
+                            "Add Endpoint"
+                        }
+                    }
+                }
+            }
+        }
+    }
+}
@@ -12,6 +12,10 @@ pub struct DatasetForm {
     pub prompt_id: i32,
     pub prompt_name: String,
     pub selected_dataset_ids: Vec<i32>,
+    pub vector_weight: f32,
+    pub lexical_weight: f32,
+    pub rerank_enabled: bool,
+    pub rerank_pool_size: i32,
     #[serde(skip)]
     pub error: Option<String>,
     #[serde(skip)]
@@ -128,6 +132,62 @@ pub fn page(team_id: i32, rbac: Rbac, form: DatasetForm) -> String {
                         }
                     }
 
+                    Card {
+                        class: "mb-6",
+                        CardHeader {
+                            title: "Retrieval"
+                        }
+                        CardBody {
+                            p {
+                                class: "text-sm text-base-content/70 mb-4",
+                                "We combine a semantic (vector) search with a keyword search. Keyword search finds exact matches such as part numbers, error codes and names. Set a weight to zero to turn that search off."
+                            }
+                            div {
+                                class: "grid grid-cols-1 md:grid-cols-2 gap-4",
+                                Fieldset {
+                                    legend: "Vector Weight",
+                                    help_text: "How much the semantic search counts",
+                                    Input {
+                                        input_type: InputType::Number,
+                                        step: "0.1",
+                                        name: "vector_weight",
+                                        value: "{form.vector_weight}",
+                                        required: true
+                                    }
+                                }
+                                Fieldset {
+                                    legend: "Keyword Weight",
+                                    help_text: "How much the keyword search counts",
+                                    Input {
+                                        input_type: InputType::Number,
+                                        step: "0.1",
+                                        name: "lexical_weight",
+                                        value: "{form.lexical_weight}",
+                                        required: true
+                                    }
+                                }
+                            }
+                            div {
+                                class: "form-control mt-4",
+                                label {
+                                    class: "label cursor-pointer justify-start gap-4",
+                                    input { "type": "checkbox", name: "rerank_enabled", class: "checkbox", checked: form.rerank_enabled }
+                                    span { class: "label-text", "Rerank results with the Reranker model" }
+                                }
+                            }
+                            Fieldset {
+                                legend: "Rerank Candidates",
+                                help_text: "How many chunks we retrieve for the reranker to choose from",
+                                Input {
+                                    input_type: InputType::Number,
+                                    name: "rerank_pool_size",
+                                    value: "{form.rerank_pool_size}",
+                                    required: true
+                                }
+                            }
+                        }
+                    }
+
                     // Form Actions
                     Card {
                         CardBody {
@@ -22,6 +22,8 @@ pub struct PromptForm {
     pub example3: Option<String>,
     pub example4: Option<String>,
     pub max_history_items: i32,
+    pub history_strategy: String,
+    pub summary_model_id: i32,
     pub max_chunks: i32,
     pub max_tokens: i32,
     pub trim_ratio: i32,
@@ -368,6 +370,50 @@ pub fn page(team_id: i32, rbac: Rbac, prompt: PromptForm, show_company_visibilit
                                             }
                                         }
                                     }
+                                    div {
+                                        class: "flex flex-col",
+                                        Fieldset {
+                                            legend: "When History Doesn't Fit",
+                                            help_text: "Trim drops the oldest turns, Summarise folds them into a summary of the conversation.",
+                                            Select {
+                                                name: "history_strategy",
+                                                value: "{prompt.history_strategy}",
+                                                SelectOption {
+                                                    value: "Trim",
+                                                    selected_value: "{prompt.history_strategy}",
+                                                    "Trim"
+                                                }
+                                                SelectOption {
+                                                    value: "Summarise",
+                                                    selected_value: "{prompt.history_strategy}",
+                                                    "Summarise"
+                                                }
+                                            }
+                                        }
+                                    }
+                                    div {
+                                        class: "flex flex-col",
+                                        Fieldset {
+                                            legend: "Summary Model",
+                                            help_text: "The model that writes summaries, a small model keeps this cheap.",
+                                            Select {
+                                                name: "summary_model_id",
+                                                value: "{prompt.summary_model_id}",
+                                                SelectOption {
+                                                    value: "-1",
+                                                    selected_value: "{prompt.summary_model_id}",
+                                                    "The assistant's model"
+                                                }
+                                                for model_prompt in &prompt.models {
+                                                    SelectOption {
+                                                        value: "{model_prompt.model_id}",
+                                                        selected_value: "{prompt.summary_model_id}",
+                                                        "{model_prompt.name}"
+                                                    }
+                                                }
+                                            }
+                                        }
+                                    }
                                     div {
                                         class: "flex flex-col",
                                         Fieldset {
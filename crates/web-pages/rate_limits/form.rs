@@ -20,38 +20,66 @@ pub fn Form(team_id: i32, models: Vec<Model>) -> Element {
                         class: "flex flex-col",
 
                         Fieldset {
-                            legend: "API Key ID",
+                            legend: "Applies To",
                             legend_class: "mt-4",
-                            help_text: "We need the ID of the Api Key from the ID field",
-                            Input {
-                                input_type: InputType::Number,
-                                placeholder: "Api Key Id i.e. 1234",
-                                required: true,
-                                name: "api_key_id"
+                            help_text: "The most specific limit wins, a user limit beats a role limit which beats a limit for everyone",
+                            Select {
+                                name: "applies_to",
+                                SelectOption { value: "Everyone", "Everyone" }
+                                SelectOption { value: "Role", "Everyone with a role" }
+                                SelectOption { value: "User", "A single user" }
                             }
                         }
 
                         Fieldset {
-                            legend: "Tokens per Minute",
+                            legend: "User Email",
                             legend_class: "mt-4",
-                            help_text: "Tokens Per minute",
+                            help_text: "Only used when the limit applies to a single user",
                             Input {
-                                input_type: InputType::Number,
-                                placeholder: "Tokens per Minute e.g. 1000",
-                                required: true,
-                                name: "tpm_limit"
+                                input_type: InputType::Email,
+                                placeholder: "user@example.com",
+                                name: "user_email"
+                            }
+                        }
+
+                        Fieldset {
+                            legend: "Role",
+                            legend_class: "mt-4",
+                            help_text: "Only used when the limit applies to a role in the team",
+                            Select {
+                                name: "limits_role",
+                                SelectOption { value: "", "Select a role" }
+                                SelectOption { value: "Collaborator", "Collaborator" }
+                                SelectOption { value: "TeamManager", "Team Manager" }
+                                SelectOption { value: "SystemAdministrator", "System Administrator" }
+                            }
+                        }
+
+                        Fieldset {
+                            legend: "Model",
+                            legend_class: "mt-4",
+                            help_text: "Leave as All Models to share the budget across every model",
+                            Select {
+                                name: "model_id",
+                                SelectOption { value: "", "All Models" }
+                                for model in models {
+                                    SelectOption {
+                                        value: "{model.id}",
+                                        "{model.name}"
+                                    }
+                                }
                             }
                         }
 
                         Fieldset {
-                            legend: "Requests per Minute",
+                            legend: "Tokens per Hour",
                             legend_class: "mt-4",
-                            help_text: "Requests Per minute",
+                            help_text: "Prompt and completion tokens over a rolling hour",
                             Input {
                                 input_type: InputType::Number,
-                                placeholder: "Requests per Minute e.g. 1000",
+                                placeholder: "Tokens per Hour e.g. 100000",
                                 required: true,
-                                name: "rpm_limit"
+                                name: "tokens_per_hour"
                             }
                         }
                     }
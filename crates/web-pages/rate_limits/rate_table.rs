@@ -15,9 +15,9 @@ pub fn RateTable(rate_limits: Vec<db::RateLimit>, team_id: i32) -> Element {
                 table {
                     class: "table table-sm",
                     thead {
-                        th { "API Key ID" }
-                        th { "TPM Limit" }
-                        th { "RPM Limit" }
+                        th { "Applies To" }
+                        th { "Model" }
+                        th { "Tokens per Hour" }
                         th {
                             class: "text-right",
                             "Action"
@@ -27,14 +27,19 @@ pub fn RateTable(rate_limits: Vec<db::RateLimit>, team_id: i32) -> Element {
                         for limit in rate_limits {
                             tr {
                                 td {
-                                    "{limit.api_key_id}"
+                                    if let Some(email) = &limit.user_email {
+                                        "{email}"
+                                    } else if let Some(role) = limit.limits_role {
+                                        crate::team::team_role::Role { role }
+                                    } else {
+                                        "Everyone"
+                                    }
                                 }
                                 td {
-                                    Badge {
-                                        badge_color: BadgeColor::Success,
-                                        badge_style: BadgeStyle::Outline,
-                                        badge_size: BadgeSize::Sm,
-                                        "{limit.tpm_limit}"
+                                    if let Some(model_name) = &limit.model_name {
+                                        "{model_name}"
+                                    } else {
+                                        "All Models"
                                     }
                                 }
                                 td {
@@ -42,7 +47,7 @@ pub fn RateTable(rate_limits: Vec<db::RateLimit>, team_id: i32) -> Element {
                                         badge_color: BadgeColor::Success,
                                         badge_style: BadgeStyle::Outline,
                                         badge_size: BadgeSize::Sm,
-                                        "{limit.rpm_limit}"
+                                        "{limit.tokens_per_hour}"
                                     }
                                 }
                                 td {
@@ -81,6 +81,59 @@ pub mod automations {
         pub prompt_id: i32,
         pub trigger_id: i32,
     }
+
+    #[derive(TypedPath, Deserialize)]
+    #[typed_path("/app/team/{team_id}/automation/{prompt_id}/webhooks/add")]
+    pub struct AddWebhookTrigger {
+        pub team_id: i32,
+        pub prompt_id: i32,
+    }
+
+    #[derive(TypedPath, Deserialize)]
+    #[typed_path("/app/team/{team_id}/automation/{prompt_id}/webhooks/rotate/{trigger_id}")]
+    pub struct RotateWebhookTrigger {
+        pub team_id: i32,
+        pub prompt_id: i32,
+        pub trigger_id: i32,
+    }
+
+    #[derive(TypedPath, Deserialize)]
+    #[typed_path("/app/team/{team_id}/automation/{prompt_id}/webhooks/remove/{trigger_id}")]
+    pub struct RemoveWebhookTrigger {
+        pub team_id: i32,
+        pub prompt_id: i32,
+        pub trigger_id: i32,
+    }
+
+    #[derive(TypedPath, Deserialize)]
+    #[typed_path("/app/team/{team_id}/automation/{prompt_id}/runs")]
+    pub struct Runs {
+        pub team_id: i32,
+        pub prompt_id: i32,
+    }
+
+    #[derive(TypedPath, Deserialize)]
+    #[typed_path("/app/team/{team_id}/automation/{prompt_id}/runs/{run_id}")]
+    pub struct RunDetail {
+        pub team_id: i32,
+        pub prompt_id: i32,
+        pub run_id: i32,
+    }
+
+    #[derive(TypedPath, Deserialize)]
+    #[typed_path("/app/team/{team_id}/automation/{prompt_id}/runs/{run_id}/rerun")]
+    pub struct Rerun {
+        pub team_id: i32,
+        pub prompt_id: i32,
+        pub run_id: i32,
+    }
+
+    // Called by external systems, authenticated with the trigger secret.
+    #[derive(TypedPath, Deserialize)]
+    #[typed_path("/v1/automations/{trigger_id}/webhook")]
+    pub struct Webhook {
+        pub trigger_id: i32,
+    }
 }
 
 pub mod history {
@@ -100,6 +153,23 @@ pub mod history {
     }
 }
 
+pub mod feedback {
+    use axum_extra::routing::TypedPath;
+    use serde::Deserialize;
+
+    #[derive(TypedPath, Deserialize)]
+    #[typed_path("/app/team/{team_id}/feedback_dashboard")]
+    pub struct Index {
+        pub team_id: i32,
+    }
+
+    #[derive(TypedPath, Deserialize)]
+    #[typed_path("/app/team/{team_id}/feedback_dashboard/export")]
+    pub struct Export {
+        pub team_id: i32,
+    }
+}
+
 pub mod rate_limits {
     use axum_extra::routing::TypedPath;
     use serde::Deserialize;
@@ -220,12 +290,50 @@ pub mod console {
         pub team_id: i32,
     }
 
+    #[derive(TypedPath, Deserialize)]
+    #[typed_path("/app/team/{team_id}/edit_message")]
+    pub struct EditMessage {
+        pub team_id: i32,
+    }
+
+    #[derive(TypedPath, Deserialize)]
+    #[typed_path("/app/team/{team_id}/regenerate")]
+    pub struct Regenerate {
+        pub team_id: i32,
+    }
+
+    #[derive(TypedPath, Deserialize)]
+    #[typed_path("/app/team/{team_id}/switch_branch")]
+    pub struct SwitchBranch {
+        pub team_id: i32,
+    }
+
+    #[derive(TypedPath, Deserialize)]
+    #[typed_path("/app/team/{team_id}/feedback")]
+    pub struct Feedback {
+        pub team_id: i32,
+    }
+
+    // Handled by the llm-proxy as it calls the speech to text model
+    #[derive(TypedPath, Deserialize)]
+    #[typed_path("/app/team/{team_id}/transcribe")]
+    pub struct Transcribe {
+        pub team_id: i32,
+    }
+
     #[derive(TypedPath, Deserialize)]
     #[typed_path("/app/team/{team_id}/console/delete/{id}")]
     pub struct Delete {
         pub team_id: i32,
         pub id: i64,
     }
+
+    #[derive(TypedPath, Deserialize)]
+    #[typed_path("/app/team/{team_id}/console/attachment/{id}")]
+    pub struct Attachment {
+        pub team_id: i32,
+        pub id: i32,
+    }
 }
 
 pub mod prompts {
@@ -373,6 +481,21 @@ pub mod models {
         pub team_id: i32,
         pub id: i32,
     }
+
+    #[derive(TypedPath, Deserialize)]
+    #[typed_path("/app/team/{team_id}/models/{model_id}/endpoints")]
+    pub struct AddEndpoint {
+        pub team_id: i32,
+        pub model_id: i32,
+    }
+
+    #[derive(TypedPath, Deserialize)]
+    #[typed_path("/app/team/{team_id}/models/{model_id}/endpoints/delete/{id}")]
+    pub struct DeleteEndpoint {
+        pub team_id: i32,
+        pub model_id: i32,
+        pub id: i32,
+    }
 }
 
 pub mod integrations {
@@ -500,6 +623,13 @@ pub mod datasets {
         pub team_id: i32,
         pub id: i32,
     }
+
+    #[derive(TypedPath, Deserialize)]
+    #[typed_path("/app/team/{team_id}/datasets/reindex/{id}")]
+    pub struct Reindex {
+        pub team_id: i32,
+        pub id: i32,
+    }
 }
 
 pub mod categories {
@@ -563,6 +693,13 @@ pub mod documents {
         pub team_id: i32,
         pub document_id: i32,
     }
+
+    #[derive(TypedPath, Deserialize)]
+    #[typed_path("/app/team/{team_id}/retry_doc/{document_id}")]
+    pub struct Retry {
+        pub team_id: i32,
+        pub document_id: i32,
+    }
 }
 
 pub mod teams {
@@ -58,6 +58,8 @@ pub struct Config {
     pub enable_barricade: bool,
     // Public base URL for redirects
     pub base_url: String,
+    // Run automation cron triggers and pending runs from this instance
+    pub enable_automation_scheduler: bool,
 }
 
 impl Default for Config {
@@ -98,6 +100,8 @@ impl Config {
 
         let app_database_url = env::var("APP_DATABASE_URL").expect("APP_DATABASE_URL not set");
 
+        let enable_automation_scheduler = env::var("DISABLE_AUTOMATION_SCHEDULER").is_err();
+
         Config {
             max_upload_size_mb,
             port,
@@ -107,6 +111,7 @@ impl Config {
             saas,
             enable_barricade,
             base_url,
+            enable_automation_scheduler,
         }
     }
 
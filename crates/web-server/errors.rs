@@ -8,6 +8,7 @@ use std::fmt;
 #[derive(Debug)]
 pub enum CustomError {
     FaultySetup(String),
+    BadRequest(String),
     Database(String),
     ExternalApi(String),
     Authentication(String),
@@ -20,6 +21,7 @@ impl fmt::Display for CustomError {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         match *self {
             CustomError::FaultySetup(ref cause) => write!(f, "Setup Error: {}", cause),
+            CustomError::BadRequest(ref cause) => write!(f, "Bad Request: {}", cause),
             CustomError::ExternalApi(ref cause) => write!(f, "Api Error: {}", cause),
             CustomError::Authentication(ref cause) => write!(f, "Api Error: {}", cause),
             CustomError::Limits(ref cause) => write!(f, "Api Error: {}", cause),
@@ -37,6 +39,7 @@ impl IntoResponse for CustomError {
         let (status, error_message) = match self {
             CustomError::Database(message) => (StatusCode::UNPROCESSABLE_ENTITY, message),
             CustomError::FaultySetup(message) => (StatusCode::UNPROCESSABLE_ENTITY, message),
+            CustomError::BadRequest(message) => (StatusCode::BAD_REQUEST, message),
             CustomError::ExternalApi(message) => (StatusCode::UNPROCESSABLE_ENTITY, message),
             CustomError::Authentication(message) => (StatusCode::UNAUTHORIZED, message),
             CustomError::Limits(message) => (StatusCode::TOO_MANY_REQUESTS, message),
@@ -55,6 +55,12 @@ pub async fn conversation(
         .await?
         .is_empty();
 
+    let is_stt_disabled = queries::models::models()
+        .bind(&transaction, &ModelType::SpeechToText)
+        .all()
+        .await?
+        .is_empty();
+
     let capabilities = queries::capabilities::get_model_capabilities()
         .bind(&transaction, &prompt.model_id)
         .all()
@@ -72,6 +78,7 @@ pub async fn conversation(
         prompt,
         conversation_id,
         is_tts_disabled,
+        is_stt_disabled,
         capabilities,
         enabled_tools,
         available_tools,
@@ -4,11 +4,11 @@ use axum::response::Html;
 use db::authz;
 use db::Pool;
 use db::{queries, PromptType};
-use web_pages::visibility_to_string;
 use web_pages::{
     assistants,
     routes::prompts::{Edit, Index, New},
 };
+use web_pages::{history_strategy_to_string, visibility_to_string};
 
 pub async fn index_loader(
     Index { team_id }: Index,
@@ -76,6 +76,8 @@ pub async fn new_assistant_loader(
         model_id: -1,
         category_id: -1,
         max_history_items: 99,
+        history_strategy: "Trim".to_string(),
+        summary_model_id: -1,
         max_chunks: 10,
         max_tokens: 1024,
         trim_ratio: 80,
@@ -164,6 +166,8 @@ pub async fn edit_assistant_loader(
         model_id: prompt.model_id,
         category_id: prompt.category_id,
         max_history_items: prompt.max_history_items,
+        history_strategy: history_strategy_to_string(prompt.history_strategy),
+        summary_model_id: prompt.summary_model_id.unwrap_or(-1),
         max_chunks: prompt.max_chunks,
         max_tokens: prompt.max_tokens,
         trim_ratio: prompt.trim_ratio,
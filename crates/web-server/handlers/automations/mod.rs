@@ -3,7 +3,9 @@ mod delete;
 mod index;
 mod integrations;
 mod loaders;
+mod runs;
 mod triggers;
+mod webhook;
 
 use axum::Router;
 use axum_extra::routing::RouterExt;
@@ -16,11 +18,18 @@ pub fn routes() -> Router {
         .typed_get(loaders::edit_automation_loader)
         .typed_get(integrations::manage_integrations)
         .typed_get(triggers::manage_triggers)
+        .typed_get(runs::runs)
+        .typed_get(runs::run_detail)
         // Actions
         .typed_post(actions::upsert)
         .typed_post(integrations::add_integration_action)
         .typed_post(integrations::remove_integration_action)
         .typed_post(triggers::add_cron_trigger)
         .typed_post(triggers::remove_cron_trigger)
+        .typed_post(triggers::add_webhook_trigger)
+        .typed_post(triggers::rotate_webhook_trigger)
+        .typed_post(triggers::remove_webhook_trigger)
+        .typed_post(runs::rerun)
+        .typed_post(webhook::webhook)
         .typed_post(delete::delete)
 }
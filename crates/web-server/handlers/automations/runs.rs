@@ -0,0 +1,106 @@
+use crate::{CustomError, Jwt};
+use axum::{
+    extract::Extension,
+    response::{Html, IntoResponse},
+};
+use db::{authz, queries, Pool};
+use web_pages::routes::automations::{Rerun, RunDetail, Runs};
+
+// How many runs we show in the history
+const MAX_RUNS: i64 = 100;
+
+pub async fn runs(
+    Runs { team_id, prompt_id }: Runs,
+    current_user: Jwt,
+    Extension(pool): Extension<Pool>,
+) -> Result<Html<String>, CustomError> {
+    let mut client = pool.get().await?;
+    let transaction = client.transaction().await?;
+
+    let rbac = authz::get_permissions(&transaction, &current_user.into(), team_id).await?;
+
+    // Also checks the automation belongs to the team
+    let prompt = queries::prompts::prompt()
+        .bind(&transaction, &prompt_id, &team_id)
+        .one()
+        .await?;
+
+    let runs = queries::automation_runs::runs_by_prompt()
+        .bind(&transaction, &prompt_id, &MAX_RUNS)
+        .all()
+        .await?;
+
+    let html = web_pages::automations::runs::page(team_id, prompt_id, prompt.name, rbac, runs);
+
+    Ok(Html(html))
+}
+
+pub async fn run_detail(
+    RunDetail {
+        team_id,
+        prompt_id,
+        run_id,
+    }: RunDetail,
+    current_user: Jwt,
+    Extension(pool): Extension<Pool>,
+) -> Result<Html<String>, CustomError> {
+    let mut client = pool.get().await?;
+    let transaction = client.transaction().await?;
+
+    let rbac = authz::get_permissions(&transaction, &current_user.into(), team_id).await?;
+
+    let prompt = queries::prompts::prompt()
+        .bind(&transaction, &prompt_id, &team_id)
+        .one()
+        .await?;
+
+    let run = queries::automation_runs::run()
+        .bind(&transaction, &run_id, &prompt_id)
+        .one()
+        .await?;
+
+    let chats = queries::automation_runs::run_chats()
+        .bind(&transaction, &run_id, &prompt_id)
+        .all()
+        .await?;
+
+    let html =
+        web_pages::automations::run_detail::page(team_id, prompt_id, prompt.name, rbac, run, chats);
+
+    Ok(Html(html))
+}
+
+pub async fn rerun(
+    Rerun {
+        team_id,
+        prompt_id,
+        run_id,
+    }: Rerun,
+    current_user: Jwt,
+    Extension(pool): Extension<Pool>,
+) -> Result<impl IntoResponse, CustomError> {
+    let mut client = pool.get().await?;
+    let transaction = client.transaction().await?;
+
+    let _rbac = authz::get_permissions(&transaction, &current_user.into(), team_id).await?;
+
+    queries::prompts::prompt()
+        .bind(&transaction, &prompt_id, &team_id)
+        .one()
+        .await?;
+
+    queries::automation_runs::insert_rerun()
+        .bind(&transaction, &run_id, &prompt_id)
+        .one()
+        .await?;
+
+    transaction.commit().await?;
+
+    Ok(
+        crate::layout::redirect_and_snackbar(
+            &Runs { team_id, prompt_id }.to_string(),
+            "Run queued",
+        )
+        .into_response(),
+    )
+}
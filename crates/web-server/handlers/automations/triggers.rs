@@ -1,3 +1,4 @@
+use crate::config::Config;
 use crate::{CustomError, Jwt};
 use axum::{
     extract::Extension,
@@ -5,31 +6,50 @@ use axum::{
 };
 use axum_extra::extract::Form;
 use db::{authz, queries, Pool};
+use rand::distr::Alphanumeric;
+use rand::{rng, Rng};
 use serde::Deserialize;
-use web_pages::routes::automations::{AddCronTrigger, ManageTriggers, RemoveCronTrigger};
+use web_pages::routes::automations::{
+    AddCronTrigger, AddWebhookTrigger, ManageTriggers, RemoveCronTrigger, RemoveWebhookTrigger,
+    RotateWebhookTrigger,
+};
 
 pub async fn manage_triggers(
     ManageTriggers { team_id, prompt_id }: ManageTriggers,
     current_user: Jwt,
     Extension(pool): Extension<Pool>,
+    Extension(config): Extension<Config>,
 ) -> Result<Html<String>, CustomError> {
     let mut client = pool.get().await?;
     let transaction = client.transaction().await?;
 
     let rbac = authz::get_permissions(&transaction, &current_user.into(), team_id).await?;
+    check_team_prompt(&transaction, prompt_id, team_id).await?;
 
     let triggers = queries::automation_triggers::cron_triggers_by_prompt()
         .bind(&transaction, &prompt_id)
         .all()
         .await?;
 
+    let webhooks = queries::automation_triggers::webhook_triggers_by_prompt()
+        .bind(&transaction, &prompt_id)
+        .all()
+        .await?;
+
     let prompt = queries::prompts::prompt()
         .bind(&transaction, &prompt_id, &team_id)
         .one()
         .await?;
 
-    let html =
-        web_pages::automations::triggers::page(team_id, prompt_id, prompt.name, rbac, triggers);
+    let html = web_pages::automations::triggers::page(
+        team_id,
+        prompt_id,
+        prompt.name,
+        rbac,
+        triggers,
+        webhooks,
+        config.base_url,
+    );
 
     Ok(Html(html))
 }
@@ -59,6 +79,16 @@ pub async fn add_cron_trigger(
         form.minute, form.hour, form.day, form.month, form.weekday
     );
 
+    // Don't store anything the scheduler won't be able to run
+    if let Err(e) = llm_proxy::cron::CronSchedule::parse(&cron) {
+        tracing::warn!("Rejected cron expression {}: {}", cron, e);
+        return Ok(crate::layout::redirect_and_snackbar(
+            &web_pages::routes::automations::ManageTriggers { team_id, prompt_id }.to_string(),
+            "Invalid cron expression",
+        )
+        .into_response());
+    }
+
     queries::automation_triggers::insert_cron_trigger()
         .bind(&transaction, &prompt_id, &cron)
         .one()
@@ -99,3 +129,109 @@ pub async fn remove_cron_trigger(
     )
     .into_response())
 }
+
+pub async fn add_webhook_trigger(
+    AddWebhookTrigger { team_id, prompt_id }: AddWebhookTrigger,
+    current_user: Jwt,
+    Extension(pool): Extension<Pool>,
+) -> Result<impl IntoResponse, CustomError> {
+    let mut client = pool.get().await?;
+    let transaction = client.transaction().await?;
+
+    let _rbac = authz::get_permissions(&transaction, &current_user.into(), team_id).await?;
+    check_team_prompt(&transaction, prompt_id, team_id).await?;
+
+    queries::automation_triggers::insert_webhook_trigger()
+        .bind(&transaction, &prompt_id, &generate_secret())
+        .one()
+        .await?;
+
+    transaction.commit().await?;
+
+    Ok(crate::layout::redirect_and_snackbar(
+        &web_pages::routes::automations::ManageTriggers { team_id, prompt_id }.to_string(),
+        "Webhook trigger added",
+    )
+    .into_response())
+}
+
+pub async fn rotate_webhook_trigger(
+    RotateWebhookTrigger {
+        team_id,
+        prompt_id,
+        trigger_id,
+    }: RotateWebhookTrigger,
+    current_user: Jwt,
+    Extension(pool): Extension<Pool>,
+) -> Result<impl IntoResponse, CustomError> {
+    let mut client = pool.get().await?;
+    let transaction = client.transaction().await?;
+
+    let _rbac = authz::get_permissions(&transaction, &current_user.into(), team_id).await?;
+    check_team_prompt(&transaction, prompt_id, team_id).await?;
+
+    queries::automation_triggers::rotate_webhook_trigger()
+        .bind(&transaction, &generate_secret(), &trigger_id, &prompt_id)
+        .await?;
+
+    transaction.commit().await?;
+
+    Ok(crate::layout::redirect_and_snackbar(
+        &web_pages::routes::automations::ManageTriggers { team_id, prompt_id }.to_string(),
+        "Webhook secret rotated",
+    )
+    .into_response())
+}
+
+pub async fn remove_webhook_trigger(
+    RemoveWebhookTrigger {
+        team_id,
+        prompt_id,
+        trigger_id,
+    }: RemoveWebhookTrigger,
+    current_user: Jwt,
+    Extension(pool): Extension<Pool>,
+) -> Result<impl IntoResponse, CustomError> {
+    let mut client = pool.get().await?;
+    let transaction = client.transaction().await?;
+
+    let _rbac = authz::get_permissions(&transaction, &current_user.into(), team_id).await?;
+    check_team_prompt(&transaction, prompt_id, team_id).await?;
+
+    queries::automation_triggers::delete_webhook_trigger()
+        .bind(&transaction, &trigger_id, &prompt_id)
+        .await?;
+
+    transaction.commit().await?;
+
+    Ok(crate::layout::redirect_and_snackbar(
+        &web_pages::routes::automations::ManageTriggers { team_id, prompt_id }.to_string(),
+        "Webhook trigger removed",
+    )
+    .into_response())
+}
+
+async fn check_team_prompt(
+    transaction: &db::Transaction<'_>,
+    prompt_id: i32,
+    team_id: i32,
+) -> Result<(), CustomError> {
+    let is_team_prompt = queries::automation_triggers::is_team_prompt()
+        .bind(transaction, &prompt_id, &team_id)
+        .one()
+        .await?;
+
+    if !is_team_prompt {
+        return Err(CustomError::Authorization);
+    }
+
+    Ok(())
+}
+
+fn generate_secret() -> String {
+    rng()
+        .sample_iter(&Alphanumeric)
+        .take(40)
+        .map(char::from)
+        .collect()
+}
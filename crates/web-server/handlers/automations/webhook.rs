@@ -0,0 +1,143 @@
+use crate::CustomError;
+use axum::{body::Bytes, extract::Extension, response::IntoResponse, Json};
+use db::{queries, Pool};
+use hmac::{Hmac, Mac};
+use http::{header::AUTHORIZATION, HeaderMap, StatusCode};
+use serde_json::json;
+use sha2::Sha256;
+use web_pages::routes::automations::Webhook;
+
+// Hex encoded HMAC SHA256 of the request body, optionally prefixed with `sha256=`
+pub const SIGNATURE_HEADER: &str = "x-bionic-signature";
+
+// Called by external systems to start an automation. We queue the run and
+// return straight away, the automation scheduler picks it up.
+pub async fn webhook(
+    Webhook { trigger_id }: Webhook,
+    Extension(pool): Extension<Pool>,
+    headers: HeaderMap,
+    body: Bytes,
+) -> Result<impl IntoResponse, CustomError> {
+    let client = pool.get().await?;
+
+    let trigger = queries::automation_triggers::webhook_trigger()
+        .bind(&client, &trigger_id)
+        .opt()
+        .await?
+        .ok_or_else(|| CustomError::Authentication("Unknown webhook".to_string()))?;
+
+    if !is_authorized(&headers, &body, &trigger.secret) {
+        return Err(CustomError::Authentication(
+            "Invalid webhook signature".to_string(),
+        ));
+    }
+
+    // The payload becomes the user message for the automation
+    let payload: serde_json::Value = if body.is_empty() {
+        json!({})
+    } else {
+        serde_json::from_slice(&body)
+            .map_err(|e| CustomError::BadRequest(format!("Invalid JSON payload: {}", e)))?
+    };
+    let input = serde_json::to_string_pretty(&payload)?;
+
+    let run_id = queries::automation_runs::insert_webhook_run()
+        .bind(&client, &trigger.prompt_id, &trigger.id, &input)
+        .one()
+        .await?;
+
+    tracing::info!("Webhook {} queued automation run {}", trigger.id, run_id);
+
+    Ok((
+        StatusCode::ACCEPTED,
+        Json(json!({
+            "run_id": run_id,
+            "status": "Pending"
+        })),
+    ))
+}
+
+// Callers either sign the body with the secret or send the secret as a
+// bearer token.
+fn is_authorized(headers: &HeaderMap, body: &[u8], secret: &str) -> bool {
+    if let Some(signature) = headers
+        .get(SIGNATURE_HEADER)
+        .and_then(|value| value.to_str().ok())
+    {
+        let signature = signature.trim().trim_start_matches("sha256=");
+        let Ok(signature) = hex::decode(signature) else {
+            return false;
+        };
+        let Ok(mut mac) = Hmac::<Sha256>::new_from_slice(secret.as_bytes()) else {
+            return false;
+        };
+        mac.update(body);
+        return mac.verify_slice(&signature).is_ok();
+    }
+
+    if let Some(token) = headers
+        .get(AUTHORIZATION)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.strip_prefix("Bearer "))
+    {
+        return constant_time_eq(token.trim().as_bytes(), secret.as_bytes());
+    }
+
+    false
+}
+
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0u8, |acc, (x, y)| acc | (x ^ y)) == 0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use http::HeaderValue;
+
+    fn sign(secret: &str, body: &[u8]) -> String {
+        let mut mac = Hmac::<Sha256>::new_from_slice(secret.as_bytes()).unwrap();
+        mac.update(body);
+        format!("sha256={}", hex::encode(mac.finalize().into_bytes()))
+    }
+
+    #[test]
+    fn test_valid_signature() {
+        let body = br#"{"hello":"world"}"#;
+        let mut headers = HeaderMap::new();
+        headers.insert(
+            SIGNATURE_HEADER,
+            HeaderValue::from_str(&sign("secret", body)).unwrap(),
+        );
+        assert!(is_authorized(&headers, body, "secret"));
+    }
+
+    #[test]
+    fn test_invalid_signature() {
+        let body = br#"{"hello":"world"}"#;
+        let mut headers = HeaderMap::new();
+        headers.insert(
+            SIGNATURE_HEADER,
+            HeaderValue::from_str(&sign("other", body)).unwrap(),
+        );
+        assert!(!is_authorized(&headers, body, "secret"));
+
+        headers.insert(SIGNATURE_HEADER, HeaderValue::from_static("not-hex"));
+        assert!(!is_authorized(&headers, body, "secret"));
+    }
+
+    #[test]
+    fn test_bearer_secret() {
+        let mut headers = HeaderMap::new();
+        headers.insert(AUTHORIZATION, HeaderValue::from_static("Bearer secret"));
+        assert!(is_authorized(&headers, b"", "secret"));
+
+        headers.insert(AUTHORIZATION, HeaderValue::from_static("Bearer wrong"));
+        assert!(!is_authorized(&headers, b"", "secret"));
+    }
+
+    #[test]
+    fn test_no_credentials() {
+        assert!(!is_authorized(&HeaderMap::new(), b"", "secret"));
+    }
+}
@@ -0,0 +1,30 @@
+use crate::{CustomError, Jwt};
+use axum::body::{Body, Bytes};
+use axum::extract::Extension;
+use axum::response::{IntoResponse, Response};
+use db::authz;
+use db::queries::attachments;
+use db::Pool;
+use web_pages::routes::console::Attachment;
+
+// Serves a chat attachment, the query makes sure it's from one of the user's conversations
+pub async fn attachment(
+    Attachment { team_id, id }: Attachment,
+    current_user: Jwt,
+    Extension(pool): Extension<Pool>,
+) -> Result<impl IntoResponse, CustomError> {
+    let mut client = pool.get().await?;
+    let transaction = client.transaction().await?;
+
+    let _rbac = authz::get_permissions(&transaction, &current_user.into(), team_id).await?;
+
+    let attachment = attachments::get_content()
+        .bind(&transaction, &id)
+        .one()
+        .await?;
+
+    Ok(Response::builder()
+        .header("Content-Type", attachment.mime_type)
+        .header("Cache-Control", "private, max-age=86400")
+        .body(Body::from(Bytes::from(attachment.object_data)))?)
+}
@@ -34,6 +34,12 @@ pub async fn conversation(
         .await?
         .is_empty();
 
+    let is_stt_disabled = queries::models::models()
+        .bind(&transaction, &ModelType::SpeechToText)
+        .all()
+        .await?
+        .is_empty();
+
     // Process chats to get chat_history and pending_chat_state
     let (chat_history, pending_chat_state) =
         super::utils::process_chats(&transaction, chats).await?;
@@ -71,6 +77,7 @@ pub async fn conversation(
         prompt,
         conversation_id,
         is_tts_disabled,
+        is_stt_disabled,
         capabilities,
         enabled_tools,
         available_tools,
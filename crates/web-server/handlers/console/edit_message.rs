@@ -0,0 +1,68 @@
+use super::utils::conversation_url;
+use crate::{CustomError, Jwt};
+use axum::{
+    extract::{Extension, Form},
+    response::IntoResponse,
+};
+use db::queries::{attachments, chats};
+use db::{authz, ChatRole, ChatStatus, Pool};
+use serde::Deserialize;
+use validator::Validate;
+use web_pages::routes::console::EditMessage;
+
+#[derive(Deserialize, Validate, Default, Debug)]
+pub struct Edit {
+    pub chat_id: i32,
+    #[validate(length(min = 1))]
+    pub message: String,
+}
+
+/// Editing a message adds the new version alongside the original and sends
+/// it to the model, the original and the replies to it stay on their branch.
+pub async fn edit_message(
+    EditMessage { team_id }: EditMessage,
+    current_user: Jwt,
+    Extension(pool): Extension<Pool>,
+    Form(edit): Form<Edit>,
+) -> Result<impl IntoResponse, CustomError> {
+    let mut client = pool.get().await?;
+    let transaction = client.transaction().await?;
+
+    let _permissions = authz::get_permissions(&transaction, &current_user.into(), team_id).await?;
+
+    let chat = chats::chat()
+        .bind(&transaction, &edit.chat_id)
+        .one()
+        .await?;
+
+    if edit.validate().is_ok() && chat.role == ChatRole::User {
+        // The edit follows whatever the original followed
+        chats::set_active_chat()
+            .bind(&transaction, &chat.parent_id, &chat.conversation_id)
+            .await?;
+
+        let chat_id = chats::new_chat()
+            .bind(
+                &transaction,
+                &chat.conversation_id,
+                &chat.prompt_id,
+                &None::<String>,
+                &None::<String>,
+                &edit.message,
+                &ChatRole::User,
+                &ChatStatus::Pending,
+            )
+            .one()
+            .await?;
+
+        attachments::copy_to_chat()
+            .bind(&transaction, &chat_id, &chat.id)
+            .await?;
+    }
+
+    let url = conversation_url(&transaction, team_id, &chat).await?;
+
+    transaction.commit().await?;
+
+    crate::layout::redirect(&url)
+}
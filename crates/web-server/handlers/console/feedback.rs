@@ -0,0 +1,75 @@
+use super::utils::conversation_url;
+use crate::{CustomError, Jwt};
+use axum::{extract::Extension, response::IntoResponse};
+use axum_extra::extract::Form;
+use db::queries::{chat_feedback, chats};
+use db::{authz, ChatRole, FeedbackRating, Pool};
+use serde::Deserialize;
+use validator::Validate;
+use web_pages::console::FEEDBACK_REASONS;
+use web_pages::routes::console::Feedback;
+
+#[derive(Deserialize, Validate, Default, Debug)]
+pub struct Rating {
+    pub chat_id: i32,
+    pub rating: String,
+    #[serde(default)]
+    pub comment: String,
+    #[serde(default)]
+    pub reasons: Vec<String>,
+}
+
+/// Saves what the user thought of an answer, rating it again replaces it.
+pub async fn feedback(
+    Feedback { team_id }: Feedback,
+    current_user: Jwt,
+    Extension(pool): Extension<Pool>,
+    Form(rating): Form<Rating>,
+) -> Result<impl IntoResponse, CustomError> {
+    let mut client = pool.get().await?;
+    let transaction = client.transaction().await?;
+
+    // Checks the user is in the team, rating answers needs no other permission
+    authz::get_permissions(&transaction, &current_user.into(), team_id).await?;
+
+    // The query makes sure the chat is from one of the user's conversations
+    let chat = chats::chat()
+        .bind(&transaction, &rating.chat_id)
+        .one()
+        .await?;
+
+    if chat.role != ChatRole::Assistant {
+        return Err(CustomError::BadRequest(
+            "Only answers can be rated".to_string(),
+        ));
+    }
+
+    let feedback_rating = match rating.rating.as_str() {
+        "Positive" => FeedbackRating::Positive,
+        "Negative" => FeedbackRating::Negative,
+        _ => {
+            return Err(CustomError::BadRequest(format!(
+                "Unknown rating {}",
+                rating.rating
+            )))
+        }
+    };
+
+    // Keep to the tags we offer so the dashboard can group them
+    let reasons: Vec<String> = rating
+        .reasons
+        .into_iter()
+        .filter(|reason| FEEDBACK_REASONS.contains(&reason.as_str()))
+        .collect();
+    let comment = Some(rating.comment.trim().to_string()).filter(|comment| !comment.is_empty());
+
+    chat_feedback::upsert_feedback()
+        .bind(&transaction, &chat.id, &feedback_rating, &comment, &reasons)
+        .await?;
+
+    let url = conversation_url(&transaction, team_id, &chat).await?;
+
+    transaction.commit().await?;
+
+    crate::layout::redirect(&url)
+}
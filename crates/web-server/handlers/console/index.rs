@@ -6,6 +6,7 @@ use axum::extract::Extension;
 use axum::response::Html;
 use db::authz;
 use db::queries;
+use db::ModelType;
 use db::Pool;
 use integrations;
 use web_pages::console;
@@ -48,6 +49,12 @@ pub async fn index(
             .await?
     };
 
+    let is_stt_disabled = queries::models::models()
+        .bind(&transaction, &ModelType::SpeechToText)
+        .all()
+        .await?
+        .is_empty();
+
     let capabilities = queries::capabilities::get_model_capabilities()
         .bind(&transaction, &prompt.model_id)
         .all()
@@ -62,6 +69,7 @@ pub async fn index(
         prompts,
         prompt,
         rbac,
+        is_stt_disabled,
         capabilities,
         enabled_tools,
         available_tools,
@@ -1,9 +1,14 @@
+mod attachment;
 mod conversation;
 mod delete;
+mod edit_message;
+mod feedback;
 mod index;
+mod regenerate;
 mod send_message;
 mod set_default_prompt;
 mod set_tools;
+mod switch_branch;
 mod update_response;
 mod utils;
 
@@ -13,10 +18,15 @@ pub use utils::process_chats;
 
 pub fn routes() -> Router {
     Router::new()
+        .typed_get(attachment::attachment)
         .typed_get(conversation::conversation)
         .typed_get(index::index)
         .typed_post(send_message::send_message)
         .typed_post(update_response::update_response)
+        .typed_post(edit_message::edit_message)
+        .typed_post(regenerate::regenerate)
+        .typed_post(switch_branch::switch_branch)
+        .typed_post(feedback::feedback)
         .typed_post(delete::delete)
         .typed_post(set_default_prompt::set_default_prompt)
         .typed_post(set_tools::set_tools)
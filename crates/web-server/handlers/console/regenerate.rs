@@ -0,0 +1,62 @@
+use super::utils::conversation_url;
+use crate::{CustomError, Jwt};
+use axum::{
+    extract::{Extension, Form},
+    response::IntoResponse,
+};
+use db::queries::chats;
+use db::{authz, ChatRole, ChatStatus, Pool};
+use serde::Deserialize;
+use validator::Validate;
+use web_pages::routes::console::Regenerate;
+
+#[derive(Deserialize, Validate, Default, Debug)]
+pub struct Reply {
+    pub chat_id: i32,
+}
+
+/// Asks the model to answer the message before this reply again. The new
+/// reply is a sibling of the old one, which stays on its own branch.
+pub async fn regenerate(
+    Regenerate { team_id }: Regenerate,
+    current_user: Jwt,
+    Extension(pool): Extension<Pool>,
+    Form(reply): Form<Reply>,
+) -> Result<impl IntoResponse, CustomError> {
+    let mut client = pool.get().await?;
+    let transaction = client.transaction().await?;
+
+    let _permissions = authz::get_permissions(&transaction, &current_user.into(), team_id).await?;
+
+    let chat = chats::chat()
+        .bind(&transaction, &reply.chat_id)
+        .one()
+        .await?;
+
+    // The reply may have called tools, so look back for the question
+    let branch = chats::chats()
+        .bind(&transaction, &chat.conversation_id)
+        .all()
+        .await?;
+    let question = branch
+        .iter()
+        .take_while(|branch_chat| branch_chat.id != chat.id)
+        .filter(|branch_chat| branch_chat.role == ChatRole::User)
+        .last();
+
+    if let Some(question) = question {
+        chats::set_active_chat()
+            .bind(&transaction, &Some(question.id), &chat.conversation_id)
+            .await?;
+        // The console picks up the pending chat and streams a new reply
+        chats::set_chat_status()
+            .bind(&transaction, &ChatStatus::Pending, &question.id)
+            .await?;
+    }
+
+    let url = conversation_url(&transaction, team_id, &chat).await?;
+
+    transaction.commit().await?;
+
+    crate::layout::redirect(&url)
+}
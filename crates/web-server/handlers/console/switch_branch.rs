@@ -0,0 +1,42 @@
+use super::utils::conversation_url;
+use crate::{CustomError, Jwt};
+use axum::{
+    extract::{Extension, Form},
+    response::IntoResponse,
+};
+use db::queries::chats;
+use db::{authz, Pool};
+use serde::Deserialize;
+use validator::Validate;
+use web_pages::routes::console::SwitchBranch;
+
+#[derive(Deserialize, Validate, Default, Debug)]
+pub struct Branch {
+    pub chat_id: i32,
+}
+
+/// Shows the branch of the conversation that goes through this chat.
+pub async fn switch_branch(
+    SwitchBranch { team_id }: SwitchBranch,
+    current_user: Jwt,
+    Extension(pool): Extension<Pool>,
+    Form(branch): Form<Branch>,
+) -> Result<impl IntoResponse, CustomError> {
+    let mut client = pool.get().await?;
+    let transaction = client.transaction().await?;
+
+    let _permissions = authz::get_permissions(&transaction, &current_user.into(), team_id).await?;
+
+    let chat = chats::chat()
+        .bind(&transaction, &branch.chat_id)
+        .one()
+        .await?;
+
+    chats::switch_branch().bind(&transaction, &chat.id).await?;
+
+    let url = conversation_url(&transaction, team_id, &chat).await?;
+
+    transaction.commit().await?;
+
+    crate::layout::redirect(&url)
+}
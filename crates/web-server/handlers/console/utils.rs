@@ -1,6 +1,11 @@
 use crate::CustomError;
-use db::queries::{chats::Chat, chats_chunks};
-use db::{ChatRole, ChatStatus, Transaction};
+use db::queries::{
+    chat_feedback,
+    chats::{self, Chat, Sibling},
+    chats_chunks::{self, ChatChunks},
+    prompts,
+};
+use db::{ChatRole, ChatStatus, PromptType, Transaction};
 use openai_api::ToolCall;
 use serde_json::from_str;
 use web_pages::console::{ChatWithChunks, PendingChat, PendingChatState};
@@ -69,6 +74,20 @@ pub async fn process_chats(
 ) -> Result<(Vec<ChatWithChunks>, PendingChatState), CustomError> {
     let mut chat_history: Vec<ChatWithChunks> = Vec::new();
 
+    let (siblings, feedback) = match chats.first() {
+        Some(chat) => (
+            chats::siblings()
+                .bind(transaction, &chat.conversation_id)
+                .all()
+                .await?,
+            chat_feedback::conversation_feedback()
+                .bind(transaction, &chat.conversation_id)
+                .all()
+                .await?,
+        ),
+        None => (Vec::new(), Vec::new()),
+    };
+
     // Determine pending state and get non-pending chats
     let (non_pending_chats, pending_chat_state) = determine_pending_chat_state(chats);
 
@@ -83,10 +102,23 @@ pub async fn process_chats(
         let chat_with_chunks = ChatWithChunks {
             chat: chat.clone(),
             chunks: chunks_chats,
+            siblings: alternatives(chat, &siblings),
+            citations: Vec::new(),
+            feedback: feedback
+                .iter()
+                .find(|feedback| feedback.chat_id == chat.id)
+                .cloned(),
         };
         chat_history.push(chat_with_chunks);
     }
 
+    let citations: Vec<Vec<ChatChunks>> = (0..chat_history.len())
+        .map(|index| turn_citations(&chat_history[..=index]))
+        .collect();
+    for (chat_with_chunks, citations) in chat_history.iter_mut().zip(citations) {
+        chat_with_chunks.citations = citations;
+    }
+
     tracing::debug!(
         "Shall we call the model {}",
         pending_chat_state.shall_we_call_the_model()
@@ -96,6 +128,68 @@ pub async fn process_chats(
     Ok((chat_history, pending_chat_state))
 }
 
+// The chats that share a parent with this one, it's one of them
+fn alternatives(chat: &Chat, siblings: &[Sibling]) -> Vec<i32> {
+    siblings
+        .iter()
+        .filter(|sibling| sibling.parent_id == chat.parent_id)
+        .map(|sibling| sibling.id)
+        .collect()
+}
+
+// A reply can cite any chunk given to the model since the user's question,
+// the turn ends with the reply. If a number was used twice the latest wins.
+fn turn_citations(turn: &[ChatWithChunks]) -> Vec<ChatChunks> {
+    let mut citations: Vec<ChatChunks> = Vec::new();
+    if !matches!(turn.last(), Some(reply) if reply.chat.role == ChatRole::Assistant) {
+        return citations;
+    }
+
+    for chat_with_chunks in turn.iter().rev() {
+        for chunk in &chat_with_chunks.chunks {
+            let seen = citations
+                .iter()
+                .any(|citation| citation.position == chunk.position);
+            if chunk.position.is_some() && !seen {
+                citations.push(chunk.clone());
+            }
+        }
+        if chat_with_chunks.chat.role == ChatRole::User {
+            break;
+        }
+    }
+
+    citations.sort_by_key(|citation| citation.position);
+    citations
+}
+
+// Where to show a conversation, assistants have their own console
+pub async fn conversation_url(
+    transaction: &Transaction<'_>,
+    team_id: i32,
+    chat: &Chat,
+) -> Result<String, CustomError> {
+    let prompt = prompts::prompt()
+        .bind(transaction, &chat.prompt_id, &team_id)
+        .one()
+        .await?;
+
+    if prompt.prompt_type == PromptType::Assistant {
+        Ok(web_pages::routes::prompts::Conversation {
+            team_id,
+            conversation_id: chat.conversation_id,
+            prompt_id: prompt.id,
+        }
+        .to_string())
+    } else {
+        Ok(web_pages::routes::console::Conversation {
+            team_id,
+            conversation_id: chat.conversation_id,
+        }
+        .to_string())
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -111,6 +205,7 @@ mod tests {
         Chat {
             id,
             conversation_id: 49,
+            parent_id: None,
             content: Some("Test content".to_string()),
             role,
             tool_call_id: None,
@@ -201,4 +296,67 @@ mod tests {
             _ => panic!("Expected PendingToolChats state"),
         }
     }
+
+    #[test]
+    fn test_alternatives_share_a_parent() {
+        let now = OffsetDateTime::now_utc();
+        let sibling = |id, parent_id| Sibling { id, parent_id };
+        let siblings = vec![
+            sibling(1, None),
+            sibling(2, Some(1)),
+            sibling(3, Some(2)),
+            sibling(4, Some(1)),
+            sibling(5, None),
+        ];
+
+        let mut reply = create_mock_chat(4, ChatStatus::Success, ChatRole::Assistant, now);
+        reply.parent_id = Some(1);
+        assert_eq!(alternatives(&reply, &siblings), vec![2, 4]);
+
+        let first = create_mock_chat(1, ChatStatus::Success, ChatRole::User, now);
+        assert_eq!(alternatives(&first, &siblings), vec![1, 5]);
+    }
+
+    #[test]
+    fn test_replies_cite_chunks_from_their_turn() {
+        let now = OffsetDateTime::now_utc();
+        let chunk = |chat_id, position, file_name: &str| ChatChunks {
+            chunk_id: chat_id * 10 + position,
+            chat_id,
+            position: Some(position),
+            page_number: 1,
+            file_name: file_name.to_string(),
+            preview: "Preview".to_string(),
+        };
+        let chat = |id, role, chunks| ChatWithChunks {
+            chat: create_mock_chat(id, ChatStatus::Success, role, now),
+            chunks,
+            siblings: vec![id],
+            citations: Vec::new(),
+            feedback: None,
+        };
+
+        let history = vec![
+            chat(1, ChatRole::User, vec![chunk(1, 1, "old.pdf")]),
+            chat(2, ChatRole::Assistant, vec![]),
+            chat(
+                3,
+                ChatRole::User,
+                vec![chunk(3, 1, "a.pdf"), chunk(3, 2, "b.pdf")],
+            ),
+            chat(4, ChatRole::Assistant, vec![chunk(4, 3, "c.pdf")]),
+            chat(5, ChatRole::Tool, vec![chunk(5, 2, "d.pdf")]),
+            chat(6, ChatRole::Assistant, vec![]),
+        ];
+
+        let names = |citations: Vec<ChatChunks>| -> Vec<String> {
+            citations.into_iter().map(|c| c.file_name).collect()
+        };
+        assert_eq!(names(turn_citations(&history[..2])), vec!["old.pdf"]);
+        assert_eq!(
+            names(turn_citations(&history)),
+            vec!["a.pdf", "d.pdf", "c.pdf"]
+        );
+        assert!(turn_citations(&history[..3]).is_empty());
+    }
 }
@@ -6,13 +6,12 @@ use axum::{
 };
 use db::authz;
 use db::queries;
-use db::types::public::ChunkingStrategy;
 use db::{Pool, Visibility};
 use serde::Deserialize;
 use validator::Validate;
 use web_pages::{
-    routes::datasets::{Delete, Upsert},
-    string_to_visibility,
+    routes::datasets::{Delete, Reindex, Upsert},
+    string_to_chunking_strategy, string_to_visibility,
 };
 
 // Delete function
@@ -35,16 +34,43 @@ pub async fn action_delete(
     )
 }
 
+// Re-embed every chunk, the rag-engine picks this up
+pub async fn action_reindex(
+    Reindex { team_id, id }: Reindex,
+    current_user: Jwt,
+    Extension(pool): Extension<Pool>,
+) -> Result<impl IntoResponse, CustomError> {
+    let mut client = pool.get().await?;
+    let transaction = client.transaction().await?;
+    let rbac = authz::get_permissions(&transaction, &current_user.into(), team_id).await?;
+
+    if !rbac.can_manage_datasets() {
+        return Err(CustomError::Authorization);
+    }
+
+    queries::datasets::request_reindex()
+        .bind(&transaction, &id)
+        .await?;
+
+    transaction.commit().await?;
+
+    crate::layout::redirect_and_snackbar(
+        &web_pages::routes::datasets::Index { team_id }.to_string(),
+        "Dataset Re-index Started",
+    )
+}
+
 // Upsert function
 #[derive(Deserialize, Validate, Default, Debug)]
 pub struct NewDataset {
     pub id: Option<i32>,
     #[validate(length(min = 1, message = "The name is mandatory"))]
     pub name: String,
-    #[allow(dead_code)]
     pub chunking_strategy: String,
     pub combine_under_n_chars: i32,
     pub new_after_n_chars: i32,
+    #[validate(range(min = 0))]
+    pub chunk_overlap: i32,
     pub embeddings_model_id: i32,
     pub visibility: String,
     pub multipage_sections: bool,
@@ -61,7 +87,7 @@ pub async fn action_upsert(
     let transaction = client.transaction().await?;
     let permissions = authz::get_permissions(&transaction, &current_user.into(), team_id).await?;
 
-    let chunking_strategy = ChunkingStrategy::ByTitle;
+    let chunking_strategy = string_to_chunking_strategy(&new_dataset.chunking_strategy);
 
     let mut visibility = string_to_visibility(&new_dataset.visibility);
 
@@ -84,6 +110,7 @@ pub async fn action_upsert(
                     &new_dataset.combine_under_n_chars,
                     &new_dataset.new_after_n_chars,
                     &new_dataset.multipage_sections,
+                    &new_dataset.chunk_overlap,
                     &id,
                 )
                 .await?;
@@ -106,6 +133,7 @@ pub async fn action_upsert(
                     &new_dataset.combine_under_n_chars,
                     &new_dataset.new_after_n_chars,
                     &new_dataset.multipage_sections,
+                    &new_dataset.chunk_overlap,
                     &visibility,
                 )
                 .one()
@@ -18,6 +18,11 @@ pub async fn loader(
 
     let datasets = datasets::datasets().bind(&transaction).all().await?;
 
+    let reindex_progress = datasets::reindex_progress()
+        .bind(&transaction)
+        .all()
+        .await?;
+
     let models = models::models()
         .bind(&transaction, &ModelType::Embeddings)
         .all()
@@ -29,6 +34,7 @@ pub async fn loader(
         rbac,
         team_id,
         datasets,
+        reindex_progress,
         models,
         can_set_visibility_to_company,
     );
@@ -12,4 +12,5 @@ pub fn routes() -> Router {
         .typed_get(loader::loader)
         .typed_post(actions::action_upsert)
         .typed_post(actions::action_delete)
+        .typed_post(actions::action_reindex)
 }
@@ -11,7 +11,7 @@ use db::queries::{self, datasets, documents};
 use db::Pool;
 use serde::Deserialize;
 use validator::Validate;
-use web_pages::routes::documents::{Delete, Index, Processing, Upload};
+use web_pages::routes::documents::{Delete, Index, Processing, Retry, Upload};
 
 use crate::{CustomError, Jwt};
 
@@ -20,6 +20,7 @@ pub fn routes() -> Router {
     Router::new()
         .typed_post(upload_action)
         .typed_post(delete_action)
+        .typed_post(retry_action)
         .typed_get(row)
         .layer(axum::extract::DefaultBodyLimit::max(50000000))
         .typed_get(loader)
@@ -92,6 +93,44 @@ pub async fn delete_action(
     )
 }
 
+// Retry the parts of a document that failed
+pub async fn retry_action(
+    Retry {
+        team_id,
+        document_id,
+    }: Retry,
+    current_user: Jwt,
+    Extension(pool): Extension<Pool>,
+) -> Result<impl IntoResponse, CustomError> {
+    let mut client = pool.get().await?;
+    let transaction = client.transaction().await?;
+    let _permissions = authz::get_permissions(&transaction, &current_user.into(), team_id).await?;
+
+    let document = documents::document()
+        .bind(&transaction, &document_id)
+        .one()
+        .await?;
+
+    queries::documents::retry_document()
+        .bind(&transaction, &document_id)
+        .await?;
+
+    queries::chunks::retry_failed_chunks()
+        .bind(&transaction, &document_id)
+        .await?;
+
+    transaction.commit().await?;
+
+    crate::layout::redirect_and_snackbar(
+        &web_pages::routes::documents::Index {
+            team_id,
+            dataset_id: document.dataset_id,
+        }
+        .to_string(),
+        "Retrying Document",
+    )
+}
+
 // Processing function
 pub async fn row(
     Processing {
@@ -0,0 +1,177 @@
+use crate::{CustomError, Jwt};
+use axum::body::Body;
+use axum::extract::{Extension, Form};
+use axum::response::{Html, IntoResponse, Response};
+use axum::Router;
+use axum_extra::routing::RouterExt;
+use db::queries::chat_feedback::{self, LowRated};
+use db::{authz, Pool, Transaction};
+use serde::Deserialize;
+use serde_json::{json, Value};
+use web_pages::{
+    feedback::{page::Dashboard, PERIODS},
+    routes::feedback::{Export, Index},
+};
+
+const DEFAULT_DAYS: i32 = 30;
+
+pub fn routes() -> Router {
+    Router::new()
+        .typed_get(loader)
+        .typed_post(filter_action)
+        .typed_post(export_action)
+}
+
+#[derive(Deserialize, Default, Debug)]
+pub struct Period {
+    pub days: i32,
+}
+
+impl Period {
+    pub fn get_days(&self) -> i32 {
+        if PERIODS.contains(&self.days) {
+            self.days
+        } else {
+            DEFAULT_DAYS
+        }
+    }
+}
+
+pub async fn loader(
+    Index { team_id }: Index,
+    current_user: Jwt,
+    Extension(pool): Extension<Pool>,
+) -> Result<Html<String>, CustomError> {
+    let mut client = pool.get().await?;
+    let transaction = client.transaction().await?;
+    let rbac = authz::get_permissions(&transaction, &current_user.into(), team_id).await?;
+
+    if !rbac.can_view_feedback() {
+        return Err(CustomError::Authorization);
+    }
+
+    let dashboard = dashboard(&transaction, team_id, DEFAULT_DAYS).await?;
+
+    let html = web_pages::feedback::page::page(rbac, team_id, dashboard);
+
+    Ok(Html(html))
+}
+
+pub async fn filter_action(
+    Index { team_id }: Index,
+    current_user: Jwt,
+    Extension(pool): Extension<Pool>,
+    Form(period): Form<Period>,
+) -> Result<Html<String>, CustomError> {
+    let mut client = pool.get().await?;
+    let transaction = client.transaction().await?;
+    let rbac = authz::get_permissions(&transaction, &current_user.into(), team_id).await?;
+
+    if !rbac.can_view_feedback() {
+        return Err(CustomError::Authorization);
+    }
+
+    let dashboard = dashboard(&transaction, team_id, period.get_days()).await?;
+
+    let html = web_pages::feedback::page::page(rbac, team_id, dashboard);
+
+    Ok(Html(html))
+}
+
+// Downloads the answers rated not helpful as JSON, so they can be used to
+// improve the prompts and datasets.
+pub async fn export_action(
+    Export { team_id }: Export,
+    current_user: Jwt,
+    Extension(pool): Extension<Pool>,
+    Form(period): Form<Period>,
+) -> Result<impl IntoResponse, CustomError> {
+    let mut client = pool.get().await?;
+    let transaction = client.transaction().await?;
+    let rbac = authz::get_permissions(&transaction, &current_user.into(), team_id).await?;
+
+    if !rbac.can_view_feedback() {
+        return Err(CustomError::Authorization);
+    }
+
+    let low_rated = chat_feedback::low_rated()
+        .bind(&transaction, &team_id, &period.get_days().to_string())
+        .all()
+        .await?;
+
+    let export: Vec<Value> = low_rated.into_iter().map(to_json).collect();
+    let body = serde_json::to_string_pretty(&export)?;
+
+    Ok(Response::builder()
+        .header("Content-Type", "application/json")
+        .header(
+            "Content-Disposition",
+            "attachment; filename=\"low-rated-answers.json\"",
+        )
+        .body(Body::from(body))?)
+}
+
+async fn dashboard(
+    transaction: &Transaction<'_>,
+    team_id: i32,
+    days: i32,
+) -> Result<Dashboard, CustomError> {
+    let period = days.to_string();
+
+    let daily = chat_feedback::daily_satisfaction()
+        .bind(transaction, &team_id, &period)
+        .all()
+        .await?;
+
+    let by_assistant = chat_feedback::satisfaction_by_assistant()
+        .bind(transaction, &team_id, &period)
+        .all()
+        .await?;
+
+    let by_model = chat_feedback::satisfaction_by_model()
+        .bind(transaction, &team_id, &period)
+        .all()
+        .await?;
+
+    let by_dataset = chat_feedback::satisfaction_by_dataset()
+        .bind(transaction, &team_id, &period)
+        .all()
+        .await?;
+
+    Ok(Dashboard {
+        days,
+        daily,
+        by_assistant,
+        by_model,
+        by_dataset,
+    })
+}
+
+fn to_json(answer: LowRated) -> Value {
+    json!({
+        "chat_id": answer.chat_id,
+        "conversation_id": answer.conversation_id,
+        "created_at": answer.created_at,
+        "reasons": answer.reasons,
+        "comment": answer.comment,
+        "assistant": answer.assistant,
+        "model": answer.model,
+        "system_prompt": answer.system_prompt,
+        "question": answer.question,
+        "answer": answer.answer,
+        "chunks": answer.chunks.unwrap_or_else(|| json!([])),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_unknown_periods_use_the_default() {
+        assert_eq!(Period { days: 7 }.get_days(), 7);
+        assert_eq!(Period { days: 90 }.get_days(), 90);
+        assert_eq!(Period { days: 100000 }.get_days(), DEFAULT_DAYS);
+        assert_eq!(Period::default().get_days(), DEFAULT_DAYS);
+    }
+}
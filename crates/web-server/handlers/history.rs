@@ -33,14 +33,27 @@ pub async fn loader(
 }
 
 use axum::Form;
+use db::FeedbackRating;
 use serde::Deserialize;
 use validator::Validate;
 use web_pages::routes::history::Search;
 
 #[derive(Deserialize, Validate, Default, Debug)]
 pub struct SearchForm {
-    #[validate(length(min = 1, message = "The search field is mandatory"))]
+    #[serde(default)]
     pub search: String,
+    #[serde(default)]
+    pub rating: String,
+}
+
+impl SearchForm {
+    pub fn get_rating(&self) -> Option<FeedbackRating> {
+        match self.rating.as_str() {
+            "Positive" => Some(FeedbackRating::Positive),
+            "Negative" => Some(FeedbackRating::Negative),
+            _ => None,
+        }
+    }
 }
 
 pub async fn search_action(
@@ -56,7 +69,13 @@ pub async fn search_action(
 
     // Use SQL-based search instead of embeddings
     let history = db::queries::history::search_history()
-        .bind(&transaction, &rbac.user_id, &search.search, &10)
+        .bind(
+            &transaction,
+            &rbac.user_id,
+            &search.search,
+            &search.get_rating(),
+            &10,
+        )
         .all()
         .await?;
 
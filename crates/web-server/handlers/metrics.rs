@@ -1,6 +1,10 @@
 use axum::{response::IntoResponse, Extension};
 use axum_extra::routing::TypedPath;
-use db::{queries::inference_metrics, Pool};
+use db::{
+    queries::{inference_metrics, model_endpoints},
+    Pool,
+};
+use llm_proxy::endpoints::{endpoint_stats, EndpointKey};
 use serde::Deserialize;
 
 use crate::CustomError;
@@ -26,16 +30,70 @@ pub async fn track_metrics(
     for im in &inference_metrics {
         prometheus_metrics.push_str(&format!(
             "tokens_send_in_the_last_minute{{model=\"{}\"}} {}\n",
-            im.model_name, im.tpm_sent
+            label_value(&im.model_name),
+            im.tpm_sent
         ))
     }
 
     for im in inference_metrics {
         prometheus_metrics.push_str(&format!(
             "tokens_received_in_the_last_minute{{model=\"{}\"}} {}\n",
-            im.model_name, im.tpm_recv
+            label_value(&im.model_name),
+            im.tpm_recv
         ))
     }
 
+    // Requests in flight are counted by this process only, so scrape every
+    // instance and sum them.
+    let endpoints = model_endpoints::endpoint_pool()
+        .bind(&transaction)
+        .all()
+        .await?;
+    let stats = endpoint_stats();
+
+    for endpoint in endpoints {
+        let current = stats
+            .get(&EndpointKey {
+                model_id: endpoint.model_id,
+                endpoint_id: endpoint.endpoint_id,
+            })
+            .copied()
+            .unwrap_or_default();
+        // The base URL is left out as it can contain credentials
+        let labels = format!(
+            "model=\"{}\",endpoint=\"{}\"",
+            label_value(&endpoint.model_name),
+            endpoint.endpoint_id
+        );
+        prometheus_metrics.push_str(&format!(
+            "endpoint_in_flight_requests{{{}}} {}\n",
+            labels, current.in_flight
+        ));
+        prometheus_metrics.push_str(&format!(
+            "endpoint_healthy{{{}}} {}\n",
+            labels,
+            u8::from(current.healthy)
+        ));
+    }
+
     Ok(prometheus_metrics)
 }
+
+// Label values are quoted, so escape what the exposition format requires
+fn label_value(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_label_value() {
+        assert_eq!(label_value("gpt-4"), "gpt-4");
+        assert_eq!(label_value("a \"b\"\\c\nd"), "a \\\"b\\\"\\\\c\\nd");
+    }
+}
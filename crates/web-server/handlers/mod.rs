@@ -7,6 +7,7 @@ pub mod categories;
 pub mod console;
 pub mod datasets;
 pub mod documents;
+pub mod feedback;
 pub mod history;
 pub mod integrations;
 pub mod licence;
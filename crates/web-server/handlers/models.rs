@@ -3,12 +3,12 @@ use crate::{CustomError, Jwt};
 use axum::extract::Extension;
 use axum::response::Html;
 use axum::response::IntoResponse;
-use axum::Form;
 use axum::Router;
+use axum_extra::extract::Form;
 use axum_extra::routing::RouterExt;
 use db::authz;
 use db::queries;
-use db::queries::models;
+use db::queries::{model_endpoints, model_fallbacks, models};
 use db::ModelCapability;
 use db::ModelType;
 use db::Pool;
@@ -18,8 +18,11 @@ use db::queries::capabilities;
 use serde::Deserialize;
 use validator::Validate;
 use web_pages::models::upsert as model_page;
-use web_pages::routes::models::{Delete, Edit, Index, New, Upsert};
-use web_pages::{string_to_visibility, visibility_to_string};
+use web_pages::routes::models::{AddEndpoint, Delete, DeleteEndpoint, Edit, Index, New, Upsert};
+use web_pages::{
+    load_balancing_to_string, provider_to_string, string_to_load_balancing, string_to_provider,
+    string_to_visibility, visibility_to_string,
+};
 
 pub fn routes() -> Router {
     Router::new()
@@ -28,6 +31,8 @@ pub fn routes() -> Router {
         .typed_get(edit_loader)
         .typed_post(upsert_action)
         .typed_post(delete_action)
+        .typed_post(add_endpoint_action)
+        .typed_post(delete_endpoint_action)
 }
 
 pub async fn loader(
@@ -101,11 +106,24 @@ pub async fn new_loader(
         name: "".to_string(),
         display_name: "".to_string(),
         model_type: "LLM".to_string(),
+        provider: provider_to_string(db::ModelProvider::OpenAI),
         base_url: "".to_string(),
         api_key: "".to_string(),
         tpm_limit: 10_000,
         rpm_limit: 10_000,
         context_size_bytes: 2048,
+        embeddings_batch_size: 32,
+        embeddings_concurrency: 4,
+        tokenizer: openai_api::tokenizer::DEFAULT_TOKENIZER.to_string(),
+        retry_max_attempts: 1,
+        retry_backoff_ms: 500,
+        retry_status_codes: "429,500,502,503,504".to_string(),
+        circuit_breaker_threshold: 5,
+        circuit_breaker_cooldown_seconds: 30,
+        load_balancing: load_balancing_to_string(db::LoadBalancing::LeastOutstanding),
+        endpoints: Vec::new(),
+        fallback_model_ids: Vec::new(),
+        fallback_options: fallback_options(&transaction, team_id, None).await?,
         visibility: visibility_to_string(if rbac.is_sys_admin {
             Visibility::Company
         } else {
@@ -121,6 +139,7 @@ pub async fn new_loader(
         has_capability_vision: false,
         has_capability_tool_use: false,
         has_capability_guard: false,
+        has_capability_stream_usage: false,
         error: None,
     };
 
@@ -175,13 +194,18 @@ pub async fn edit_loader(
     let has_guard = capabilities
         .iter()
         .any(|c| c.capability == ModelCapability::Guarded);
+    let has_stream_usage = capabilities
+        .iter()
+        .any(|c| c.capability == ModelCapability::StreamUsage);
 
     let model_type = match model.model_type {
         ModelType::LLM => "LLM".to_string(),
         ModelType::Image => "Image".to_string(),
         ModelType::Embeddings => "Embeddings".to_string(),
         ModelType::TextToSpeech => "TextToSpeech".to_string(),
+        ModelType::SpeechToText => "SpeechToText".to_string(),
         ModelType::Guard => "Guard".to_string(),
+        ModelType::Reranker => "Reranker".to_string(),
     };
 
     let form = model_page::ModelForm {
@@ -191,11 +215,27 @@ pub async fn edit_loader(
         // Preserve existing form values when editing
         display_name: model.display_name.clone(),
         model_type,
+        provider: provider_to_string(model.provider),
         base_url: model.base_url,
         api_key: model.api_key.unwrap_or_default(),
         tpm_limit: model.tpm_limit,
         rpm_limit: model.rpm_limit,
         context_size_bytes: model.context_size,
+        embeddings_batch_size: model.embeddings_batch_size,
+        embeddings_concurrency: model.embeddings_concurrency,
+        tokenizer: model.tokenizer,
+        retry_max_attempts: model.retry_max_attempts,
+        retry_backoff_ms: model.retry_backoff_ms,
+        retry_status_codes: model.retry_status_codes,
+        circuit_breaker_threshold: model.circuit_breaker_threshold,
+        circuit_breaker_cooldown_seconds: model.circuit_breaker_cooldown_seconds,
+        load_balancing: load_balancing_to_string(model.load_balancing),
+        endpoints: endpoint_rows(&transaction, id).await?,
+        fallback_model_ids: model_fallbacks::fallbacks()
+            .bind(&transaction, &id)
+            .all()
+            .await?,
+        fallback_options: fallback_options(&transaction, team_id, Some(id)).await?,
         visibility,
         description: model.description.clone(),
         disclaimer: model.disclaimer,
@@ -207,6 +247,7 @@ pub async fn edit_loader(
         has_capability_vision: has_vision,
         has_capability_tool_use: has_tool_use,
         has_capability_guard: has_guard,
+        has_capability_stream_usage: has_stream_usage,
         error: None,
     };
 
@@ -215,6 +256,108 @@ pub async fn edit_loader(
     Ok(Html(html))
 }
 
+// The team's LLM models that can stand in for the model being edited
+async fn fallback_options(
+    transaction: &db::Transaction<'_>,
+    team_id: i32,
+    model_id: Option<i32>,
+) -> Result<Vec<(i32, String)>, CustomError> {
+    let models = model_fallbacks::fallback_options()
+        .bind(transaction, &team_id)
+        .all()
+        .await?;
+
+    Ok(models
+        .into_iter()
+        .filter(|model| Some(model.id) != model_id)
+        .map(|model| (model.id, model.name))
+        .collect())
+}
+
+async fn check_team_model(
+    transaction: &db::Transaction<'_>,
+    team_id: i32,
+    model_id: i32,
+) -> Result<(), CustomError> {
+    let is_team_model = model_endpoints::is_team_model()
+        .bind(transaction, &model_id, &team_id)
+        .one()
+        .await?;
+
+    if !is_team_model {
+        return Err(CustomError::Authorization);
+    }
+
+    Ok(())
+}
+
+// The model's endpoints with what this server knows about their health
+async fn endpoint_rows(
+    transaction: &db::Transaction<'_>,
+    model_id: i32,
+) -> Result<Vec<model_page::EndpointRow>, CustomError> {
+    let endpoints = model_endpoints::endpoints()
+        .bind(transaction, &model_id)
+        .all()
+        .await?;
+    let stats = llm_proxy::endpoints::endpoint_stats();
+
+    Ok(endpoints
+        .into_iter()
+        .map(|endpoint| {
+            let stats = stats
+                .get(&llm_proxy::endpoints::EndpointKey {
+                    model_id,
+                    endpoint_id: endpoint.id,
+                })
+                .copied()
+                .unwrap_or_default();
+            model_page::EndpointRow {
+                id: endpoint.id,
+                base_url: endpoint.base_url,
+                weight: endpoint.weight,
+                max_concurrency: endpoint.max_concurrency,
+                in_flight: stats.in_flight,
+                healthy: stats.healthy,
+            }
+        })
+        .collect())
+}
+
+// Replace the model's fallbacks, keeping the order they were picked in
+async fn save_fallbacks(
+    transaction: &db::Transaction<'_>,
+    team_id: i32,
+    model_id: i32,
+    model_type: ModelType,
+    fallback_model_ids: &[String],
+) -> Result<(), CustomError> {
+    model_fallbacks::delete_all()
+        .bind(transaction, &model_id)
+        .await?;
+
+    if model_type != ModelType::LLM {
+        return Ok(());
+    }
+
+    // Only models the team could pick from
+    let options = fallback_options(transaction, team_id, Some(model_id)).await?;
+
+    let mut priority = 0;
+    for fallback_model_id in fallback_model_ids {
+        if let Ok(fallback_model_id) = fallback_model_id.parse::<i32>() {
+            if options.iter().any(|(id, _)| *id == fallback_model_id) {
+                priority += 1;
+                model_fallbacks::insert()
+                    .bind(transaction, &model_id, &fallback_model_id, &priority)
+                    .await?;
+            }
+        }
+    }
+
+    Ok(())
+}
+
 pub async fn delete_action(
     Delete { id, team_id }: Delete,
     current_user: Jwt,
@@ -238,6 +381,97 @@ pub async fn delete_action(
         "Model Deleted",
     )
 }
+
+#[derive(Deserialize, Validate, Default, Debug)]
+pub struct EndpointForm {
+    #[validate(length(min = 1, message = "The base url is mandatory"))]
+    pub base_url: String,
+    #[serde(deserialize_with = "empty_string_is_none")]
+    pub api_key: Option<String>,
+    #[validate(range(min = 1))]
+    pub weight: i32,
+    #[validate(range(min = 0))]
+    pub max_concurrency: i32,
+}
+
+pub async fn add_endpoint_action(
+    AddEndpoint { team_id, model_id }: AddEndpoint,
+    current_user: Jwt,
+    Extension(pool): Extension<Pool>,
+    Form(endpoint_form): Form<EndpointForm>,
+) -> Result<impl IntoResponse, CustomError> {
+    let mut client = pool.get().await?;
+    let transaction = client.transaction().await?;
+    let rbac = authz::get_permissions(&transaction, &current_user.into(), team_id).await?;
+
+    if !rbac.can_setup_models() {
+        return Err(CustomError::Authorization);
+    }
+
+    check_team_model(&transaction, team_id, model_id).await?;
+
+    let edit = Edit {
+        team_id,
+        id: model_id,
+    }
+    .to_string();
+
+    if endpoint_form.validate().is_err() {
+        return crate::layout::redirect_and_snackbar(&edit, "Problem with Endpoint Validation");
+    }
+
+    model_endpoints::insert()
+        .bind(
+            &transaction,
+            &model_id,
+            &endpoint_form.base_url,
+            &endpoint_form.api_key,
+            &endpoint_form.weight,
+            &endpoint_form.max_concurrency,
+        )
+        .one()
+        .await?;
+
+    transaction.commit().await?;
+
+    crate::layout::redirect_and_snackbar(&edit, "Endpoint Added")
+}
+
+pub async fn delete_endpoint_action(
+    DeleteEndpoint {
+        team_id,
+        model_id,
+        id,
+    }: DeleteEndpoint,
+    current_user: Jwt,
+    Extension(pool): Extension<Pool>,
+) -> Result<impl IntoResponse, CustomError> {
+    let mut client = pool.get().await?;
+    let transaction = client.transaction().await?;
+    let rbac = authz::get_permissions(&transaction, &current_user.into(), team_id).await?;
+
+    if !rbac.can_setup_models() {
+        return Err(CustomError::Authorization);
+    }
+
+    check_team_model(&transaction, team_id, model_id).await?;
+
+    model_endpoints::delete()
+        .bind(&transaction, &id, &model_id)
+        .await?;
+
+    transaction.commit().await?;
+
+    crate::layout::redirect_and_snackbar(
+        &Edit {
+            team_id,
+            id: model_id,
+        }
+        .to_string(),
+        "Endpoint Deleted",
+    )
+}
+
 #[derive(Deserialize, Validate, Default, Debug)]
 pub struct ModelForm {
     pub id: Option<i32>,
@@ -249,11 +483,30 @@ pub struct ModelForm {
     #[validate(length(min = 1, message = "The prompt is mandatory"))]
     pub base_url: String,
     pub model_type: String,
+    pub provider: String,
     #[serde(deserialize_with = "empty_string_is_none")]
     pub api_key: Option<String>,
     pub tpm_limit: i32,
     pub rpm_limit: i32,
     pub context_size: i32,
+    #[validate(range(min = 1))]
+    pub embeddings_batch_size: i32,
+    #[validate(range(min = 1))]
+    pub embeddings_concurrency: i32,
+    pub tokenizer: String,
+    #[validate(range(min = 1))]
+    pub retry_max_attempts: i32,
+    #[validate(range(min = 0))]
+    pub retry_backoff_ms: i32,
+    pub retry_status_codes: String,
+    #[validate(range(min = 1))]
+    pub circuit_breaker_threshold: i32,
+    #[validate(range(min = 0))]
+    pub circuit_breaker_cooldown_seconds: i32,
+    pub load_balancing: String,
+    // Empty when "None" is picked
+    #[serde(default)]
+    pub fallback_model_ids: Vec<String>,
     pub visibility: String,
     pub disclaimer: String,
     pub description: String,
@@ -266,6 +519,7 @@ pub struct ModelForm {
     pub capability_vision: Option<String>,
     pub capability_tool_use: Option<String>,
     pub capability_guard: Option<String>,
+    pub capability_stream_usage: Option<String>,
 }
 
 pub async fn upsert_action(
@@ -287,7 +541,9 @@ pub async fn upsert_action(
         "LLM" => ModelType::LLM,
         "Image" => ModelType::Image,
         "TextToSpeech" => ModelType::TextToSpeech,
+        "SpeechToText" => ModelType::SpeechToText,
         "Guard" => ModelType::Guard,
+        "Reranker" => ModelType::Reranker,
         _ => ModelType::Embeddings,
     };
 
@@ -309,10 +565,29 @@ pub async fn upsert_action(
                     &model_form.tpm_limit,
                     &model_form.rpm_limit,
                     &model_form.context_size,
+                    &model_form.embeddings_batch_size,
+                    &model_form.embeddings_concurrency,
+                    &model_form.tokenizer,
+                    &model_form.retry_max_attempts,
+                    &model_form.retry_backoff_ms,
+                    &model_form.retry_status_codes,
+                    &model_form.circuit_breaker_threshold,
+                    &model_form.circuit_breaker_cooldown_seconds,
+                    &string_to_load_balancing(&model_form.load_balancing),
+                    &string_to_provider(&model_form.provider),
                     &model_id,
                 )
                 .await?;
 
+            save_fallbacks(
+                &transaction,
+                team_id,
+                model_id,
+                model_type,
+                &model_form.fallback_model_ids,
+            )
+            .await?;
+
             let system_prompt: Option<&String> = None;
 
             if let Some(prompt_id) = model_form.prompt_id {
@@ -371,6 +646,11 @@ pub async fn upsert_action(
                         .bind(&transaction, &model_id, &ModelCapability::Guarded)
                         .await?;
                 }
+                if model_form.capability_stream_usage.is_some() {
+                    capabilities::set_model_capability()
+                        .bind(&transaction, &model_id, &ModelCapability::StreamUsage)
+                        .await?;
+                }
             }
 
             transaction.commit().await?;
@@ -393,10 +673,29 @@ pub async fn upsert_action(
                     &model_form.tpm_limit,
                     &model_form.rpm_limit,
                     &model_form.context_size,
+                    &model_form.embeddings_batch_size,
+                    &model_form.embeddings_concurrency,
+                    &model_form.tokenizer,
+                    &model_form.retry_max_attempts,
+                    &model_form.retry_backoff_ms,
+                    &model_form.retry_status_codes,
+                    &model_form.circuit_breaker_threshold,
+                    &model_form.circuit_breaker_cooldown_seconds,
+                    &string_to_load_balancing(&model_form.load_balancing),
+                    &string_to_provider(&model_form.provider),
                 )
                 .one()
                 .await?;
 
+            save_fallbacks(
+                &transaction,
+                team_id,
+                model_id,
+                model_type,
+                &model_form.fallback_model_ids,
+            )
+            .await?;
+
             let system_prompt: Option<String> = None;
             let image_icon: Option<i32> = None;
 
@@ -458,6 +757,11 @@ pub async fn upsert_action(
                         .bind(&transaction, &model_id, &ModelCapability::Guarded)
                         .await?;
                 }
+                if model_form.capability_stream_usage.is_some() {
+                    capabilities::set_model_capability()
+                        .bind(&transaction, &model_id, &ModelCapability::StreamUsage)
+                        .await?;
+                }
             }
 
             transaction.commit().await?;
@@ -4,7 +4,7 @@ use axum::{extract::Extension, response::IntoResponse};
 use axum_typed_multipart::{FieldData, TryFromMultipart, TypedMultipart};
 use db::{authz, queries, Pool, Transaction, Visibility};
 use validator::Validate;
-use web_pages::{routes::prompts::Upsert, string_to_visibility};
+use web_pages::{routes::prompts::Upsert, string_to_history_strategy, string_to_visibility};
 
 #[derive(TryFromMultipart, Validate, Default, Debug)]
 pub struct NewPromptTemplate {
@@ -15,6 +15,8 @@ pub struct NewPromptTemplate {
     pub model_id: i32,
     pub category_id: i32,
     pub max_history_items: i32,
+    pub history_strategy: String,
+    pub summary_model_id: i32,
     pub max_chunks: i32,
     pub max_tokens: i32,
     pub trim_ratio: i32,
@@ -90,8 +92,9 @@ pub async fn upsert(
                     .bind(&transaction, &image_object_id, &id)
                     .await?;
             }
+            update_history_strategy(&transaction, &new_prompt_template, id).await?;
         } else {
-            let _prompt_id = insert_prompt(
+            let prompt_id = insert_prompt(
                 &transaction,
                 &new_prompt_template,
                 image_object_id,
@@ -100,6 +103,7 @@ pub async fn upsert(
                 team_id,
             )
             .await?;
+            update_history_strategy(&transaction, &new_prompt_template, prompt_id).await?;
         }
 
         transaction.commit().await?;
@@ -133,6 +137,24 @@ fn non_empty_string(s: &String) -> Option<&String> {
     }
 }
 
+async fn update_history_strategy(
+    transaction: &Transaction<'_>,
+    new_prompt_template: &NewPromptTemplate,
+    id: i32,
+) -> Result<(), CustomError> {
+    // -1 means the assistant's own model writes the summaries
+    let summary_model_id = Some(new_prompt_template.summary_model_id).filter(|id| *id > 0);
+    queries::prompts::update_history_strategy()
+        .bind(
+            transaction,
+            &string_to_history_strategy(&new_prompt_template.history_strategy),
+            &summary_model_id,
+            &id,
+        )
+        .await?;
+    Ok(())
+}
+
 async fn update_prompt(
     transaction: &Transaction<'_>,
     new_prompt_template: &NewPromptTemplate,
@@ -14,6 +14,10 @@ use web_pages::{
 pub struct DatasetUpdateForm {
     #[serde(default)]
     pub datasets: Vec<i32>,
+    pub vector_weight: f32,
+    pub lexical_weight: f32,
+    pub rerank_enabled: Option<String>,
+    pub rerank_pool_size: i32,
 }
 
 async fn update_datasets(
@@ -48,6 +52,18 @@ pub async fn update_datasets_action(
     // Add new dataset connections
     update_datasets(&transaction, prompt_id, form.datasets).await?;
 
+    // Negative weights would push matches to the bottom, treat them as off
+    queries::prompts::update_retrieval_settings()
+        .bind(
+            &transaction,
+            &form.vector_weight.max(0.0),
+            &form.lexical_weight.max(0.0),
+            &form.rerank_enabled.is_some(),
+            &form.rerank_pool_size.max(1),
+            &prompt_id,
+        )
+        .await?;
+
     transaction.commit().await?;
 
     Ok(crate::layout::redirect_and_snackbar(
@@ -93,6 +109,10 @@ pub async fn manage_datasets(
         prompt_name: prompt.name,
         datasets,
         selected_dataset_ids,
+        vector_weight: prompt.vector_weight,
+        lexical_weight: prompt.lexical_weight,
+        rerank_enabled: prompt.rerank_enabled,
+        rerank_pool_size: prompt.rerank_pool_size,
         error: None,
     };
 
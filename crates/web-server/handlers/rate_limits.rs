@@ -1,12 +1,13 @@
 // Consolidated rate_limits.rs
 
+use crate::layout::empty_string_is_none;
 use crate::{CustomError, Jwt};
 use axum::response::Html;
 use axum::Router;
 use axum::{extract::Extension, response::IntoResponse};
 use axum_extra::extract::Form;
 use axum_extra::routing::RouterExt;
-use db::{authz, queries, ModelType, Pool};
+use db::{authz, queries, ModelType, Pool, Role};
 use serde::Deserialize;
 use validator::Validate;
 use web_pages::{
@@ -97,9 +98,18 @@ pub async fn delete_action(
 #[derive(Deserialize, Validate, Default, Debug)]
 pub struct RateLimitForm {
     pub id: Option<i32>,
-    pub api_key_id: i32,
-    pub tpm_limit: i32,
-    pub rpm_limit: i32,
+    // Either "User", "Role" or "Everyone"
+    pub applies_to: String,
+    #[serde(default, deserialize_with = "empty_string_is_none")]
+    #[validate(email)]
+    pub user_email: Option<String>,
+    #[serde(default, deserialize_with = "empty_string_is_none")]
+    pub limits_role: Option<String>,
+    // Empty for all models
+    #[serde(default, deserialize_with = "empty_string_is_none")]
+    pub model_id: Option<String>,
+    #[validate(range(min = 1))]
+    pub tokens_per_hour: i32,
 }
 
 pub async fn upsert_action(
@@ -124,13 +134,33 @@ pub async fn upsert_action(
         )
         .into_response()),
         (Ok(_), None) => {
+            // Only keep the scope the admin picked
+            let (user_email, limits_role) = match form.applies_to.as_str() {
+                "User" => (form.user_email.map(|email| email.trim().to_string()), None),
+                "Role" => (None, form.limits_role.as_deref().and_then(string_to_role)),
+                _ => (None, None),
+            };
+
+            if form.applies_to == "User" && user_email.is_none()
+                || form.applies_to == "Role" && limits_role.is_none()
+            {
+                return Ok(crate::layout::redirect_and_snackbar(
+                    &web_pages::routes::rate_limits::Index { team_id }.to_string(),
+                    "Problem with Rate Limit Validation",
+                )
+                .into_response());
+            }
+
+            let model_id = form.model_id.and_then(|id| id.parse::<i32>().ok());
+
             // The form is valid save to the database
             queries::rate_limits::new()
                 .bind(
                     &transaction,
-                    &form.api_key_id,
-                    &form.tpm_limit,
-                    &form.rpm_limit,
+                    &limits_role,
+                    &user_email,
+                    &model_id,
+                    &form.tokens_per_hour,
                 )
                 .one()
                 .await?;
@@ -150,3 +180,12 @@ pub async fn upsert_action(
         .into_response()),
     }
 }
+
+fn string_to_role(role: &str) -> Option<Role> {
+    match role {
+        "SystemAdministrator" => Some(Role::SystemAdministrator),
+        "TeamManager" => Some(Role::TeamManager),
+        "Collaborator" => Some(Role::Collaborator),
+        _ => None,
+    }
+}
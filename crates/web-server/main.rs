@@ -76,6 +76,10 @@ async fn main() {
     let pool = db::create_pool(&config.app_database_url);
     let addr = SocketAddr::from(([0, 0, 0, 0], config.port));
 
+    if config.enable_automation_scheduler {
+        tokio::spawn(llm_proxy::automations::run_scheduler(pool.clone()));
+    }
+
     // build our application with a route
     let app = Router::new()
         .typed_get(handlers::static_files::static_path)
@@ -88,6 +92,7 @@ async fn main() {
         .merge(handlers::console::routes())
         .merge(handlers::datasets::routes())
         .merge(handlers::documents::routes())
+        .merge(handlers::feedback::routes())
         .merge(handlers::history::routes())
         .merge(handlers::integrations::routes())
         .merge(handlers::oauth2::routes())